@@ -0,0 +1,150 @@
+//! A benchmark contrasting the receipt of updates decoded into owned
+//! data with their decoding into types borrowing from the received
+//! frames, in terms of time as well as of allocations.
+
+use std::alloc::GlobalAlloc;
+use std::alloc::Layout;
+use std::alloc::System;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use distributed_datalog::BorrowedItem;
+use distributed_datalog::BorrowedTcpReceiver;
+use distributed_datalog::Observable;
+use distributed_datalog::Observer;
+use distributed_datalog::TcpReceiver;
+use distributed_datalog::TcpSender;
+
+/// The number of transactions transmitted per iteration.
+const TRANSACTIONS: u64 = 16;
+
+/// The number of updates per transaction.
+const UPDATES: usize = 4096;
+
+/// An allocator counting the allocations made through it.
+struct CountingAllocator;
+
+/// The number of allocations made so far, by any thread.
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The family of updates borrowed as string slices.
+struct Str;
+
+impl<'de> BorrowedItem<'de> for Str {
+    type Item = &'de str;
+}
+
+/// An observer summing up the lengths of the strings it receives.
+#[derive(Debug, Default)]
+struct LenObserver {
+    len: usize,
+}
+
+impl<T> Observer<T, String> for LenObserver
+where
+    T: AsRef<str> + Send,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        self.len += updates.map(|update| update.as_ref().len()).sum::<usize>();
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A sender transmitting transactions of strings to a receiver.
+struct Transmitter {
+    send: TcpSender<String>,
+    updates: Vec<String>,
+    acked: u64,
+}
+
+impl Transmitter {
+    fn new(addr: SocketAddr) -> Self {
+        let mut send = TcpSender::new(addr).unwrap();
+        send.wait_connected().unwrap();
+
+        Self {
+            send,
+            updates: (0..UPDATES).map(|i| format!("update #{}", i)).collect(),
+            acked: 0,
+        }
+    }
+
+    /// Transmit a number of transactions, waiting for each to be
+    /// acknowledged.
+    fn transmit(&mut self) {
+        for _ in 0..TRANSACTIONS {
+            let observer = &mut self.send as &mut dyn Observer<String, _>;
+            observer.on_start().unwrap();
+            observer
+                .on_updates(Box::new(self.updates.clone().into_iter()))
+                .unwrap();
+            observer.on_commit().unwrap();
+            self.acked += 1;
+            self.send.await_ack(self.acked).unwrap();
+        }
+    }
+
+    /// Count the allocations made, across all threads, per transmitted
+    /// transaction.
+    fn allocations(&mut self) -> usize {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        self.transmit();
+        (ALLOCATIONS.load(Ordering::Relaxed) - before) / TRANSACTIONS as usize
+    }
+}
+
+fn decoding(c: &mut Criterion) {
+    let mut recv = TcpReceiver::<String, String>::new("127.0.0.1:0").unwrap();
+    recv.subscribe(Box::new(LenObserver::default())).unwrap();
+    let mut owned = Transmitter::new(*recv.addr());
+
+    let borrowed_recv =
+        BorrowedTcpReceiver::<Str, _>::new("127.0.0.1:0", LenObserver::default()).unwrap();
+    let mut borrowed = Transmitter::new(*borrowed_recv.addr());
+
+    println!(
+        "allocations per transaction: owned {}, borrowed {}",
+        owned.allocations(),
+        borrowed.allocations()
+    );
+
+    let mut group = c.benchmark_group("decoding");
+    group.sample_size(10);
+    group.bench_function("borrowed", |b| b.iter(|| borrowed.transmit()));
+    group.bench_function("owned", |b| b.iter(|| owned.transmit()));
+    group.finish();
+}
+
+criterion_group!(benches, decoding);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+//! A benchmark contrasting the inline delivery of updates with their
+//! delivery through a thread pool.
+
+use std::sync::Arc;
+
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+
+use distributed_datalog::DeliveryExecutor;
+use distributed_datalog::InlineExecutor;
+use distributed_datalog::Observer;
+use distributed_datalog::ObserverExt;
+use distributed_datalog::ThreadPoolExecutor;
+
+/// The number of independent pipelines updates are delivered to.
+const PIPELINES: usize = 4;
+
+/// The number of transactions delivered to each pipeline.
+const TRANSACTIONS: u64 = 64;
+
+/// The number of updates per transaction.
+const UPDATES: u64 = 256;
+
+/// An observer performing some CPU-bound work for every update.
+#[derive(Debug, Default)]
+struct BusyObserver {
+    sum: u64,
+}
+
+impl Observer<u64, ()> for BusyObserver {
+    fn on_start(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = u64> + 'a>) -> Result<(), ()> {
+        for update in updates {
+            let mut value = update;
+            for _ in 0..1000 {
+                value = black_box(value.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1));
+            }
+            self.sum = self.sum.wrapping_add(value);
+        }
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), ()> {
+        Ok(())
+    }
+}
+
+/// Deliver transactions to a number of pipelines, round robin, using
+/// the given executor.
+fn deliver(executor: Arc<dyn DeliveryExecutor>) {
+    let mut observers = (0..PIPELINES)
+        .map(|_| BusyObserver::default().deliver_on(executor.clone()))
+        .collect::<Vec<_>>();
+
+    for txn in 0..TRANSACTIONS {
+        for observer in &mut observers {
+            let observer = observer as &mut dyn Observer<u64, ()>;
+            observer.on_start().unwrap();
+            let updates = (0..UPDATES).map(|update| txn * UPDATES + update);
+            observer.on_updates(Box::new(updates)).unwrap();
+            observer.on_commit().unwrap();
+        }
+    }
+
+    for observer in &mut observers {
+        Observer::<u64, ()>::on_completed(observer).unwrap();
+    }
+}
+
+fn delivery(c: &mut Criterion) {
+    let mut group = c.benchmark_group("delivery");
+    group.sample_size(10);
+
+    group.bench_function("inline", |b| b.iter(|| deliver(Arc::new(InlineExecutor))));
+
+    for threads in [1, 2, 4].iter() {
+        let pool = Arc::new(ThreadPoolExecutor::new(*threads)) as Arc<dyn DeliveryExecutor>;
+        group.bench_with_input(BenchmarkId::new("pooled", threads), &pool, |b, pool| {
+            b.iter(|| deliver(pool.clone()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, delivery);
+criterion_main!(benches);
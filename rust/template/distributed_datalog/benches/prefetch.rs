@@ -0,0 +1,121 @@
+//! A benchmark contrasting the receipt of updates by a slow observer
+//! with and without messages being read ahead of their processing.
+
+use std::net::SocketAddr;
+
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+
+use distributed_datalog::Observable;
+use distributed_datalog::Observer;
+use distributed_datalog::TcpReceiver;
+use distributed_datalog::TcpReceiverBuilder;
+use distributed_datalog::TcpSender;
+
+/// The number of transactions transmitted per iteration.
+const TRANSACTIONS: u64 = 8;
+
+/// The number of updates per transaction.
+const UPDATES: usize = 4096;
+
+/// The maximum number of updates per message.
+const CHUNK_LEN: usize = 64;
+
+/// An observer performing some CPU-bound work for every update.
+#[derive(Debug, Default)]
+struct BusyObserver {
+    sum: u64,
+}
+
+impl Observer<String, String> for BusyObserver {
+    fn on_start(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = String> + 'a>,
+    ) -> Result<(), String> {
+        for update in updates {
+            let mut value = update.len() as u64;
+            for _ in 0..1000 {
+                value = black_box(
+                    value
+                        .wrapping_mul(6_364_136_223_846_793_005)
+                        .wrapping_add(1),
+                );
+            }
+            self.sum = self.sum.wrapping_add(value);
+        }
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A sender transmitting transactions of strings to a receiver, split
+/// up into many messages.
+struct Transmitter {
+    send: TcpSender<String>,
+    updates: Vec<String>,
+    acked: u64,
+}
+
+impl Transmitter {
+    fn new(addr: SocketAddr) -> Self {
+        let mut send = TcpSender::with_max_chunk_len(addr, CHUNK_LEN).unwrap();
+        send.wait_connected().unwrap();
+
+        Self {
+            send,
+            updates: (0..UPDATES).map(|i| format!("update #{}", i)).collect(),
+            acked: 0,
+        }
+    }
+
+    /// Transmit a number of transactions, waiting for the last one to
+    /// be acknowledged.
+    fn transmit(&mut self) {
+        for _ in 0..TRANSACTIONS {
+            let observer = &mut self.send as &mut dyn Observer<String, _>;
+            observer.on_start().unwrap();
+            observer
+                .on_updates(Box::new(self.updates.clone().into_iter()))
+                .unwrap();
+            observer.on_commit().unwrap();
+        }
+        self.acked += TRANSACTIONS;
+        self.send.await_ack(self.acked).unwrap();
+    }
+}
+
+fn prefetch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("prefetch");
+    group.sample_size(10);
+
+    for prefetch in [0, 4, 64].iter() {
+        let mut recv: TcpReceiver<String, String> = TcpReceiverBuilder::new("127.0.0.1:0")
+            .prefetch(*prefetch)
+            .build()
+            .unwrap();
+        recv.subscribe(Box::new(BusyObserver::default())).unwrap();
+        let mut transmitter = Transmitter::new(*recv.addr());
+
+        group.bench_function(BenchmarkId::from_parameter(prefetch), |b| {
+            b.iter(|| transmitter.transmit())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, prefetch);
+criterion_main!(benches);
@@ -0,0 +1,119 @@
+//! A benchmark contrasting the latency of receiving a single update
+//! through a `TcpReceiver` with that of a `RingTcpReceiver`.
+
+use std::convert::TryInto;
+use std::io::Write;
+use std::net::TcpStream;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+use distributed_datalog::FixedLayout;
+use distributed_datalog::Observable;
+use distributed_datalog::Observer;
+use distributed_datalog::RingTcpReceiver;
+use distributed_datalog::TcpReceiver;
+use distributed_datalog::TcpSender;
+
+/// Records consisting of a little endian `u64`.
+struct Record;
+
+impl<'a> FixedLayout<'a> for Record {
+    const SIZE: usize = 8;
+    type Item = u64;
+
+    fn view(record: &'a [u8]) -> Self::Item {
+        u64::from_le_bytes(record.try_into().unwrap())
+    }
+}
+
+/// An observer reporting every update it received on a channel.
+#[derive(Debug)]
+struct ReportingObserver(Sender<u64>);
+
+impl Observer<u64, String> for ReportingObserver {
+    fn on_start(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        updates: Box<dyn Iterator<Item = u64> + 'a>,
+    ) -> Result<(), String> {
+        for update in updates {
+            self.0.send(update).map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Send `update` using `send` and wait for it to arrive on `received`.
+fn round_trip<F>(update: u64, send: F, received: &Receiver<u64>)
+where
+    F: FnOnce(u64),
+{
+    send(update);
+    assert_eq!(received.recv().unwrap(), update);
+}
+
+fn latency(c: &mut Criterion) {
+    let mut group = c.benchmark_group("latency");
+
+    {
+        let (sender, received) = channel();
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(ReportingObserver(sender))).unwrap();
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+
+        let mut update = 0;
+        group.bench_function("standard", |b| {
+            b.iter(|| {
+                update += 1;
+                let send = |update| {
+                    let observer = &mut send as &mut dyn Observer<u64, _>;
+                    observer.on_start().unwrap();
+                    observer
+                        .on_updates(Box::new(Some(update).into_iter()))
+                        .unwrap();
+                    observer.on_commit().unwrap();
+                };
+                round_trip(update, send, &received)
+            })
+        });
+    }
+
+    {
+        let (sender, received) = channel();
+        let recv =
+            RingTcpReceiver::<Record, _>::new("127.0.0.1:0", 64, ReportingObserver(sender))
+                .unwrap();
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        socket.set_nodelay(true).unwrap();
+
+        let mut update = 0;
+        group.bench_function("ring", |b| {
+            b.iter(|| {
+                update += 1;
+                let send = |update: u64| socket.write_all(&update.to_le_bytes()).unwrap();
+                round_trip(update, send, &received)
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, latency);
+criterion_main!(benches);
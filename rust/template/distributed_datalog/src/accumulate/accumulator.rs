@@ -201,9 +201,13 @@ where
         );
         self.distributor.unsubscribe(subscription)
     }
+
+    fn subscriber_count(&self) -> usize {
+        self.distributor.subscriber_count()
+    }
 }
 
-/// All calls except `on_completed` of the Observer trait are delegated to the AccumulatingObserver.
+/// All calls except `on_completed` and `on_disconnected` of the Observer trait are delegated to the AccumulatingObserver.
 impl<V, E> Observer<Update<V>, E> for DistributingAccumulator<Update<V>, V, E>
 where
     V: Debug + Send + Eq + Hash + Clone + 'static,
@@ -227,6 +231,24 @@ where
         self.observer.on_updates(updates)
     }
 
+    fn on_snapshot<'a>(
+        &mut self,
+        items: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_snapshot", self.id);
+        self.observer.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_abort", self.id);
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_flush", self.id);
+        self.observer.on_flush()
+    }
+
     /// Triggers the on_completed() actions for Accumulator's distributor and
     /// observer.
     fn on_completed(&mut self) -> Result<(), E> {
@@ -236,6 +258,16 @@ where
 
         self.observer.on_completed()
     }
+
+    /// Triggers the on_disconnected() actions for Accumulator's distributor
+    /// and observer.
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        trace!("DistributingAccumulator({})::on_disconnected", self.id);
+        let mut distributor = self.distributor.lock().unwrap();
+        let _ = distributor.on_disconnected();
+
+        self.observer.on_disconnected()
+    }
 }
 
 #[cfg(test)]
@@ -4,6 +4,7 @@ use std::collections::HashSet;
 use std::collections::LinkedList;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::mem::replace;
 
 use log::trace;
 use uid::Id;
@@ -34,6 +35,12 @@ where
     data: HashMap<RelId, HashSet<V>>,
     /// Temporary buffer to cache the updates before committing.
     buffer: Option<LinkedList<Vec<T>>>,
+    /// Whether the buffered transaction carries a snapshot, meaning
+    /// that it replaces the accumulated state on commit.
+    snapshot: bool,
+    /// Whether the buffered transaction got flushed, meaning that the
+    /// next `on_start` continues it.
+    flushed: bool,
 }
 
 impl<T, V, E> AccumulatingObserver<T, V, E>
@@ -51,6 +58,8 @@ where
             observer: SharedObserver::default(),
             data: HashMap::new(),
             buffer: None,
+            snapshot: false,
+            flushed: false,
         }
     }
 
@@ -75,6 +84,8 @@ where
             self.id
         );
         let _ = self.buffer.take();
+        self.snapshot = false;
+        self.flushed = false;
         self.data.drain().collect()
     }
 }
@@ -114,6 +125,10 @@ where
         trace!("AccumulatingObserver({})::unsubscribe()", self.id);
         self.observer.lock().unwrap().take()
     }
+
+    fn subscriber_count(&self) -> usize {
+        usize::from(self.observer.lock().unwrap().is_some())
+    }
 }
 
 /// Forwards the incoming data to the observer while keeping track of the current state
@@ -125,13 +140,13 @@ where
     fn on_start(&mut self) -> Result<(), E> {
         trace!("AccumulatingObserver({})::on_start", self.id);
 
-        if self.buffer.is_some() {
-            panic!("received multiple on_start events")
-        } else {
+        if self.buffer.is_none() {
             self.buffer = Some(LinkedList::new());
-            let mut guard = self.observer.lock().unwrap();
-            guard.on_start()
+        } else if !replace(&mut self.flushed, false) {
+            panic!("received multiple on_start events")
         }
+        let mut guard = self.observer.lock().unwrap();
+        guard.on_start()
     }
 
     fn on_commit(&mut self) -> Result<(), E> {
@@ -143,6 +158,10 @@ where
                 let mut guard = self.observer.lock().unwrap();
                 guard.on_commit()?;
             }
+            self.flushed = false;
+            if replace(&mut self.snapshot, false) {
+                self.data.clear();
+            }
             // apply the buffered updates to the accumulated state if successful
             buffer
                 .into_iter()
@@ -190,13 +209,69 @@ where
         }
     }
 
+    /// Buffer the snapshot like regular updates, but have it replace
+    /// the accumulated state once the transaction commits.
+    fn on_snapshot<'a>(
+        &mut self,
+        items: Box<dyn Iterator<Item = Update<V>> + 'a>,
+    ) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_snapshot", self.id);
+
+        if let Some(ref mut buffer) = self.buffer {
+            let items = items.collect::<Vec<_>>();
+            buffer.push_back(items.clone());
+            self.snapshot = true;
+
+            let mut guard = self.observer.lock().unwrap();
+            guard.on_snapshot(Box::new(items.into_iter()))
+        } else {
+            panic!("on_snapshot was not preceded by an on_start event")
+        }
+    }
+
+    /// Discard the buffered updates, leaving the accumulated state untouched.
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_abort", self.id);
+
+        if self.buffer.take().is_some() {
+            self.snapshot = false;
+            self.flushed = false;
+            let mut guard = self.observer.lock().unwrap();
+            guard.on_abort()
+        } else {
+            panic!("on_abort was not preceded by an on_start event")
+        }
+    }
+
+    /// Hold on to the buffered updates; the transaction continues with
+    /// the next `on_start` and only gets applied once it commits.
+    fn on_flush(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_flush", self.id);
+
+        if self.buffer.is_some() {
+            self.flushed = true;
+            let mut guard = self.observer.lock().unwrap();
+            guard.on_flush()
+        } else {
+            panic!("on_flush was not preceded by an on_start event")
+        }
+    }
+
     /// signals that the source has been removed, clears the accumulated state.
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("AccumulatingObserver({})::on_completed", self.id);
         let _ = self.buffer.take();
+        self.snapshot = false;
+        self.flushed = false;
         let _ = self.data.drain();
         Ok(())
     }
+
+    /// signals that the source went away unexpectedly, clears the accumulated state.
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        trace!("AccumulatingObserver({})::on_disconnected", self.id);
+        self.on_completed()
+    }
 }
 
 #[cfg(test)]
@@ -387,4 +462,46 @@ mod tests {
                 _ => panic!("Unexpected relid!"),
             });
     }
+
+    /// Test that aborted transactions leave the accumulated state alone,
+    /// flushed ones continue with the next `on_start`, and snapshots
+    /// replace the state once committed.
+    #[test]
+    fn abort_flush_snapshot_accumulation() {
+        let mut observer = AccumulatingObserver::<Update<usize>, usize, ()>::new();
+        let mock = Arc::new(Mutex::new(Some(MockObserver::default())));
+        let _subscription = observer.subscribe(Box::new(mock.clone()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_abort, 1);
+        assert!(observer.buffer_is_empty());
+        assert!(observer.data.is_empty());
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_1()), Ok(()));
+        assert_eq!(observer.on_flush(), Ok(()));
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_flush, 1);
+        assert!(observer.data.is_empty());
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(get_usize_insert_updates_3()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.data.values().map(HashSet::len).sum::<usize>(), 7);
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_snapshot(get_usize_insert_updates_2()), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_updates, 13);
+        assert_eq!(observer.data.len(), 2);
+        assert_eq!(
+            observer.data[&1],
+            vec!(2, 3).into_iter().collect::<HashSet<_>>()
+        );
+        assert_eq!(
+            observer.data[&2],
+            vec!(3).into_iter().collect::<HashSet<_>>()
+        );
+    }
 }
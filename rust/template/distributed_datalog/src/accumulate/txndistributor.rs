@@ -89,6 +89,16 @@ where
             .remove(subscription)
             .map(|observer| Box::new(observer) as ObserverBox<T, E>)
     }
+
+    /// Count the observers subscribed directly as well as those
+    /// subscribed to one of the observables created via
+    /// `create_observable`.
+    fn subscriber_count(&self) -> usize {
+        self.observers
+            .values()
+            .filter(|observer| observer.lock().unwrap().is_some())
+            .count()
+    }
 }
 
 /// Receives the values, clones them and sends them to each observer
@@ -137,6 +147,26 @@ where
         }
     }
 
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_snapshot", self.id);
+
+        // clone the snapshot for each observer
+        let items = items.collect::<Vec<T>>();
+        self.observers
+            .values_mut()
+            .try_for_each(|o| o.on_snapshot(Box::new(items.clone().into_iter())))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_abort", self.id);
+        self.observers.values_mut().try_for_each(|o| o.on_abort())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_flush", self.id);
+        self.observers.values_mut().try_for_each(|o| o.on_flush())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("TxnDistributor({})::on_completed", self.id);
         match self.observers.values_mut()
@@ -147,6 +177,13 @@ where
             Err(error) => Err(error)
         }
     }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        trace!("TxnDistributor({})::on_disconnected", self.id);
+        self.observers
+            .values_mut()
+            .try_for_each(|o| o.on_disconnected())
+    }
 }
 
 #[cfg(test)]
@@ -189,6 +226,7 @@ mod tests {
 
         assert!(distributor.subscribe(Box::new(mock1.clone())).is_ok());
         assert!(distributor.subscribe(Box::new(mock2.clone())).is_ok());
+        assert_eq!(distributor.subscriber_count(), 2);
 
         assert_eq!(distributor.on_start(), Ok(()));
         assert_eq!(mock1.lock().unwrap().called_on_start, 1);
@@ -215,9 +253,12 @@ mod tests {
         let mock2 = Arc::new(Mutex::new(MockObserver::new()));
         let mut observable1 = distributor.create_observable();
         let mut observable2 = distributor.create_observable();
+        // Observables nobody subscribed to yet do not count.
+        assert_eq!(distributor.subscriber_count(), 0);
 
         assert!(observable1.subscribe(Box::new(mock1.clone())).is_ok());
         assert!(observable2.subscribe(Box::new(mock2.clone())).is_ok());
+        assert_eq!(distributor.subscriber_count(), 2);
 
         assert_eq!(distributor.on_start(), Ok(()));
         assert_eq!(mock1.lock().unwrap().called_on_start, 1);
@@ -231,4 +272,38 @@ mod tests {
         assert_eq!(mock1.lock().unwrap().called_on_commit, 1);
         assert_eq!(mock2.lock().unwrap().called_on_commit, 1);
     }
+
+    /// Test that a `TxnDistributor` forwards snapshots, aborts, flushes
+    /// and disconnects to all of its observers.
+    #[test]
+    fn forward_all_events_distributor() {
+        let mut distributor = TxnDistributor::<_, ()>::new();
+        let mock1 = Arc::new(Mutex::new(MockObserver::new()));
+        let mock2 = Arc::new(Mutex::new(MockObserver::new()));
+        let mut observable = distributor.create_observable();
+
+        assert!(distributor.subscribe(Box::new(mock1.clone())).is_ok());
+        assert!(observable.subscribe(Box::new(mock2.clone())).is_ok());
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(distributor.on_snapshot(Box::new([1, 3].iter())), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_updates, 2);
+        assert_eq!(mock2.lock().unwrap().called_on_updates, 2);
+
+        assert_eq!(distributor.on_abort(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_abort, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_abort, 1);
+
+        assert_eq!(distributor.on_start(), Ok(()));
+        assert_eq!(distributor.on_updates(Box::new([2].iter())), Ok(()));
+        assert_eq!(distributor.on_flush(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_flush, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_flush, 1);
+
+        assert_eq!(distributor.on_disconnected(), Ok(()));
+        assert_eq!(mock1.lock().unwrap().called_on_disconnected, 1);
+        assert_eq!(mock2.lock().unwrap().called_on_disconnected, 1);
+        assert_eq!(mock1.lock().unwrap().called_on_commit, 0);
+        assert_eq!(mock2.lock().unwrap().called_on_commit, 0);
+    }
 }
@@ -0,0 +1,343 @@
+//! A module providing an observer broadcasting the items it receives to
+//! any number of independent consumers, each with a bounded queue of
+//! its own.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::mem::replace;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+use log::trace;
+
+use uid::Id;
+
+use crate::observe::Observer;
+use crate::poison::MutexExt;
+
+/// The contents of the queue of a consumer.
+#[derive(Debug)]
+struct Contents<T> {
+    /// The items queued.
+    items: VecDeque<T>,
+    /// The number of items dropped because the queue was full.
+    dropped: u64,
+    /// Whether no more items will arrive.
+    closed: bool,
+}
+
+/// The bounded queue of a consumer.
+#[derive(Debug)]
+struct Queue<T> {
+    /// The maximum number of items queued.
+    capacity: usize,
+    /// The contents of the queue.
+    contents: Mutex<Contents<T>>,
+    /// A condition variable signaled whenever an item got queued or the
+    /// queue got closed.
+    cond: Condvar,
+}
+
+impl<T> Queue<T> {
+    /// Queue `items`, dropping the oldest ones queued as necessary to
+    /// stay within our capacity.
+    fn push<I>(&self, items: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut contents = self.contents.lock_unpoisoned();
+        for item in items {
+            if contents.items.len() == self.capacity {
+                let _ = contents.items.pop_front();
+                contents.dropped += 1;
+            }
+            contents.items.push_back(item);
+        }
+        self.cond.notify_all();
+    }
+
+    /// Close the queue.
+    fn close(&self) {
+        self.contents.lock_unpoisoned().closed = true;
+        self.cond.notify_all();
+    }
+}
+
+/// The receiving end of a consumer subscribed to a `BroadcastObserver`.
+///
+/// Once the observer completed (or got dropped), the items still queued
+/// can be received, after which receiving fails.
+#[derive(Debug)]
+pub struct BroadcastReceiver<T>(Arc<Queue<T>>);
+
+impl<T> BroadcastReceiver<T> {
+    /// Receive the next item, blocking until one arrives. `None` is
+    /// returned once no more items will arrive.
+    pub fn recv(&self) -> Option<T> {
+        let mut contents = self.0.contents.lock_unpoisoned();
+        loop {
+            if let Some(item) = contents.items.pop_front() {
+                return Some(item);
+            }
+            if contents.closed {
+                return None;
+            }
+            contents = self
+                .0
+                .cond
+                .wait(contents)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Receive the next item, blocking for at most `timeout` until one
+    /// arrives.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut contents = self.0.contents.lock_unpoisoned();
+        loop {
+            if let Some(item) = contents.items.pop_front() {
+                return Some(item);
+            }
+            let now = Instant::now();
+            if contents.closed || now >= deadline {
+                return None;
+            }
+            contents = self
+                .0
+                .cond
+                .wait_timeout(contents, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Receive the next item, if one is queued already.
+    pub fn try_recv(&self) -> Option<T> {
+        self.0.contents.lock_unpoisoned().items.pop_front()
+    }
+
+    /// Retrieve the number of items queued.
+    pub fn len(&self) -> usize {
+        self.0.contents.lock_unpoisoned().items.len()
+    }
+
+    /// Check whether no items are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Retrieve the number of items dropped because they could not be
+    /// received before the queue overflowed.
+    pub fn dropped(&self) -> u64 {
+        self.0.contents.lock_unpoisoned().dropped
+    }
+}
+
+/// An `Observer` broadcasting the items it receives to any number of
+/// consumers, each receiving them through a `BroadcastReceiver`.
+///
+/// Every consumer has a bounded queue of its own, so that a slow
+/// consumer does not hold up the others, let alone the observer: once
+/// the queue of a consumer is full, the oldest item queued is dropped
+/// to make room for a new one (and accounted for in
+/// `BroadcastReceiver::dropped`). The items of a transaction are
+/// buffered until its commit, so that consumers never see those of an
+/// aborted one; a transaction that got flushed is held on to and
+/// continued by the next one. A consumer dropping its receiver is
+/// unsubscribed.
+#[derive(Debug)]
+pub struct BroadcastObserver<T> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The queues of the consumers subscribed.
+    queues: Vec<Weak<Queue<T>>>,
+    /// The items of the transaction in progress.
+    items: Vec<T>,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// the next `on_start` continues it.
+    flushed: bool,
+}
+
+impl<T> BroadcastObserver<T> {
+    /// Create a new `BroadcastObserver`, without any consumers.
+    pub fn new() -> Self {
+        let id = Id::<()>::new().get();
+        trace!("BroadcastObserver({})::new", id);
+
+        Self {
+            id,
+            queues: Vec::new(),
+            items: Vec::new(),
+            flushed: false,
+        }
+    }
+
+    /// Subscribe a new consumer, receiving the items of transactions
+    /// committed from now on through a queue of the given capacity,
+    /// which has to be non-zero.
+    pub fn subscribe(&mut self, capacity: usize) -> BroadcastReceiver<T> {
+        trace!("BroadcastObserver({})::subscribe: {}", self.id, capacity);
+        assert!(capacity > 0, "queue capacity must be non-zero");
+
+        let queue = Arc::new(Queue {
+            capacity,
+            contents: Mutex::new(Contents {
+                items: VecDeque::new(),
+                dropped: 0,
+                closed: false,
+            }),
+            cond: Condvar::new(),
+        });
+        self.queues.push(Arc::downgrade(&queue));
+        BroadcastReceiver(queue)
+    }
+
+    /// Retrieve the number of consumers subscribed.
+    pub fn receiver_count(&self) -> usize {
+        self.queues
+            .iter()
+            .filter(|queue| queue.strong_count() > 0)
+            .count()
+    }
+
+    /// Close the queues of all consumers.
+    fn close(&mut self) {
+        for queue in self.queues.drain(..) {
+            if let Some(queue) = queue.upgrade() {
+                queue.close()
+            }
+        }
+    }
+}
+
+impl<T> Default for BroadcastObserver<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for BroadcastObserver<T> {
+    fn drop(&mut self) {
+        self.close()
+    }
+}
+
+impl<T> Observer<T, String> for BroadcastObserver<T>
+where
+    T: Clone + Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_start", self.id);
+        if !replace(&mut self.flushed, false) {
+            self.items.clear();
+        }
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_commit", self.id);
+
+        let items = &self.items;
+        self.queues.retain(|queue| match queue.upgrade() {
+            Some(queue) => {
+                queue.push(items.iter().cloned());
+                true
+            }
+            None => false,
+        });
+        self.items.clear();
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_updates", self.id);
+        self.items.extend(updates);
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_snapshot", self.id);
+        self.items.extend(items);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_abort", self.id);
+        self.items.clear();
+        self.flushed = false;
+        Ok(())
+    }
+
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_flush", self.id);
+        self.flushed = true;
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_completed", self.id);
+        self.close();
+        Ok(())
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        trace!("BroadcastObserver({})::on_disconnected", self.id);
+        self.close();
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        "broadcast".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::iter::from_fn;
+
+    /// Send a transaction containing `updates` to `observer`.
+    fn send(observer: &mut dyn Observer<u64, String>, updates: Vec<u64>) {
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+    }
+
+    /// Check that every consumer receives the committed items, with a
+    /// slow one losing the oldest items only.
+    #[test]
+    fn broadcast() {
+        let mut broadcast = BroadcastObserver::<u64>::new();
+        let fast = broadcast.subscribe(8);
+        let slow = broadcast.subscribe(2);
+        let gone = broadcast.subscribe(1);
+        assert_eq!(broadcast.receiver_count(), 3);
+        drop(gone);
+        assert_eq!(broadcast.receiver_count(), 2);
+
+        let observer = &mut broadcast as &mut dyn Observer<u64, String>;
+        send(observer, vec![1, 2]);
+        assert_eq!(fast.recv(), Some(1));
+        assert_eq!(fast.recv(), Some(2));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![9].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        send(observer, vec![3, 4]);
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        assert_eq!(from_fn(|| fast.recv()).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(slow.len(), 2);
+        assert_eq!(slow.dropped(), 2);
+        assert_eq!(from_fn(|| slow.recv()).collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(slow.recv_timeout(Duration::from_millis(10)), None);
+        assert!(slow.is_empty());
+    }
+}
@@ -0,0 +1,346 @@
+//! A module providing adapters between the `Observer`/`Observable`
+//! world and `std::sync::mpsc` channels transferring `Message`s.
+
+use std::fmt::Debug;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::spawn;
+
+use log::error;
+use log::trace;
+
+use uid::Id;
+
+use crate::observe::Observable;
+use crate::observe::Observer;
+use crate::observe::ObserverBox;
+use crate::observe::OptionalObserver;
+use crate::observe::SharedObserver;
+use crate::poison::MutexExt;
+use crate::tcp_channel::relay;
+use crate::tcp_channel::Event;
+use crate::tcp_channel::Message;
+use crate::tcp_channel::RestartPolicy;
+use crate::tcp_channel::Session;
+
+/// An `Observer` sending all events it receives as `Message`s through
+/// a channel.
+///
+/// A disconnect has no message of its own and is passed on by dropping
+/// our end of the channel instead, which a `ChannelObservable` signals
+/// as a disconnect once all sending ends are gone. Once the receiving
+/// end of the channel is gone, or ours got dropped, every event fails
+/// with an error.
+#[derive(Debug)]
+pub struct ChannelObserver<T> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The channel we send messages through, until disconnected.
+    sender: Option<Sender<Message<T>>>,
+}
+
+impl<T> ChannelObserver<T> {
+    /// Create a new `ChannelObserver` sending messages through
+    /// `sender`.
+    pub fn new(sender: Sender<Message<T>>) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ChannelObserver({})::new", id);
+
+        Self {
+            id,
+            sender: Some(sender),
+        }
+    }
+
+    /// Send a message through the channel.
+    fn send(&self, message: Message<T>) -> Result<(), String> {
+        match &self.sender {
+            Some(sender) => sender
+                .send(message)
+                .map_err(|e| format!("failed to send {} message: channel disconnected", e.0)),
+            None => Err(format!(
+                "failed to send {} message: observer disconnected",
+                message
+            )),
+        }
+    }
+}
+
+impl<T> Observer<T, String> for ChannelObserver<T>
+where
+    T: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_start", self.id);
+        self.send(Message::Start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_commit", self.id);
+        self.send(Message::Commit)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_updates", self.id);
+        self.send(Message::Updates(updates.collect()))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_snapshot", self.id);
+        self.send(Message::Snapshot(items.collect()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_abort", self.id);
+        self.send(Message::Abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_flush", self.id);
+        self.send(Message::Flush)
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_completed", self.id);
+        self.send(Message::Complete)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        trace!("ChannelObserver({})::on_disconnected", self.id);
+        self.sender = None;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        "channel".to_string()
+    }
+}
+
+/// An `Observable` relaying the `Message`s received through a channel
+/// to its observer.
+///
+/// Messages are relayed by a thread started with the first
+/// subscription. Messages arriving while no observer is subscribed are
+/// dropped. Once all sending ends of the channel are gone, a
+/// transaction left open is aborted and the observer is notified of
+/// the disconnect (unless a `Complete` message was received already),
+/// mirroring how a `TcpReceiver` treats a sender closing its
+/// connection. The thread exits at this point and not earlier, i.e., it
+/// may outlive the `ChannelObservable` itself. A restart rejected by
+/// the configured `RestartPolicy` is handled in the same way, except
+/// that the thread stops relaying right away.
+#[derive(Debug)]
+pub struct ChannelObservable<T> {
+    /// The observable's unique ID.
+    id: usize,
+    /// The channel to receive messages from, until the relaying
+    /// thread got started.
+    receiver: Option<Receiver<Message<T>>>,
+    /// How to handle a transaction restarted while still open.
+    restart: RestartPolicy,
+    /// The observer to relay messages to, if any; shared with the
+    /// relaying thread.
+    observer: SharedObserver<OptionalObserver<ObserverBox<T, String>>>,
+}
+
+impl<T> ChannelObservable<T>
+where
+    T: Debug + Send + 'static,
+{
+    /// Create a new `ChannelObservable` relaying messages received
+    /// through `receiver`.
+    pub fn new(receiver: Receiver<Message<T>>) -> Self {
+        Self::with_restart_policy(receiver, RestartPolicy::Abort)
+    }
+
+    /// Create a new `ChannelObservable` relaying messages received
+    /// through `receiver`, handling a transaction restarted while
+    /// still open according to `restart`.
+    pub fn with_restart_policy(receiver: Receiver<Message<T>>, restart: RestartPolicy) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ChannelObservable({})::new", id);
+
+        Self {
+            id,
+            receiver: Some(receiver),
+            restart,
+            observer: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Relay all messages received through `receiver` to the observer.
+    fn relay(
+        id: usize,
+        receiver: Receiver<Message<T>>,
+        observer: SharedObserver<OptionalObserver<ObserverBox<T, String>>>,
+        restart: RestartPolicy,
+    ) {
+        let mut session = Session::new(restart);
+
+        for message in receiver.iter() {
+            let (event, result) =
+                relay::<T, T, _>(message, &mut *observer.lock_unpoisoned(), &mut session);
+            if let Err(e) = result {
+                error!(
+                    "ChannelObservable({}): observer failed to process {} event: {}",
+                    id, event, e
+                );
+            }
+            if event == Event::Restart {
+                break;
+            }
+        }
+
+        trace!("ChannelObservable({}): stopped relaying", id);
+
+        let mut observer = observer.lock_unpoisoned();
+        if session.open {
+            if let Err(e) = observer.on_abort() {
+                error!(
+                    "ChannelObservable({}): observer failed to process on_abort event: {}",
+                    id, e
+                );
+            }
+        }
+        if !session.completed {
+            if let Err(e) = observer.on_disconnected() {
+                error!(
+                    "ChannelObservable({}): observer failed to process on_disconnected event: {}",
+                    id, e
+                );
+            }
+        }
+    }
+}
+
+impl<T> Drop for ChannelObservable<T> {
+    fn drop(&mut self) {
+        // The relaying thread keeps running until the channel gets
+        // disconnected, but it must not reach out to the observer any
+        // longer.
+        let _ = self.observer.lock_unpoisoned().take();
+    }
+}
+
+impl<T> Observable<T, String> for ChannelObservable<T>
+where
+    T: Debug + Send + 'static,
+{
+    type Subscription = ();
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<T, String>,
+    ) -> Result<Self::Subscription, ObserverBox<T, String>> {
+        trace!("ChannelObservable({})::subscribe", self.id);
+
+        let mut guard = self.observer.lock_unpoisoned();
+        if guard.is_some() {
+            return Err(observer);
+        }
+        *guard = Some(observer);
+        drop(guard);
+
+        if let Some(receiver) = self.receiver.take() {
+            let id = self.id;
+            let observer = self.observer.clone();
+            let restart = self.restart;
+            let _ = spawn(move || Self::relay(id, receiver, observer, restart));
+        }
+        Ok(())
+    }
+
+    fn unsubscribe(
+        &mut self,
+        _subscription: &Self::Subscription,
+    ) -> Option<ObserverBox<T, String>> {
+        trace!("ChannelObservable({})::unsubscribe", self.id);
+
+        self.observer.lock_unpoisoned().take()
+    }
+
+    fn subscriber_count(&self) -> usize {
+        usize::from(self.observer.lock_unpoisoned().is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc::channel;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::await_expected;
+    use crate::MockObserver;
+
+    /// Round trip a stream of transactions through a channel and check
+    /// that the order of updates is preserved.
+    #[test]
+    fn round_trip() {
+        let (sender, receiver) = channel();
+        let mut observable = ChannelObservable::<u64>::new(receiver);
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        observable.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut channel = ChannelObserver::new(sender);
+        let observer = &mut channel as &mut dyn Observer<u64, String>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![3, 1, 2].into_iter()))
+            .unwrap();
+        observer.on_updates(Box::new(vec![5].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![4, 0].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        observer.on_completed().unwrap();
+
+        await_expected(|| {
+            let on_completed = mock.lock().unwrap().called_on_completed;
+            assert_eq!(on_completed, 1);
+        });
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.received_updates, vec![3, 1, 2, 5, 4, 0]);
+    }
+
+    /// Check that sending fails once the receiving end is gone.
+    #[test]
+    fn receiver_disconnected() {
+        let (sender, receiver) = channel::<Message<u64>>();
+        let mut channel = ChannelObserver::new(sender);
+        drop(receiver);
+
+        let observer = &mut channel as &mut dyn Observer<u64, String>;
+        assert!(observer.on_start().is_err());
+    }
+
+    /// Check that a transaction left open by the sending end going away
+    /// is aborted and completion is signaled.
+    #[test]
+    fn sender_disconnected() {
+        let (sender, receiver) = channel();
+        let mut observable = ChannelObservable::<u64>::new(receiver);
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        observable.subscribe(Box::new(mock.clone())).unwrap();
+
+        sender.send(Message::Start).unwrap();
+        sender.send(Message::Updates(vec![1, 2])).unwrap();
+        drop(sender);
+
+        await_expected(|| {
+            let on_completed = mock.lock().unwrap().called_on_completed;
+            assert_eq!(on_completed, 1);
+        });
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_updates, 2);
+        assert_eq!(mock.called_on_abort, 1);
+    }
+}
@@ -47,15 +47,20 @@
 pub mod accumulate;
 #[cfg(any(test, feature = "test"))]
 mod assign;
+mod broadcast;
+mod channel;
 mod instantiate;
 mod observe;
+mod poison;
 mod read_config;
+mod record;
 mod schema;
 mod server;
 mod tcp_channel;
 #[cfg(any(test, feature = "test"))]
 mod test;
 mod txnmux;
+mod wal;
 
 /// A module comprising sinks to forward data from a computation.
 pub mod sinks;
@@ -66,17 +71,67 @@ pub mod sources;
 /// A module providing functionality for using d3log with ZooKeeper.
 pub mod zookeeper;
 
+pub use broadcast::BroadcastObserver;
+pub use broadcast::BroadcastReceiver;
+pub use channel::ChannelObservable;
+pub use channel::ChannelObserver;
 pub use instantiate::instantiate;
 pub use instantiate::Realization;
+pub use observe::AdaptErrObserver;
+pub use observe::CallTimings;
+pub use observe::CatchObserver;
+pub use observe::ChangeObserver;
+pub use observe::Clock;
+pub use observe::CompactObserver;
+pub use observe::ConcatObservable;
+pub use observe::DeadlineObserver;
+pub use observe::DedupObserver;
+pub use observe::DeliveryExecutor;
+pub use observe::ErrorPolicy;
+pub use observe::ExecutorObserver;
+pub use observe::FlattenObserver;
+pub use observe::InlineExecutor;
+pub use observe::MapObservable;
+pub use observe::MapObserver;
+pub use observe::MapSubscription;
+pub use observe::MetricsObserver;
 pub use observe::Observable;
 pub use observe::ObservableBox;
 pub use observe::Observer;
 pub use observe::ObserverBox;
+pub use observe::ObserverExt;
 pub use observe::OptionalObserver;
+pub use observe::PeriodicCommitObserver;
+pub use observe::ProgressObserver;
+pub use observe::RouteObserver;
+pub use observe::ScanObserver;
 pub use observe::SharedObserver;
+pub use observe::SortObserver;
+pub use observe::Strictness;
+pub use observe::SystemClock;
+pub use observe::TakeObserver;
+pub use observe::ThreadPoolExecutor;
+pub use observe::TimeoutPolicy;
+pub use observe::TimestampObserver;
+pub use observe::TimingObserver;
+pub use observe::Timings;
+pub use observe::TolerateErrorsObserver;
+pub use observe::TransactionBufferObserver;
+pub use observe::TransactionIsolationObserver;
+pub use observe::TransactionObserver;
+pub use observe::TransactionSink;
+pub use observe::TransactionStats;
+pub use observe::TransactionalSinkObserver;
+pub use observe::TumblingWindowObserver;
 pub use observe::UpdatesObservable;
+pub use observe::ValidatingObserver;
+pub use observe::VariantRouterObserver;
+pub use observe::Weighted;
+pub use observe::WindowObserver;
 pub use read_config::ReadConfig;
 pub use read_config::ReadMembers;
+pub use record::MessagePlayer;
+pub use record::MessageRecorder;
 pub use schema::Addr;
 pub use schema::Member;
 pub use schema::Members;
@@ -87,9 +142,44 @@ pub use schema::Sink;
 pub use schema::Source;
 pub use schema::SysCfg;
 pub use server::DDlogServer;
+pub use tcp_channel::Backoff;
+pub use tcp_channel::BincodeCodec;
+pub use tcp_channel::BincodeConfigCodec;
+pub use tcp_channel::BorrowedItem;
+pub use tcp_channel::BorrowedTcpReceiver;
+pub use tcp_channel::CloseError;
+pub use tcp_channel::Codec;
+pub use tcp_channel::CodecError;
+pub use tcp_channel::Connection;
+pub use tcp_channel::ConnectionEvent;
+pub use tcp_channel::EmptyBatchPolicy;
+pub use tcp_channel::EofPolicy;
+pub use tcp_channel::FixedLayout;
+pub use tcp_channel::IpNet;
+pub use tcp_channel::Message;
+pub use tcp_channel::MessageIter;
+#[cfg(feature = "futures")]
+pub use tcp_channel::MessageStream;
+pub use tcp_channel::ObserverSignal;
+pub use tcp_channel::RawBytes;
+pub use tcp_channel::ReceiverError;
+pub use tcp_channel::ReceiverMetrics;
+pub use tcp_channel::RestartPolicy;
+pub use tcp_channel::RingTcpReceiver;
+pub use tcp_channel::StallPolicy;
+pub use tcp_channel::TcpDemuxReceiver;
 pub use tcp_channel::TcpReceiver;
+pub use tcp_channel::TcpReceiverBuilder;
+pub use tcp_channel::TcpRelay;
 pub use tcp_channel::TcpSender;
+pub use tcp_channel::WeightedUpdate;
+pub use txnmux::SubscribePolicy;
 pub use txnmux::TxnMux;
+pub use wal::replay;
+pub use wal::WalObserver;
 
 #[cfg(any(test, feature = "test"))]
-pub use {assign::simple_assign, observe::MockObserver, test::await_expected};
+pub use {
+    assign::simple_assign, observe::MockClock, observe::MockObserver, observe::NullObserver,
+    test::await_expected,
+};
@@ -0,0 +1,156 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` converting the errors reported by an inner observer
+/// into a different error type.
+pub struct AdaptErrObserver<O, F, E> {
+    /// The observer whose errors we convert.
+    observer: O,
+    /// The function converting errors.
+    f: F,
+    _phantom: PhantomData<fn(E)>,
+}
+
+impl<O, F, E> AdaptErrObserver<O, F, E> {
+    /// Create a new `AdaptErrObserver` converting errors produced by
+    /// `observer` using `f`.
+    pub fn new(observer: O, f: F) -> Self {
+        Self {
+            observer,
+            f,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, F, E> Debug for AdaptErrObserver<O, F, E>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("AdaptErrObserver")
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+impl<O, F, T, E1, E2> Observer<T, E2> for AdaptErrObserver<O, F, E1>
+where
+    O: Observer<T, E1>,
+    F: Fn(E1) -> E2 + Send,
+    T: Send,
+    E1: Send,
+    E2: Send,
+{
+    fn on_start(&mut self) -> Result<(), E2> {
+        self.observer.on_start().map_err(&self.f)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E2> {
+        self.observer.on_commit().map_err(&self.f)
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E2> {
+        self.observer.on_commit_with_stats(stats).map_err(&self.f)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E2> {
+        self.observer.on_updates(updates).map_err(&self.f)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E2> {
+        self.observer.on_snapshot(items).map_err(&self.f)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E2> {
+        self.observer.on_abort().map_err(&self.f)
+    }
+
+    fn on_flush(&mut self) -> Result<(), E2> {
+        self.observer.on_flush().map_err(&self.f)
+    }
+
+    fn on_completed(&mut self) -> Result<(), E2> {
+        self.observer.on_completed().map_err(&self.f)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E2> {
+        self.observer.on_disconnected().map_err(&self.f)
+    }
+
+    fn describe(&self) -> String {
+        format!("adapt_err -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::observe::ObserverExt;
+
+    /// An observer failing every operation with a `String` error.
+    #[derive(Debug)]
+    struct FailingObserver;
+
+    impl Observer<u64, String> for FailingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Err("on_start".to_string())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            Err("on_commit".to_string())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Err("on_updates".to_string())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Err("on_completed".to_string())
+        }
+    }
+
+    /// A typed error to convert into.
+    #[derive(Debug, PartialEq)]
+    enum Error {
+        Observer(String),
+    }
+
+    /// Convert a `String` error observer into one using a typed error.
+    #[test]
+    fn string_to_typed_error() {
+        let mut adapted = FailingObserver.adapt_err(Error::Observer);
+        let observer = &mut adapted as &mut dyn Observer<u64, Error>;
+
+        assert_eq!(
+            observer.on_start(),
+            Err(Error::Observer("on_start".to_string()))
+        );
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Err(Error::Observer("on_updates".to_string()))
+        );
+        assert_eq!(
+            observer.on_commit(),
+            Err(Error::Observer("on_commit".to_string()))
+        );
+        assert_eq!(
+            observer.on_completed(),
+            Err(Error::Observer("on_completed".to_string()))
+        );
+    }
+}
@@ -0,0 +1,204 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::panic::catch_unwind;
+use std::panic::AssertUnwindSafe;
+
+use log::error;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// Retrieve the message of a panic from its payload, if it has one.
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "<unknown>"
+    }
+}
+
+/// An `Observer` isolating upstream from failures of an inner
+/// observer.
+///
+/// Panics of the inner observer are caught and logged. An observer
+/// that panicked is considered broken and does not receive any more
+/// events. Errors reported by the inner observer are either passed on
+/// or, if so configured, logged and suppressed.
+///
+/// Note that the inner observer is treated as unwind safe: after it
+/// panicked it is never touched again (other than being dropped).
+#[derive(Debug)]
+pub struct CatchObserver<O> {
+    /// The observer we isolate.
+    observer: O,
+    /// Whether to suppress errors reported by the observer.
+    suppress_errors: bool,
+    /// Whether the observer panicked.
+    panicked: bool,
+}
+
+impl<O> CatchObserver<O> {
+    /// Create a new `CatchObserver` wrapping `observer`, optionally
+    /// suppressing the errors it reports.
+    pub fn new(observer: O, suppress_errors: bool) -> Self {
+        Self {
+            observer,
+            suppress_errors,
+            panicked: false,
+        }
+    }
+
+    /// Check whether the inner observer panicked.
+    pub fn panicked(&self) -> bool {
+        self.panicked
+    }
+
+    /// Invoke `f` on the inner observer, catching panics and handling
+    /// errors as configured.
+    fn catch<F, E>(&mut self, event: &str, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut O) -> Result<(), E>,
+        E: Debug,
+    {
+        if self.panicked {
+            return Ok(());
+        }
+
+        let observer = &mut self.observer;
+        match catch_unwind(AssertUnwindSafe(|| f(observer))) {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => {
+                if self.suppress_errors {
+                    error!("observer failed to process {} event: {:?}", event, e);
+                    Ok(())
+                } else {
+                    Err(e)
+                }
+            }
+            Err(payload) => {
+                error!(
+                    "observer panicked processing {} event: {}",
+                    event,
+                    panic_message(payload.as_ref())
+                );
+                self.panicked = true;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<O, T, E> Observer<T, E> for CatchObserver<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.catch("on_start", Observer::on_start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.catch("on_commit", Observer::on_commit)
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.catch("on_commit", |o| o.on_commit_with_stats(stats))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.catch("on_updates", |o| o.on_updates(updates))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.catch("on_snapshot", |o| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.catch("on_abort", Observer::on_abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.catch("on_flush", Observer::on_flush)
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.catch("on_completed", Observer::on_completed)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.catch("on_disconnected", Observer::on_disconnected)
+    }
+
+    fn describe(&self) -> String {
+        format!("catch_unwind -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An observer panicking on its second batch of updates and failing
+    /// commits.
+    #[derive(Debug, Default)]
+    struct PanickingObserver {
+        batches: usize,
+    }
+
+    impl Observer<u64, String> for PanickingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            Err("on_commit".to_string())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            self.batches += 1;
+            if self.batches > 1 {
+                panic!("too many batches")
+            }
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            panic!("on_completed must not be reached")
+        }
+    }
+
+    /// Check that errors are passed on unless suppressed.
+    #[test]
+    fn errors() {
+        let mut catch = CatchObserver::new(PanickingObserver::default(), false);
+        let observer = &mut catch as &mut dyn Observer<u64, String>;
+        assert_eq!(observer.on_commit(), Err("on_commit".to_string()));
+
+        let mut catch = CatchObserver::new(PanickingObserver::default(), true);
+        let observer = &mut catch as &mut dyn Observer<u64, String>;
+        assert_eq!(observer.on_commit(), Ok(()));
+    }
+
+    /// Check that a panic is caught and the panicking observer not
+    /// invoked afterwards.
+    #[test]
+    fn panic() {
+        let mut catch = CatchObserver::new(PanickingObserver::default(), false);
+        let observer = &mut catch as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![1].into_iter())), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![2].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        assert!(catch.panicked());
+        assert_eq!(catch.observer.batches, 2);
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::marker::PhantomData;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// The values last seen by a `ChangeObserver`, tracked by the hashes
+/// of their keys and of the values themselves.
+#[derive(Debug, Default)]
+struct Values {
+    /// The state used for hashing keys and values.
+    hasher: RandomState,
+    /// The hashes of the values last seen as part of a committed
+    /// transaction, by the hashes of their keys.
+    committed: HashMap<u64, u64>,
+    /// The hashes of the values seen as part of the transaction in
+    /// progress, by the hashes of their keys.
+    pending: HashMap<u64, u64>,
+}
+
+impl Values {
+    /// Record `value` as the one last seen for `key`, reporting whether
+    /// it differs from the previous one.
+    fn update<K, T>(&mut self, key: &K, value: &T) -> bool
+    where
+        K: Hash,
+        T: Hash,
+    {
+        let key = self.hasher.hash_one(key);
+        let value = self.hasher.hash_one(value);
+
+        let last = self
+            .pending
+            .get(&key)
+            .or_else(|| self.committed.get(&key))
+            .copied();
+        if last == Some(value) {
+            false
+        } else {
+            let _ = self.pending.insert(key, value);
+            true
+        }
+    }
+
+    /// Make the values seen as part of the transaction in progress the
+    /// last seen ones.
+    fn commit(&mut self) {
+        self.committed.extend(self.pending.drain());
+    }
+
+    /// Forget the values seen as part of the transaction in progress.
+    fn abort(&mut self) {
+        self.pending.clear();
+    }
+}
+
+/// An `Observer` forwarding an item only if its value changed, i.e.,
+/// differs from the last one seen for the same key, e.g., for a source
+/// polling and resending state that mostly stays the same.
+///
+/// The key of an item is determined by a key function, while the item
+/// as a whole makes up its value. Only the (64 bit) hashes of keys and
+/// values are remembered, so a changed value whose hash collides with
+/// that of the previous one is dropped, although such collisions are
+/// very unlikely. Memory usage is bounded by the number of distinct
+/// keys seen, as one value is remembered per key, for the lifetime of
+/// the observer.
+///
+/// Values only become the last seen ones once the transaction they are
+/// part of got committed (or flushed); an aborted transaction leaves
+/// them alone.
+pub struct ChangeObserver<O, K, F> {
+    /// The observer we forward changed items to.
+    observer: O,
+    /// The function extracting the key of an item.
+    key: F,
+    /// The values last seen.
+    values: Values,
+    _phantom: PhantomData<fn() -> K>,
+}
+
+impl<O, K, F> ChangeObserver<O, K, F> {
+    /// Create a new `ChangeObserver` keeping track of the values of
+    /// items by the key returned by `key`.
+    pub fn new<T>(observer: O, key: F) -> Self
+    where
+        F: FnMut(&T) -> K,
+    {
+        Self {
+            observer,
+            key,
+            values: Values::default(),
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, K, F> Debug for ChangeObserver<O, K, F>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ChangeObserver")
+            .field("observer", &self.observer)
+            .field("keys", &self.values.committed.len())
+            .finish()
+    }
+}
+
+impl<O, K, F, T, E> Observer<T, E> for ChangeObserver<O, K, F>
+where
+    O: Observer<T, E>,
+    K: Hash,
+    F: FnMut(&T) -> K + Send,
+    T: Hash + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.values.abort();
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.values.commit();
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.values.commit();
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let Self {
+            observer,
+            key,
+            values,
+            ..
+        } = self;
+        observer.on_updates(Box::new(updates.filter(move |t| values.update(&key(t), t))))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let Self {
+            observer,
+            key,
+            values,
+            ..
+        } = self;
+        observer.on_snapshot(Box::new(items.filter(move |t| values.update(&key(t), t))))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.values.abort();
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        // The items forwarded so far got delivered, so their values are
+        // the ones last seen, no matter what becomes of the remainder.
+        self.values.commit();
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("change -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Send a transaction containing `items` to `observer`.
+    fn send(observer: &mut dyn Observer<(u64, &'static str), ()>, items: Vec<(u64, &'static str)>) {
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(items.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+    }
+
+    /// Check that only items with changed values are forwarded.
+    #[test]
+    fn forward_changes() {
+        let mut change = ChangeObserver::new(
+            UpdatesMockObserver::<(u64, &str)>::new(),
+            |(id, _): &(u64, &str)| *id,
+        );
+        let observer = &mut change as &mut dyn Observer<(u64, &str), ()>;
+
+        send(observer, vec![(1, "a"), (2, "b")]);
+        // The same state sent again is suppressed.
+        send(observer, vec![(1, "a"), (2, "b")]);
+        send(observer, vec![(1, "c"), (2, "b"), (1, "c")]);
+        send(observer, vec![(1, "a")]);
+
+        let mock = change.into_inner();
+        assert_eq!(
+            mock.received_updates,
+            vec![(1, "a"), (2, "b"), (1, "c"), (1, "a")]
+        );
+        assert_eq!(mock.called_on_commit, 4);
+    }
+
+    /// Check that the values of an aborted transaction are forgotten.
+    #[test]
+    fn abort() {
+        let mut change = ChangeObserver::new(
+            UpdatesMockObserver::<(u64, &str)>::new(),
+            |(id, _): &(u64, &str)| *id,
+        );
+        let observer = &mut change as &mut dyn Observer<(u64, &str), ()>;
+
+        send(observer, vec![(1, "a")]);
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![(1, "b")].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_abort(), Ok(()));
+        send(observer, vec![(1, "a"), (1, "b")]);
+
+        let mock = change.into_inner();
+        assert_eq!(mock.received_updates, vec![(1, "a"), (1, "b"), (1, "b")]);
+    }
+}
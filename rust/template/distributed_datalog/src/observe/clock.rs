@@ -0,0 +1,20 @@
+use std::fmt::Debug;
+use std::time::Instant;
+
+/// A trait abstracting over the source of time used by time-aware
+/// observers, allowing for the injection of a controllable clock in
+/// tests.
+pub trait Clock: Debug + Send {
+    /// Retrieve the current point in time.
+    fn now(&self) -> Instant;
+}
+
+/// A `Clock` reporting the system's monotonic time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
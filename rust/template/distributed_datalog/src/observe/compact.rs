@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::Hash;
+use std::ops::Add;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// A trait for items carrying a weight (or multiplicity) along with
+/// the value they update, as consumed by a `CompactObserver`.
+pub trait Weighted: Sized {
+    /// The type of the values updated.
+    type Value: Eq + Hash;
+    /// The type of the weights, with the default value being zero.
+    type Weight: Add<Output = Self::Weight> + Copy + Default + PartialEq;
+
+    /// Split the item into its value and its weight.
+    fn into_parts(self) -> (Self::Value, Self::Weight);
+
+    /// Assemble an item from a value and a weight.
+    fn from_parts(value: Self::Value, weight: Self::Weight) -> Self;
+}
+
+impl<V, W> Weighted for (V, W)
+where
+    V: Eq + Hash,
+    W: Add<Output = W> + Copy + Default + PartialEq,
+{
+    type Value = V;
+    type Weight = W;
+
+    fn into_parts(self) -> (V, W) {
+        self
+    }
+
+    fn from_parts(value: V, weight: W) -> Self {
+        (value, weight)
+    }
+}
+
+/// An `Observer` compacting the weighted updates of a transaction
+/// before forwarding them, i.e., summing up the weights of updates of
+/// the same value and dropping those netting to zero, such as an
+/// insertion followed by a deletion.
+///
+/// Compacted updates are forwarded on commit (or flush), in the order
+/// their values were first seen. That means that the updates of the
+/// transaction in progress are buffered, with memory usage growing with
+/// the number of distinct values updated. Snapshots are passed through
+/// as they are.
+pub struct CompactObserver<O, T>
+where
+    T: Weighted,
+{
+    /// The observer we forward compacted updates to.
+    observer: O,
+    /// The net weights of the values updated in the transaction in
+    /// progress, along with the order they were first seen in.
+    weights: HashMap<T::Value, (usize, T::Weight)>,
+}
+
+impl<O, T> CompactObserver<O, T>
+where
+    T: Weighted,
+{
+    /// Create a new `CompactObserver` forwarding compacted updates to
+    /// `observer`.
+    pub fn new(observer: O) -> Self {
+        Self {
+            observer,
+            weights: HashMap::new(),
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Forward the compacted updates buffered to the inner observer.
+    fn forward<E>(&mut self) -> Result<(), E>
+    where
+        O: Observer<T, E>,
+        T: Send,
+        E: Send,
+    {
+        let mut updates = self
+            .weights
+            .drain()
+            .filter(|(_, (_, weight))| *weight != T::Weight::default())
+            .collect::<Vec<_>>();
+        if updates.is_empty() {
+            return Ok(());
+        }
+
+        updates.sort_unstable_by_key(|(_, (order, _))| *order);
+        let updates = updates
+            .into_iter()
+            .map(|(value, (_, weight))| T::from_parts(value, weight));
+        self.observer.on_updates(Box::new(updates))
+    }
+}
+
+impl<O, T> Debug for CompactObserver<O, T>
+where
+    O: Debug,
+    T: Weighted,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("CompactObserver")
+            .field("observer", &self.observer)
+            .field("weights", &self.weights.len())
+            .finish()
+    }
+}
+
+impl<O, T, E> Observer<T, E> for CompactObserver<O, T>
+where
+    O: Observer<T, E>,
+    T: Weighted + Send,
+    T::Value: Send,
+    T::Weight: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.weights.clear();
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.forward()?;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.forward()?;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        for update in updates {
+            let (value, weight) = update.into_parts();
+            let order = self.weights.len();
+            let (_, total) = self
+                .weights
+                .entry(value)
+                .or_insert((order, T::Weight::default()));
+            *total = *total + weight;
+        }
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.weights.clear();
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.forward()?;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("compact -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that insertions and deletions of the same value cancel
+    /// each other out.
+    #[test]
+    fn cancel_out() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<(char, i64)>::new()));
+        let mut compact = CompactObserver::new(mock.clone());
+        let observer = &mut compact as &mut dyn Observer<(char, i64), ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let updates = vec![('a', 1), ('b', 1), ('a', -1)];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        let updates = vec![('b', -1), ('a', 1), ('a', -1)];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        {
+            let mock = mock.lock().unwrap();
+            assert!(mock.received_updates.is_empty());
+            assert_eq!(mock.called_on_commit, 1);
+        }
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let updates = vec![('c', 2), ('b', 1), ('c', -1), ('d', -1), ('b', -1)];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates, vec![('c', 1), ('d', -1)]);
+        assert_eq!(mock.called_on_commit, 2);
+    }
+}
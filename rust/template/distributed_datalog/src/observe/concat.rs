@@ -0,0 +1,474 @@
+use std::any::Any;
+use std::fmt::Debug;
+use std::mem::take;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use log::trace;
+use uid::Id;
+
+use crate::observe::Observable;
+use crate::observe::ObservableBox;
+use crate::observe::Observer;
+use crate::observe::ObserverBox;
+use crate::observe::OptionalObserver;
+use crate::observe::SharedObserver;
+use crate::observe::TransactionStats;
+use crate::poison::MutexExt;
+
+/// An event emitted by an observable whose turn has not yet come,
+/// buffered for later delivery.
+#[derive(Debug)]
+enum Event<T> {
+    Start,
+    Commit(Option<TransactionStats>),
+    Updates(Vec<T>),
+    Snapshot(Vec<T>),
+    Abort,
+    Flush,
+}
+
+/// The events buffered for an observable whose turn has not yet come.
+#[derive(Debug)]
+struct Buffer<T> {
+    /// The events emitted so far, in order.
+    events: Vec<Event<T>>,
+    /// Whether the observable completed already.
+    completed: bool,
+}
+
+impl<T> Default for Buffer<T> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            completed: false,
+        }
+    }
+}
+
+/// The state a `ConcatObservable` shares with the observers it
+/// subscribed to its observables.
+#[derive(Debug)]
+struct Concat<T, E> {
+    /// The observer subscribed to the `ConcatObservable`.
+    observer: OptionalObserver<ObserverBox<T, E>>,
+    /// The index of the observable whose events are forwarded.
+    current: usize,
+    /// The events of the observables after the current one, by index.
+    buffers: Vec<Buffer<T>>,
+    /// Whether the current observable is in the middle of a
+    /// transaction.
+    open: bool,
+    /// Whether any of the observables disconnected rather than
+    /// completing.
+    disconnected: bool,
+}
+
+impl<T, E> Concat<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    /// Whether the last observable completed, i.e., the concatenated
+    /// stream is over.
+    fn is_completed(&self) -> bool {
+        self.current >= self.buffers.len()
+    }
+
+    /// Forward an event of the current observable to the observer.
+    fn forward(&mut self, event: Event<T>) -> Result<(), E> {
+        match event {
+            Event::Start => {
+                self.open = true;
+                self.observer.on_start()
+            }
+            Event::Commit(stats) => {
+                self.open = false;
+                match stats {
+                    Some(stats) => self.observer.on_commit_with_stats(stats),
+                    None => self.observer.on_commit(),
+                }
+            }
+            Event::Updates(updates) => self.observer.on_updates(Box::new(updates.into_iter())),
+            Event::Snapshot(items) => self.observer.on_snapshot(Box::new(items.into_iter())),
+            Event::Abort => {
+                self.open = false;
+                self.observer.on_abort()
+            }
+            Event::Flush => {
+                self.open = false;
+                self.observer.on_flush()
+            }
+        }
+    }
+
+    /// Switch over to the observables after the current one, which
+    /// completed, replaying what they emitted in the meantime.
+    fn advance(&mut self) -> Result<(), E> {
+        // A transaction left open by a completed observable is never
+        // going to be committed.
+        if self.open {
+            self.open = false;
+            self.observer.on_abort()?;
+        }
+
+        loop {
+            self.current += 1;
+            if self.is_completed() {
+                return if self.disconnected {
+                    self.observer.on_disconnected()
+                } else {
+                    self.observer.on_completed()
+                };
+            }
+
+            let buffer = take(&mut self.buffers[self.current]);
+            for event in buffer.events {
+                self.forward(event)?;
+            }
+            if !buffer.completed {
+                return Ok(());
+            }
+            if self.open {
+                self.open = false;
+                self.observer.on_abort()?;
+            }
+        }
+    }
+}
+
+/// The observer a `ConcatObservable` subscribes to each of its
+/// observables.
+#[derive(Debug)]
+struct ConcatObserver<T, E> {
+    /// The index of the observable we are subscribed to.
+    index: usize,
+    /// The state shared with the `ConcatObservable`.
+    concat: SharedObserver<Concat<T, E>>,
+}
+
+impl<T, E> ConcatObserver<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    /// Forward an event to the observer if it is our observable's turn,
+    /// or buffer it if the turn has yet to come.
+    fn event(&mut self, event: Event<T>) -> Result<(), E> {
+        let mut concat = self.concat.lock_unpoisoned();
+        if self.index == concat.current {
+            concat.forward(event)
+        } else {
+            if self.index > concat.current {
+                concat.buffers[self.index].events.push(event);
+            }
+            Ok(())
+        }
+    }
+
+    /// Handle our observable completing or disconnecting, switching
+    /// over to the next one if it is its turn.
+    fn end(&mut self, disconnected: bool) -> Result<(), E> {
+        let mut concat = self.concat.lock_unpoisoned();
+        concat.disconnected |= disconnected;
+        if self.index == concat.current {
+            concat.advance()
+        } else {
+            if self.index > concat.current {
+                concat.buffers[self.index].completed = true;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl<T, E> Observer<T, E> for ConcatObserver<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.event(Event::Start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.event(Event::Commit(None))
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.event(Event::Commit(Some(stats)))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.event(Event::Updates(updates.collect()))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.event(Event::Snapshot(items.collect()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.event(Event::Abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.event(Event::Flush)
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.end(false)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.end(true)
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "concat -> {}",
+            self.concat.lock_unpoisoned().observer.describe()
+        )
+    }
+}
+
+/// An `Observable` concatenating the streams of a sequence of
+/// observables, e.g., for replaying a snapshot followed by live
+/// updates. The events of one observable are forwarded until it
+/// completes, at which point we switch over to the next one.
+/// Completion is signaled only once the last one completed, as a
+/// disconnect if any of them disconnected instead of completing.
+///
+/// Transactions are forwarded as they are, meaning that there is no
+/// `on_start` marking the beginning of the concatenated stream as a
+/// whole, but one at the start of every transaction, no matter the
+/// observable it originates from. A transaction left open by a
+/// completing observable is aborted before switching over.
+///
+/// All observables get subscribed to as they are pushed, so as to not
+/// miss anything they emit before their turn. Such events are
+/// buffered, in memory, until the turn comes.
+#[derive(Debug)]
+pub struct ConcatObservable<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    /// The concatenation's unique ID.
+    id: usize,
+    /// The observables we concatenate and our subscriptions to them.
+    subscriptions: Vec<(ObservableBox<T, E>, Box<dyn Any + Send>)>,
+    /// The state shared with the observers subscribed to the
+    /// observables.
+    concat: SharedObserver<Concat<T, E>>,
+}
+
+impl<T, E> ConcatObservable<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `ConcatObservable`, without any observables.
+    pub fn new() -> Self {
+        let id = Id::<()>::new().get();
+        trace!("ConcatObservable({})::new", id);
+
+        Self {
+            id,
+            subscriptions: Vec::new(),
+            concat: Arc::new(Mutex::new(Concat {
+                observer: None,
+                current: 0,
+                buffers: Vec::new(),
+                open: false,
+                disconnected: false,
+            })),
+        }
+    }
+
+    /// Append an `Observable` to the sequence of observables to
+    /// concatenate.
+    ///
+    /// The observable is handed back if it refuses our subscription or
+    /// if the concatenated stream is over already, i.e., the last
+    /// observable completed.
+    pub fn push(&mut self, mut observable: ObservableBox<T, E>) -> Result<(), ObservableBox<T, E>> {
+        trace!("ConcatObservable({})::push", self.id);
+
+        let index = {
+            let mut concat = self.concat.lock_unpoisoned();
+            if !self.subscriptions.is_empty() && concat.is_completed() {
+                return Err(observable);
+            }
+            concat.buffers.push(Buffer::default());
+            concat.buffers.len() - 1
+        };
+
+        // The lock must not be held while subscribing, as the
+        // observable may emit events right away.
+        let observer = ConcatObserver {
+            index,
+            concat: self.concat.clone(),
+        };
+        match observable.subscribe_any(Box::new(observer)) {
+            Ok(subscription) => {
+                self.subscriptions.push((observable, subscription));
+                Ok(())
+            }
+            Err(_) => {
+                let _ = self.concat.lock_unpoisoned().buffers.pop();
+                Err(observable)
+            }
+        }
+    }
+}
+
+impl<T, E> Default for ConcatObservable<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> Drop for ConcatObservable<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    fn drop(&mut self) {
+        for (observable, subscription) in self.subscriptions.iter_mut().rev() {
+            let _result = observable.unsubscribe_any(subscription.as_ref());
+            debug_assert!(_result.is_some(), "{:?}", subscription);
+        }
+        self.subscriptions.clear();
+    }
+}
+
+impl<T, E> Observable<T, E> for ConcatObservable<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = ();
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<T, E>,
+    ) -> Result<Self::Subscription, ObserverBox<T, E>> {
+        trace!("ConcatObservable({})::subscribe", self.id);
+
+        let mut concat = self.concat.lock_unpoisoned();
+        if concat.observer.is_some() {
+            Err(observer)
+        } else {
+            let _ = concat.observer.replace(observer);
+            Ok(())
+        }
+    }
+
+    fn unsubscribe(&mut self, _subscription: &Self::Subscription) -> Option<ObserverBox<T, E>> {
+        trace!("ConcatObservable({})::unsubscribe", self.id);
+        self.concat.lock_unpoisoned().observer.take()
+    }
+
+    fn subscriber_count(&self) -> usize {
+        usize::from(self.concat.lock_unpoisoned().observer.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MockObserver;
+    use crate::observe::UpdatesObservable;
+
+    /// Send a transaction containing `updates` to `observer`.
+    fn send(observer: &mut dyn Observer<u64, ()>, updates: Vec<u64>) {
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+    }
+
+    /// Check that the streams of observables are concatenated, with
+    /// events of later ones buffered until their turn.
+    #[test]
+    fn concat() {
+        let first = UpdatesObservable::<u64, ()>::default();
+        let second = UpdatesObservable::<u64, ()>::default();
+        let third = UpdatesObservable::<u64, ()>::default();
+        let mut first_tx = first.observer.clone();
+        let mut second_tx = second.observer.clone();
+        let mut third_tx = third.observer.clone();
+
+        let mut concat = ConcatObservable::new();
+        concat.push(Box::new(first)).unwrap();
+        concat.push(Box::new(second)).unwrap();
+        concat.push(Box::new(third)).unwrap();
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        concat.subscribe(Box::new(mock.clone())).unwrap();
+
+        send(&mut first_tx, vec![1]);
+        send(&mut second_tx, vec![3]);
+        send(&mut first_tx, vec![2]);
+        // The third observable completes before its turn, in the middle
+        // of a transaction.
+        send(&mut third_tx, vec![5]);
+        assert_eq!(third_tx.on_start(), Ok(()));
+        assert_eq!(third_tx.on_completed(), Ok(()));
+        assert_eq!(mock.lock().unwrap().received_updates, vec![1, 2]);
+
+        assert_eq!(first_tx.on_completed(), Ok(()));
+        // Events of a completed observable are ignored.
+        send(&mut first_tx, vec![0]);
+        assert_eq!(mock.lock().unwrap().received_updates, vec![1, 2, 3]);
+        assert_eq!(mock.lock().unwrap().called_on_completed, 0);
+
+        send(&mut second_tx, vec![4]);
+        assert_eq!(second_tx.on_completed(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 4, 5]);
+        assert_eq!(mock.called_on_start, 6);
+        assert_eq!(mock.called_on_commit, 5);
+        assert_eq!(mock.called_on_completed, 1);
+        drop(mock);
+
+        // Once the concatenated stream is over, it cannot be extended.
+        assert!(concat
+            .push(Box::new(UpdatesObservable::<u64, ()>::default()))
+            .is_err());
+    }
+
+    /// Check that the concatenated stream ends in a disconnect if one
+    /// of the observables disconnected.
+    #[test]
+    fn concat_disconnected() {
+        let first = UpdatesObservable::<u64, ()>::default();
+        let second = UpdatesObservable::<u64, ()>::default();
+        let mut first_tx = first.observer.clone();
+        let mut second_tx = second.observer.clone();
+
+        let mut concat = ConcatObservable::new();
+        concat.push(Box::new(first)).unwrap();
+        concat.push(Box::new(second)).unwrap();
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        concat.subscribe(Box::new(mock.clone())).unwrap();
+
+        send(&mut first_tx, vec![1]);
+        assert_eq!(first_tx.on_disconnected(), Ok(()));
+        assert_eq!(mock.lock().unwrap().called_on_disconnected, 0);
+
+        send(&mut second_tx, vec![2]);
+        assert_eq!(second_tx.on_completed(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.called_on_disconnected, 1);
+    }
+}
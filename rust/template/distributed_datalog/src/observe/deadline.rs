@@ -0,0 +1,316 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc::TryRecvError;
+use std::thread::spawn;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use log::warn;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// A call of the inner observer, made on the worker thread.
+type Call<O, E> = Box<dyn FnOnce(&mut O) -> Result<(), E> + Send>;
+
+/// The policy of a `DeadlineObserver` for dealing with a call of its
+/// inner observer that missed the deadline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeoutPolicy {
+    /// Log the timeout and report success, skipping the event.
+    Skip,
+    /// Log the timeout and report an error.
+    Fail,
+}
+
+/// An `Observer` delivering events to an inner observer on a worker
+/// thread, waiting at most a given amount of time for each call to
+/// return.
+///
+/// A call missing the deadline is not interrupted: it runs to
+/// completion on the worker, but its result is discarded and the event
+/// is dealt with according to a `TimeoutPolicy` instead, so that a
+/// wedged observer does not stall the pipeline upstream. Events
+/// arriving while such a call is still in progress are timed out right
+/// away, without being delivered. The inner observer thus may see
+/// partial transactions.
+///
+/// Note that updates get collected before being handed to the worker.
+pub struct DeadlineObserver<O, T, E> {
+    /// The maximum time to wait for a call to return.
+    timeout: Duration,
+    /// How to deal with a call missing the deadline.
+    policy: TimeoutPolicy,
+    /// The description of the inner observer.
+    description: String,
+    /// The channel for sending calls to the worker.
+    calls: Option<Sender<Call<O, E>>>,
+    /// The channel the worker reports the results of calls on.
+    results: Receiver<Result<(), E>>,
+    /// The worker, owning the inner observer.
+    worker: Option<JoinHandle<O>>,
+    /// Whether a call missed the deadline and is still in progress.
+    lagging: bool,
+    /// The number of events that timed out.
+    timeouts: usize,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<O, T, E> DeadlineObserver<O, T, E>
+where
+    O: Observer<T, E> + 'static,
+    T: Send,
+    E: Send + 'static,
+{
+    /// Create a new `DeadlineObserver` delivering events to `observer`
+    /// and dealing with calls not returning within `timeout` according
+    /// to `policy`.
+    pub fn new(observer: O, timeout: Duration, policy: TimeoutPolicy) -> Self {
+        let description = observer.describe();
+        let (calls, receiver) = channel::<Call<O, E>>();
+        let (sender, results) = channel();
+        let worker = spawn(move || {
+            let mut observer = observer;
+            for call in receiver {
+                if sender.send(call(&mut observer)).is_err() {
+                    break;
+                }
+            }
+            observer
+        });
+
+        Self {
+            timeout,
+            policy,
+            description,
+            calls: Some(calls),
+            results,
+            worker: Some(worker),
+            lagging: false,
+            timeouts: 0,
+            _phantom: PhantomData,
+        }
+    }
+}
+
+impl<O, T, E> DeadlineObserver<O, T, E> {
+    /// Retrieve the number of events that timed out.
+    pub fn timeouts(&self) -> usize {
+        self.timeouts
+    }
+
+    /// Retrieve the wrapped observer, blocking until a call still in
+    /// progress returned. `None` is returned if the observer panicked.
+    pub fn into_inner(mut self) -> Option<O> {
+        self.calls = None;
+        self.worker.take().and_then(|worker| worker.join().ok())
+    }
+}
+
+impl<O, T, E> DeadlineObserver<O, T, E>
+where
+    E: From<String>,
+{
+    /// Deliver an event to the inner observer by making `call` on the
+    /// worker, waiting for it to return until the deadline.
+    fn deliver(&mut self, event: &str, call: Call<O, E>) -> Result<(), E> {
+        if self.lagging {
+            match self.results.try_recv() {
+                // The result of a call that missed the deadline is of no
+                // interest anymore.
+                Ok(_) => self.lagging = false,
+                Err(TryRecvError::Empty) => return self.timed_out(event),
+                Err(TryRecvError::Disconnected) => return Err(Self::panicked(event)),
+            }
+        }
+
+        let sent = self.calls.as_ref().map(|calls| calls.send(call).is_ok());
+        if sent != Some(true) {
+            return Err(Self::panicked(event));
+        }
+
+        match self.results.recv_timeout(self.timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                self.lagging = true;
+                self.timed_out(event)
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(Self::panicked(event)),
+        }
+    }
+
+    /// Deal with an event that timed out according to our policy.
+    fn timed_out(&mut self, event: &str) -> Result<(), E> {
+        self.timeouts += 1;
+        warn!(
+            "observer failed to process {} event within {:?}",
+            event, self.timeout
+        );
+
+        match self.policy {
+            TimeoutPolicy::Skip => Ok(()),
+            TimeoutPolicy::Fail => Err(E::from(format!(
+                "observer failed to process {} event within {:?}",
+                event, self.timeout
+            ))),
+        }
+    }
+
+    /// Create the error reported once the inner observer panicked.
+    fn panicked(event: &str) -> E {
+        E::from(format!(
+            "failed to deliver {} event: observer panicked",
+            event
+        ))
+    }
+}
+
+impl<O, T, E> Debug for DeadlineObserver<O, T, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("DeadlineObserver")
+            .field("timeout", &self.timeout)
+            .field("policy", &self.policy)
+            .field("lagging", &self.lagging)
+            .field("timeouts", &self.timeouts)
+            .finish()
+    }
+}
+
+impl<O, T, E> Observer<T, E> for DeadlineObserver<O, T, E>
+where
+    O: Observer<T, E> + 'static,
+    T: Send + 'static,
+    E: From<String> + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.deliver("on_start", Box::new(|o| Observer::<T, E>::on_start(o)))
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.deliver("on_commit", Box::new(|o| Observer::<T, E>::on_commit(o)))
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.deliver(
+            "on_commit",
+            Box::new(move |o| o.on_commit_with_stats(stats)),
+        )
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let updates = updates.collect::<Vec<_>>();
+        self.deliver(
+            "on_updates",
+            Box::new(move |o| o.on_updates(Box::new(updates.into_iter()))),
+        )
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let items = items.collect::<Vec<_>>();
+        self.deliver(
+            "on_snapshot",
+            Box::new(move |o| o.on_snapshot(Box::new(items.into_iter()))),
+        )
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.deliver("on_abort", Box::new(|o| Observer::<T, E>::on_abort(o)))
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.deliver("on_flush", Box::new(|o| Observer::<T, E>::on_flush(o)))
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.deliver(
+            "on_completed",
+            Box::new(|o| Observer::<T, E>::on_completed(o)),
+        )
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.deliver(
+            "on_disconnected",
+            Box::new(|o| Observer::<T, E>::on_disconnected(o)),
+        )
+    }
+
+    fn describe(&self) -> String {
+        format!("deadline({:?}) -> {}", self.timeout, self.description)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread::sleep;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MapObserver;
+
+    /// Check that a slow observer missing the deadline does not hold up
+    /// the delivery of events.
+    #[test]
+    fn slow_observer() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        // The observer takes its time for items with a value of zero.
+        let slow = MapObserver::new(mock.clone(), |x: u64| {
+            if x == 0 {
+                sleep(Duration::from_millis(300))
+            }
+            x
+        });
+        let timeout = Duration::from_millis(50);
+        let mut deadline = DeadlineObserver::new(slow, timeout, TimeoutPolicy::Skip);
+        let observer = &mut deadline as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![0].into_iter())), Ok(()));
+        // The observer is still busy, so these updates get skipped.
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(deadline.timeouts(), 2);
+
+        sleep(Duration::from_millis(400));
+        let observer = &mut deadline as &mut dyn Observer<u64, String>;
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(deadline.timeouts(), 2);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates, vec![1, 2, 0, 4]);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Check that an observer missing the deadline results in an error
+    /// if so configured.
+    #[test]
+    fn fail_on_timeout() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let slow = MapObserver::new(mock.clone(), |x: u64| {
+            sleep(Duration::from_millis(100));
+            x
+        });
+        let timeout = Duration::from_millis(10);
+        let mut deadline = DeadlineObserver::new(slow, timeout, TimeoutPolicy::Fail);
+        let observer = &mut deadline as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert!(observer.on_updates(Box::new(vec![1].into_iter())).is_err());
+
+        let _ = deadline.into_inner().unwrap();
+        assert_eq!(mock.lock().unwrap().received_updates, vec![1]);
+    }
+}
@@ -0,0 +1,315 @@
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hash::BuildHasher;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::mem::replace;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// The set of keys seen by a `DedupObserver`, tracked by their hashes.
+#[derive(Debug, Default)]
+struct Seen {
+    /// The state used for hashing keys.
+    hasher: RandomState,
+    /// The hashes of the keys seen, along with the time they were last
+    /// seen at.
+    hashes: HashMap<u64, u64>,
+    /// The hashes of the keys seen, by the time they were last seen
+    /// at; only maintained if the number of keys is bounded.
+    recency: BTreeMap<u64, u64>,
+    /// A logical clock, advanced for every key.
+    time: u64,
+    /// The maximum number of keys to remember, if any.
+    capacity: Option<usize>,
+}
+
+impl Seen {
+    /// Record `key`, reporting whether it was not seen before.
+    fn insert<K>(&mut self, key: &K) -> bool
+    where
+        K: Hash,
+    {
+        let hash = self.hasher.hash_one(key);
+
+        self.time += 1;
+        let last = self.hashes.insert(hash, self.time);
+        if let Some(capacity) = self.capacity {
+            if let Some(last) = last {
+                let _ = self.recency.remove(&last);
+            }
+            let _ = self.recency.insert(self.time, hash);
+
+            if self.hashes.len() > capacity {
+                let oldest = self.recency.keys().next().copied();
+                if let Some(hash) = oldest.and_then(|time| self.recency.remove(&time)) {
+                    let _ = self.hashes.remove(&hash);
+                }
+            }
+        }
+        last.is_none()
+    }
+
+    /// Forget all keys seen.
+    fn clear(&mut self) {
+        self.hashes.clear();
+        self.recency.clear();
+    }
+
+    /// Retrieve the number of keys remembered.
+    fn len(&self) -> usize {
+        self.hashes.len()
+    }
+}
+
+/// An `Observer` forwarding only the first occurrence of each item
+/// within a transaction.
+///
+/// The identity of an item is determined by a key function, allowing
+/// for only part of an item to be considered. Only the (64 bit) hashes
+/// of keys are remembered, i.e., an item whose key collides with that
+/// of a different item seen before is dropped, although such
+/// collisions are very unlikely.
+///
+/// By default, the set of keys seen is reset with every transaction,
+/// so that memory usage is bounded by the size of the largest
+/// transaction. Keys may also be remembered across transactions (see
+/// `persistent`), e.g., for catching items redelivered after a
+/// reconnect, in which case the number of keys remembered should be
+/// bounded (see `capacity`).
+pub struct DedupObserver<O, K, F> {
+    /// The observer we forward deduplicated items to.
+    observer: O,
+    /// The function extracting the key identifying an item.
+    key: F,
+    /// The keys of the items seen.
+    seen: Seen,
+    /// Whether to remember keys across transactions.
+    persistent: bool,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// the next `on_start` continues it and the keys seen still apply.
+    flushed: bool,
+    _phantom: PhantomData<fn() -> K>,
+}
+
+impl<O, T> DedupObserver<O, T, fn(&T) -> T>
+where
+    T: Clone,
+{
+    /// Create a new `DedupObserver` considering items in their
+    /// entirety.
+    pub fn new(observer: O) -> Self {
+        Self::with_key(observer, T::clone)
+    }
+}
+
+impl<O, K, F> DedupObserver<O, K, F> {
+    /// Create a new `DedupObserver` identifying items by the key
+    /// returned by `key`.
+    pub fn with_key<T>(observer: O, key: F) -> Self
+    where
+        F: FnMut(&T) -> K,
+    {
+        Self {
+            observer,
+            key,
+            seen: Seen::default(),
+            persistent: false,
+            flushed: false,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Remember at most `capacity` keys, forgetting the least recently
+    /// seen one once the limit is exceeded.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.seen.capacity = Some(capacity);
+        self
+    }
+
+    /// Set whether keys are remembered across transactions, instead of
+    /// being forgotten once a transaction ends.
+    pub fn persistent(mut self, persistent: bool) -> Self {
+        self.persistent = persistent;
+        self
+    }
+
+    /// Forget the keys seen, unless they are to be remembered across
+    /// transactions.
+    fn reset(&mut self) {
+        if !self.persistent {
+            self.seen.clear();
+        }
+    }
+}
+
+impl<O, K, F> Debug for DedupObserver<O, K, F>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("DedupObserver")
+            .field("observer", &self.observer)
+            .field("seen", &self.seen.len())
+            .field("capacity", &self.seen.capacity)
+            .field("persistent", &self.persistent)
+            .finish()
+    }
+}
+
+impl<O, K, F, T, E> Observer<T, E> for DedupObserver<O, K, F>
+where
+    O: Observer<T, E>,
+    K: Hash,
+    F: FnMut(&T) -> K + Send,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if !replace(&mut self.flushed, false) {
+            self.reset();
+        }
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.reset();
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.reset();
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let Self {
+            observer,
+            key,
+            seen,
+            ..
+        } = self;
+        observer.on_updates(Box::new(updates.filter(move |t| seen.insert(&key(t)))))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let Self {
+            observer,
+            key,
+            seen,
+            ..
+        } = self;
+        observer.on_snapshot(Box::new(items.filter(move |t| seen.insert(&key(t)))))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.flushed = false;
+        self.reset();
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.flushed = true;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("dedup -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that duplicate items are only forwarded once per
+    /// transaction.
+    #[test]
+    fn duplicates_in_batch() {
+        let mut dedup = DedupObserver::new(UpdatesMockObserver::<u64>::new());
+        let observer = &mut dedup as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2, 1, 3, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(
+            observer.on_updates(Box::new(vec![3, 4].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 1].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(dedup.observer.received_updates, vec![1, 2, 3, 4, 1]);
+    }
+
+    /// Check that items are identified by the provided key.
+    #[test]
+    fn dedup_by_key() {
+        let mut dedup = DedupObserver::with_key(
+            UpdatesMockObserver::<(u64, &str)>::new(),
+            |(id, _): &(u64, &str)| *id,
+        );
+        let observer = &mut dedup as &mut dyn Observer<(u64, &str), ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![(1, "a"), (2, "b"), (1, "c")].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(dedup.observer.received_updates, vec![(1, "a"), (2, "b")]);
+    }
+
+    /// Check that a bounded number of keys is remembered across
+    /// transactions, forgetting the least recently seen ones.
+    #[test]
+    fn bounded_persistent() {
+        let mut dedup = DedupObserver::new(UpdatesMockObserver::<u64>::new())
+            .capacity(2)
+            .persistent(true);
+        let observer = &mut dedup as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        // Seeing 1 again makes 2 the least recently seen key, which
+        // gets forgotten once 3 arrives.
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2, 1, 3, 2, 3, 1].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![3, 1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(dedup.seen.len(), 2);
+        assert_eq!(dedup.observer.received_updates, vec![1, 2, 3, 2, 1, 2]);
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::mpsc::channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::thread::spawn;
+use std::thread::JoinHandle;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+use crate::poison::MutexExt;
+
+/// A unit of work handed to a `DeliveryExecutor`.
+pub type Job = Box<dyn FnOnce() + Send>;
+
+/// An abstraction over the means by which events are delivered to an
+/// `ExecutorObserver`'s inner observer.
+pub trait DeliveryExecutor: Debug + Send + Sync {
+    /// Execute `job`, either right away or at some later point, on an
+    /// arbitrary thread.
+    fn execute(&self, job: Job);
+}
+
+/// A `DeliveryExecutor` running jobs right away, on the calling
+/// thread.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct InlineExecutor;
+
+impl DeliveryExecutor for InlineExecutor {
+    fn execute(&self, job: Job) {
+        job()
+    }
+}
+
+/// A `DeliveryExecutor` running jobs on a fixed number of worker
+/// threads.
+#[derive(Debug)]
+pub struct ThreadPoolExecutor {
+    /// The channel for handing jobs to the workers.
+    sender: Mutex<Option<Sender<Job>>>,
+    /// The worker threads.
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPoolExecutor {
+    /// Create a new `ThreadPoolExecutor` using `threads` worker
+    /// threads, at least one.
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let threads = (0..threads.max(1))
+            .map(|_| {
+                let receiver = receiver.clone();
+                spawn(move || Self::work(&receiver))
+            })
+            .collect();
+
+        Self {
+            sender: Mutex::new(Some(sender)),
+            threads,
+        }
+    }
+
+    /// Run jobs until the pool is dropped.
+    fn work(receiver: &Mutex<Receiver<Job>>) {
+        loop {
+            let job = receiver.lock_unpoisoned().recv();
+            match job {
+                Ok(job) => job(),
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl DeliveryExecutor for ThreadPoolExecutor {
+    fn execute(&self, job: Job) {
+        if let Some(sender) = self.sender.lock_unpoisoned().as_ref() {
+            // The workers only exit once the sender is gone, so sending
+            // cannot fail.
+            let _ = sender.send(job);
+        }
+    }
+}
+
+impl Drop for ThreadPoolExecutor {
+    fn drop(&mut self) {
+        // Disconnect the channel, which makes the workers exit once
+        // they ran out of jobs.
+        let _ = self.sender.lock_unpoisoned().take();
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// An event queued for delivery.
+#[derive(Debug)]
+enum Event<T> {
+    Start,
+    Updates(Vec<T>),
+    Snapshot(Vec<T>),
+    Commit(Option<TransactionStats>),
+    Abort,
+    Flush,
+    Completed,
+    Disconnected,
+}
+
+/// The events queued for delivery by an `ExecutorObserver`.
+#[derive(Debug)]
+struct Queue<T, E> {
+    /// The events not yet delivered, in order.
+    events: VecDeque<Event<T>>,
+    /// Whether a job delivering queued events is scheduled or running.
+    draining: bool,
+    /// The first error reported by the observer that we have not yet
+    /// passed on.
+    error: Option<E>,
+}
+
+/// The state shared between an `ExecutorObserver` and the jobs it
+/// submitted.
+#[derive(Debug)]
+struct Shared<O, T, E> {
+    /// The observer events are delivered to.
+    observer: Mutex<O>,
+    /// The events queued for delivery.
+    queue: Mutex<Queue<T, E>>,
+    /// A condition variable signaled once the queue got drained.
+    drained: Condvar,
+}
+
+impl<O, T, E> Shared<O, T, E>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+    /// Deliver queued events until the queue is empty.
+    fn drain(&self) {
+        let mut observer = self.observer.lock_unpoisoned();
+        loop {
+            let event = {
+                let mut queue = self.queue.lock_unpoisoned();
+                match queue.events.pop_front() {
+                    Some(event) => event,
+                    None => {
+                        queue.draining = false;
+                        self.drained.notify_all();
+                        break;
+                    }
+                }
+            };
+
+            let result = match event {
+                Event::Start => observer.on_start(),
+                Event::Updates(updates) => observer.on_updates(Box::new(updates.into_iter())),
+                Event::Snapshot(items) => observer.on_snapshot(Box::new(items.into_iter())),
+                Event::Commit(stats) => match stats {
+                    Some(stats) => observer.on_commit_with_stats(stats),
+                    None => observer.on_commit(),
+                },
+                Event::Abort => observer.on_abort(),
+                Event::Flush => observer.on_flush(),
+                Event::Completed => observer.on_completed(),
+                Event::Disconnected => observer.on_disconnected(),
+            };
+            if let Err(e) = result {
+                let mut queue = self.queue.lock_unpoisoned();
+                if queue.error.is_none() {
+                    queue.error = Some(e);
+                }
+            }
+        }
+    }
+}
+
+/// An `Observer` decoupling the delivery of events to an inner
+/// observer from the thread emitting them, by delivering them through
+/// a `DeliveryExecutor`.
+///
+/// Events are delivered strictly in order, one after the other, so
+/// that transactions are processed sequentially. However, with an
+/// executor running jobs on a thread pool, the emitting thread (e.g.,
+/// one reading from a socket) may carry on while earlier events are
+/// still being processed, and multiple `ExecutorObserver`s sharing an
+/// executor deliver their events in parallel.
+///
+/// As a consequence, errors reported by the inner observer cannot be
+/// passed on right away. Rather, the first error is reported by the
+/// next event emitted after it occurred. `on_commit` and `on_completed`
+/// wait for all events to be delivered and report any outstanding
+/// error, so that a commit succeeding means that the inner observer
+/// committed, e.g., before the commit gets acknowledged to a sender.
+/// Only the events in between are delivered asynchronously.
+#[derive(Debug)]
+pub struct ExecutorObserver<O, T, E> {
+    /// The state shared with the jobs we submitted.
+    shared: Arc<Shared<O, T, E>>,
+    /// The executor running delivery jobs.
+    executor: Arc<dyn DeliveryExecutor>,
+}
+
+impl<O, T, E> ExecutorObserver<O, T, E>
+where
+    O: Observer<T, E> + 'static,
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    /// Create a new `ExecutorObserver` delivering events to `observer`
+    /// through `executor`.
+    pub fn new(observer: O, executor: Arc<dyn DeliveryExecutor>) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                observer: Mutex::new(observer),
+                queue: Mutex::new(Queue {
+                    events: VecDeque::new(),
+                    draining: false,
+                    error: None,
+                }),
+                drained: Condvar::new(),
+            }),
+            executor,
+        }
+    }
+
+    /// Queue an event for delivery, reporting the first error that
+    /// occurred since the last event, if any.
+    fn enqueue(&mut self, event: Event<T>) -> Result<(), E> {
+        let mut queue = self.shared.queue.lock_unpoisoned();
+        queue.events.push_back(event);
+        if !queue.draining {
+            queue.draining = true;
+            drop(queue);
+
+            let shared = self.shared.clone();
+            self.executor.execute(Box::new(move || shared.drain()));
+            queue = self.shared.queue.lock_unpoisoned();
+        }
+        queue.error.take().map_or(Ok(()), Err)
+    }
+
+    /// Block until all queued events have been delivered, reporting
+    /// the first error that occurred since the last event, if any.
+    fn wait(&self) -> Result<(), E> {
+        let mut queue = self.shared.queue.lock_unpoisoned();
+        while queue.draining {
+            queue = self
+                .shared
+                .drained
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+        queue.error.take().map_or(Ok(()), Err)
+    }
+}
+
+impl<O, T, E> Observer<T, E> for ExecutorObserver<O, T, E>
+where
+    O: Observer<T, E> + 'static,
+    T: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.enqueue(Event::Start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        let queued = self.enqueue(Event::Commit(None));
+        let delivered = self.wait();
+        queued.and(delivered)
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        let queued = self.enqueue(Event::Commit(Some(stats)));
+        let delivered = self.wait();
+        queued.and(delivered)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.enqueue(Event::Updates(updates.collect()))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.enqueue(Event::Snapshot(items.collect()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.enqueue(Event::Abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.enqueue(Event::Flush)
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        let queued = self.enqueue(Event::Completed);
+        let delivered = self.wait();
+        queued.and(delivered)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        let queued = self.enqueue(Event::Disconnected);
+        let delivered = self.wait();
+        queued.and(delivered)
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "deliver_on -> {}",
+            self.shared.observer.lock_unpoisoned().describe()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::ObserverExt;
+
+    /// Check that events are delivered in order through a thread pool.
+    #[test]
+    fn pooled_delivery() {
+        let pool = Arc::new(ThreadPoolExecutor::new(4));
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut pooled = mock.clone().deliver_on(pool);
+        let observer = &mut pooled as &mut dyn Observer<u64, ()>;
+
+        for i in 0..100 {
+            assert_eq!(observer.on_start(), Ok(()));
+            assert_eq!(
+                observer.on_updates(Box::new(vec![2 * i, 2 * i + 1].into_iter())),
+                Ok(())
+            );
+            assert_eq!(observer.on_commit(), Ok(()));
+        }
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_commit, 100);
+        assert_eq!(mock.received_updates, (0..200).collect::<Vec<_>>());
+    }
+
+    /// An observer failing every commit.
+    #[derive(Debug)]
+    struct FailingObserver;
+
+    impl Observer<u64, String> for FailingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            Err("on_commit".to_string())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that a failed commit is reported by the commit itself, no
+    /// matter the executor.
+    #[test]
+    fn deferred_errors() {
+        let mut inline = FailingObserver.deliver_on(Arc::new(InlineExecutor));
+        let observer = &mut inline as &mut dyn Observer<u64, String>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Err("on_commit".to_string()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let mut pooled = FailingObserver.deliver_on(Arc::new(ThreadPoolExecutor::new(1)));
+        let observer = &mut pooled as &mut dyn Observer<u64, String>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Err("on_commit".to_string()));
+        assert_eq!(observer.on_completed(), Ok(()));
+    }
+}
@@ -0,0 +1,177 @@
+use crate::observe::AdaptErrObserver;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::observe::CatchObserver;
+use crate::observe::CompactObserver;
+use crate::observe::DeadlineObserver;
+use crate::observe::DeliveryExecutor;
+use crate::observe::ErrorPolicy;
+use crate::observe::ExecutorObserver;
+use crate::observe::FlattenObserver;
+use crate::observe::MapObserver;
+use crate::observe::MetricsObserver;
+use crate::observe::Observer;
+use crate::observe::PeriodicCommitObserver;
+use crate::observe::ProgressObserver;
+use crate::observe::Strictness;
+use crate::observe::SystemClock;
+use crate::observe::TakeObserver;
+use crate::observe::TimeoutPolicy;
+use crate::observe::TolerateErrorsObserver;
+use crate::observe::TransactionIsolationObserver;
+use crate::observe::ValidatingObserver;
+use crate::observe::Weighted;
+
+/// An extension trait providing combinators for `Observer`s.
+pub trait ObserverExt<T, E>: Observer<T, E>
+where
+    T: Send,
+    E: Send,
+{
+    /// Convert the errors reported by this observer using the provided
+    /// function.
+    fn adapt_err<F, E2>(self, f: F) -> AdaptErrObserver<Self, F, E>
+    where
+        Self: Sized,
+        F: Fn(E) -> E2 + Send,
+        E2: Send,
+    {
+        AdaptErrObserver::new(self, f)
+    }
+
+    /// Isolate upstream from panics of this observer, optionally
+    /// suppressing the errors it reports.
+    fn catch_unwind(self, suppress_errors: bool) -> CatchObserver<Self>
+    where
+        Self: Sized,
+    {
+        CatchObserver::new(self, suppress_errors)
+    }
+
+    /// Compact the weighted updates of each transaction before
+    /// forwarding them to this observer.
+    fn compact(self) -> CompactObserver<Self, T>
+    where
+        Self: Sized,
+        T: Weighted,
+    {
+        CompactObserver::new(self)
+    }
+
+    /// Deliver events to this observer on a worker thread, dealing
+    /// with calls not returning within `timeout` according to `policy`.
+    fn deadline(self, timeout: Duration, policy: TimeoutPolicy) -> DeadlineObserver<Self, T, E>
+    where
+        Self: Sized + 'static,
+        E: 'static,
+    {
+        DeadlineObserver::new(self, timeout, policy)
+    }
+
+    /// Deliver events to this observer through `executor`, decoupling
+    /// their processing from the thread emitting them.
+    fn deliver_on(self, executor: Arc<dyn DeliveryExecutor>) -> ExecutorObserver<Self, T, E>
+    where
+        Self: Sized + 'static,
+        T: Debug + 'static,
+        E: Debug + 'static,
+    {
+        ExecutorObserver::new(self, executor)
+    }
+
+    /// Accept batches of groups of items, forwarding the flattened
+    /// items to this observer.
+    fn flatten(self) -> FlattenObserver<Self>
+    where
+        Self: Sized,
+    {
+        FlattenObserver::new(self)
+    }
+
+    /// Hold back the updates of each transaction until it is
+    /// committed, forwarding them to this observer as a single batch.
+    fn isolate_transactions(self) -> TransactionIsolationObserver<Self, T>
+    where
+        Self: Sized,
+    {
+        TransactionIsolationObserver::new(self)
+    }
+
+    /// Convert the items passed to this observer using the provided
+    /// function.
+    fn map<F, U>(self, f: F) -> MapObserver<Self, F, U>
+    where
+        Self: Sized,
+        F: Fn(U) -> T + Send,
+        U: Send,
+    {
+        MapObserver::new(self, f)
+    }
+
+    /// Record metrics about the events passing through to this
+    /// observer, under names derived from `name`.
+    fn metrics<S>(self, name: S) -> MetricsObserver<Self>
+    where
+        Self: Sized,
+        S: Into<String>,
+    {
+        MetricsObserver::new(self, name)
+    }
+
+    /// Commit the transaction in progress on behalf of upstream once it
+    /// has been open for `interval`.
+    fn periodic_commit(self, interval: Duration) -> PeriodicCommitObserver<Self, SystemClock>
+    where
+        Self: Sized,
+    {
+        PeriodicCommitObserver::new(self, interval)
+    }
+
+    /// Report the number of items of a batch this observer consumed
+    /// before failing along with the error, as combined by `f`.
+    fn track_progress<F>(self, f: F) -> ProgressObserver<Self, F>
+    where
+        Self: Sized,
+        F: Fn(E, usize) -> E + Send,
+    {
+        ProgressObserver::new(self, f)
+    }
+
+    /// Forward at most `count` items, signaling completion once the
+    /// limit has been reached.
+    fn take(self, count: usize) -> TakeObserver<Self>
+    where
+        Self: Sized,
+    {
+        TakeObserver::new(self, count)
+    }
+
+    /// Tolerate errors reported by this observer, handling them
+    /// according to `policy` instead of passing them on.
+    fn tolerate_errors(self, policy: ErrorPolicy) -> TolerateErrorsObserver<Self>
+    where
+        Self: Sized,
+    {
+        TolerateErrorsObserver::new(self, policy)
+    }
+
+    /// Enforce that the events passed to this observer adhere to the
+    /// transaction life cycle, dealing with violations as per
+    /// `strictness`.
+    fn validate(self, strictness: Strictness) -> ValidatingObserver<Self>
+    where
+        Self: Sized,
+    {
+        ValidatingObserver::new(self, strictness)
+    }
+}
+
+impl<O, T, E> ObserverExt<T, E> for O
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+}
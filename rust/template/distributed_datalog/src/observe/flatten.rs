@@ -0,0 +1,105 @@
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` accepting batches of groups of items and forwarding
+/// the flattened items to an inner observer; the observer analog of
+/// `Iterator::flatten`.
+///
+/// Groups are forwarded in order, each with its items in order. Empty
+/// groups are skipped, i.e., they simply do not contribute any items.
+/// Every batch of groups results in exactly one batch of items, which
+/// may be empty.
+#[derive(Debug)]
+pub struct FlattenObserver<O> {
+    /// The observer we forward flattened items to.
+    observer: O,
+}
+
+impl<O> FlattenObserver<O> {
+    /// Create a new `FlattenObserver` forwarding to `observer`.
+    pub fn new(observer: O) -> Self {
+        Self { observer }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, I, E> Observer<I, E> for FlattenObserver<O>
+where
+    O: Observer<I::Item, E>,
+    I: IntoIterator + Send,
+    I::Item: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = I> + 'a>) -> Result<(), E> {
+        self.observer.on_updates(Box::new(updates.flatten()))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = I> + 'a>) -> Result<(), E> {
+        self.observer.on_snapshot(Box::new(items.flatten()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("flatten -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that groups of items are flattened in order, skipping
+    /// empty ones.
+    #[test]
+    fn flatten_groups() {
+        let mut flatten = FlattenObserver::new(UpdatesMockObserver::<u64>::new());
+        let observer = &mut flatten as &mut dyn Observer<Vec<u64>, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let groups = vec![vec![3, 1], vec![], vec![2]];
+        assert_eq!(observer.on_updates(Box::new(groups.into_iter())), Ok(()));
+        let groups = vec![vec![], vec![]];
+        assert_eq!(observer.on_updates(Box::new(groups.into_iter())), Ok(()));
+        let groups = vec![vec![5, 4]];
+        assert_eq!(observer.on_updates(Box::new(groups.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = flatten.into_inner();
+        assert_eq!(mock.received_updates, vec![3, 1, 2, 5, 4]);
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+}
@@ -0,0 +1,221 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::mem::take;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` holding back each transaction until it is committed,
+/// so that downstream never sees updates of a transaction that may
+/// still be aborted.
+///
+/// Nothing of a transaction is forwarded before its commit, at which
+/// point it gets replayed in one go: a start, the snapshot (if any),
+/// all updates as a single batch, and the commit. Aborted transactions
+/// are discarded without downstream ever learning about them.
+///
+/// A flush is not forwarded, as it would expose part of a transaction
+/// that was not committed. The buffered updates are kept instead and
+/// released along with those of the transaction carrying the remainder,
+/// once that gets committed.
+///
+/// As opposed to a `TransactionBufferObserver`, which hands committed
+/// transactions to a `TransactionObserver`, this observer forwards to
+/// a regular `Observer` and may be put in front of any of them.
+pub struct TransactionIsolationObserver<O, T> {
+    /// The observer we forward committed transactions to.
+    observer: O,
+    /// The snapshot of the transaction in progress, if any.
+    snapshot: Option<Vec<T>>,
+    /// The updates of the transaction in progress.
+    updates: Vec<T>,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// its remainder follows as part of the next one.
+    flushed: bool,
+}
+
+impl<O, T> TransactionIsolationObserver<O, T> {
+    /// Create a new `TransactionIsolationObserver` forwarding committed
+    /// transactions to `observer`.
+    pub fn new(observer: O) -> Self {
+        Self {
+            observer,
+            snapshot: None,
+            updates: Vec::new(),
+            flushed: false,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Replay the transaction in progress to the inner observer, up to
+    /// but excluding its commit.
+    fn replay<E>(&mut self) -> Result<(), E>
+    where
+        O: Observer<T, E>,
+        T: Send,
+        E: Send,
+    {
+        self.observer.on_start()?;
+        if let Some(snapshot) = self.snapshot.take() {
+            self.observer.on_snapshot(Box::new(snapshot.into_iter()))?;
+        }
+        let updates = take(&mut self.updates);
+        if !updates.is_empty() {
+            self.observer.on_updates(Box::new(updates.into_iter()))?;
+        }
+        Ok(())
+    }
+}
+
+impl<O, T> Debug for TransactionIsolationObserver<O, T>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TransactionIsolationObserver")
+            .field("observer", &self.observer)
+            .field("snapshot", &self.snapshot.as_ref().map(Vec::len))
+            .field("updates", &self.updates.len())
+            .field("flushed", &self.flushed)
+            .finish()
+    }
+}
+
+impl<O, T, E> Observer<T, E> for TransactionIsolationObserver<O, T>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if !self.flushed {
+            self.snapshot = None;
+            self.updates.clear();
+        }
+        self.flushed = false;
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.replay()?;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.replay()?;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.updates.extend(updates);
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.snapshot.get_or_insert_with(Vec::new).extend(items);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        // Downstream has not seen anything of the transaction.
+        self.snapshot = None;
+        self.updates.clear();
+        self.flushed = false;
+        Ok(())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.flushed = true;
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("isolate -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An `Observer` recording the batches of updates of the
+    /// transactions it received.
+    #[derive(Debug, Default)]
+    struct Batches {
+        /// The batches of each committed transaction.
+        committed: Vec<Vec<Vec<u64>>>,
+        /// The batches of the transaction in progress, if any.
+        ongoing: Option<Vec<Vec<u64>>>,
+    }
+
+    impl Observer<u64, ()> for Batches {
+        fn on_start(&mut self) -> Result<(), ()> {
+            self.ongoing = Some(Vec::new());
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            self.committed.push(self.ongoing.take().unwrap());
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), ()> {
+            self.ongoing.as_mut().unwrap().push(updates.collect());
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Check that the updates of a transaction are only forwarded on
+    /// commit, as a single batch, even when flushed midway, and that
+    /// aborted transactions never make it downstream.
+    #[test]
+    fn isolate_transactions() {
+        let mut isolate = TransactionIsolationObserver::new(Batches::default());
+        let observer = &mut isolate as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(observer.on_flush(), Ok(()));
+        assert_eq!(isolate.observer.ongoing, None);
+
+        let observer = &mut isolate as &mut dyn Observer<u64, ()>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![5].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let batches = isolate.into_inner();
+        assert_eq!(batches.committed, vec![vec![vec![1, 2, 3, 4]], vec![]]);
+        assert_eq!(batches.ongoing, None);
+    }
+}
@@ -0,0 +1,262 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use crate::observe::Observable;
+use crate::observe::Observer;
+use crate::observe::ObserverBox;
+use crate::observe::OptionalObserver;
+use crate::observe::SharedObserver;
+use crate::observe::TransactionStats;
+use crate::poison::MutexExt;
+
+/// An `Observer` converting the items it receives using a function
+/// before passing them on to an inner observer.
+pub struct MapObserver<O, F, T> {
+    /// The observer we pass converted items on to.
+    observer: O,
+    /// The function converting items.
+    f: F,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<O, F, T> MapObserver<O, F, T> {
+    /// Create a new `MapObserver` converting items using `f` before
+    /// passing them on to `observer`.
+    pub fn new(observer: O, f: F) -> Self {
+        Self {
+            observer,
+            f,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, F, T> Debug for MapObserver<O, F, T>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MapObserver")
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+impl<O, F, T, U, E> Observer<T, E> for MapObserver<O, F, T>
+where
+    O: Observer<U, E>,
+    F: Fn(T) -> U + Send,
+    T: Send,
+    U: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_updates(Box::new(updates.map(&self.f)))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_snapshot(Box::new(items.map(&self.f)))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("map -> {}", self.observer.describe())
+    }
+}
+
+/// The subscription handed out by a `MapObservable`.
+pub struct MapSubscription<S, U, E> {
+    /// The subscription to the wrapped observable.
+    subscription: S,
+    /// The observer that got subscribed, shared with the `MapObserver`
+    /// installed on its behalf.
+    observer: SharedObserver<OptionalObserver<ObserverBox<U, E>>>,
+}
+
+impl<S, U, E> Debug for MapSubscription<S, U, E>
+where
+    S: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MapSubscription")
+            .field("subscription", &self.subscription)
+            .finish()
+    }
+}
+
+/// An `Observable` adapting the items emitted by an inner observable
+/// for subscribed observers, by converting them using a function.
+///
+/// Subscribing an observer installs a `MapObserver` wrapping it into
+/// the inner observable. Unsubscribing hands back the very observer
+/// that was subscribed, not the `MapObserver`.
+pub struct MapObservable<O, F, T> {
+    /// The observable whose items we convert.
+    observable: O,
+    /// The function converting items.
+    f: F,
+    _phantom: PhantomData<fn(T)>,
+}
+
+impl<O, F, T> MapObservable<O, F, T> {
+    /// Create a new `MapObservable` converting the items emitted by
+    /// `observable` using `f`.
+    pub fn new(observable: O, f: F) -> Self {
+        Self {
+            observable,
+            f,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Retrieve the wrapped observable.
+    pub fn into_inner(self) -> O {
+        self.observable
+    }
+}
+
+impl<O, F, T> Debug for MapObservable<O, F, T>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MapObservable")
+            .field("observable", &self.observable)
+            .finish()
+    }
+}
+
+impl<O, F, T, U, E> Observable<U, E> for MapObservable<O, F, T>
+where
+    O: Observable<T, E>,
+    F: Fn(T) -> U + Clone + Send + 'static,
+    T: Send + 'static,
+    U: Debug + Send + 'static,
+    E: Debug + Send + 'static,
+{
+    type Subscription = MapSubscription<O::Subscription, U, E>;
+
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<U, E>,
+    ) -> Result<Self::Subscription, ObserverBox<U, E>> {
+        let observer = Arc::new(Mutex::new(Some(observer)));
+        let mapped = MapObserver::new(observer.clone(), self.f.clone());
+
+        match self.observable.subscribe(Box::new(mapped)) {
+            Ok(subscription) => Ok(MapSubscription {
+                subscription,
+                observer,
+            }),
+            // The rejected `MapObserver` got dropped already, so we are
+            // left with the only reference to the original observer.
+            Err(_) => Err(observer.lock_unpoisoned().take().unwrap()),
+        }
+    }
+
+    fn unsubscribe(&mut self, subscription: &Self::Subscription) -> Option<ObserverBox<U, E>> {
+        self.observable
+            .unsubscribe(&subscription.subscription)
+            .and_then(|_mapped| subscription.observer.lock_unpoisoned().take())
+    }
+
+    fn subscriber_count(&self) -> usize {
+        self.observable.subscriber_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::UpdatesObservable;
+
+    /// Check that a `MapObserver` converts updates and snapshots.
+    #[test]
+    fn map_items() {
+        let mock = UpdatesMockObserver::<String>::new();
+        let mut map = MapObserver::new(mock, |x: u64| x.to_string());
+        let observer = &mut map as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_snapshot(Box::new(vec![1].into_iter())), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![2, 3].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = map.into_inner();
+        assert_eq!(mock.received_updates, vec!["1", "2", "3"]);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Subscribe to and unsubscribe from a `MapObservable`, making sure
+    /// that unsubscribing recovers the original observer.
+    #[test]
+    fn subscribe_unsubscribe_mapped() {
+        let inner = UpdatesObservable::<u64, ()>::default();
+        let mut observable = inner.map_subscriber(|x: u64| x.to_string());
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<String>::new()));
+
+        let subscription = observable.subscribe(Box::new(mock.clone())).unwrap();
+        let other = Box::new(UpdatesMockObserver::<String>::new());
+        assert!(observable.subscribe(other).is_err());
+
+        {
+            let mut emitter = observable.observable.observer.clone();
+            let emitter = &mut emitter as &mut dyn Observer<u64, ()>;
+            assert_eq!(emitter.on_start(), Ok(()));
+            assert_eq!(emitter.on_updates(Box::new(vec![4, 2].into_iter())), Ok(()));
+            assert_eq!(emitter.on_commit(), Ok(()));
+        }
+
+        let mut observer = observable.unsubscribe(&subscription).unwrap();
+        assert!(observable.observable.observer.lock().unwrap().is_none());
+        assert!(observable.unsubscribe(&subscription).is_none());
+
+        // The observer we got back receives items without conversion.
+        assert_eq!(
+            observer.on_updates(Box::new(vec!["1".to_string()].into_iter())),
+            Ok(())
+        );
+        assert_eq!(mock.lock().unwrap().received_updates, vec!["4", "2", "1"]);
+    }
+}
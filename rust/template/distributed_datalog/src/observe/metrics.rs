@@ -0,0 +1,241 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+#[cfg(feature = "metrics")]
+use std::time::SystemTime;
+#[cfg(feature = "metrics")]
+use std::time::UNIX_EPOCH;
+
+#[cfg(feature = "metrics")]
+use metrics::register_counter;
+#[cfg(feature = "metrics")]
+use metrics::register_gauge;
+#[cfg(feature = "metrics")]
+use metrics::register_histogram;
+#[cfg(feature = "metrics")]
+use metrics::Counter;
+#[cfg(feature = "metrics")]
+use metrics::Gauge;
+#[cfg(feature = "metrics")]
+use metrics::Histogram;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// The metrics recorded by a `MetricsObserver`.
+#[cfg(feature = "metrics")]
+struct Metrics {
+    /// The name the metrics are derived from.
+    name: String,
+    /// The counter tracking the number of updates.
+    updates: Counter,
+    /// The histogram tracking the sizes of batches of updates.
+    batch_sizes: Histogram,
+    /// The gauge tracking the time of the last commit, in seconds since
+    /// the Unix epoch.
+    last_commit: Gauge,
+}
+
+/// An `Observer` recording metrics about the events passing through
+/// it, through the facade provided by the `metrics` crate.
+///
+/// For a given name, the following metrics are recorded:
+/// - `<name>_updates_total`, a counter of the updates seen
+/// - `<name>_batch_size`, a histogram of the number of updates per
+///   batch
+/// - `<name>_last_commit_timestamp_seconds`, a gauge set to the time
+///   of the last successful commit, in seconds since the Unix epoch
+///
+/// Metrics are only recorded if the `metrics` feature is enabled;
+/// otherwise all events are merely forwarded to the inner observer.
+pub struct MetricsObserver<O> {
+    /// The observer we forward events to.
+    observer: O,
+    /// The metrics we record.
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+}
+
+impl<O> MetricsObserver<O> {
+    /// Create a new `MetricsObserver` wrapping `observer` and recording
+    /// metrics derived from `name`.
+    #[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+    pub fn new<S>(observer: O, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        #[cfg(feature = "metrics")]
+        let metrics = {
+            let name = name.into();
+            Metrics {
+                updates: register_counter!(format!("{}_updates_total", name)),
+                batch_sizes: register_histogram!(format!("{}_batch_size", name)),
+                last_commit: register_gauge!(format!("{}_last_commit_timestamp_seconds", name)),
+                name,
+            }
+        };
+
+        Self {
+            observer,
+            #[cfg(feature = "metrics")]
+            metrics,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Record a successful commit.
+    fn committed(&mut self) {
+        #[cfg(feature = "metrics")]
+        {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            self.metrics.last_commit.set(now.as_secs_f64());
+        }
+    }
+}
+
+impl<O> Debug for MetricsObserver<O>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let mut debug = f.debug_struct("MetricsObserver");
+        let _ = debug.field("observer", &self.observer);
+        #[cfg(feature = "metrics")]
+        let _ = debug.field("name", &self.metrics.name);
+        debug.finish()
+    }
+}
+
+impl<O, T, E> Observer<T, E> for MetricsObserver<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()?;
+        self.committed();
+        Ok(())
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)?;
+        self.committed();
+        Ok(())
+    }
+
+    #[cfg(feature = "metrics")]
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let mut count = 0;
+        let result = self
+            .observer
+            .on_updates(Box::new(updates.inspect(|_| count += 1)));
+
+        self.metrics.updates.increment(count);
+        self.metrics.batch_sizes.record(count as f64);
+        result
+    }
+
+    #[cfg(not(feature = "metrics"))]
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_updates(updates)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("metrics -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(all(test, feature = "metrics"))]
+mod tests {
+    use super::*;
+
+    use std::collections::HashMap;
+
+    use metrics_util::debugging::DebugValue;
+    use metrics_util::debugging::DebuggingRecorder;
+    use metrics_util::debugging::Snapshotter;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that metrics get updated as events pass through.
+    #[test]
+    fn record_metrics() {
+        // The recorder keeps metrics per thread, so we don't care
+        // whether somebody else installed it already.
+        let _ = DebuggingRecorder::per_thread().install();
+
+        let mock = UpdatesMockObserver::<u64>::new();
+        let mut metrics = MetricsObserver::new(mock, "test");
+        let observer = &mut metrics as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2, 3].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        // Histogram values get drained by a snapshot, so we can only
+        // take a single one.
+        let mut snapshot = Snapshotter::current_thread_snapshot()
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .map(|(key, .., value)| (key.key().name().to_string(), value))
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(
+            snapshot.remove("test_updates_total"),
+            Some(DebugValue::Counter(4))
+        );
+        match snapshot.remove("test_batch_size") {
+            Some(DebugValue::Histogram(sizes)) => {
+                let sizes = sizes
+                    .into_iter()
+                    .map(|size| size.into_inner())
+                    .collect::<Vec<_>>();
+                assert_eq!(sizes, vec![3.0, 1.0]);
+            }
+            value => panic!("unexpected batch size histogram: {:?}", value),
+        }
+        match snapshot.remove("test_last_commit_timestamp_seconds") {
+            Some(DebugValue::Gauge(time)) => assert!(time.into_inner() > 0.0),
+            value => panic!("unexpected last commit gauge: {:?}", value),
+        }
+
+        let mock = metrics.into_inner();
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 4]);
+    }
+}
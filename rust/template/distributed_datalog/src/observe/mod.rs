@@ -1,10 +1,61 @@
 //! A publish-subscribe infrastructure for differential-datalog programs.
 
+mod adapt_err;
+mod catch;
+mod change;
+mod clock;
+mod compact;
+mod concat;
+mod deadline;
+mod dedup;
+mod deliver;
+mod ext;
+mod flatten;
+mod isolate;
+mod map;
+mod metrics;
 mod observable;
 mod observer;
+mod periodic;
+mod progress;
+mod route;
+mod scan;
+mod sink;
+mod sort;
+mod take;
 #[cfg(any(test, feature = "test"))]
 mod test;
+mod timestamp;
+mod timing;
+mod tolerate;
+mod transaction;
+mod tumble;
+mod validate;
+mod variant;
+mod window;
 
+pub use adapt_err::AdaptErrObserver;
+pub use catch::CatchObserver;
+pub use change::ChangeObserver;
+pub use clock::Clock;
+pub use clock::SystemClock;
+pub use compact::CompactObserver;
+pub use compact::Weighted;
+pub use concat::ConcatObservable;
+pub use deadline::DeadlineObserver;
+pub use deadline::TimeoutPolicy;
+pub use dedup::DedupObserver;
+pub use deliver::DeliveryExecutor;
+pub use deliver::ExecutorObserver;
+pub use deliver::InlineExecutor;
+pub use deliver::ThreadPoolExecutor;
+pub use ext::ObserverExt;
+pub use flatten::FlattenObserver;
+pub use isolate::TransactionIsolationObserver;
+pub use map::MapObservable;
+pub use map::MapObserver;
+pub use map::MapSubscription;
+pub use metrics::MetricsObserver;
 pub use observable::Observable;
 pub use observable::ObservableAny;
 pub use observable::ObservableBox;
@@ -14,6 +65,32 @@ pub use observer::Observer;
 pub use observer::ObserverBox;
 pub use observer::OptionalObserver;
 pub use observer::SharedObserver;
+pub use observer::TransactionStats;
+pub use periodic::PeriodicCommitObserver;
+pub use progress::ProgressObserver;
+pub use route::RouteObserver;
+pub use scan::ScanObserver;
+pub use sink::TransactionSink;
+pub use sink::TransactionalSinkObserver;
+pub use sort::SortObserver;
+pub use take::TakeObserver;
+pub use timestamp::TimestampObserver;
+pub use timing::CallTimings;
+pub use timing::TimingObserver;
+pub use timing::Timings;
+pub use tolerate::ErrorPolicy;
+pub use tolerate::TolerateErrorsObserver;
+pub use transaction::TransactionBufferObserver;
+pub use transaction::TransactionObserver;
+pub use tumble::TumblingWindowObserver;
+pub use validate::Strictness;
+pub use validate::ValidatingObserver;
+pub use variant::VariantRouterObserver;
+pub use window::WindowObserver;
 
+#[cfg(any(test, feature = "test"))]
+pub use test::MockClock;
 #[cfg(any(test, feature = "test"))]
 pub use test::MockObserver;
+#[cfg(any(test, feature = "test"))]
+pub use test::NullObserver;
@@ -3,9 +3,11 @@ use std::fmt::Debug;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use crate::observe::map::MapObservable;
 use crate::observe::observer::ObserverBox;
 use crate::observe::observer::OptionalObserver;
 use crate::observe::observer::SharedObserver;
+use crate::poison::MutexExt;
 
 /// A boxed up `ObservableAny`.
 pub type ObservableBox<T, E> = Box<dyn ObservableAny<T, E> + Send>;
@@ -34,6 +36,24 @@ where
     /// Cancel a subscription so that the observer stops listening to
     /// the observable.
     fn unsubscribe(&mut self, subscription: &Self::Subscription) -> Option<ObserverBox<T, E>>;
+
+    /// Retrieve the number of observers currently subscribed, e.g., for
+    /// deciding whether producing data is worthwhile at all. The
+    /// default implementation reports zero; observables keeping track
+    /// of their subscriptions should override it.
+    fn subscriber_count(&self) -> usize {
+        0
+    }
+
+    /// Convert the items emitted by this observable using `f`, allowing
+    /// for the subscription of observers of the converted items.
+    fn map_subscriber<F, U>(self, f: F) -> MapObservable<Self, F, T>
+    where
+        Self: Sized,
+        F: Fn(T) -> U + Clone + Send + 'static,
+    {
+        MapObservable::new(self, f)
+    }
 }
 
 /// An easily sharable `Observable`.
@@ -51,13 +71,17 @@ where
         &mut self,
         observer: ObserverBox<T, E>,
     ) -> Result<Self::Subscription, ObserverBox<T, E>> {
-        self.lock().unwrap().subscribe(observer)
+        self.lock_unpoisoned().subscribe(observer)
     }
 
     /// Cancel a subscription so that the observer stops listening to
     /// the observable.
     fn unsubscribe(&mut self, subscription: &Self::Subscription) -> Option<ObserverBox<T, E>> {
-        self.lock().unwrap().unsubscribe(subscription)
+        self.lock_unpoisoned().unsubscribe(subscription)
+    }
+
+    fn subscriber_count(&self) -> usize {
+        self.lock_unpoisoned().subscriber_count()
     }
 }
 
@@ -77,6 +101,10 @@ where
     /// Unsubscribe an `Observer` from this `Observable` using a
     /// previously handed out subscription.
     fn unsubscribe_any(&mut self, subscription: &dyn Any) -> Option<ObserverBox<T, E>>;
+
+    /// Retrieve the number of observers currently subscribed to this
+    /// `Observable`.
+    fn subscriber_count_any(&self) -> usize;
 }
 
 /// Any `Observable` whose subscription type implements `Any` is also an
@@ -101,6 +129,10 @@ where
             .downcast_ref::<S>()
             .and_then(|s| self.unsubscribe(s))
     }
+
+    fn subscriber_count_any(&self) -> usize {
+        self.subscriber_count()
+    }
 }
 
 /// A very simple observable that supports subscription of a single
@@ -122,7 +154,7 @@ where
         &mut self,
         observer: ObserverBox<T, E>,
     ) -> Result<Self::Subscription, ObserverBox<T, E>> {
-        let mut guard = self.observer.lock().unwrap();
+        let mut guard = self.observer.lock_unpoisoned();
         if guard.is_some() {
             Err(observer)
         } else {
@@ -132,7 +164,11 @@ where
     }
 
     fn unsubscribe(&mut self, _subscription: &Self::Subscription) -> Option<ObserverBox<T, E>> {
-        self.observer.lock().unwrap().take()
+        self.observer.lock_unpoisoned().take()
+    }
+
+    fn subscriber_count(&self) -> usize {
+        usize::from(self.observer.lock_unpoisoned().is_some())
     }
 }
 
@@ -140,6 +176,8 @@ where
 mod tests {
     use super::*;
 
+    use std::thread::spawn;
+
     use crate::observe::test::MockObserver;
 
     /// Test subscribing and unsubscribing for an `UpdatesObservable`.
@@ -148,8 +186,11 @@ mod tests {
         let mut observable = UpdatesObservable::<(), ()>::default();
         let observer = Box::new(MockObserver::new());
 
+        assert_eq!(observable.subscriber_count(), 0);
         assert!(observable.subscribe(observer).is_ok());
+        assert_eq!(observable.subscriber_count(), 1);
         assert!(observable.unsubscribe(&()).is_some());
+        assert_eq!(observable.subscriber_count(), 0);
     }
 
     /// Test multiple subscriptions to an `UpdatesObservable`.
@@ -170,6 +211,27 @@ mod tests {
         let observer = Box::new(MockObserver::new());
 
         let subscription = observable.subscribe_any(observer).unwrap();
+        assert_eq!(observable.subscriber_count_any(), 1);
         assert!(observable.unsubscribe_any(subscription.as_ref()).is_some());
+        assert_eq!(observable.subscriber_count_any(), 0);
+    }
+
+    /// Check that subscribing recovers from the observer lock being
+    /// poisoned by a thread that panicked while holding it.
+    #[test]
+    fn subscribe_poisoned() {
+        let mut observable = UpdatesObservable::<(), ()>::default();
+        let observer = observable.observer.clone();
+        let result = spawn(move || {
+            let _guard = observer.lock().unwrap();
+            panic!("poisoning observer lock")
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(observable.observer.is_poisoned());
+
+        let observer = Box::new(MockObserver::new());
+        assert!(observable.subscribe(observer).is_ok());
+        assert!(observable.unsubscribe(&()).is_some());
     }
 }
@@ -1,11 +1,39 @@
 use std::fmt::Debug;
+use std::ops::Deref;
 use std::ops::DerefMut;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::poison::MutexExt;
 
 /// A boxed up `Observer`.
 pub type ObserverBox<T, E> = Box<dyn Observer<T, E> + Send>;
 
+/// Statistics about a transaction, as delivered along with its commit
+/// by `Observer::on_commit_with_stats`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransactionStats {
+    /// The number of items the transaction carried, including those of
+    /// a snapshot.
+    pub items: usize,
+    /// The number of bytes the transaction took up on the wire,
+    /// including framing; zero if it was not received over the wire.
+    pub bytes: u64,
+    /// The time the transaction started.
+    pub started_at: Instant,
+}
+
+impl Default for TransactionStats {
+    fn default() -> Self {
+        Self {
+            items: 0,
+            bytes: 0,
+            started_at: Instant::now(),
+        }
+    }
+}
+
 /// A trait for objects that can observe an observable one.
 pub trait Observer<T, E>: Debug + Send
 where
@@ -20,14 +48,77 @@ where
     /// Observable is committed.
     fn on_commit(&mut self) -> Result<(), E>;
 
+    /// Action to perform when a transaction is committed, for which
+    /// the `Observable` gathered statistics, such as a `TcpReceiver`
+    /// does. The default implementation ignores the statistics and
+    /// invokes `on_commit`.
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        let _ = stats;
+        self.on_commit()
+    }
+
     /// Process a series of incoming items.
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E>;
 
+    /// Process a snapshot of the full state of the `Observable`, as
+    /// sent to bootstrap an observer joining a long-running stream.
+    ///
+    /// A snapshot is delivered as part of a transaction, ahead of any
+    /// updates of that transaction. The default implementation treats
+    /// the items of a snapshot as regular updates.
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.on_updates(items)
+    }
+
+    /// Action to perform when the transaction in progress is abandoned
+    /// without a commit, e.g., because the `Observable` went away.
+    ///
+    /// Observers buffering updates until a commit should discard them.
+    /// The default implementation does nothing.
+    fn on_abort(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Action to perform when the updates of a transaction still in
+    /// progress were delivered ahead of its commit, e.g., because a
+    /// buffer got flushed forcibly during shutdown.
+    ///
+    /// The updates seen since the last `on_start` form a partial
+    /// transaction; the remainder, if any, follows as part of a new
+    /// one. Observers applying transactions atomically should hold on
+    /// to the partial transaction and continue it with the next
+    /// `on_start`. The default implementation does nothing.
+    fn on_flush(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
     /// Action to perform when the `Observable` is about to shut down.
     ///
     /// This method is typically used to clean up any state associated
     /// with the `Observable`.
     fn on_completed(&mut self) -> Result<(), E>;
+
+    /// Action to perform when the `Observable` went away unexpectedly,
+    /// without signaling completion, e.g., because the connection to a
+    /// sender dropped. More data may follow once it reconnects.
+    ///
+    /// Observers finalizing their state on completion may want to
+    /// merely mark it as stale instead. The default implementation
+    /// treats a disconnect as completion.
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.on_completed()
+    }
+
+    /// Describe the chain of observers events pass through, starting
+    /// with this one, e.g., `"map -> take(10) -> channel"`, as an aid
+    /// for debugging the wiring of combinators.
+    ///
+    /// Combinators describe themselves followed by the observer they
+    /// wrap. The default implementation describes the observer as
+    /// `"opaque"`.
+    fn describe(&self) -> String {
+        "opaque".to_string()
+    }
 }
 
 // We need a direct implementation of `Observer` for boxed up observers
@@ -47,16 +138,53 @@ where
         self.deref_mut().on_commit()
     }
 
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.deref_mut().on_commit_with_stats(stats)
+    }
+
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
         self.deref_mut().on_updates(updates)
     }
 
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.deref_mut().on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.deref_mut().on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.deref_mut().on_flush()
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.deref_mut().on_completed()
     }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.deref_mut().on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        self.deref().describe()
+    }
 }
 
 /// An easily sharable `Observer`.
+///
+/// Being `Clone`, a `SharedObserver` (e.g., a
+/// `SharedObserver<ObserverBox<T, E>>`) allows for handing the same
+/// observer to several branches of a routing or fanout tree. Note that
+/// the mutex serializes the calls made through the clones: a call
+/// blocks while another one is in progress, even if made by another
+/// thread, so that the observer only ever sees one call at a time.
+/// Events of transactions delivered concurrently through different
+/// clones interleave, though.
+///
+/// Events are delivered even if the lock got poisoned by a thread
+/// panicking while holding it, leaving it to the observer to cope with
+/// an event interrupted midway.
 pub type SharedObserver<O> = Arc<Mutex<O>>;
 
 impl<O, T, E> Observer<T, E> for SharedObserver<O>
@@ -66,19 +194,43 @@ where
     E: Send,
 {
     fn on_start(&mut self) -> Result<(), E> {
-        self.lock().unwrap().on_start()
+        self.lock_unpoisoned().on_start()
     }
 
     fn on_commit(&mut self) -> Result<(), E> {
-        self.lock().unwrap().on_commit()
+        self.lock_unpoisoned().on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.lock_unpoisoned().on_commit_with_stats(stats)
     }
 
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
-        self.lock().unwrap().on_updates(updates)
+        self.lock_unpoisoned().on_updates(updates)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.lock_unpoisoned().on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.lock_unpoisoned().on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.lock_unpoisoned().on_flush()
     }
 
     fn on_completed(&mut self) -> Result<(), E> {
-        self.lock().unwrap().on_completed()
+        self.lock_unpoisoned().on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.lock_unpoisoned().on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        self.lock_unpoisoned().describe()
     }
 }
 
@@ -100,13 +252,39 @@ where
         self.as_mut().map_or(Ok(()), Observer::on_commit)
     }
 
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.as_mut()
+            .map_or(Ok(()), |o| o.on_commit_with_stats(stats))
+    }
+
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
         self.as_mut().map_or(Ok(()), |o| o.on_updates(updates))
     }
 
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), |o| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), Observer::on_abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), Observer::on_flush)
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.as_mut().map_or(Ok(()), Observer::on_completed)
     }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.as_mut().map_or(Ok(()), Observer::on_disconnected)
+    }
+
+    fn describe(&self) -> String {
+        self.as_ref()
+            .map_or_else(|| "none".to_string(), |o| o.describe())
+    }
 }
 
 #[cfg(test)]
@@ -114,6 +292,8 @@ mod tests {
     use super::*;
 
     use crate::observe::test::MockObserver;
+    use crate::observe::MapObserver;
+    use crate::observe::TakeObserver;
 
     /// Test the workings of an `OptionalObserver` with no actual
     /// observer present.
@@ -142,4 +322,46 @@ mod tests {
         assert_eq!(observer.on_commit(), Ok(()));
         assert_eq!(mock.lock().unwrap().called_on_commit, 1);
     }
+
+    /// Check that a chain of combinators describes itself.
+    #[test]
+    fn describe_chain() {
+        let observer = TakeObserver::new(MockObserver::new(), 2);
+        let observer = MapObserver::new(observer, |x: u64| x + 1);
+        let observer = Some(Box::new(observer) as ObserverBox<u64, ()>);
+        assert_eq!(
+            Observer::<u64, ()>::describe(&observer),
+            "map -> take(2) -> opaque"
+        );
+        assert_eq!(
+            Observer::<u64, ()>::describe(&OptionalObserver::<MockObserver>::None),
+            "none"
+        );
+    }
+
+    /// Check that clones of a `SharedObserver` deliver to the same
+    /// boxed observer.
+    #[test]
+    fn shared_boxed_observer() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let boxed = Box::new(mock.clone()) as ObserverBox<_, ()>;
+        let shared = Arc::new(Mutex::new(boxed));
+        let mut first = shared.clone();
+        let mut second = shared;
+
+        let observer = &mut first as &mut dyn Observer<_, ()>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new([1, 3].iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let observer = &mut second as &mut dyn Observer<_, ()>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new([2].iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_updates, 3);
+        assert_eq!(mock.called_on_commit, 2);
+    }
 }
@@ -0,0 +1,221 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::observe::Clock;
+use crate::observe::Observer;
+use crate::observe::SystemClock;
+use crate::observe::TransactionStats;
+
+/// An `Observer` committing the transaction in progress on behalf of
+/// upstream once it has been open for a given interval, e.g., for
+/// sources emitting a continuous stream of updates without ever
+/// committing.
+///
+/// Updates are forwarded as they arrive. When updates arrive after the
+/// transaction has been open for at least the interval, a commit gets
+/// synthesized right after forwarding them. No timer is involved, so a
+/// transaction is only ever committed along with updates. Subsequent
+/// updates open a new transaction, which a real commit eventually
+/// completes; a real commit arriving right after a synthetic one thus
+/// results in an empty transaction. An abort only affects the updates
+/// forwarded since the last synthetic commit.
+pub struct PeriodicCommitObserver<O, C> {
+    /// The observer we forward events to.
+    observer: O,
+    /// The maximum time a transaction stays open while receiving
+    /// updates.
+    interval: Duration,
+    /// The clock used for tracking how long a transaction is open.
+    clock: C,
+    /// The point in time the transaction in progress downstream got
+    /// started at, if any.
+    started: Option<Instant>,
+    /// The number of commits synthesized.
+    synthetic: usize,
+}
+
+impl<O> PeriodicCommitObserver<O, SystemClock> {
+    /// Create a new `PeriodicCommitObserver` committing transactions
+    /// open for `interval` on behalf of upstream.
+    pub fn new(observer: O, interval: Duration) -> Self {
+        Self::with_clock(observer, interval, SystemClock)
+    }
+}
+
+impl<O, C> PeriodicCommitObserver<O, C>
+where
+    C: Clock,
+{
+    /// Create a new `PeriodicCommitObserver` using the provided clock.
+    pub fn with_clock(observer: O, interval: Duration, clock: C) -> Self {
+        Self {
+            observer,
+            interval,
+            clock,
+            started: None,
+            synthetic: 0,
+        }
+    }
+
+    /// Start a transaction downstream, unless one is in progress
+    /// already.
+    fn start<T, E>(&mut self) -> Result<(), E>
+    where
+        O: Observer<T, E>,
+        T: Send,
+        E: Send,
+    {
+        if self.started.is_none() {
+            self.observer.on_start()?;
+            self.started = Some(self.clock.now());
+        }
+        Ok(())
+    }
+}
+
+impl<O, C> PeriodicCommitObserver<O, C> {
+    /// Retrieve the number of commits synthesized.
+    pub fn synthetic_commits(&self) -> usize {
+        self.synthetic
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, C> Debug for PeriodicCommitObserver<O, C>
+where
+    O: Debug,
+    C: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("PeriodicCommitObserver")
+            .field("observer", &self.observer)
+            .field("interval", &self.interval)
+            .field("clock", &self.clock)
+            .field("started", &self.started)
+            .field("synthetic", &self.synthetic)
+            .finish()
+    }
+}
+
+impl<O, C, T, E> Observer<T, E> for PeriodicCommitObserver<O, C>
+where
+    O: Observer<T, E>,
+    C: Clock,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.start()?;
+        self.started = None;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.start()?;
+        self.started = None;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.start()?;
+        self.observer.on_updates(updates)?;
+
+        let now = self.clock.now();
+        match self.started {
+            Some(started) if now.duration_since(started) >= self.interval => {
+                self.started = None;
+                self.synthetic += 1;
+                self.observer.on_commit()
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        // Updates committed on behalf of upstream can't be taken back.
+        match self.started.take() {
+            Some(_) => self.observer.on_abort(),
+            None => Ok(()),
+        }
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "periodic_commit({:?}) -> {}",
+            self.interval,
+            self.observer.describe()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MockClock;
+
+    /// Check that commits are synthesized at the configured interval
+    /// for a stream that never commits, and that a real commit still
+    /// gets through.
+    #[test]
+    fn synthesize_commits() {
+        let clock = MockClock::new();
+        let mut periodic = PeriodicCommitObserver::with_clock(
+            UpdatesMockObserver::<u64>::new(),
+            Duration::from_secs(10),
+            clock.clone(),
+        );
+        let observer = &mut periodic as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        for i in 0..25 {
+            assert_eq!(observer.on_updates(Box::new(Some(i).into_iter())), Ok(()));
+            clock.advance(Duration::from_secs(1));
+        }
+        assert_eq!(periodic.synthetic_commits(), 2);
+        {
+            let mock = &periodic.observer;
+            assert_eq!(mock.called_on_start, 3);
+            assert_eq!(mock.called_on_commit, 2);
+            assert_eq!(mock.received_updates, (0..25).collect::<Vec<_>>());
+        }
+
+        // The commit of the transaction finally arriving completes the
+        // one we started.
+        let observer = &mut periodic as &mut dyn Observer<u64, ()>;
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(periodic.synthetic_commits(), 2);
+
+        let mock = periodic.into_inner();
+        assert_eq!(mock.called_on_start, 3);
+        assert_eq!(mock.called_on_commit, 3);
+    }
+}
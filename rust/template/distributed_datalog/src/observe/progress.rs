@@ -0,0 +1,177 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` keeping track of how many items of a batch an inner
+/// observer consumed before failing to process it.
+///
+/// An observer reporting an error from `on_updates` (or `on_snapshot`)
+/// may have pulled only part of the batch from the iterator, with the
+/// remainder being dropped unseen. For observers with side effects per
+/// item that leaves the batch half processed, without a record of where
+/// processing stopped. This observer counts the items pulled from each
+/// batch and, on error, passes the count along with the error to a
+/// user-provided function producing the error to report, e.g., one
+/// mentioning the progress made, so that callers can resume processing
+/// or report precise progress.
+pub struct ProgressObserver<O, F> {
+    /// The observer whose progress we track.
+    observer: O,
+    /// The function combining an error with the number of items
+    /// consumed.
+    f: F,
+}
+
+impl<O, F> ProgressObserver<O, F> {
+    /// Create a new `ProgressObserver` tracking the progress of
+    /// `observer` and amending its errors using `f`.
+    pub fn new(observer: O, f: F) -> Self {
+        Self { observer, f }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, F> Debug for ProgressObserver<O, F>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ProgressObserver")
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+impl<O, F, T, E> Observer<T, E> for ProgressObserver<O, F>
+where
+    O: Observer<T, E>,
+    F: Fn(E, usize) -> E + Send,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let mut consumed = 0;
+        let result = self
+            .observer
+            .on_updates(Box::new(updates.inspect(|_| consumed += 1)));
+        result.map_err(|e| (self.f)(e, consumed))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let mut consumed = 0;
+        let result = self
+            .observer
+            .on_snapshot(Box::new(items.inspect(|_| consumed += 1)));
+        result.map_err(|e| (self.f)(e, consumed))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("track_progress -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An observer failing after having consumed a given number of
+    /// items of a batch.
+    #[derive(Debug)]
+    struct FailingObserver {
+        /// The number of items to consume before failing.
+        fail_after: usize,
+        /// The items consumed.
+        consumed: Vec<u64>,
+    }
+
+    impl Observer<u64, String> for FailingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            for update in updates {
+                self.consumed.push(update);
+                if self.consumed.len() == self.fail_after {
+                    return Err("storage full".to_string());
+                }
+            }
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that the number of items consumed before an error is
+    /// reported along with it.
+    #[test]
+    fn report_progress() {
+        let failing = FailingObserver {
+            fail_after: 3,
+            consumed: Vec::new(),
+        };
+        let mut progress = ProgressObserver::new(failing, |e, consumed| {
+            format!("{} after consuming {} items", e, consumed)
+        });
+        let observer = &mut progress as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(0..10)),
+            Err("storage full after consuming 3 items".to_string())
+        );
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        // Counting starts afresh with every batch.
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(10..12)), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let failing = progress.into_inner();
+        assert_eq!(failing.consumed, vec![0, 1, 2, 10, 11]);
+    }
+}
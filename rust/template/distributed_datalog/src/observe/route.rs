@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use crate::observe::Observer;
+use crate::observe::ObserverBox;
+use crate::observe::OptionalObserver;
+use crate::observe::TransactionStats;
+
+/// An `Observer` dispatching each item to one of a set of downstream
+/// observers, as selected by a key derived from the item.
+///
+/// Every batch of updates, and likewise a snapshot, is split up by key
+/// and each downstream observer receives the items routed to it as a
+/// single batch, in the order they arrived in. Observers that no item was routed to do not
+/// see the batch at all. Items with a key for which no route exists go
+/// to the default observer, if any, and are dropped otherwise.
+///
+/// All other events are fanned out to all downstream observers, in key
+/// order followed by the default observer. An error reported by one of
+/// them does not prevent delivery to the others; the first error is
+/// reported once everybody was notified.
+pub struct RouteObserver<T, E, K, F> {
+    /// The function mapping an item to its key.
+    key: F,
+    /// The observers items are routed to, by key.
+    routes: BTreeMap<K, ObserverBox<T, E>>,
+    /// The observer receiving items for which no route exists.
+    default: OptionalObserver<ObserverBox<T, E>>,
+}
+
+impl<T, E, K, F> RouteObserver<T, E, K, F>
+where
+    K: Ord,
+    F: FnMut(&T) -> K,
+{
+    /// Create a new `RouteObserver` selecting the route for an item
+    /// based on the key returned by `key`.
+    pub fn new(key: F) -> Self {
+        Self {
+            key,
+            routes: BTreeMap::new(),
+            default: None,
+        }
+    }
+
+    /// Route items with the given key to `observer`, replacing any
+    /// previous route for that key.
+    pub fn route(mut self, key: K, observer: ObserverBox<T, E>) -> Self {
+        let _ = self.routes.insert(key, observer);
+        self
+    }
+
+    /// Route items with a key for which no route exists to `observer`.
+    pub fn default_route(mut self, observer: ObserverBox<T, E>) -> Self {
+        self.default = Some(observer);
+        self
+    }
+
+    /// Split up `items` by key and hand the items routed to each
+    /// downstream observer to `f` as a single batch, reporting the
+    /// first error, if any.
+    fn dispatch<'a, G>(
+        &mut self,
+        items: Box<dyn Iterator<Item = T> + 'a>,
+        mut f: G,
+    ) -> Result<(), E>
+    where
+        T: 'a,
+        G: FnMut(&mut ObserverBox<T, E>, Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E>,
+    {
+        let mut routed = BTreeMap::<K, Vec<T>>::new();
+        let mut unrouted = Vec::new();
+
+        for item in items {
+            let key = (self.key)(&item);
+            if self.routes.contains_key(&key) {
+                routed.entry(key).or_default().push(item);
+            } else if self.default.is_some() {
+                unrouted.push(item);
+            }
+        }
+
+        let mut result = Ok(());
+        for (key, items) in routed {
+            if let Some(observer) = self.routes.get_mut(&key) {
+                result = result.and(f(observer, Box::new(items.into_iter())));
+            }
+        }
+        if let Some(observer) = self.default.as_mut() {
+            if !unrouted.is_empty() {
+                result = result.and(f(observer, Box::new(unrouted.into_iter())));
+            }
+        }
+        result
+    }
+
+    /// Invoke `f` on all downstream observers, reporting the first
+    /// error, if any.
+    fn fan_out<G>(&mut self, f: G) -> Result<(), E>
+    where
+        G: FnMut(&mut ObserverBox<T, E>) -> Result<(), E>,
+    {
+        self.routes
+            .values_mut()
+            .chain(self.default.iter_mut())
+            .map(f)
+            .fold(Ok(()), Result::and)
+    }
+}
+
+impl<T, E, K, F> Debug for RouteObserver<T, E, K, F>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("RouteObserver")
+            .field("routes", &self.routes.keys().collect::<Vec<_>>())
+            .field("default", &self.default.is_some())
+            .finish()
+    }
+}
+
+impl<T, E, K, F> Observer<T, E> for RouteObserver<T, E, K, F>
+where
+    T: Send,
+    E: Send,
+    K: Debug + Ord + Send,
+    F: FnMut(&T) -> K + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_start())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_commit())
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.fan_out(|o| o.on_commit_with_stats(stats))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.dispatch(updates, |o, updates| o.on_updates(updates))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.dispatch(items, |o, items| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_abort())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_flush())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_completed())
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_disconnected())
+    }
+
+    fn describe(&self) -> String {
+        let mut routes = self
+            .routes
+            .iter()
+            .map(|(key, observer)| format!("{:?}: {}", key, observer.describe()))
+            .collect::<Vec<_>>();
+        if let Some(observer) = &self.default {
+            routes.push(format!("_: {}", observer.describe()));
+        }
+        format!("route({})", routes.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that items are routed by key, preserving their order, and
+    /// that other events reach all downstream observers.
+    #[test]
+    fn route_by_key() {
+        let even = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let odd = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let other = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+
+        let mut route = RouteObserver::new(|x: &u64| if *x >= 10 { 2 } else { x % 2 })
+            .route(0, Box::new(even.clone()))
+            .route(1, Box::new(odd.clone()))
+            .default_route(Box::new(other.clone()));
+        let observer = &mut route as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let updates = vec![5, 2, 11, 3, 4, 12, 1];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![7].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let even = even.lock().unwrap();
+        assert_eq!(even.received_updates, vec![2, 4]);
+        assert_eq!(even.called_on_start, 1);
+        assert_eq!(even.called_on_commit, 1);
+
+        let odd = odd.lock().unwrap();
+        assert_eq!(odd.received_updates, vec![5, 3, 1, 7]);
+        assert_eq!(odd.called_on_commit, 1);
+
+        let other = other.lock().unwrap();
+        assert_eq!(other.received_updates, vec![11, 12]);
+        assert_eq!(other.called_on_commit, 1);
+    }
+}
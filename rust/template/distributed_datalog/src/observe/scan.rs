@@ -0,0 +1,208 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::mem::replace;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` maintaining an accumulator state across the items it
+/// sees, emitting the result of folding each item into that state to
+/// an inner observer.
+///
+/// This is the observer analog of `Iterator::scan`.
+pub struct ScanObserver<O, S, F> {
+    /// The observer we emit folded items to.
+    observer: O,
+    /// The state we started out with; used when resetting.
+    init: S,
+    /// The current accumulator state.
+    state: S,
+    /// The function folding an item into the state.
+    f: F,
+    /// Whether to reset the state to `init` on every `on_start`.
+    reset_on_start: bool,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// the next `on_start` continues it rather than starting afresh.
+    flushed: bool,
+}
+
+impl<O, S, F> ScanObserver<O, S, F>
+where
+    S: Clone,
+{
+    /// Create a new `ScanObserver` wrapping the provided observer,
+    /// starting out with `init` as its state.
+    ///
+    /// If `reset_on_start` is set, the state is reset to `init` at the
+    /// start of every transaction, but not when continuing one that got
+    /// flushed. Otherwise it is carried over.
+    pub fn new<T, U>(observer: O, init: S, f: F, reset_on_start: bool) -> Self
+    where
+        F: FnMut(&mut S, T) -> U,
+    {
+        Self {
+            observer,
+            state: init.clone(),
+            init,
+            f,
+            reset_on_start,
+            flushed: false,
+        }
+    }
+
+    /// Retrieve a copy of the current accumulator state.
+    pub fn state(&self) -> S {
+        self.state.clone()
+    }
+}
+
+impl<O, S, F> Debug for ScanObserver<O, S, F>
+where
+    O: Debug,
+    S: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("ScanObserver")
+            .field("observer", &self.observer)
+            .field("state", &self.state)
+            .field("reset_on_start", &self.reset_on_start)
+            .field("flushed", &self.flushed)
+            .finish()
+    }
+}
+
+impl<O, S, F, T, U, E> Observer<T, E> for ScanObserver<O, S, F>
+where
+    O: Observer<U, E>,
+    S: Clone + Debug + Send,
+    F: FnMut(&mut S, T) -> U + Send,
+    T: Send,
+    U: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if self.reset_on_start && !replace(&mut self.flushed, false) {
+            self.state = self.init.clone();
+        }
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let Self {
+            observer, state, f, ..
+        } = self;
+        observer.on_updates(Box::new(updates.map(move |t| f(state, t))))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let Self {
+            observer, state, f, ..
+        } = self;
+        observer.on_snapshot(Box::new(items.map(move |t| f(state, t))))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.flushed = false;
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.flushed = true;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("scan -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Maintain a running count of inserted and deleted items.
+    #[test]
+    fn running_count() {
+        let mock = UpdatesMockObserver::<(usize, usize)>::new();
+        let f = |count: &mut (usize, usize), insert| {
+            if insert {
+                count.0 += 1
+            } else {
+                count.1 += 1
+            }
+            *count
+        };
+        let mut scan = ScanObserver::new(mock, (0, 0), f, false);
+        let observer = &mut scan as &mut dyn Observer<_, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![true, true, false].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![false].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(scan.state(), (2, 2));
+        assert_eq!(
+            scan.observer.received_updates,
+            vec![(1, 0), (2, 0), (2, 1), (2, 2)]
+        );
+    }
+
+    /// Check that the state is reset on `on_start` if so desired.
+    #[test]
+    fn reset_on_start() {
+        let mock = UpdatesMockObserver::<u64>::new();
+        let mut scan = ScanObserver::new(
+            mock,
+            0u64,
+            |sum, x| {
+                *sum += x;
+                *sum
+            },
+            true,
+        );
+        let observer = &mut scan as &mut dyn Observer<_, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1u64, 2, 3].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![4u64].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(scan.state(), 4);
+        assert_eq!(scan.observer.received_updates, vec![1, 3, 6, 4]);
+    }
+}
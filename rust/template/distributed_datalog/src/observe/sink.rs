@@ -0,0 +1,257 @@
+use std::fmt::Debug;
+use std::mem::replace;
+
+use crate::observe::Observer;
+
+/// A transactional store, e.g., a SQL database, that the transactions
+/// received by a `TransactionalSinkObserver` get mirrored into.
+pub trait TransactionSink<T, E>: Debug + Send {
+    /// Begin a transaction.
+    fn begin(&mut self) -> Result<(), E>;
+
+    /// Apply `items` as part of the transaction in progress.
+    fn apply(&mut self, items: &[T]) -> Result<(), E>;
+
+    /// Commit the transaction in progress.
+    fn commit(&mut self) -> Result<(), E>;
+
+    /// Roll back the transaction in progress.
+    fn rollback(&mut self) -> Result<(), E>;
+}
+
+/// An `Observer` mirroring the transactions it receives into a
+/// `TransactionSink`, atomically.
+///
+/// The items of a transaction are buffered until its commit, and only
+/// then applied to the sink, as part of a single transaction of the
+/// sink's own, with the snapshot (if any) ahead of the updates. If that
+/// fails, the sink's transaction is rolled back and
+/// the error reported. An aborted transaction is discarded without ever
+/// touching the sink, as is one without any items. A transaction that
+/// got flushed is held on to and continued by the next one, so that it
+/// reaches the sink in one piece.
+#[derive(Debug)]
+pub struct TransactionalSinkObserver<T, S> {
+    /// The sink we apply transactions to.
+    sink: S,
+    /// The items of the transaction in progress.
+    items: Vec<T>,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// the next `on_start` continues it.
+    flushed: bool,
+}
+
+impl<T, S> TransactionalSinkObserver<T, S> {
+    /// Create a new `TransactionalSinkObserver` applying transactions
+    /// to `sink`.
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            items: Vec::new(),
+            flushed: false,
+        }
+    }
+
+    /// Retrieve the wrapped sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    /// Apply `items` to `sink` as a transaction.
+    fn apply<E>(sink: &mut S, items: &[T]) -> Result<(), E>
+    where
+        S: TransactionSink<T, E>,
+    {
+        sink.begin()?;
+        match sink.apply(items).and_then(|_| sink.commit()) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                // The original error is the one worth reporting.
+                let _ = sink.rollback();
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<T, S, E> Observer<T, E> for TransactionalSinkObserver<T, S>
+where
+    S: TransactionSink<T, E>,
+    T: Debug + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if !replace(&mut self.flushed, false) {
+            self.items.clear();
+        }
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        if self.items.is_empty() {
+            return Ok(());
+        }
+        let result = Self::apply(&mut self.sink, &self.items);
+        self.items.clear();
+        result
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.items.extend(updates);
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.items.extend(items);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.items.clear();
+        self.flushed = false;
+        Ok(())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.flushed = true;
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        // A transaction that got flushed is never going to be continued.
+        self.items.clear();
+        self.flushed = false;
+        Ok(())
+    }
+
+    fn describe(&self) -> String {
+        "transactional_sink".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sink recording the calls made to it.
+    #[derive(Debug, Default)]
+    struct MockSink {
+        /// The calls made, in order.
+        calls: Vec<String>,
+        /// The items committed.
+        committed: Vec<u64>,
+        /// The items of the transaction in progress.
+        pending: Vec<u64>,
+        /// Whether to fail the next commit.
+        fail: bool,
+    }
+
+    impl TransactionSink<u64, String> for MockSink {
+        fn begin(&mut self) -> Result<(), String> {
+            self.calls.push("begin".to_string());
+            Ok(())
+        }
+
+        fn apply(&mut self, items: &[u64]) -> Result<(), String> {
+            self.calls.push(format!("apply {:?}", items));
+            self.pending.extend_from_slice(items);
+            Ok(())
+        }
+
+        fn commit(&mut self) -> Result<(), String> {
+            self.calls.push("commit".to_string());
+            if self.fail {
+                self.fail = false;
+                Err("constraint violated".to_string())
+            } else {
+                self.committed.append(&mut self.pending);
+                Ok(())
+            }
+        }
+
+        fn rollback(&mut self) -> Result<(), String> {
+            self.calls.push("rollback".to_string());
+            self.pending.clear();
+            Ok(())
+        }
+    }
+
+    /// Check that committed transactions are applied to the sink as a
+    /// whole, while aborted ones are discarded.
+    #[test]
+    fn apply_transactions() {
+        let mut sink = TransactionalSinkObserver::new(MockSink::default());
+        let observer = &mut sink as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![5].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let sink = sink.into_inner();
+        assert_eq!(
+            sink.calls,
+            vec![
+                "begin",
+                "apply [1, 2, 3]",
+                "commit",
+                "begin",
+                "apply [5]",
+                "commit"
+            ]
+        );
+        assert_eq!(sink.committed, vec![1, 2, 3, 5]);
+    }
+
+    /// Check that a transaction failing to commit is rolled back.
+    #[test]
+    fn rollback() {
+        let mut sink = TransactionalSinkObserver::new(MockSink {
+            fail: true,
+            ..Default::default()
+        });
+        let observer = &mut sink as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![1].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Err("constraint violated".to_string()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![2].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let sink = sink.into_inner();
+        assert_eq!(
+            sink.calls,
+            vec![
+                "begin",
+                "apply [1]",
+                "commit",
+                "rollback",
+                "begin",
+                "apply [2]",
+                "commit"
+            ]
+        );
+        assert_eq!(sink.committed, vec![2]);
+    }
+}
@@ -0,0 +1,221 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` reordering the items of a transaction according to a
+/// user-provided comparator.
+///
+/// All items of a transaction are buffered and only emitted, sorted
+/// and as a single batch, when the transaction is committed (or
+/// flushed). Because it necessarily holds on to the entire
+/// transaction, this observer is inappropriate for unbounded streams
+/// without regular commits. Sorting is stable, so items comparing equal
+/// keep their order of arrival.
+///
+/// Alternatively, an observer created via `per_batch` sorts each batch
+/// of updates on its own and forwards it right away. That only
+/// guarantees order within a batch, but memory usage is bounded by the
+/// size of the largest batch (which still is fully collected, as
+/// opposed to being streamed through).
+pub struct SortObserver<O, T, F> {
+    /// The observer we emit sorted items to.
+    observer: O,
+    /// The function comparing two items.
+    compare: F,
+    /// The items of the current transaction.
+    items: Vec<T>,
+    /// Whether to sort each batch of updates on its own instead of the
+    /// transaction as a whole.
+    per_batch: bool,
+}
+
+impl<O, T, F> SortObserver<O, T, F> {
+    /// Create a new `SortObserver` ordering items using `compare`.
+    pub fn new(observer: O, compare: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        Self {
+            observer,
+            compare,
+            items: Vec::new(),
+            per_batch: false,
+        }
+    }
+
+    /// Create a new `SortObserver` ordering the items of each batch of
+    /// updates using `compare` and forwarding it immediately.
+    pub fn per_batch(observer: O, compare: F) -> Self
+    where
+        F: FnMut(&T, &T) -> Ordering,
+    {
+        Self {
+            per_batch: true,
+            ..Self::new(observer, compare)
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, T, F> SortObserver<O, T, F>
+where
+    F: FnMut(&T, &T) -> Ordering,
+{
+    /// Sort the buffered items and emit them to the inner observer.
+    fn emit<E>(&mut self) -> Result<(), E>
+    where
+        O: Observer<T, E>,
+        T: Send,
+        E: Send,
+    {
+        if self.items.is_empty() {
+            return Ok(());
+        }
+
+        let mut items = self.items.split_off(0);
+        items.sort_by(&mut self.compare);
+        self.observer.on_updates(Box::new(items.into_iter()))
+    }
+}
+
+impl<O, T, F> Debug for SortObserver<O, T, F>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("SortObserver")
+            .field("observer", &self.observer)
+            .field("items", &self.items.len())
+            .field("per_batch", &self.per_batch)
+            .finish()
+    }
+}
+
+impl<O, T, F, E> Observer<T, E> for SortObserver<O, T, F>
+where
+    O: Observer<T, E>,
+    F: FnMut(&T, &T) -> Ordering + Send,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.items.clear();
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.emit()?;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.emit()?;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.items.extend(updates);
+        if self.per_batch {
+            self.emit()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        // A snapshot precedes all updates of a transaction, so it can
+        // be sorted and forwarded on its own right away.
+        let mut items = items.collect::<Vec<_>>();
+        items.sort_by(&mut self.compare);
+        self.observer.on_snapshot(Box::new(items.into_iter()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.items.clear();
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.emit()?;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("sort -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that the items of a transaction are delivered sorted on
+    /// commit, while those of an aborted one are dropped.
+    #[test]
+    fn sort_on_commit() {
+        let mock = UpdatesMockObserver::<(u64, char)>::new();
+        let mut sort = SortObserver::new(mock, |x: &(u64, char), y: &(u64, char)| x.0.cmp(&y.0));
+        let observer = &mut sort as &mut dyn Observer<(u64, char), ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        let updates = vec![(3, 'a'), (1, 'b'), (2, 'c')];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        let updates = vec![(1, 'd'), (0, 'e')];
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![(0, 'f')].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        let mock = sort.into_inner();
+        assert_eq!(
+            mock.received_updates,
+            vec![(0, 'e'), (1, 'b'), (1, 'd'), (2, 'c'), (3, 'a')]
+        );
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Check that an observer sorting per batch forwards each batch
+    /// sorted, as it arrives.
+    #[test]
+    fn sort_per_batch() {
+        let mock = UpdatesMockObserver::<u64>::new();
+        let mut sort = SortObserver::per_batch(mock, |x: &u64, y: &u64| x.cmp(y));
+        let observer = &mut sort as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![3, 1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![0].into_iter())), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = sort.into_inner();
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 0]);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// An `Observer` forwarding at most a given number of items to an
+/// inner observer, signaling completion once the limit is reached.
+///
+/// The batch crossing the limit is truncated and all items after it
+/// are dropped. If the limit is reached in the middle of a transaction,
+/// the transaction is still forwarded until its end, i.e., its commit
+/// (or abort) reaches the inner observer, and completion is signaled
+/// right after. Subsequent events are ignored, as is an `on_completed`
+/// from upstream after we signaled completion already.
+#[derive(Debug)]
+pub struct TakeObserver<O> {
+    /// The observer we forward items to.
+    observer: O,
+    /// The number of items we may still forward.
+    remaining: usize,
+    /// Whether completion has been signaled to the inner observer.
+    completed: bool,
+}
+
+impl<O> TakeObserver<O> {
+    /// Create a new `TakeObserver` forwarding at most `count` items to
+    /// `observer`.
+    pub fn new(observer: O, count: usize) -> Self {
+        Self {
+            observer,
+            remaining: count,
+            completed: false,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, T, E> Observer<T, E> for TakeObserver<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if self.completed {
+            Ok(())
+        } else if self.remaining == 0 {
+            self.on_completed()
+        } else {
+            self.observer.on_start()
+        }
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        if self.completed {
+            return Ok(());
+        }
+
+        self.observer.on_commit()?;
+        if self.remaining == 0 {
+            self.on_completed()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        if self.completed {
+            return Ok(());
+        }
+
+        self.observer.on_commit_with_stats(stats)?;
+        if self.remaining == 0 {
+            self.on_completed()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        if self.completed || self.remaining == 0 {
+            return Ok(());
+        }
+
+        let remaining = &mut self.remaining;
+        let updates = updates.take(*remaining).inspect(move |_| *remaining -= 1);
+        self.observer.on_updates(Box::new(updates))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        if self.completed || self.remaining == 0 {
+            return Ok(());
+        }
+
+        let remaining = &mut self.remaining;
+        let items = items.take(*remaining).inspect(move |_| *remaining -= 1);
+        self.observer.on_snapshot(Box::new(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        if self.completed {
+            return Ok(());
+        }
+
+        self.observer.on_abort()?;
+        if self.remaining == 0 {
+            self.on_completed()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        // Completion is only signaled once the transaction carrying the
+        // last item got committed.
+        if self.completed {
+            Ok(())
+        } else {
+            self.observer.on_flush()
+        }
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        if self.completed {
+            Ok(())
+        } else {
+            self.completed = true;
+            self.observer.on_completed()
+        }
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        if self.completed {
+            Ok(())
+        } else {
+            self.completed = true;
+            self.observer.on_disconnected()
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("take({}) -> {}", self.remaining, self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Check that only the requested number of items is forwarded and
+    /// that completion is signaled once.
+    #[test]
+    fn take_across_batches() {
+        let mut take = TakeObserver::new(UpdatesMockObserver::<u64>::new(), 4);
+        let observer = &mut take as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2, 3].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![4, 5].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![6].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![7].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let mock = take.into_inner();
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 4]);
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+}
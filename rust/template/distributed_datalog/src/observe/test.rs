@@ -1,5 +1,11 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
 use log::trace;
 
+use crate::observe::Clock;
 use crate::Observer;
 
 /// A dummy observer merely counting method invocations.
@@ -11,8 +17,15 @@ pub struct MockObserver {
     pub called_on_commit: usize,
     /// The number of updates the observer has received.
     pub called_on_updates: usize,
+    /// The number of `on_abort` calls the observer has seen.
+    pub called_on_abort: usize,
+    /// The number of `on_flush` calls the observer has seen.
+    pub called_on_flush: usize,
     /// The number of `on_completed` calls the observer has seen.
     pub called_on_completed: usize,
+    /// The number of `on_disconnected` calls the observer has seen.
+    /// Each is treated as completion as well.
+    pub called_on_disconnected: usize,
 }
 
 impl MockObserver {
@@ -22,7 +35,10 @@ impl MockObserver {
             called_on_start: 0,
             called_on_commit: 0,
             called_on_updates: 0,
+            called_on_abort: 0,
+            called_on_flush: 0,
             called_on_completed: 0,
+            called_on_disconnected: 0,
         }
     }
 }
@@ -50,9 +66,85 @@ where
         Ok(())
     }
 
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("MockObserver::on_abort");
+        self.called_on_abort += 1;
+        Ok(())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        trace!("MockObserver::on_flush");
+        self.called_on_flush += 1;
+        Ok(())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("MockObserver::on_completed");
         self.called_on_completed += 1;
         Ok(())
     }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        trace!("MockObserver::on_disconnected");
+        self.called_on_disconnected += 1;
+        Observer::<T, E>::on_completed(self)
+    }
+}
+
+/// An observer discarding everything it receives, e.g., for
+/// benchmarking the cost of producing updates without that of
+/// consuming them. Updates are still drained, so that lazily produced
+/// ones are accounted for. Use `MockObserver` for counting events.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NullObserver;
+
+impl<T, E> Observer<T, E> for NullObserver
+where
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        updates.for_each(drop);
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// A `Clock` that only advances when told to.
+#[derive(Clone, Debug)]
+pub struct MockClock(Arc<Mutex<Instant>>);
+
+impl MockClock {
+    /// Create a new `MockClock` starting at the current time.
+    pub fn new() -> Self {
+        Self(Arc::new(Mutex::new(Instant::now())))
+    }
+
+    /// Advance the clock by the given duration.
+    pub fn advance(&self, duration: Duration) {
+        *self.0.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.0.lock().unwrap()
+    }
 }
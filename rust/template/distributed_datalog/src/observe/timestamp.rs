@@ -0,0 +1,205 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::time::Instant;
+
+use crate::observe::Clock;
+use crate::observe::Observer;
+use crate::observe::SystemClock;
+use crate::observe::TransactionStats;
+
+/// Pair up an item with its timestamp.
+fn pair<T>(time: Instant, item: T) -> (Instant, T) {
+    (time, item)
+}
+
+/// An `Observer` attaching arrival timestamps to the items passing
+/// through it.
+///
+/// All items of a batch share the same timestamp, taken when the batch
+/// arrives. Timestamps never go backwards, even if the clock does.
+pub struct TimestampObserver<O, C, F> {
+    /// The observer we forward timestamped items to.
+    observer: O,
+    /// The clock used for timestamping items.
+    clock: C,
+    /// The function combining an item with its timestamp.
+    f: F,
+    /// The most recent timestamp handed out.
+    last: Option<Instant>,
+}
+
+impl<O, T> TimestampObserver<O, SystemClock, fn(Instant, T) -> (Instant, T)> {
+    /// Create a new `TimestampObserver` forwarding each item paired up
+    /// with its arrival time.
+    pub fn new(observer: O) -> Self {
+        Self::with_clock(observer, SystemClock)
+    }
+}
+
+impl<O, C, T> TimestampObserver<O, C, fn(Instant, T) -> (Instant, T)> {
+    /// Create a new `TimestampObserver` using the provided clock.
+    pub fn with_clock(observer: O, clock: C) -> Self {
+        Self::with_fn(observer, clock, pair)
+    }
+}
+
+impl<O, C, F> TimestampObserver<O, C, F> {
+    /// Create a new `TimestampObserver` using the provided clock and
+    /// combining items with their timestamp using `f`.
+    pub fn with_fn<T, U>(observer: O, clock: C, f: F) -> Self
+    where
+        F: FnMut(Instant, T) -> U,
+    {
+        Self {
+            observer,
+            clock,
+            f,
+            last: None,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, C, F> Debug for TimestampObserver<O, C, F>
+where
+    O: Debug,
+    C: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TimestampObserver")
+            .field("observer", &self.observer)
+            .field("clock", &self.clock)
+            .field("last", &self.last)
+            .finish()
+    }
+}
+
+impl<O, C, F, T, U, E> Observer<T, E> for TimestampObserver<O, C, F>
+where
+    O: Observer<U, E>,
+    C: Clock,
+    F: FnMut(Instant, T) -> U + Send,
+    T: Send,
+    U: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let now = self.clock.now();
+        let now = self.last.map_or(now, |last| last.max(now));
+        self.last = Some(now);
+
+        let f = &mut self.f;
+        self.observer
+            .on_updates(Box::new(updates.map(|item| f(now, item))))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let now = self.clock.now();
+        let now = self.last.map_or(now, |last| last.max(now));
+        self.last = Some(now);
+
+        let f = &mut self.f;
+        self.observer
+            .on_snapshot(Box::new(items.map(|item| f(now, item))))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("timestamp -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MockClock;
+
+    /// Check that items are paired up with the time of arrival of
+    /// their batch.
+    #[test]
+    fn timestamp_updates() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mock = UpdatesMockObserver::<(Instant, u64)>::new();
+        let mut timestamp = TimestampObserver::with_clock(mock, clock.clone());
+        let observer = &mut timestamp as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = timestamp.into_inner();
+        let second = start + Duration::from_secs(1);
+        let third = start + Duration::from_secs(3);
+        assert_eq!(
+            mock.received_updates,
+            vec![(start, 1), (start, 2), (second, 3), (third, 4)]
+        );
+        assert!(mock
+            .received_updates
+            .windows(2)
+            .all(|pair| pair[0].0 <= pair[1].0));
+    }
+
+    /// Check that a user-provided function combines items with their
+    /// timestamps.
+    #[test]
+    fn timestamp_with_fn() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let mock = UpdatesMockObserver::<u64>::new();
+        let f = move |time: Instant, item: u64| time.duration_since(start).as_secs() * 10 + item;
+        let mut timestamp = TimestampObserver::with_fn(mock, clock.clone(), f);
+        let observer = &mut timestamp as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_updates(Box::new(vec![1].into_iter())), Ok(()));
+        clock.advance(Duration::from_secs(4));
+        assert_eq!(observer.on_updates(Box::new(vec![2].into_iter())), Ok(()));
+
+        let mock = timestamp.into_inner();
+        assert_eq!(mock.received_updates, vec![1, 42]);
+    }
+}
@@ -0,0 +1,276 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::time::Duration;
+
+use crate::observe::Clock;
+use crate::observe::Observer;
+use crate::observe::SystemClock;
+use crate::observe::TransactionStats;
+
+/// Statistics about the time spent in one kind of callback.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CallTimings {
+    /// The number of calls measured.
+    pub count: u64,
+    /// The total time spent in all calls.
+    pub total: Duration,
+    /// The shortest time spent in a single call.
+    pub min: Duration,
+    /// The longest time spent in a single call.
+    pub max: Duration,
+}
+
+impl CallTimings {
+    /// Account for a call that took `duration`.
+    fn record(&mut self, duration: Duration) {
+        self.min = if self.count == 0 {
+            duration
+        } else {
+            self.min.min(duration)
+        };
+        self.max = self.max.max(duration);
+        self.total += duration;
+        self.count += 1;
+    }
+
+    /// Retrieve the mean time spent in a call, if any call was
+    /// measured.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            let nanos = self.total.as_nanos() / u128::from(self.count);
+            Some(Duration::from_nanos(nanos as u64))
+        }
+    }
+}
+
+/// The time an observer spent in each of its callbacks, as measured by
+/// a `TimingObserver`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timings {
+    /// The time spent in `on_start`.
+    pub on_start: CallTimings,
+    /// The time spent in `on_updates`.
+    pub on_updates: CallTimings,
+    /// The time spent in `on_snapshot`.
+    pub on_snapshot: CallTimings,
+    /// The time spent in `on_commit`.
+    pub on_commit: CallTimings,
+    /// The time spent in `on_abort`.
+    pub on_abort: CallTimings,
+    /// The time spent in `on_flush`.
+    pub on_flush: CallTimings,
+    /// The time spent in `on_completed`.
+    pub on_completed: CallTimings,
+    /// The time spent in `on_disconnected`.
+    pub on_disconnected: CallTimings,
+}
+
+/// An `Observer` measuring how long the observer it wraps spends in
+/// each of its callbacks, e.g., for finding out where the time goes in
+/// a pipeline of observers.
+///
+/// Note that updates are passed on lazily, so the time measured for
+/// `on_updates` includes that spent producing them upstream. Timing
+/// can be disabled, in which case events are merely forwarded, without
+/// even querying the clock.
+pub struct TimingObserver<O, C> {
+    /// The observer we measure.
+    observer: O,
+    /// The clock used for measuring durations.
+    clock: C,
+    /// Whether timing is enabled.
+    enabled: bool,
+    /// The timings recorded so far.
+    timings: Timings,
+}
+
+impl<O> TimingObserver<O, SystemClock> {
+    /// Create a new `TimingObserver` measuring `observer`.
+    pub fn new(observer: O) -> Self {
+        Self::with_clock(observer, SystemClock)
+    }
+}
+
+impl<O, C> TimingObserver<O, C> {
+    /// Create a new `TimingObserver` using the provided clock.
+    pub fn with_clock(observer: O, clock: C) -> Self {
+        Self {
+            observer,
+            clock,
+            enabled: true,
+            timings: Timings::default(),
+        }
+    }
+
+    /// Enable or disable timing. Timings recorded so far are retained.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Retrieve the timings recorded so far.
+    pub fn timings(&self) -> Timings {
+        self.timings
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, C> TimingObserver<O, C>
+where
+    C: Clock,
+{
+    /// Invoke `f` on the wrapped observer, accounting for the time it
+    /// took in the timings selected by `select`.
+    fn time<F, R>(&mut self, select: fn(&mut Timings) -> &mut CallTimings, f: F) -> R
+    where
+        F: FnOnce(&mut O) -> R,
+    {
+        if !self.enabled {
+            return f(&mut self.observer);
+        }
+
+        let start = self.clock.now();
+        let result = f(&mut self.observer);
+        let duration = self.clock.now().saturating_duration_since(start);
+        select(&mut self.timings).record(duration);
+        result
+    }
+}
+
+impl<O, C> Debug for TimingObserver<O, C>
+where
+    O: Debug,
+    C: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TimingObserver")
+            .field("observer", &self.observer)
+            .field("clock", &self.clock)
+            .field("enabled", &self.enabled)
+            .field("timings", &self.timings)
+            .finish()
+    }
+}
+
+impl<O, C, T, E> Observer<T, E> for TimingObserver<O, C>
+where
+    O: Observer<T, E>,
+    C: Clock,
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.time(|t| &mut t.on_start, |o| o.on_start())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.time(|t| &mut t.on_commit, |o| o.on_commit())
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.time(|t| &mut t.on_commit, |o| o.on_commit_with_stats(stats))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.time(|t| &mut t.on_updates, |o| o.on_updates(updates))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.time(|t| &mut t.on_snapshot, |o| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.time(|t| &mut t.on_abort, |o| o.on_abort())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.time(|t| &mut t.on_flush, |o| o.on_flush())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.time(|t| &mut t.on_completed, |o| o.on_completed())
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.time(|t| &mut t.on_disconnected, |o| o.on_disconnected())
+    }
+
+    fn describe(&self) -> String {
+        format!("timing -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::observe::MockClock;
+
+    /// An observer taking one second per update and two per commit, as
+    /// far as the clock it advances is concerned.
+    #[derive(Debug)]
+    struct SlowObserver(MockClock);
+
+    impl Observer<u64, ()> for SlowObserver {
+        fn on_start(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), ()> {
+            self.0.advance(Duration::from_secs(2));
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), ()> {
+            self.0.advance(Duration::from_secs(updates.count() as u64));
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    /// Check that the time spent in callbacks gets recorded, unless
+    /// timing is disabled.
+    #[test]
+    fn record_timings() {
+        let clock = MockClock::new();
+        let mut timing = TimingObserver::with_clock(SlowObserver(clock.clone()), clock);
+        let observer = &mut timing as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2, 3].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let timings = timing.timings();
+        assert_eq!(timings.on_start.count, 1);
+        assert_eq!(timings.on_start.max, Duration::from_secs(0));
+        assert_eq!(timings.on_updates.count, 2);
+        assert_eq!(timings.on_updates.min, Duration::from_secs(1));
+        assert_eq!(timings.on_updates.max, Duration::from_secs(3));
+        assert_eq!(timings.on_updates.mean(), Some(Duration::from_secs(2)));
+        assert_eq!(timings.on_commit.total, Duration::from_secs(2));
+        assert_eq!(timings.on_abort, CallTimings::default());
+        assert_eq!(timings.on_abort.mean(), None);
+
+        timing.set_enabled(false);
+        let observer = &mut timing as &mut dyn Observer<u64, ()>;
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(timing.timings(), timings);
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Debug;
+
+use log::error;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// The policy of a `TolerateErrorsObserver` for dealing with errors
+/// reported by its inner observer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorPolicy {
+    /// Log every error and continue.
+    Log,
+    /// Silently count errors and continue.
+    Count,
+    /// Log errors and continue, unless the given number of errors
+    /// occurred in a row, in which case errors are passed on until an
+    /// event got processed successfully again.
+    Escalate(usize),
+}
+
+/// An `Observer` tolerating errors of an inner observer, so that a
+/// single bad batch does not bring down the pipeline upstream.
+///
+/// Errors are handled according to an `ErrorPolicy`; errors that are
+/// tolerated are reported to upstream as success. All errors are
+/// counted, regardless of the policy.
+#[derive(Debug)]
+pub struct TolerateErrorsObserver<O> {
+    /// The observer whose errors we tolerate.
+    observer: O,
+    /// How to deal with errors.
+    policy: ErrorPolicy,
+    /// The total number of errors reported by the observer.
+    errors: usize,
+    /// The number of errors reported by the observer in a row.
+    consecutive: usize,
+}
+
+impl<O> TolerateErrorsObserver<O> {
+    /// Create a new `TolerateErrorsObserver` wrapping `observer` and
+    /// dealing with its errors according to `policy`.
+    pub fn new(observer: O, policy: ErrorPolicy) -> Self {
+        Self {
+            observer,
+            policy,
+            errors: 0,
+            consecutive: 0,
+        }
+    }
+
+    /// Retrieve the total number of errors the inner observer
+    /// reported.
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Invoke `f` on the inner observer, handling errors according to
+    /// our policy.
+    fn tolerate<F, E>(&mut self, event: &str, f: F) -> Result<(), E>
+    where
+        F: FnOnce(&mut O) -> Result<(), E>,
+        E: Debug,
+    {
+        match f(&mut self.observer) {
+            Ok(()) => {
+                self.consecutive = 0;
+                Ok(())
+            }
+            Err(e) => {
+                self.errors += 1;
+                self.consecutive += 1;
+
+                match self.policy {
+                    ErrorPolicy::Count => Ok(()),
+                    ErrorPolicy::Escalate(max) if self.consecutive >= max => Err(e),
+                    ErrorPolicy::Log | ErrorPolicy::Escalate(_) => {
+                        error!("observer failed to process {} event: {:?}", event, e);
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<O, T, E> Observer<T, E> for TolerateErrorsObserver<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.tolerate("on_start", Observer::on_start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.tolerate("on_commit", Observer::on_commit)
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.tolerate("on_commit", |o| o.on_commit_with_stats(stats))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.tolerate("on_updates", |o| o.on_updates(updates))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.tolerate("on_snapshot", |o| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.tolerate("on_abort", Observer::on_abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.tolerate("on_flush", Observer::on_flush)
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.tolerate("on_completed", Observer::on_completed)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.tolerate("on_disconnected", Observer::on_disconnected)
+    }
+
+    fn describe(&self) -> String {
+        format!("tolerate_errors -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An observer failing to process updates containing zeros.
+    #[derive(Debug, Default)]
+    struct ZeroObserver;
+
+    impl Observer<u64, String> for ZeroObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            mut updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            if updates.any(|update| update == 0) {
+                Err("zero".to_string())
+            } else {
+                Ok(())
+            }
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that errors are tolerated and counted.
+    #[test]
+    fn tolerate_errors() {
+        for policy in [ErrorPolicy::Log, ErrorPolicy::Count].iter() {
+            let mut tolerate = TolerateErrorsObserver::new(ZeroObserver, *policy);
+            let observer = &mut tolerate as &mut dyn Observer<u64, String>;
+
+            assert_eq!(observer.on_start(), Ok(()));
+            assert_eq!(observer.on_updates(Box::new(vec![0].into_iter())), Ok(()));
+            assert_eq!(observer.on_updates(Box::new(vec![1].into_iter())), Ok(()));
+            assert_eq!(observer.on_updates(Box::new(vec![0].into_iter())), Ok(()));
+            assert_eq!(observer.on_commit(), Ok(()));
+            assert_eq!(tolerate.errors(), 2);
+        }
+    }
+
+    /// Check that errors are escalated once too many of them occurred
+    /// in a row.
+    #[test]
+    fn escalate_errors() {
+        let mut tolerate = TolerateErrorsObserver::new(ZeroObserver, ErrorPolicy::Escalate(2));
+        let observer = &mut tolerate as &mut dyn Observer<u64, String>;
+        let zero = || Box::new(vec![0].into_iter());
+
+        assert_eq!(observer.on_updates(zero()), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![1].into_iter())), Ok(()));
+        assert_eq!(observer.on_updates(zero()), Ok(()));
+        assert_eq!(observer.on_updates(zero()), Err("zero".to_string()));
+        assert_eq!(observer.on_updates(zero()), Err("zero".to_string()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_updates(zero()), Ok(()));
+        assert_eq!(tolerate.errors(), 5);
+    }
+}
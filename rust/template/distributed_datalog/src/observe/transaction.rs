@@ -0,0 +1,171 @@
+use std::fmt::Debug;
+use std::mem::replace;
+use std::mem::take;
+
+use crate::observe::Observer;
+
+/// A consumer of whole transactions, as a simpler alternative to
+/// implementing `Observer` directly. Use a `TransactionBufferObserver`
+/// to subscribe it to an `Observable`.
+pub trait TransactionObserver<T, E>: Debug + Send {
+    /// Consume the items of a committed transaction.
+    fn on_transaction(&mut self, updates: Vec<T>) -> Result<(), E>;
+
+    /// Handle the end of the stream of transactions.
+    fn on_completed(&mut self) -> Result<(), E> {
+        Ok(())
+    }
+
+    /// Handle the stream of transactions ending because its source
+    /// disconnected. The default implementation treats it as completion.
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.on_completed()
+    }
+}
+
+/// An `Observer` buffering the items of each transaction it receives
+/// and handing them to a `TransactionObserver` on commit, in one go.
+///
+/// Every committed transaction results in exactly one call to
+/// `TransactionObserver::on_transaction`, even if it did not contain
+/// any items, while aborted transactions are discarded without one. A
+/// snapshot is handed over as part of the transaction carrying it, ahead
+/// of that transaction's updates. A
+/// transaction that got flushed is continued by the next one and handed
+/// over along with it.
+#[derive(Debug)]
+pub struct TransactionBufferObserver<T, O> {
+    /// The observer we hand transactions to.
+    observer: O,
+    /// The items of the transaction in progress.
+    items: Vec<T>,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// the next `on_start` continues it.
+    flushed: bool,
+}
+
+impl<T, O> TransactionBufferObserver<T, O> {
+    /// Create a new `TransactionBufferObserver` handing transactions to
+    /// `observer`.
+    pub fn new(observer: O) -> Self {
+        Self {
+            observer,
+            items: Vec::new(),
+            flushed: false,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<T, O, E> Observer<T, E> for TransactionBufferObserver<T, O>
+where
+    O: TransactionObserver<T, E>,
+    T: Debug + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        if !replace(&mut self.flushed, false) {
+            self.items.clear();
+        }
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_transaction(take(&mut self.items))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.items.extend(updates);
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.items.extend(items);
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.items.clear();
+        self.flushed = false;
+        Ok(())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.flushed = true;
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        // A transaction that got flushed is never going to be continued.
+        self.items.clear();
+        self.flushed = false;
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        "transactions".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `TransactionObserver` recording the transactions it received.
+    #[derive(Debug, Default)]
+    struct Transactions {
+        /// The transactions received, in order.
+        transactions: Vec<Vec<u64>>,
+        /// Whether `on_completed` got called.
+        completed: bool,
+    }
+
+    impl TransactionObserver<u64, String> for Transactions {
+        fn on_transaction(&mut self, updates: Vec<u64>) -> Result<(), String> {
+            self.transactions.push(updates);
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            self.completed = true;
+            Ok(())
+        }
+    }
+
+    /// Check that each committed transaction is handed over as a
+    /// whole, while aborted ones are skipped.
+    #[test]
+    fn buffer_transactions() {
+        let mut buffer = TransactionBufferObserver::new(Transactions::default());
+        let observer = &mut buffer as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![5].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let transactions = buffer.into_inner();
+        assert_eq!(transactions.transactions, vec![vec![1, 2, 3], vec![5]]);
+        assert!(transactions.completed);
+    }
+}
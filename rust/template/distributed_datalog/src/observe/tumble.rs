@@ -0,0 +1,336 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::observe::Clock;
+use crate::observe::Observer;
+use crate::observe::SystemClock;
+
+/// An `Observer` folding all items seen within fixed, non-overlapping
+/// time windows into a single summary per window, e.g., for downsampling
+/// a high-rate stream of metrics.
+///
+/// The first window starts when the observer is created. Items are
+/// attributed to windows based on their time of arrival, but only once
+/// the transaction they are part of got committed (or flushed). The summaries of all
+/// windows that have ended by the time of a commit are emitted to the
+/// inner observer as the items of the corresponding transaction, oldest
+/// first. No timer is involved, so a window ending while no data is
+/// flowing gets emitted with the next commit or on completion, at the
+/// latest. Windows without any data are skipped, unless configured
+/// otherwise.
+pub struct TumblingWindowObserver<O, T, U, C, F> {
+    /// The observer we emit summaries to.
+    observer: O,
+    /// The length of a window.
+    window: Duration,
+    /// The clock used for timestamping items.
+    clock: C,
+    /// The value each window's summary starts out with.
+    init: U,
+    /// The function folding an item into a summary.
+    fold: F,
+    /// Whether to emit summaries for windows without any data.
+    emit_empty: bool,
+    /// The point in time the current window ends at.
+    end: Instant,
+    /// The summary of the current window, if it has seen any data.
+    summary: Option<U>,
+    /// The items of the current transaction, along with their arrival
+    /// time.
+    items: Vec<(Instant, T)>,
+}
+
+impl<O, T, U, F> TumblingWindowObserver<O, T, U, SystemClock, F> {
+    /// Create a new `TumblingWindowObserver` folding the items seen
+    /// within each `window` into `init` using `fold`.
+    pub fn new(observer: O, window: Duration, init: U, fold: F) -> Self
+    where
+        F: FnMut(U, T) -> U,
+    {
+        Self::with_clock(observer, window, SystemClock, init, fold)
+    }
+}
+
+impl<O, T, U, C, F> TumblingWindowObserver<O, T, U, C, F>
+where
+    C: Clock,
+{
+    /// Create a new `TumblingWindowObserver` using the provided clock.
+    pub fn with_clock(observer: O, window: Duration, clock: C, init: U, fold: F) -> Self
+    where
+        F: FnMut(U, T) -> U,
+    {
+        assert!(window > Duration::from_secs(0), "window must not be empty");
+
+        let end = clock.now() + window;
+        Self {
+            observer,
+            window,
+            clock,
+            init,
+            fold,
+            emit_empty: false,
+            end,
+            summary: None,
+            items: Vec::new(),
+        }
+    }
+}
+
+impl<O, T, U, C, F> TumblingWindowObserver<O, T, U, C, F> {
+    /// Set whether to emit a summary, i.e., the initial value, for
+    /// windows that saw no data. Note that after a long period of
+    /// inactivity, that may be a large number of summaries at once.
+    pub fn emit_empty(mut self, emit_empty: bool) -> Self {
+        self.emit_empty = emit_empty;
+        self
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+}
+
+impl<O, T, U, C, F> TumblingWindowObserver<O, T, U, C, F>
+where
+    U: Clone,
+    F: FnMut(U, T) -> U,
+{
+    /// Close all windows that have ended at `time`, collecting their
+    /// summaries in `summaries`.
+    fn close_until(&mut self, time: Instant, summaries: &mut Vec<U>) {
+        while time >= self.end {
+            match self.summary.take() {
+                Some(summary) => summaries.push(summary),
+                None if self.emit_empty => summaries.push(self.init.clone()),
+                None => {
+                    // Skip over all empty windows in one go.
+                    let windows = (time - self.end).as_nanos() / self.window.as_nanos();
+                    let skip = self.window.as_nanos() * windows;
+                    self.end += Duration::from_nanos(skip as u64);
+                }
+            }
+            self.end += self.window;
+        }
+    }
+
+    /// Attribute the items of the committed transaction to their
+    /// windows and collect the summaries of all windows that ended by
+    /// now.
+    fn collect(&mut self, now: Instant) -> Vec<U> {
+        let mut summaries = Vec::new();
+        for (time, item) in self.items.split_off(0) {
+            self.close_until(time, &mut summaries);
+
+            let summary = self.summary.take().unwrap_or_else(|| self.init.clone());
+            self.summary = Some((self.fold)(summary, item));
+        }
+        self.close_until(now, &mut summaries);
+        summaries
+    }
+
+    /// Attribute the items of the transaction in progress to their
+    /// windows and emit the summaries of all windows that ended by now.
+    fn emit<E>(&mut self) -> Result<(), E>
+    where
+        O: Observer<U, E>,
+        C: Clock,
+        T: Send,
+        U: Send,
+        E: Send,
+    {
+        let now = self.clock.now();
+        let summaries = self.collect(now);
+        if !summaries.is_empty() {
+            self.observer.on_updates(Box::new(summaries.into_iter()))?;
+        }
+        Ok(())
+    }
+
+    /// Emit the summary of the window cut short by the end of the
+    /// stream, if it saw any data, as a transaction of its own.
+    fn cut_short<E>(&mut self) -> Result<(), E>
+    where
+        O: Observer<U, E>,
+        U: Send,
+        E: Send,
+    {
+        if let Some(summary) = self.summary.take() {
+            self.observer.on_start()?;
+            self.observer
+                .on_updates(Box::new(Some(summary).into_iter()))?;
+            self.observer.on_commit()?;
+        }
+        Ok(())
+    }
+}
+
+impl<O, T, U, C, F> Debug for TumblingWindowObserver<O, T, U, C, F>
+where
+    O: Debug,
+    C: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("TumblingWindowObserver")
+            .field("observer", &self.observer)
+            .field("window", &self.window)
+            .field("clock", &self.clock)
+            .field("emit_empty", &self.emit_empty)
+            .field("end", &self.end)
+            .field("items", &self.items.len())
+            .finish()
+    }
+}
+
+impl<O, T, U, C, F, E> Observer<T, E> for TumblingWindowObserver<O, T, U, C, F>
+where
+    O: Observer<U, E>,
+    C: Clock,
+    F: FnMut(U, T) -> U + Send,
+    T: Send,
+    U: Clone + Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.items.clear();
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.emit()?;
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let now = self.clock.now();
+        self.items.extend(updates.map(|item| (now, item)));
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        // The state we are bootstrapped with enters the window as of
+        // its arrival, just like updates.
+        let now = self.clock.now();
+        self.items.extend(items.map(|item| (now, item)));
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        // Items of the aborted transaction never enter a window.
+        self.items.clear();
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        // The items flushed got delivered, so they enter their windows
+        // just like those of a committed transaction.
+        self.emit()?;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.cut_short()?;
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.cut_short()?;
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!(
+            "tumbling_window({:?}) -> {}",
+            self.window,
+            self.observer.describe()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MockClock;
+
+    /// Create a `TumblingWindowObserver` summing up items in windows of
+    /// ten seconds.
+    fn sum_observer(
+        clock: &MockClock,
+    ) -> TumblingWindowObserver<
+        UpdatesMockObserver<u64>,
+        u64,
+        u64,
+        MockClock,
+        impl FnMut(u64, u64) -> u64,
+    > {
+        TumblingWindowObserver::with_clock(
+            UpdatesMockObserver::new(),
+            Duration::from_secs(10),
+            clock.clone(),
+            0,
+            |sum, x| sum + x,
+        )
+    }
+
+    /// Send a transaction containing `items` to `observer`.
+    fn send(observer: &mut dyn Observer<u64, ()>, items: Vec<u64>) {
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(items.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+    }
+
+    /// Check that items are summarized per window, skipping empty
+    /// windows, and that the last window is emitted on completion.
+    #[test]
+    fn summarize_windows() {
+        let clock = MockClock::new();
+        let mut window = sum_observer(&clock);
+        let observer = &mut window as &mut dyn Observer<u64, ()>;
+
+        send(observer, vec![1, 2]);
+        clock.advance(Duration::from_secs(9));
+        send(observer, vec![3]);
+
+        // Items of aborted transactions are not accounted for.
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![100].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        // The first window ended at the boundary.
+        clock.advance(Duration::from_secs(1));
+        send(observer, vec![4]);
+
+        // Two windows without data pass by.
+        clock.advance(Duration::from_secs(30));
+        send(observer, vec![5]);
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let mock = window.into_inner();
+        assert_eq!(mock.received_updates, vec![6, 4, 5]);
+        assert_eq!(mock.called_on_commit, 5);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that summaries are emitted for empty windows if so
+    /// desired.
+    #[test]
+    fn emit_empty_windows() {
+        let clock = MockClock::new();
+        let mut window = sum_observer(&clock).emit_empty(true);
+        let observer = &mut window as &mut dyn Observer<u64, ()>;
+
+        send(observer, vec![1]);
+        clock.advance(Duration::from_secs(25));
+        send(observer, vec![2]);
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let mock = window.into_inner();
+        assert_eq!(mock.received_updates, vec![1, 0, 2]);
+    }
+}
@@ -0,0 +1,345 @@
+use log::warn;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+
+/// How a `ValidatingObserver` deals with violations of the transaction
+/// life cycle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strictness {
+    /// Log a warning and pass the offending event on regardless.
+    Warn,
+    /// Report an error instead of passing the offending event on.
+    Error,
+}
+
+/// The point in the transaction life cycle an observer is at.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    /// No transaction is in progress.
+    Idle,
+    /// A transaction is in progress; the flag indicates whether any
+    /// updates were seen as part of it.
+    Transaction(bool),
+    /// The stream completed.
+    Completed,
+}
+
+/// An `Observer` enforcing that the events it sees adhere to the
+/// transaction life cycle, before passing them on to an inner observer.
+///
+/// A well-formed stream consists of any number of transactions, each
+/// made up of an `on_start`, optionally followed by an `on_snapshot`,
+/// any number of `on_updates`, and an `on_commit`, `on_abort`, or
+/// `on_flush`. An `on_completed` may only occur between transactions,
+/// and nothing may follow it. Putting this observer right behind a
+/// receiver helps catching protocol bugs of senders early, instead of
+/// having them show up as corrupted state much further down the line.
+#[derive(Debug)]
+pub struct ValidatingObserver<O> {
+    /// The observer we pass valid events on to.
+    observer: O,
+    /// How to deal with violations.
+    strictness: Strictness,
+    /// The point in the life cycle we are at.
+    state: State,
+}
+
+impl<O> ValidatingObserver<O> {
+    /// Create a new `ValidatingObserver` validating the events destined
+    /// for `observer` and dealing with violations as per `strictness`.
+    pub fn new(observer: O, strictness: Strictness) -> Self {
+        Self {
+            observer,
+            strictness,
+            state: State::Idle,
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Check that `event` is valid in the current state, transitioning
+    /// into state `next`. On a violation, the transition is still made
+    /// if we merely warn about it.
+    fn validate<E>(
+        &mut self,
+        event: &str,
+        next: State,
+        check: fn(State) -> Result<(), &'static str>,
+    ) -> Result<(), E>
+    where
+        E: From<String>,
+    {
+        if let Err(violation) = check(self.state) {
+            let error = format!("invalid {} event: {}", event, violation);
+            match self.strictness {
+                Strictness::Warn => warn!("ValidatingObserver: {}", error),
+                Strictness::Error => return Err(E::from(error)),
+            }
+        }
+        self.state = next;
+        Ok(())
+    }
+
+    /// Validate an event ending a transaction.
+    fn end<E>(&mut self, event: &str) -> Result<(), E>
+    where
+        E: From<String>,
+    {
+        self.validate(event, State::Idle, |state| match state {
+            State::Transaction(_) => Ok(()),
+            State::Idle => Err("no transaction in progress"),
+            State::Completed => Err("stream already completed"),
+        })
+    }
+}
+
+impl<O, T, E> Observer<T, E> for ValidatingObserver<O>
+where
+    O: Observer<T, E>,
+    T: Send,
+    E: From<String> + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.validate("on_start", State::Transaction(false), |state| match state {
+            State::Idle => Ok(()),
+            State::Transaction(_) => Err("transaction already in progress"),
+            State::Completed => Err("stream already completed"),
+        })?;
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.end("on_commit")?;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.end("on_commit")?;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.validate(
+            "on_updates",
+            State::Transaction(true),
+            |state| match state {
+                State::Transaction(_) => Ok(()),
+                State::Idle => Err("no transaction in progress"),
+                State::Completed => Err("stream already completed"),
+            },
+        )?;
+        self.observer.on_updates(updates)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.validate(
+            "on_snapshot",
+            State::Transaction(true),
+            |state| match state {
+                State::Transaction(false) => Ok(()),
+                State::Transaction(true) => Err("snapshot follows updates"),
+                State::Idle => Err("no transaction in progress"),
+                State::Completed => Err("stream already completed"),
+            },
+        )?;
+        self.observer.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.end("on_abort")?;
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.end("on_flush")?;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.validate("on_completed", State::Completed, |state| match state {
+            State::Idle => Ok(()),
+            State::Transaction(_) => Err("transaction still in progress"),
+            State::Completed => Err("stream already completed"),
+        })?;
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.validate("on_disconnected", State::Completed, |state| match state {
+            State::Idle => Ok(()),
+            State::Transaction(_) => Err("transaction still in progress"),
+            State::Completed => Err("stream already completed"),
+        })?;
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("validate -> {}", self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::observe::MockObserver;
+
+    /// An event to feed to an observer.
+    #[derive(Clone, Copy, Debug)]
+    enum Event {
+        Start,
+        Updates,
+        Snapshot,
+        Commit,
+        Abort,
+        Flush,
+        Completed,
+    }
+
+    /// Feed `event` to `observer`.
+    fn feed(observer: &mut dyn Observer<u64, String>, event: Event) -> Result<(), String> {
+        match event {
+            Event::Start => observer.on_start(),
+            Event::Updates => observer.on_updates(Box::new(vec![1].into_iter())),
+            Event::Snapshot => observer.on_snapshot(Box::new(vec![2].into_iter())),
+            Event::Commit => observer.on_commit(),
+            Event::Abort => observer.on_abort(),
+            Event::Flush => observer.on_flush(),
+            Event::Completed => observer.on_completed(),
+        }
+    }
+
+    /// Check that a well-formed stream passes.
+    #[test]
+    fn well_formed() {
+        let mut validating = ValidatingObserver::new(MockObserver::new(), Strictness::Error);
+        let events = [
+            Event::Start,
+            Event::Snapshot,
+            Event::Updates,
+            Event::Updates,
+            Event::Commit,
+            Event::Start,
+            Event::Abort,
+            Event::Start,
+            Event::Updates,
+            Event::Flush,
+            Event::Start,
+            Event::Commit,
+            Event::Completed,
+        ];
+        for event in &events {
+            assert_eq!(feed(&mut validating, *event), Ok(()), "{:?}", event);
+        }
+
+        let mock = validating.into_inner();
+        assert_eq!(mock.called_on_start, 4);
+        assert_eq!(mock.called_on_updates, 4);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that each kind of violation gets reported as an error and
+    /// that the offending event is not passed on.
+    #[test]
+    fn violations() {
+        use Event::*;
+
+        let cases: &[(&[Event], Event, &str)] = &[
+            (
+                &[],
+                Updates,
+                "invalid on_updates event: no transaction in progress",
+            ),
+            (
+                &[],
+                Snapshot,
+                "invalid on_snapshot event: no transaction in progress",
+            ),
+            (
+                &[],
+                Commit,
+                "invalid on_commit event: no transaction in progress",
+            ),
+            (
+                &[],
+                Abort,
+                "invalid on_abort event: no transaction in progress",
+            ),
+            (
+                &[],
+                Flush,
+                "invalid on_flush event: no transaction in progress",
+            ),
+            (
+                &[Start],
+                Start,
+                "invalid on_start event: transaction already in progress",
+            ),
+            (
+                &[Start, Commit],
+                Commit,
+                "invalid on_commit event: no transaction in progress",
+            ),
+            (
+                &[Start, Updates],
+                Snapshot,
+                "invalid on_snapshot event: snapshot follows updates",
+            ),
+            (
+                &[Start],
+                Completed,
+                "invalid on_completed event: transaction still in progress",
+            ),
+            (
+                &[Completed],
+                Start,
+                "invalid on_start event: stream already completed",
+            ),
+            (
+                &[Completed],
+                Completed,
+                "invalid on_completed event: stream already completed",
+            ),
+        ];
+
+        for (prefix, event, error) in cases {
+            let mut validating = ValidatingObserver::new(MockObserver::new(), Strictness::Error);
+            for event in prefix.iter() {
+                assert_eq!(feed(&mut validating, *event), Ok(()), "{:?}", event);
+            }
+
+            let before = validating.observer;
+            assert_eq!(feed(&mut validating, *event), Err(error.to_string()));
+
+            let after = validating.into_inner();
+            assert_eq!(after.called_on_start, before.called_on_start);
+            assert_eq!(after.called_on_updates, before.called_on_updates);
+            assert_eq!(after.called_on_commit, before.called_on_commit);
+            assert_eq!(after.called_on_abort, before.called_on_abort);
+            assert_eq!(after.called_on_completed, before.called_on_completed);
+        }
+    }
+
+    /// Check that violations are passed on when we merely warn about
+    /// them.
+    #[test]
+    fn warn_on_violation() {
+        let mut validating = ValidatingObserver::new(MockObserver::new(), Strictness::Warn);
+        let observer = &mut validating as &mut dyn Observer<u64, String>;
+
+        assert_eq!(observer.on_updates(Box::new(vec![1].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = validating.into_inner();
+        assert_eq!(mock.called_on_updates, 1);
+        assert_eq!(mock.called_on_commit, 3);
+        assert_eq!(mock.called_on_start, 1);
+    }
+}
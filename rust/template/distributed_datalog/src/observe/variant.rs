@@ -0,0 +1,166 @@
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+
+use crate::observe::Observer;
+use crate::observe::ObserverBox;
+use crate::observe::OptionalObserver;
+use crate::observe::TransactionStats;
+
+/// An `Observer` dispatching events to one of two downstream observers
+/// based on their kind, e.g., for handing data to a processor while
+/// letting a separate party coordinate shutdown.
+///
+/// Data, i.e., updates and snapshots, along with the transaction
+/// boundaries framing them (start, commit, abort, and flush), goes to
+/// the updates observer. The end of the stream, be it a completion or a
+/// disconnect, goes to the lifecycle observer. Events for which no
+/// observer is set are dropped.
+pub struct VariantRouterObserver<T, E> {
+    /// The observer receiving data and transaction boundaries.
+    updates: OptionalObserver<ObserverBox<T, E>>,
+    /// The observer receiving completions and disconnects.
+    lifecycle: OptionalObserver<ObserverBox<T, E>>,
+}
+
+impl<T, E> VariantRouterObserver<T, E> {
+    /// Create a new `VariantRouterObserver` dropping all events.
+    pub fn new() -> Self {
+        Self {
+            updates: None,
+            lifecycle: None,
+        }
+    }
+
+    /// Send data and transaction boundaries to `observer`.
+    pub fn updates(mut self, observer: ObserverBox<T, E>) -> Self {
+        self.updates = Some(observer);
+        self
+    }
+
+    /// Send completions and disconnects to `observer`.
+    pub fn lifecycle(mut self, observer: ObserverBox<T, E>) -> Self {
+        self.lifecycle = Some(observer);
+        self
+    }
+}
+
+impl<T, E> Default for VariantRouterObserver<T, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, E> Debug for VariantRouterObserver<T, E> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("VariantRouterObserver")
+            .field("updates", &self.updates.is_some())
+            .field("lifecycle", &self.lifecycle.is_some())
+            .finish()
+    }
+}
+
+impl<T, E> Observer<T, E> for VariantRouterObserver<T, E>
+where
+    T: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.updates.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.updates.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.updates.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.updates.on_updates(updates)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.updates.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.updates.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.updates.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.lifecycle.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.lifecycle.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        let describe = |observer: &OptionalObserver<ObserverBox<T, E>>| {
+            observer
+                .as_ref()
+                .map(|o| o.describe())
+                .unwrap_or_else(|| "_".to_string())
+        };
+        format!(
+            "variant_router(updates: {}, lifecycle: {})",
+            describe(&self.updates),
+            describe(&self.lifecycle)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MockObserver;
+
+    /// Check that updates and the completion of the stream reach
+    /// separate observers.
+    #[test]
+    fn route_by_variant() {
+        let updates = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let lifecycle = Arc::new(Mutex::new(MockObserver::new()));
+
+        let mut router = VariantRouterObserver::new()
+            .updates(Box::new(updates.clone()))
+            .lifecycle(Box::new(lifecycle.clone()));
+        let observer = &mut router as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let updates = updates.lock().unwrap();
+        assert_eq!(updates.received_updates, vec![1, 2]);
+        assert_eq!(updates.called_on_start, 1);
+        assert_eq!(updates.called_on_commit, 1);
+        assert_eq!(updates.called_on_completed, 0);
+
+        let lifecycle = lifecycle.lock().unwrap();
+        assert_eq!(lifecycle.called_on_start, 0);
+        assert_eq!(lifecycle.called_on_updates, 0);
+        assert_eq!(lifecycle.called_on_commit, 0);
+        assert_eq!(lifecycle.called_on_completed, 1);
+
+        // Without observers, everything gets dropped.
+        let mut router = VariantRouterObserver::<u64, ()>::new();
+        assert_eq!(router.on_start(), Ok(()));
+        assert_eq!(router.on_completed(), Ok(()));
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::iter::once;
+use std::time::Duration;
+use std::time::Instant;
+
+use crate::observe::Clock;
+use crate::observe::Observer;
+use crate::observe::SystemClock;
+
+/// An `Observer` computing an aggregate over all items seen within a
+/// sliding time window.
+///
+/// Items are not forwarded. Instead, on every commit, items that are
+/// older than the window are evicted and the aggregate over the
+/// remaining ones is emitted to the inner observer as the sole item of
+/// the transaction.
+pub struct WindowObserver<O, T, C, F> {
+    /// The observer we emit aggregates to.
+    observer: O,
+    /// The length of the window.
+    window: Duration,
+    /// The clock used for timestamping items.
+    clock: C,
+    /// The function computing the aggregate over the items in the
+    /// window.
+    aggregate: F,
+    /// The items in the window, along with their arrival time, oldest
+    /// first.
+    items: VecDeque<(Instant, T)>,
+    /// The number of items in the window when the current transaction
+    /// started.
+    start: usize,
+}
+
+impl<O, T, F> WindowObserver<O, T, SystemClock, F> {
+    /// Create a new `WindowObserver` aggregating the items seen within
+    /// `window` using `aggregate`.
+    pub fn new<U>(observer: O, window: Duration, aggregate: F) -> Self
+    where
+        F: FnMut(&mut dyn Iterator<Item = &T>) -> U,
+    {
+        Self::with_clock(observer, window, SystemClock, aggregate)
+    }
+}
+
+impl<O, T, C, F> WindowObserver<O, T, C, F> {
+    /// Create a new `WindowObserver` using the provided clock.
+    pub fn with_clock<U>(observer: O, window: Duration, clock: C, aggregate: F) -> Self
+    where
+        F: FnMut(&mut dyn Iterator<Item = &T>) -> U,
+    {
+        Self {
+            observer,
+            window,
+            clock,
+            aggregate,
+            items: VecDeque::new(),
+            start: 0,
+        }
+    }
+
+    /// Evict all items that have left the window.
+    fn evict(&mut self, now: Instant) {
+        while let Some((time, _)) = self.items.front() {
+            if now.duration_since(*time) >= self.window {
+                let _ = self.items.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+impl<O, T, C, F> Debug for WindowObserver<O, T, C, F>
+where
+    O: Debug,
+    C: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("WindowObserver")
+            .field("observer", &self.observer)
+            .field("window", &self.window)
+            .field("clock", &self.clock)
+            .field("items", &self.items.len())
+            .finish()
+    }
+}
+
+impl<O, T, C, F, U, E> Observer<T, E> for WindowObserver<O, T, C, F>
+where
+    O: Observer<U, E>,
+    C: Clock,
+    F: FnMut(&mut dyn Iterator<Item = &T>) -> U + Send,
+    T: Send,
+    U: Send,
+    E: Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.start = self.items.len();
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        let now = self.clock.now();
+        self.evict(now);
+
+        let aggregate = (self.aggregate)(&mut self.items.iter().map(|(_, item)| item));
+        self.observer.on_updates(Box::new(once(aggregate)))?;
+        self.observer.on_commit()
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        let now = self.clock.now();
+        self.items.extend(updates.map(|item| (now, item)));
+        Ok(())
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        // The state we are bootstrapped with enters the window as of
+        // its arrival, just like updates.
+        let now = self.clock.now();
+        self.items.extend(items.map(|item| (now, item)));
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        // Items of the aborted transaction never enter the window.
+        self.items.truncate(self.start);
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        // The items flushed stay in the window; only those following
+        // in the transaction continuing this one may still be aborted.
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("window({:?}) -> {}", self.window, self.observer.describe())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::MockClock;
+
+    /// Check that items are evicted once they reach the window
+    /// boundary.
+    #[test]
+    fn eviction_at_boundary() {
+        let clock = MockClock::new();
+        let mut window = WindowObserver::with_clock(
+            UpdatesMockObserver::<usize>::new(),
+            Duration::from_secs(10),
+            clock.clone(),
+            |items: &mut dyn Iterator<Item = &u64>| items.count(),
+        );
+        let observer = &mut window as &mut dyn Observer<u64, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        // Just before the boundary, all items are still in the window.
+        clock.advance(Duration::from_secs(5) - Duration::from_nanos(1));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        // At the boundary the first two items are evicted.
+        clock.advance(Duration::from_nanos(1));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        assert_eq!(window.observer.received_updates, vec![2, 3, 3, 1, 0]);
+        assert_eq!(window.observer.called_on_commit, 5);
+    }
+}
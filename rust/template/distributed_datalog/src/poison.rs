@@ -0,0 +1,30 @@
+//! A module providing means for recovering from poisoned locks.
+
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+
+/// An extension trait for `Mutex` objects.
+pub(crate) trait MutexExt<T>
+where
+    T: ?Sized,
+{
+    /// Acquire the lock, recovering from poisoning.
+    ///
+    /// A lock gets poisoned if a thread panicked while holding it.
+    /// The data it protects is not necessarily inconsistent as a
+    /// result, though, and refusing access to it for good would turn a
+    /// single panic into a cascade of them. Callers must only use this
+    /// method for data that remains usable no matter where a panic
+    /// occurred while it was being accessed.
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T>;
+}
+
+impl<T> MutexExt<T> for Mutex<T>
+where
+    T: ?Sized,
+{
+    fn lock_unpoisoned(&self) -> MutexGuard<'_, T> {
+        self.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
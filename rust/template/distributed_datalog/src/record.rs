@@ -0,0 +1,336 @@
+//! A module providing for recording the stream of events an observer
+//! sees to a file and for playing it back later, e.g., for reproducing
+//! a bug offline with the exact traffic that triggered it.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::thread::sleep;
+use std::time::Duration;
+use std::time::Instant;
+
+use bincode::deserialize_from;
+use bincode::serialize_into;
+use bincode::ErrorKind as BincodeError;
+
+use log::trace;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use uid::Id;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+use crate::tcp_channel::Message;
+
+/// An `Observer` recording all events, along with the time they arrived
+/// at, to a file before forwarding them to an inner observer.
+///
+/// Unlike a `WalObserver`, which only cares about committed
+/// transactions, the recording captures every event as is, so that a
+/// `MessagePlayer` can reproduce the very same sequence of calls.
+#[derive(Debug)]
+pub struct MessageRecorder<O> {
+    /// The recorder's unique ID.
+    id: usize,
+    /// The observer we forward events to.
+    observer: O,
+    /// The file we record events to.
+    file: BufWriter<File>,
+    /// The time the recording started, which events are recorded
+    /// relative to.
+    start: Instant,
+}
+
+impl<O> MessageRecorder<O> {
+    /// Create a new `MessageRecorder` recording events to `file` before
+    /// forwarding them to `observer`.
+    pub fn new(observer: O, file: File) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("MessageRecorder({})::new", id);
+
+        Self {
+            id,
+            observer,
+            file: BufWriter::new(file),
+            start: Instant::now(),
+        }
+    }
+
+    /// Retrieve the wrapped observer.
+    pub fn into_inner(self) -> O {
+        self.observer
+    }
+
+    /// Record a message, along with the time passed since the start of
+    /// the recording.
+    fn record<T>(&mut self, message: &Message<T>) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        let offset = self.start.elapsed();
+        serialize_into(&mut self.file, &(offset, message))
+            .map_err(|e| format!("failed to record '{}' event: {}", message, e))
+    }
+
+    /// Flush all events recorded so far to the file.
+    fn flush(&mut self) -> Result<(), String> {
+        self.file
+            .flush()
+            .map_err(|e| format!("failed to flush recording: {}", e))
+    }
+}
+
+impl<O, T> Observer<T, String> for MessageRecorder<O>
+where
+    O: Observer<T, String>,
+    T: Send + Serialize,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_start", self.id);
+
+        self.record(&Message::<T>::Start)?;
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_commit", self.id);
+
+        self.record(&Message::<T>::Commit)?;
+        self.flush()?;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_commit_with_stats", self.id);
+
+        self.record(&Message::<T>::Commit)?;
+        self.flush()?;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_updates", self.id);
+
+        let message = Message::Updates(updates.collect());
+        self.record(&message)?;
+
+        match message {
+            Message::Updates(updates) => self.observer.on_updates(Box::new(updates.into_iter())),
+            _ => unreachable!(),
+        }
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_snapshot", self.id);
+
+        let message = Message::Snapshot(items.collect());
+        self.record(&message)?;
+
+        match message {
+            Message::Snapshot(items) => self.observer.on_snapshot(Box::new(items.into_iter())),
+            _ => unreachable!(),
+        }
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_abort", self.id);
+
+        self.record(&Message::<T>::Abort)?;
+        self.flush()?;
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_flush", self.id);
+
+        self.record(&Message::<T>::Flush)?;
+        self.flush()?;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_completed", self.id);
+
+        self.record(&Message::<T>::Complete)?;
+        self.flush()?;
+        self.observer.on_completed()
+    }
+
+    /// A disconnect has no message of its own, so it is not recorded.
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        trace!("MessageRecorder({})::on_disconnected", self.id);
+
+        self.flush()?;
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("record -> {}", self.observer.describe())
+    }
+}
+
+/// An object playing back a recording made by a `MessageRecorder`.
+#[derive(Debug)]
+pub struct MessagePlayer<R> {
+    /// The player's unique ID.
+    id: usize,
+    /// The reader we read the recording from.
+    reader: BufReader<R>,
+    /// Whether to reproduce the original timing of events.
+    realtime: bool,
+}
+
+impl<R> MessagePlayer<R>
+where
+    R: Read,
+{
+    /// Create a new `MessagePlayer` reading a recording from `reader`.
+    pub fn new(reader: R) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("MessagePlayer({})::new", id);
+
+        Self {
+            id,
+            reader: BufReader::new(reader),
+            realtime: false,
+        }
+    }
+
+    /// Set whether to reproduce the original timing of events, by
+    /// delaying each one until as much time has passed since the start
+    /// of the playback as did when it was recorded. By default, events
+    /// are played back as fast as possible.
+    pub fn realtime(mut self, realtime: bool) -> Self {
+        self.realtime = realtime;
+        self
+    }
+
+    /// Play back all events of the recording to `observer`, returning
+    /// the number of events played.
+    ///
+    /// An event cut short at the end of the recording, as happens if
+    /// the recording process crashed, is ignored.
+    pub fn play<O, T>(&mut self, observer: &mut O) -> Result<usize, String>
+    where
+        O: Observer<T, String> + ?Sized,
+        T: Send + DeserializeOwned,
+    {
+        trace!("MessagePlayer({})::play", self.id);
+
+        let start = Instant::now();
+        let mut played = 0;
+
+        loop {
+            let (offset, message) =
+                match deserialize_from::<_, (Duration, Message<T>)>(&mut self.reader) {
+                    Ok(record) => record,
+                    Err(e) => match *e {
+                        BincodeError::Io(ref e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                        _ => return Err(format!("failed to read recording: {}", e)),
+                    },
+                };
+
+            if self.realtime {
+                let elapsed = start.elapsed();
+                if offset > elapsed {
+                    sleep(offset - elapsed);
+                }
+            }
+
+            match message {
+                Message::Start => observer.on_start()?,
+                Message::Updates(updates) => observer.on_updates(Box::new(updates.into_iter()))?,
+                Message::UpdateList(updates) => {
+                    observer.on_updates(Box::new(updates.into_iter().flatten()))?
+                }
+                Message::Snapshot(items) => observer.on_snapshot(Box::new(items.into_iter()))?,
+                Message::Commit => observer.on_commit()?,
+                Message::Abort => observer.on_abort()?,
+                Message::Flush => observer.on_flush()?,
+                Message::Complete => observer.on_completed()?,
+                Message::Ack(_) => return Err("recording contains an acknowledgement".to_string()),
+                Message::Resume(_) => return Err("recording contains a resume header".to_string()),
+                Message::Version(_) => {
+                    return Err("recording contains a version announcement".to_string())
+                }
+                Message::Framed { .. } => {
+                    return Err("recording contains a framed message".to_string())
+                }
+                Message::Identify(_) => {
+                    return Err("recording contains an identification".to_string())
+                }
+            }
+            played += 1;
+        }
+        Ok(played)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::NamedTempFile;
+
+    use crate::accumulate::UpdatesMockObserver;
+
+    /// Record a couple of events, including an abort and completion,
+    /// and play them back.
+    #[test]
+    fn record_and_play() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let file = tempfile.reopen().unwrap();
+        let mut recorder = MessageRecorder::new(UpdatesMockObserver::<u64>::new(), file);
+        let observer = &mut recorder as &mut dyn Observer<u64, String>;
+
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![4].into_iter())).unwrap();
+        observer.on_abort().unwrap();
+        observer.on_completed().unwrap();
+
+        let recorded = recorder.into_inner();
+        assert_eq!(recorded.received_updates, vec![1, 2, 3, 4]);
+
+        let mut mock = UpdatesMockObserver::<u64>::new();
+        let mut player = MessagePlayer::new(tempfile.reopen().unwrap());
+        assert_eq!(player.play(&mut mock), Ok(8));
+        assert_eq!(mock.received_updates, recorded.received_updates);
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that playing back in real time reproduces the delays
+    /// between events.
+    #[test]
+    fn play_realtime() {
+        let delay = Duration::from_millis(50);
+        let tempfile = NamedTempFile::new().unwrap();
+        let file = tempfile.reopen().unwrap();
+        let mut recorder = MessageRecorder::new(UpdatesMockObserver::<u64>::new(), file);
+        let observer = &mut recorder as &mut dyn Observer<u64, String>;
+
+        observer.on_start().unwrap();
+        sleep(delay);
+        observer.on_commit().unwrap();
+
+        let mut mock = UpdatesMockObserver::<u64>::new();
+        let mut player = MessagePlayer::new(tempfile.reopen().unwrap()).realtime(true);
+        let start = Instant::now();
+        assert_eq!(player.play(&mut mock), Ok(2));
+        assert!(start.elapsed() >= delay);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::{BTreeSet, HashMap};
 use std::fmt::Debug;
+use std::mem::replace;
 use std::sync::Arc;
 use std::time::Instant;
 
@@ -36,6 +37,9 @@ where
     prog: Option<Arc<P>>,
     outlets: Vec<Outlet>,
     redirect: HashMap<RelId, RelId>,
+    /// Whether the transaction in progress got flushed, in which case
+    /// the next `on_start` continues it.
+    flushed: bool,
 }
 
 impl<P> DDlogServer<P>
@@ -54,6 +58,7 @@ where
             prog,
             outlets: Vec::new(),
             redirect,
+            flushed: false,
         }
     }
 
@@ -101,10 +106,14 @@ impl<P> Observer<Update<DDValue>, String> for DDlogServer<P>
 where
     P: Debug + Send + Sync + DDlog,
 {
-    /// Start a transaction when deltas start coming in.
+    /// Start a transaction when deltas start coming in, unless we
+    /// continue one that got flushed.
     fn on_start(&mut self) -> Result<(), String> {
         trace!("DDlogServer({})::on_start", self.id);
 
+        if replace(&mut self.flushed, false) {
+            return Ok(());
+        }
         if let Some(ref mut prog) = self.prog {
             prog.transaction_start()
         } else {
@@ -185,6 +194,30 @@ where
         }
     }
 
+    /// Roll back the DDlog transaction, including the part of it that
+    /// got flushed, if any, so that the next transaction starts afresh.
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("DDlogServer({})::on_abort", self.id);
+
+        self.flushed = false;
+        if let Some(ref prog) = self.prog {
+            prog.transaction_rollback()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Keep the DDlog transaction open when a partial transaction got
+    /// flushed, as committing it would expose a fragment of the
+    /// transaction to the program. Its remainder follows as part of the
+    /// next transaction, which continues the DDlog one.
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("DDlogServer({})::on_flush", self.id);
+
+        self.flushed = true;
+        Ok(())
+    }
+
     fn on_completed(&mut self) -> Result<(), String> {
         trace!("DDlogServer({})::on_completed", self.id);
         println!(
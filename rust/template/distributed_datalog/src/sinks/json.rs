@@ -0,0 +1,187 @@
+use std::fmt::Debug;
+use std::io::stderr;
+use std::io::Stderr;
+use std::io::Write;
+
+use log::trace;
+use serde::Serialize;
+use serde_json::to_writer;
+use uid::Id;
+
+use crate::Observer;
+
+/// A record written by a `JsonLinesObserver`, one per line.
+///
+/// Updates are written as `{"update":<item>}`, items of a snapshot as
+/// `{"snapshot":<item>}`, and lifecycle events as plain strings, e.g.,
+/// `"commit"`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Record<'a, T> {
+    Start,
+    Update(&'a T),
+    Snapshot(&'a T),
+    Commit,
+    Abort,
+    Flush,
+    Completed,
+    Disconnected,
+}
+
+/// An object implementing the `Observer` interface and writing every
+/// item it receives as a line of JSON, for watching a live stream of
+/// data while debugging.
+///
+/// Lifecycle events are written as records of their own, so that
+/// transaction boundaries are visible. The writer is flushed at the
+/// end of every transaction.
+#[derive(Debug)]
+pub struct JsonLinesObserver<W> {
+    /// The JSON lines sink's unique ID.
+    id: usize,
+    /// The writer we write records to.
+    writer: W,
+}
+
+impl JsonLinesObserver<Stderr> {
+    /// Create a new `JsonLinesObserver` writing to stderr.
+    pub fn new() -> Self {
+        Self::with_writer(stderr())
+    }
+}
+
+impl Default for JsonLinesObserver<Stderr> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<W> JsonLinesObserver<W> {
+    /// Create a new `JsonLinesObserver` writing to `writer`.
+    pub fn with_writer(writer: W) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("JsonLinesObserver({})::new", id);
+
+        Self { id, writer }
+    }
+
+    /// Retrieve the writer we write to.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W> JsonLinesObserver<W>
+where
+    W: Write,
+{
+    /// Write a record as a line of its own.
+    fn write<T>(&mut self, record: &Record<'_, T>) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        to_writer(&mut self.writer, record)
+            .map_err(|e| e.to_string())
+            .and_then(|_| self.writer.write_all(b"\n").map_err(|e| e.to_string()))
+            .map_err(|e| format!("failed to write record: {}", e))
+    }
+
+    /// Write a record marking a lifecycle event and flush the writer.
+    fn mark<T>(&mut self, record: Record<'_, T>) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        self.write(&record)?;
+        self.writer
+            .flush()
+            .map_err(|e| format!("failed to flush records: {}", e))
+    }
+}
+
+impl<W, T> Observer<T, String> for JsonLinesObserver<W>
+where
+    W: Debug + Send + Write,
+    T: Debug + Send + Serialize,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_start", self.id);
+        self.write(&Record::<T>::Start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_commit", self.id);
+        self.mark(Record::<T>::Commit)
+    }
+
+    fn on_updates<'a>(
+        &mut self,
+        mut updates: Box<dyn Iterator<Item = T> + 'a>,
+    ) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_updates", self.id);
+        updates.try_for_each(|update| self.write(&Record::Update(&update)))
+    }
+
+    fn on_snapshot<'a>(
+        &mut self,
+        mut items: Box<dyn Iterator<Item = T> + 'a>,
+    ) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_snapshot", self.id);
+        items.try_for_each(|item| self.write(&Record::Snapshot(&item)))
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_abort", self.id);
+        self.mark(Record::<T>::Abort)
+    }
+
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_flush", self.id);
+        self.mark(Record::<T>::Flush)
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_completed", self.id);
+        self.mark(Record::<T>::Completed)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        trace!("JsonLinesObserver({})::on_disconnected", self.id);
+        self.mark(Record::<T>::Disconnected)
+    }
+
+    fn describe(&self) -> String {
+        "json_lines".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that items and lifecycle events are written as lines of
+    /// JSON.
+    #[test]
+    fn json_lines() {
+        let mut json = JsonLinesObserver::with_writer(Vec::new());
+        let observer = &mut json as &mut dyn Observer<(u64, String), String>;
+
+        let updates = vec![(1, "foo".to_string()), (2, "bar".to_string())];
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        let output = String::from_utf8(json.into_inner()).unwrap();
+        let expected = r#""start"
+{"update":[1,"foo"]}
+{"update":[2,"bar"]}
+"commit"
+"start"
+"abort"
+"completed"
+"#;
+        assert_eq!(output, expected);
+    }
+}
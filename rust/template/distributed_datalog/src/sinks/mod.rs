@@ -1,5 +1,11 @@
 //! Various sinks for forwarding data from a distributed computation.
 
 mod file;
+mod json;
+mod segment;
 
 pub use file::File;
+pub use json::JsonLinesObserver;
+pub use segment::SegmentFactory;
+pub use segment::SegmentFiles;
+pub use segment::SegmentingObserver;
@@ -0,0 +1,377 @@
+use std::fmt::Debug;
+use std::fs::remove_file;
+use std::fs::rename;
+use std::fs::File;
+use std::io::BufWriter;
+use std::io::Result as IoResult;
+use std::io::Write;
+use std::mem::replace;
+use std::path::PathBuf;
+
+use bincode::serialize_into;
+use log::trace;
+use serde::Serialize;
+use uid::Id;
+
+use crate::Observer;
+
+/// A factory for the writers of the segments a `SegmentingObserver`
+/// writes, one per transaction.
+pub trait SegmentFactory: Debug + Send {
+    /// The writer of a segment.
+    type Writer: Write;
+
+    /// Open the writer for the segment of the given epoch.
+    fn open(&mut self, epoch: u64) -> IoResult<Self::Writer>;
+
+    /// Finalize the segment of the given epoch, as its transaction got
+    /// committed.
+    fn finalize(&mut self, epoch: u64, writer: Self::Writer) -> IoResult<()>;
+
+    /// Discard the segment of the given epoch, as its transaction got
+    /// aborted.
+    fn discard(&mut self, epoch: u64, writer: Self::Writer) -> IoResult<()>;
+}
+
+/// A `SegmentFactory` writing segments to files in a directory.
+///
+/// The segment of epoch `n` is written to `<n>.partial`, with `n`
+/// padded to 20 digits, so that segments sort by their epoch. Once
+/// finalized, it is synced to disk and renamed to `<n>.segment`, so
+/// that only complete segments ever carry that extension, even in the
+/// face of a crash.
+#[derive(Debug)]
+pub struct SegmentFiles {
+    /// The directory we create segments in.
+    dir: PathBuf,
+}
+
+impl SegmentFiles {
+    /// Create a new `SegmentFiles` object creating segments in `dir`.
+    pub fn new<P>(dir: P) -> Self
+    where
+        P: Into<PathBuf>,
+    {
+        Self { dir: dir.into() }
+    }
+
+    /// Retrieve the path of the segment of `epoch` with the given
+    /// extension.
+    fn path(&self, epoch: u64, extension: &str) -> PathBuf {
+        self.dir.join(format!("{:020}.{}", epoch, extension))
+    }
+}
+
+impl SegmentFactory for SegmentFiles {
+    type Writer = BufWriter<File>;
+
+    fn open(&mut self, epoch: u64) -> IoResult<Self::Writer> {
+        File::create(self.path(epoch, "partial")).map(BufWriter::new)
+    }
+
+    fn finalize(&mut self, epoch: u64, writer: Self::Writer) -> IoResult<()> {
+        writer
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_data()?;
+        rename(self.path(epoch, "partial"), self.path(epoch, "segment"))
+    }
+
+    fn discard(&mut self, epoch: u64, writer: Self::Writer) -> IoResult<()> {
+        drop(writer);
+        remove_file(self.path(epoch, "partial"))
+    }
+}
+
+/// An object implementing the `Observer` interface and writing every
+/// transaction it receives to a segment of its own, e.g., for archiving
+/// a stream of data.
+///
+/// A segment is opened on every `on_start`, receives the items of the
+/// transaction's snapshot (if any) and its updates, each serialized
+/// using `bincode`, and is finalized
+/// on commit. The segment of an aborted transaction is discarded. Each
+/// segment is identified by an epoch, starting at zero (or the
+/// configured first epoch) and increasing by one with every committed
+/// transaction. A transaction that got flushed keeps its segment open,
+/// for the next one to continue it.
+#[derive(Debug)]
+pub struct SegmentingObserver<F>
+where
+    F: SegmentFactory,
+{
+    /// The segmenting sink's unique ID.
+    id: usize,
+    /// The factory creating the writers of segments.
+    factory: F,
+    /// The epoch of the next segment.
+    epoch: u64,
+    /// The writer of the segment in progress, if any.
+    writer: Option<F::Writer>,
+    /// Whether the transaction in progress got flushed, meaning that
+    /// the next `on_start` continues its segment.
+    flushed: bool,
+}
+
+impl<F> SegmentingObserver<F>
+where
+    F: SegmentFactory,
+{
+    /// Create a new `SegmentingObserver` writing segments created by
+    /// `factory`.
+    pub fn new(factory: F) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("SegmentingObserver({})::new", id);
+
+        Self {
+            id,
+            factory,
+            epoch: 0,
+            writer: None,
+            flushed: false,
+        }
+    }
+
+    /// Set the epoch of the first segment, e.g., to continue after the
+    /// segments written by a previous incarnation.
+    pub fn first_epoch(mut self, epoch: u64) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Retrieve the epoch the next segment will be assigned.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Retrieve the factory creating the writers of segments.
+    pub fn into_inner(self) -> F {
+        self.factory
+    }
+
+    /// Discard the segment in progress, if any.
+    fn discard(&mut self) -> Result<(), String> {
+        match self.writer.take() {
+            Some(writer) => self
+                .factory
+                .discard(self.epoch, writer)
+                .map_err(|e| format!("failed to discard segment of epoch {}: {}", self.epoch, e)),
+            None => Ok(()),
+        }
+    }
+
+    /// Write `items` to the segment in progress, with `kind` describing
+    /// them in errors.
+    fn write<'a, T>(
+        &mut self,
+        kind: &str,
+        mut items: Box<dyn Iterator<Item = T> + 'a>,
+    ) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        let epoch = self.epoch;
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| format!("{} without a transaction", kind))?;
+        items.try_for_each(|item| {
+            serialize_into(&mut *writer, &item).map_err(|e| {
+                format!(
+                    "failed to write {} to segment of epoch {}: {}",
+                    kind, epoch, e
+                )
+            })
+        })
+    }
+}
+
+impl<F, T> Observer<T, String> for SegmentingObserver<F>
+where
+    F: SegmentFactory,
+    F::Writer: Debug + Send,
+    T: Send + Serialize,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_start", self.id);
+
+        if replace(&mut self.flushed, false) && self.writer.is_some() {
+            return Ok(());
+        }
+        // A transaction restarted without a commit or abort leaves an
+        // incomplete segment behind.
+        self.discard()?;
+        let writer = self
+            .factory
+            .open(self.epoch)
+            .map_err(|e| format!("failed to open segment of epoch {}: {}", self.epoch, e))?;
+        self.writer = Some(writer);
+        Ok(())
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_commit", self.id);
+
+        let writer = self
+            .writer
+            .take()
+            .ok_or_else(|| "commit without a transaction".to_string())?;
+        self.factory
+            .finalize(self.epoch, writer)
+            .map_err(|e| format!("failed to finalize segment of epoch {}: {}", self.epoch, e))?;
+        self.epoch += 1;
+        Ok(())
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_updates", self.id);
+        self.write("updates", updates)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_snapshot", self.id);
+        self.write("snapshot", items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_abort", self.id);
+        self.flushed = false;
+        self.discard()
+    }
+
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_flush", self.id);
+
+        let epoch = self.epoch;
+        let writer = self
+            .writer
+            .as_mut()
+            .ok_or_else(|| "flush without a transaction".to_string())?;
+        writer
+            .flush()
+            .map_err(|e| format!("failed to flush segment of epoch {}: {}", epoch, e))?;
+        self.flushed = true;
+        Ok(())
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_completed", self.id);
+        self.discard()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        trace!("SegmentingObserver({})::on_disconnected", self.id);
+        self.discard()
+    }
+
+    fn describe(&self) -> String {
+        "segments".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::BTreeMap;
+    use std::fs::read;
+    use std::fs::read_dir;
+
+    use bincode::deserialize_from;
+    use tempfile::tempdir;
+
+    /// A `SegmentFactory` keeping segments in memory.
+    #[derive(Debug, Default)]
+    struct MemorySegments {
+        /// The finalized segments, by epoch.
+        segments: BTreeMap<u64, Vec<u8>>,
+        /// The epochs of the segments discarded.
+        discarded: Vec<u64>,
+    }
+
+    impl SegmentFactory for MemorySegments {
+        type Writer = Vec<u8>;
+
+        fn open(&mut self, _epoch: u64) -> IoResult<Self::Writer> {
+            Ok(Vec::new())
+        }
+
+        fn finalize(&mut self, epoch: u64, writer: Self::Writer) -> IoResult<()> {
+            assert!(self.segments.insert(epoch, writer).is_none());
+            Ok(())
+        }
+
+        fn discard(&mut self, epoch: u64, _writer: Self::Writer) -> IoResult<()> {
+            self.discarded.push(epoch);
+            Ok(())
+        }
+    }
+
+    /// Decode the updates contained in a segment.
+    fn decode(mut segment: &[u8]) -> Vec<u64> {
+        let mut updates = Vec::new();
+        while !segment.is_empty() {
+            updates.push(deserialize_from(&mut segment).unwrap());
+        }
+        updates
+    }
+
+    /// Send a transaction containing `updates` to `observer`.
+    fn send(observer: &mut dyn Observer<u64, String>, updates: Vec<u64>) {
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(updates.into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+    }
+
+    /// Check that every committed transaction ends up in a segment of
+    /// its own.
+    #[test]
+    fn segments() {
+        let mut segmenting = SegmentingObserver::new(MemorySegments::default());
+        let observer = &mut segmenting as &mut dyn Observer<u64, String>;
+
+        send(observer, vec![1, 2]);
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![3].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        send(observer, vec![4]);
+        assert_eq!(observer.on_completed(), Ok(()));
+        assert_eq!(segmenting.epoch(), 2);
+
+        let factory = segmenting.into_inner();
+        assert_eq!(factory.segments.len(), 2);
+        assert_eq!(decode(&factory.segments[&0]), vec![1, 2]);
+        assert_eq!(decode(&factory.segments[&1]), vec![4]);
+        assert_eq!(factory.discarded, vec![1]);
+    }
+
+    /// Check that segments are written to files named by their epoch.
+    #[test]
+    fn segment_files() {
+        let dir = tempdir().unwrap();
+        let mut segmenting = SegmentingObserver::new(SegmentFiles::new(dir.path())).first_epoch(7);
+        let observer = &mut segmenting as &mut dyn Observer<u64, String>;
+
+        send(observer, vec![1]);
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new(vec![2].into_iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+        send(observer, vec![3, 4]);
+
+        let mut names = read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "00000000000000000007.segment",
+                "00000000000000000008.segment"
+            ]
+        );
+
+        let segment = read(dir.path().join(&names[1])).unwrap();
+        assert_eq!(decode(&segment), vec![3, 4]);
+    }
+}
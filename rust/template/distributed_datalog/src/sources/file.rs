@@ -263,6 +263,10 @@ where
             None
         }
     }
+
+    fn subscriber_count(&self) -> usize {
+        usize::from(self.state.is_some())
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,168 @@
+//! A module providing the bookkeeping required for acknowledging
+//! transactions. A `TcpReceiver` acknowledges every transaction its
+//! observer committed successfully, and the `TcpSender` maps these
+//! acknowledgements back onto the transactions it was asked to commit.
+
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+/// The state shared between a `TcpSender` and the thread reading
+/// acknowledgements sent by the receiver.
+#[derive(Debug, Default)]
+struct State {
+    /// The sequence number of the last transaction committed.
+    sequence: u64,
+    /// The highest sequence number acknowledged by the receiver.
+    acked: u64,
+    /// The sequence numbers of transactions committed over the wire
+    /// but not yet acknowledged, in order.
+    inflight: VecDeque<u64>,
+    /// The number of commits sent over the wire that we have seen an
+    /// acknowledgement for (or that have been skipped by one).
+    wire_acked: u64,
+    /// Sequence numbers of transactions the receiver failed to commit.
+    failed: BTreeSet<u64>,
+    /// Whether the connection is gone and no more acknowledgements are
+    /// to be expected.
+    closed: bool,
+}
+
+/// An object tracking acknowledgements of committed transactions.
+#[derive(Debug, Default)]
+pub struct Acks {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+impl Acks {
+    /// Register a transaction commit, returning its sequence number.
+    ///
+    /// `sent` indicates whether the commit is going out over the wire
+    /// right away, as opposed to being buffered.
+    pub fn commit(&self, sent: bool) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.sequence += 1;
+        let sequence = state.sequence;
+        if sent {
+            state.inflight.push_back(sequence);
+        }
+        sequence
+    }
+
+    /// Register the flush of all buffered transactions.
+    ///
+    /// Buffered transactions are merged into a single one, which is
+    /// only sent at all if it is non-empty (as indicated by `sent`).
+    pub fn flush(&self, sent: bool) {
+        let mut state = self.state.lock().unwrap();
+        if sent {
+            let sequence = state.sequence;
+            state.inflight.push_back(sequence);
+        } else {
+            // There was nothing to be delivered, so there also is
+            // nothing the receiver would acknowledge.
+            state.acked = state.sequence;
+            self.cond.notify_all();
+        }
+    }
+
+    /// Handle an acknowledgement from the receiver.
+    ///
+    /// The receiver numbers the commits it sees on a connection and
+    /// only acknowledges the ones its observer processed successfully.
+    /// All commits preceding the acknowledged one that have not been
+    /// acknowledged themselves are hence considered failed.
+    pub fn ack(&self, wire_sequence: u64) {
+        let mut state = self.state.lock().unwrap();
+        while state.wire_acked < wire_sequence {
+            state.wire_acked += 1;
+            if let Some(sequence) = state.inflight.pop_front() {
+                if state.wire_acked == wire_sequence {
+                    state.acked = state.acked.max(sequence);
+                } else {
+                    let _ = state.failed.insert(sequence);
+                }
+            }
+        }
+        self.cond.notify_all();
+    }
+
+    /// Signal that no more acknowledgements will arrive.
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.cond.notify_all();
+    }
+
+    /// Retrieve the sequence number of the last committed transaction.
+    pub fn sequence(&self) -> u64 {
+        self.state.lock().unwrap().sequence
+    }
+
+    /// Retrieve the highest acknowledged sequence number.
+    pub fn acked(&self) -> u64 {
+        self.state.lock().unwrap().acked
+    }
+
+    /// Block until the transaction with the given sequence number has
+    /// been acknowledged.
+    pub fn await_ack(&self, sequence: u64) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.failed.contains(&sequence) {
+                return Err(format!(
+                    "receiver failed to commit transaction {}",
+                    sequence
+                ));
+            }
+            if state.acked >= sequence {
+                return Ok(());
+            }
+            if state.closed {
+                return Err(format!(
+                    "connection closed before transaction {} was acknowledged",
+                    sequence
+                ));
+            }
+            state = self.cond.wait(state).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that merged transactions are acknowledged together.
+    #[test]
+    fn merged_transactions() {
+        let acks = Acks::default();
+        assert_eq!(acks.commit(false), 1);
+        assert_eq!(acks.commit(false), 2);
+        acks.flush(true);
+        assert_eq!(acks.commit(true), 3);
+
+        acks.ack(1);
+        assert_eq!(acks.acked(), 2);
+        assert_eq!(acks.await_ack(1), Ok(()));
+
+        acks.ack(2);
+        assert_eq!(acks.await_ack(3), Ok(()));
+    }
+
+    /// Check that a skipped acknowledgement marks a transaction failed.
+    #[test]
+    fn failed_transaction() {
+        let acks = Acks::default();
+        let _ = acks.commit(true);
+        let _ = acks.commit(true);
+        acks.ack(2);
+
+        assert!(acks.await_ack(1).is_err());
+        assert_eq!(acks.await_ack(2), Ok(()));
+
+        acks.close();
+        assert!(acks.await_ack(3).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+//! A module providing the policy for spacing out repeated attempts at
+//! establishing a connection.
+
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::hash::Hasher;
+use std::time::Duration;
+
+/// The delay before the first retry, by default.
+const DEFAULT_INITIAL: Duration = Duration::from_millis(100);
+
+/// The upper bound on the delay between two attempts, by default.
+const DEFAULT_MAX: Duration = Duration::from_secs(30);
+
+/// A policy for spacing out repeated connection attempts, backing off
+/// exponentially with random jitter.
+///
+/// The delay before retry `n` (counting from zero) is `initial`
+/// multiplied by `multiplier` to the `n`th power, but at most `max`.
+/// A random fraction of up to `jitter` of that is then shaved off, so
+/// that peers losing their connections at the same time do not all
+/// retry in lockstep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Backoff {
+    /// The delay before the first retry.
+    pub initial: Duration,
+    /// The upper bound on the delay.
+    pub max: Duration,
+    /// The factor the delay grows by with every retry.
+    pub multiplier: u32,
+    /// The fraction of the delay, between zero and one, that is
+    /// randomized.
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: DEFAULT_INITIAL,
+            max: DEFAULT_MAX,
+            multiplier: 2,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl Backoff {
+    /// Compute the delay before retry `retry`, counting from zero.
+    pub fn delay(&self, retry: u32) -> Duration {
+        self.delay_with(retry, random())
+    }
+
+    /// Compute the delay before retry `retry`, jittered based on
+    /// `random`, which is expected to be in `[0, 1)`.
+    fn delay_with(&self, retry: u32, random: f64) -> Duration {
+        let delay = self
+            .initial
+            .checked_mul(self.multiplier.saturating_pow(retry))
+            .map_or(self.max, |delay| delay.min(self.max));
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        delay.mul_f64(1.0 - jitter * random)
+    }
+}
+
+/// Produce a random number in `[0, 1)`, drawn from the randomly keyed
+/// hasher of the standard library, which is good enough for jitter.
+fn random() -> f64 {
+    let bits = RandomState::new().build_hasher().finish() >> 11;
+    bits as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check the exponential growth and capping of delays.
+    #[test]
+    fn exponential() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(100),
+            multiplier: 3,
+            jitter: 0.0,
+        };
+
+        let delays = (0..5).map(|retry| backoff.delay(retry)).collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(10),
+                Duration::from_millis(30),
+                Duration::from_millis(90),
+                Duration::from_millis(100),
+                Duration::from_millis(100),
+            ]
+        );
+        // The delay must not overflow for many retries.
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_millis(100));
+    }
+
+    /// Check that jitter shortens delays by up to the configured
+    /// fraction.
+    #[test]
+    fn jitter() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            jitter: 0.5,
+            ..Default::default()
+        };
+
+        assert_eq!(backoff.delay_with(0, 0.0), Duration::from_millis(100));
+        assert_eq!(backoff.delay_with(0, 0.5), Duration::from_millis(75));
+        assert_eq!(backoff.delay_with(1, 0.5), Duration::from_millis(150));
+
+        for _ in 0..100 {
+            let delay = backoff.delay(0);
+            assert!(delay > Duration::from_millis(50), "{:?}", delay);
+            assert!(delay <= Duration::from_millis(100), "{:?}", delay);
+        }
+    }
+}
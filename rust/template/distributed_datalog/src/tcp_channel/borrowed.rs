@@ -0,0 +1,304 @@
+//! A module providing a TCP receiver decoding updates into types
+//! borrowing from the received data.
+
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bincode::deserialize;
+use bincode::Result as BincodeResult;
+
+use log::trace;
+
+use serde::Deserialize;
+
+use uid::Id;
+
+use crate::observe::Observer;
+use crate::observe::SharedObserver;
+use crate::observe::UpdatesObservable;
+use crate::poison::MutexExt;
+use crate::tcp_channel::builder::Config;
+use crate::tcp_channel::message::Message;
+use crate::tcp_channel::receiver::relay;
+use crate::tcp_channel::receiver::Acceptor;
+use crate::tcp_channel::receiver::ConnectionEvent;
+use crate::tcp_channel::receiver::Dispatch;
+use crate::tcp_channel::receiver::Event;
+use crate::tcp_channel::receiver::ObserverSignal;
+use crate::tcp_channel::receiver::Session;
+use crate::tcp_channel::receiver::Source;
+use crate::tcp_channel::TcpReceiverBuilder;
+
+/// A family of update types that may borrow from the data they are
+/// deserialized from, for any lifetime of said data.
+///
+/// For example, updates sent as `String` or `Vec<u8>` may be received
+/// as `&str` or `&[u8]`, respectively:
+/// ```ignore
+/// struct Bytes;
+///
+/// impl<'de> BorrowedItem<'de> for Bytes {
+///     type Item = &'de [u8];
+/// }
+/// ```
+pub trait BorrowedItem<'de> {
+    /// The update type, borrowing from data of lifetime `'de`.
+    type Item: Deserialize<'de> + Send;
+}
+
+/// A `Dispatch` decoding messages into updates borrowing from the
+/// frame they are contained in.
+struct BorrowedDispatch<F, O> {
+    observer: SharedObserver<O>,
+    _phantom: PhantomData<fn() -> F>,
+}
+
+impl<F, O> Debug for BorrowedDispatch<F, O>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("BorrowedDispatch")
+            .field("observer", &self.observer)
+            .finish()
+    }
+}
+
+impl<F, O> Dispatch for BorrowedDispatch<F, O>
+where
+    F: for<'de> BorrowedItem<'de>,
+    O: for<'de> Observer<<F as BorrowedItem<'de>>::Item, String> + Debug + Send,
+{
+    fn dispatch(
+        &mut self,
+        frame: &[u8],
+        session: &mut Session,
+    ) -> BincodeResult<(Event, Result<(), String>)> {
+        let message = deserialize::<Message<<F as BorrowedItem<'_>>::Item>>(frame)?;
+        let mut observer = self.observer.lock_unpoisoned();
+        Ok(relay(message, &mut *observer, session))
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        self.observer.on_abort()
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        self.observer.on_disconnected()
+    }
+}
+
+/// The receiving end of a TCP channel decoding updates into types
+/// borrowing from the received data, instead of allocating owned
+/// copies.
+///
+/// Updates only live for the duration of the `on_updates` call they
+/// are delivered with, which is why the observer is provided up front
+/// and has to accept updates of any lifetime. Because there is no
+/// owned data that could be buffered, connections are not multiplexed
+/// but processed one after the other, in the order they were accepted.
+#[derive(Debug)]
+pub struct BorrowedTcpReceiver<F, O> {
+    /// The TCP receiver's unique ID.
+    id: usize,
+    /// The machinery accepting connections and processing the data
+    /// arriving on them.
+    acceptor: Acceptor,
+    /// The observer all connections relay their updates to.
+    observer: SharedObserver<O>,
+    _phantom: PhantomData<fn() -> F>,
+}
+
+impl<F, O> BorrowedTcpReceiver<F, O>
+where
+    F: for<'de> BorrowedItem<'de> + 'static,
+    O: for<'de> Observer<<F as BorrowedItem<'de>>::Item, String> + Debug + Send + 'static,
+{
+    /// Create a new TCP receiver listening on `addr` and relaying
+    /// everything it receives to `observer`.
+    pub fn new<A>(addr: A, observer: O) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        TcpReceiverBuilder::new(addr).build_borrowed(observer)
+    }
+
+    /// Create a new TCP receiver with the given configuration,
+    /// accepting connections on `listener`.
+    pub(crate) fn with_config(
+        listener: TcpListener,
+        config: Config,
+        observer: O,
+    ) -> Result<Self, String> {
+        let id = Id::<()>::new().get();
+        trace!("BorrowedTcpReceiver({})::new", id);
+
+        let observer = Arc::new(Mutex::new(observer));
+        let copy = observer.clone();
+        let connect = move |_: &_| {
+            Some(BorrowedDispatch::<F, O> {
+                observer: copy.clone(),
+                _phantom: PhantomData,
+            })
+        };
+        let acceptor = Acceptor::new(id, Source::Listen(listener), config, connect)?;
+
+        Ok(Self {
+            id,
+            acceptor,
+            observer,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<F, O> BorrowedTcpReceiver<F, O> {
+    /// Retrieve the address we are listening on.
+    pub fn addr(&self) -> &SocketAddr {
+        let addr = self.acceptor.addr();
+        trace!("BorrowedTcpReceiver({})::addr: {}", self.id, addr);
+        addr
+    }
+
+    /// Retrieve the observer we relay updates to.
+    pub fn observer(&self) -> &SharedObserver<O> {
+        &self.observer
+    }
+
+    /// Retrieve the number of transactions of the sender identifying
+    /// itself as `sender` the observer committed successfully, as
+    /// announced to the sender for resumption.
+    pub fn last_processed_seq(&self, sender: &str) -> u64 {
+        self.acceptor.counters().processed(sender)
+    }
+
+    /// Retrieve the handle the observer may use to signal the receiver.
+    /// See `TcpReceiver::signal`.
+    pub fn signal(&self) -> ObserverSignal {
+        self.acceptor.signal().clone()
+    }
+
+    /// Create an observable emitting an event whenever a sender
+    /// connects or disconnects. See `TcpReceiver::connection_events`.
+    pub fn connection_events(&self) -> UpdatesObservable<ConnectionEvent, String> {
+        self.acceptor.connection_events()
+    }
+
+    /// Block until at least `count` commits have been delivered to the
+    /// observer, failing if that did not happen within `timeout`.
+    pub fn wait_for_commit(&self, count: u64, timeout: Duration) -> Result<(), String> {
+        self.acceptor.counters().await_commits(count, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_env_log::test;
+
+    use crate::MockObserver;
+    use crate::TcpSender;
+
+    /// Updates received as byte slices.
+    #[derive(Debug)]
+    struct Bytes;
+
+    impl<'de> BorrowedItem<'de> for Bytes {
+        type Item = &'de [u8];
+    }
+
+    /// An observer remembering the total length of the byte slices it
+    /// received.
+    #[derive(Debug, Default)]
+    struct LengthObserver {
+        length: usize,
+        commits: usize,
+    }
+
+    impl<'a> Observer<&'a [u8], String> for LengthObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.commits += 1;
+            Ok(())
+        }
+
+        fn on_updates<'b>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = &'a [u8]> + 'b>,
+        ) -> Result<(), String> {
+            self.length += updates.map(<[u8]>::len).sum::<usize>();
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Transmit owned byte vectors and receive them as slices.
+    #[test]
+    fn borrowed_bytes() {
+        let recv =
+            BorrowedTcpReceiver::<Bytes, _>::new("127.0.0.1:0", LengthObserver::default()).unwrap();
+
+        let mut send = TcpSender::<Vec<u8>>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<Vec<u8>, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![vec![1, 2, 3], vec![4; 16]].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+        recv.wait_for_commit(1, Duration::from_secs(10)).unwrap();
+
+        let observer = recv.observer().lock().unwrap();
+        assert_eq!(observer.length, 19);
+        assert_eq!(observer.commits, 1);
+    }
+
+    /// Check that a transaction aborted by the sender is aborted on the
+    /// receiving end as well, without getting in the way of the next.
+    #[test]
+    fn abort() {
+        let recv =
+            BorrowedTcpReceiver::<Bytes, _>::new("127.0.0.1:0", MockObserver::new()).unwrap();
+
+        let mut send = TcpSender::<Vec<u8>>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+        let observer = &mut send as &mut dyn Observer<Vec<u8>, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![vec![1]].into_iter()))
+            .unwrap();
+        observer.on_abort().unwrap();
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![vec![2]].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+        recv.wait_for_commit(1, Duration::from_secs(10)).unwrap();
+
+        let observer = recv.observer().lock().unwrap();
+        assert_eq!(observer.called_on_abort, 1);
+        assert_eq!(observer.called_on_start, 2);
+        assert_eq!(observer.called_on_commit, 1);
+    }
+}
@@ -0,0 +1,915 @@
+//! A module providing a builder for configuring `TcpReceiver` (as well
+//! as `BorrowedTcpReceiver`, `RingTcpReceiver`, and `TcpRelay`)
+//! objects.
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::ToSocketAddrs;
+use std::os::unix::io::AsRawFd;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use crate::observe::Observer;
+use crate::tcp_channel::codec::BincodeCodec;
+use crate::tcp_channel::codec::Codec;
+use crate::tcp_channel::receiver::ErrorCallback;
+use crate::tcp_channel::receiver::Source;
+use crate::tcp_channel::socket::bind;
+use crate::tcp_channel::socket::set_backlog;
+use crate::tcp_channel::token::Token;
+use crate::tcp_channel::Backoff;
+use crate::tcp_channel::BorrowedItem;
+use crate::tcp_channel::BorrowedTcpReceiver;
+use crate::tcp_channel::EmptyBatchPolicy;
+use crate::tcp_channel::EofPolicy;
+use crate::tcp_channel::FixedLayout;
+use crate::tcp_channel::IpNet;
+use crate::tcp_channel::ReceiverError;
+use crate::tcp_channel::RestartPolicy;
+use crate::tcp_channel::RingTcpReceiver;
+use crate::tcp_channel::StallPolicy;
+use crate::tcp_channel::TcpDemuxReceiver;
+use crate::tcp_channel::TcpReceiver;
+use crate::tcp_channel::TcpRelay;
+
+/// The number of consecutive messages that may fail to decode before
+/// we consider a connection corrupted and close it, by default.
+pub(crate) const DEFAULT_MAX_DECODE_FAILURES: usize = 16;
+
+/// The maximum size of a single message, in bytes, by default.
+pub(crate) const DEFAULT_MAX_FRAME_SIZE: usize = 1 << 30;
+
+/// How long dropping a receiver waits for its threads to exit, by
+/// default.
+pub(crate) const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The number of attempts a relay makes at (re-)establishing its
+/// downstream connection, by default.
+pub(crate) const DEFAULT_RECONNECT_ATTEMPTS: usize = 8;
+
+/// The delay before the second attempt at establishing a relay's
+/// downstream connection, by default.
+pub(crate) const DEFAULT_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// How long a relay waits for the downstream acknowledgement of a
+/// commit, by default.
+pub(crate) const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The configuration of a `TcpReceiver`.
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    /// The maximum number of concurrently processed connections, if
+    /// any.
+    pub max_connections: Option<usize>,
+    /// The number of consecutive messages that may fail to decode
+    /// before a connection is closed.
+    pub max_decode_failures: usize,
+    /// The maximum size of a single message, in bytes. A sender
+    /// announcing a larger message is disconnected.
+    pub max_frame_size: usize,
+    /// The number of messages read ahead of their processing, per
+    /// connection.
+    pub prefetch: usize,
+    /// How to handle a transaction restarted while still open.
+    pub restart: RestartPolicy,
+    /// What to tell the observer when a sender closes its connection.
+    pub eof: EofPolicy,
+    /// How long a transaction may stay open before being aborted, if
+    /// set.
+    pub transaction_timeout: Option<Duration>,
+    /// How to handle a transaction that timed out.
+    pub stall: StallPolicy,
+    /// The maximum number of updates passed to the observer at once,
+    /// if set explicitly.
+    pub max_batch_len: Option<usize>,
+    /// How to handle a batch without any updates.
+    pub empty_batches: EmptyBatchPolicy,
+    /// Whether an IPv6 listener socket accepts IPv6 connections only,
+    /// if set explicitly.
+    pub only_v6: Option<bool>,
+    /// The backlog of the listener socket, if set explicitly.
+    pub backlog: Option<u32>,
+    /// How long dropping the receiver waits for its threads to exit
+    /// before detaching them.
+    pub shutdown_timeout: Duration,
+    /// How often a relay tries to (re-)establish its downstream
+    /// connection before giving up.
+    pub reconnect_attempts: usize,
+    /// The delay before a relay's second connection attempt; doubled
+    /// for each subsequent one.
+    pub reconnect_delay: Duration,
+    /// How long a relay waits for the downstream acknowledgement of a
+    /// commit.
+    pub ack_timeout: Duration,
+    /// Whether a relay skips the replay of a transaction the
+    /// downstream receiver reports as processed after reconnecting.
+    pub resume: bool,
+    /// The name of the threads accepting and processing connections,
+    /// if set explicitly.
+    pub thread_name: Option<String>,
+    /// The stack size of the threads accepting and processing
+    /// connections, in bytes, if set explicitly.
+    pub stack_size: Option<usize>,
+    /// The niceness of the threads accepting and processing
+    /// connections, if set explicitly.
+    pub nice: Option<i32>,
+    /// The version senders have to announce, if any.
+    pub version: Option<String>,
+    /// The token senders have to present, if any.
+    pub token: Option<Token>,
+    /// How a receiver connecting to a sender spaces out its attempts
+    /// at (re-)establishing the connection.
+    pub backoff: Backoff,
+    /// The callback errors are reported to, if any.
+    pub on_error: Option<ErrorCallback>,
+    /// The networks peers have to be part of for their connections to
+    /// be accepted, if restricted.
+    pub allowlist: Option<Vec<IpNet>>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            max_decode_failures: DEFAULT_MAX_DECODE_FAILURES,
+            max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            prefetch: 0,
+            restart: RestartPolicy::Abort,
+            eof: EofPolicy::EmitDisconnected,
+            transaction_timeout: None,
+            stall: StallPolicy::Abort,
+            max_batch_len: None,
+            empty_batches: EmptyBatchPolicy::Suppress,
+            only_v6: None,
+            backlog: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            reconnect_attempts: DEFAULT_RECONNECT_ATTEMPTS,
+            reconnect_delay: DEFAULT_RECONNECT_DELAY,
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            resume: false,
+            thread_name: None,
+            stack_size: None,
+            nice: None,
+            version: None,
+            token: None,
+            backoff: Backoff::default(),
+            on_error: None,
+            allowlist: None,
+        }
+    }
+}
+
+/// The source of the listener socket a `TcpReceiver` accepts
+/// connections on.
+#[derive(Debug)]
+enum Listen {
+    /// Bind to one of the given addresses.
+    Addr(Result<Vec<SocketAddr>, String>),
+    /// Use an already bound listener.
+    Listener(TcpListener),
+    /// Connect to a listening sender at the given address instead.
+    Dial(Result<SocketAddr, String>),
+}
+
+impl Listen {
+    /// Retrieve the source of connections to process, binding the
+    /// listener socket if necessary, as per `config`.
+    fn into_source(self, config: &Config) -> Result<Source, String> {
+        match self {
+            Listen::Dial(addr) => Ok(Source::Dial(addr?)),
+            listen => listen
+                .into_listener(config.only_v6, config.backlog)
+                .map(Source::Listen),
+        }
+    }
+
+    /// Retrieve the listener socket, binding it if necessary, with
+    /// `IPV6_V6ONLY` set as per `only_v6` and the backlog adjusted to
+    /// `backlog`, if set.
+    fn into_listener(
+        self,
+        only_v6: Option<bool>,
+        backlog: Option<u32>,
+    ) -> Result<TcpListener, String> {
+        let listener = self.into_bound_listener(only_v6)?;
+        if let Some(backlog) = backlog {
+            let backlog = libc::c_int::try_from(backlog).unwrap_or(libc::c_int::MAX);
+            set_backlog(listener.as_raw_fd(), backlog)
+                .map_err(|e| format!("failed to set listen backlog: {}", e))?;
+        }
+        Ok(listener)
+    }
+
+    /// Retrieve the listener socket, binding it if necessary, with
+    /// `IPV6_V6ONLY` set as per `only_v6`.
+    fn into_bound_listener(self, only_v6: Option<bool>) -> Result<TcpListener, String> {
+        match self {
+            Listen::Addr(addrs) => {
+                let addrs = addrs?;
+                let result = match only_v6 {
+                    None => TcpListener::bind(addrs.as_slice()),
+                    // Just like `TcpListener::bind`, use the first
+                    // address we can bind to, reporting the last error
+                    // otherwise.
+                    Some(only_v6) => {
+                        let mut result = Err(Error::new(
+                            ErrorKind::InvalidInput,
+                            "could not resolve to any addresses",
+                        ));
+                        for addr in &addrs {
+                            result = bind(addr, Some(only_v6));
+                            if result.is_ok() {
+                                break;
+                            }
+                        }
+                        result
+                    }
+                };
+                result.map_err(|e| format!("failed to bind TCP socket: {}", e))
+            }
+            Listen::Listener(listener) => Ok(listener),
+            Listen::Dial(..) => {
+                Err("connecting to a sender is only supported by TcpReceiver".to_string())
+            }
+        }
+    }
+}
+
+/// Bind a new listener socket to `addr`, configured as per `config`.
+pub(crate) fn bind_listener(addr: SocketAddr, config: &Config) -> Result<TcpListener, String> {
+    Listen::Addr(Ok(vec![addr])).into_listener(config.only_v6, config.backlog)
+}
+
+/// A builder for `TcpReceiver` objects.
+///
+/// All options default to the behavior of `TcpReceiver::new`.
+#[derive(Debug)]
+pub struct TcpReceiverBuilder {
+    /// Where to get the listener socket from.
+    listen: Listen,
+    /// The configuration of the receiver to build.
+    config: Config,
+}
+
+impl TcpReceiverBuilder {
+    /// Create a new builder for a receiver listening on `addr`.
+    ///
+    /// Errors resolving the address are reported by `build`.
+    pub fn new<A>(addr: A) -> Self
+    where
+        A: ToSocketAddrs,
+    {
+        let addrs = addr
+            .to_socket_addrs()
+            .map(|addrs| addrs.collect())
+            .map_err(|e| format!("failed to resolve address: {}", e));
+
+        Self::with_listen(Listen::Addr(addrs))
+    }
+
+    /// Create a new builder for a receiver using an already bound
+    /// listener socket.
+    pub fn from_listener(listener: TcpListener) -> Self {
+        Self::with_listen(Listen::Listener(listener))
+    }
+
+    /// Create a new builder for a receiver connecting to the sender
+    /// listening on `addr`, instead of listening itself, e.g., for
+    /// traversing a NAT. The connection is re-established whenever it
+    /// drops, as per the configured `backoff`.
+    ///
+    /// Errors resolving the address are reported by `build`.
+    pub fn connect<A>(addr: A) -> Self
+    where
+        A: ToSocketAddrs,
+    {
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(|e| format!("failed to resolve address: {}", e))
+            .and_then(|mut addrs| {
+                addrs
+                    .next()
+                    .ok_or_else(|| "failed to resolve address: no addresses found".to_string())
+            });
+
+        Self::with_listen(Listen::Dial(addr))
+    }
+
+    fn with_listen(listen: Listen) -> Self {
+        Self {
+            listen,
+            config: Config::default(),
+        }
+    }
+
+    /// Set the maximum number of connections processed concurrently.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.config.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Set the number of consecutive messages that may fail to decode
+    /// before a connection is considered corrupted and closed.
+    pub fn max_decode_failures(mut self, max_decode_failures: usize) -> Self {
+        self.config.max_decode_failures = max_decode_failures;
+        self
+    }
+
+    /// Set the maximum size of a single message, in bytes. A sender
+    /// announcing a larger message is considered misbehaving and
+    /// disconnected.
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.config.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Set how to handle a sender starting a new transaction while its
+    /// previous one is still open, as happens if it restarted.
+    pub fn restart_policy(mut self, restart: RestartPolicy) -> Self {
+        self.config.restart = restart;
+        self
+    }
+
+    /// Set what the observer learns about a sender closing its
+    /// connection without signaling completion first, as some senders
+    /// do to end the stream, or breaking it. By default, the disconnect
+    /// is signaled on their behalf, which observers treat as completion
+    /// unless they tell the two apart.
+    pub fn eof_policy(mut self, eof: EofPolicy) -> Self {
+        self.config.eof = eof;
+        self
+    }
+
+    /// Set how long a sender may keep a transaction open before it is
+    /// aborted, measured from its start, and how to deal with the
+    /// sender after that. Without a timeout, which is the default, a
+    /// sender that stopped in the middle of a transaction without
+    /// closing its connection leaves the transaction open forever.
+    pub fn transaction_timeout(mut self, timeout: Duration, stall: StallPolicy) -> Self {
+        self.config.transaction_timeout = Some(timeout);
+        self.config.stall = stall;
+        self
+    }
+
+    /// Report errors as they are encountered to `callback`, i.e.,
+    /// failures to accept a connection, to decode a message, and of the
+    /// observer to process an event, as well as connections rejected
+    /// as per the allowlist, in addition to logging them.
+    ///
+    /// The callback is invoked on the threads accepting and processing
+    /// connections, without any locks held, and holds up the thread it
+    /// is invoked on until it returns.
+    pub fn on_error<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(ReceiverError) + Send + Sync + 'static,
+    {
+        self.config.on_error = Some(ErrorCallback::new(callback));
+        self
+    }
+
+    /// Only accept connections from peers whose address is part of one
+    /// of the networks in `allow`. Connections from other peers are
+    /// closed right after being accepted, before reading anything from
+    /// them, with the rejection being logged and reported to the error
+    /// callback. Note that this provides coarse protection at the
+    /// network level only; it does not authenticate senders.
+    pub fn allowlist(mut self, allow: Vec<IpNet>) -> Self {
+        self.config.allowlist = Some(allow);
+        self
+    }
+
+    /// Set the number of messages read ahead of their processing, per
+    /// connection. With a non-zero count, every connection gets a
+    /// thread of its own reading messages from the socket into a queue
+    /// of that many messages, while the processing thread dispatches
+    /// the messages queued, overlapping the receipt of messages with
+    /// the work of the observer. Messages are still decoded on the
+    /// processing thread. Zero disables reading ahead, which is the
+    /// default.
+    pub fn prefetch(mut self, count: usize) -> Self {
+        self.config.prefetch = count;
+        self
+    }
+
+    /// Set the maximum number of updates passed to the observer in a
+    /// single `on_updates` call. Larger batches received from a sender
+    /// are split up, so that the observer can process a jumbo
+    /// transaction incrementally instead of all at once. The
+    /// transaction still starts and commits as a whole. Zero means no
+    /// limit, which is the default.
+    pub fn max_batch_len(mut self, max_batch_len: usize) -> Self {
+        self.config.max_batch_len = Some(max_batch_len);
+        self
+    }
+
+    /// Set whether a batch without any updates, as some senders emit
+    /// for a transaction that did not change anything, is passed on to
+    /// the observer. By default such batches are suppressed, sparing
+    /// the observer a call without anything to do. Relays forward
+    /// whatever they receive either way.
+    pub fn empty_batch_policy(mut self, empty_batches: EmptyBatchPolicy) -> Self {
+        self.config.empty_batches = empty_batches;
+        self
+    }
+
+    /// Set whether an IPv6 listener socket accepts IPv6 connections
+    /// only (`true`) or IPv4 ones as well (`false`), i.e., is dual-stack.
+    /// By default the system's default behavior applies, which varies.
+    /// Has no effect on IPv4 addresses or already bound listeners.
+    pub fn only_v6(mut self, only_v6: bool) -> Self {
+        self.config.only_v6 = Some(only_v6);
+        self
+    }
+
+    /// Set the listen backlog, i.e., the maximum number of connections
+    /// queued up for being accepted, beyond which new ones are refused
+    /// or dropped. Raising it helps absorb bursts of senders connecting
+    /// at once, e.g., when reconnecting after a network partition
+    /// healed. By default the backlog is 128 (or left as is, for an
+    /// already bound listener). The system may cap the value, e.g.,
+    /// Linux at `net.core.somaxconn`.
+    pub fn backlog(mut self, backlog: u32) -> Self {
+        self.config.backlog = Some(backlog);
+        self
+    }
+
+    /// Set how long dropping the receiver waits for the threads
+    /// processing connections to exit. Threads still running after
+    /// that, e.g., because an observer is stuck, are detached.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.config.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Set how often a relay tries to (re-)establish its downstream
+    /// connection and the delay before the second attempt, which is
+    /// doubled for each subsequent one. Only relevant for `TcpRelay`s.
+    pub fn reconnect(mut self, attempts: usize, delay: Duration) -> Self {
+        self.config.reconnect_attempts = attempts;
+        self.config.reconnect_delay = delay;
+        self
+    }
+
+    /// Set how long a relay waits for the downstream acknowledgement
+    /// of a commit before considering it failed. Only relevant for
+    /// `TcpRelay`s.
+    pub fn ack_timeout(mut self, timeout: Duration) -> Self {
+        self.config.ack_timeout = timeout;
+        self
+    }
+
+    /// Set whether a relay resumes after the transactions the
+    /// downstream receiver reports as processed when reconnecting,
+    /// instead of replaying the transaction in progress in any case.
+    /// Off by default. Only relevant for `TcpRelay`s.
+    ///
+    /// The relay identifies itself to the receiver with an identity of
+    /// its own for every connection it forwards, for which the receiver
+    /// reports the transactions processed. A `TcpSender` does not
+    /// identify itself and never resumes, regardless of this setting.
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.config.resume = resume;
+        self
+    }
+
+    /// Set the name of the threads accepting and processing
+    /// connections, as shown by debuggers and profilers. Defaults to
+    /// `tcp-recv-<addr>`. Note that some systems truncate thread names,
+    /// e.g., Linux to 15 bytes.
+    pub fn thread_name<S>(mut self, name: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.thread_name = Some(name.into());
+        self
+    }
+
+    /// Set the stack size of the threads accepting and processing
+    /// connections, in bytes, e.g., to accommodate deep chains of
+    /// observers. Defaults to the standard library's default for
+    /// spawned threads.
+    pub fn stack_size(mut self, bytes: usize) -> Self {
+        self.config.stack_size = Some(bytes);
+        self
+    }
+
+    /// Set the version of the data we expect to receive, e.g., that of
+    /// the schema of the updates. Every sender has to announce the very
+    /// same version before sending anything else (see
+    /// `TcpSender::new_versioned`), or its connection is refused. A
+    /// relay in turn announces the version to the downstream receiver.
+    pub fn version<S>(mut self, version: S) -> Self
+    where
+        S: Into<String>,
+    {
+        self.config.version = Some(version.into());
+        self
+    }
+
+    /// Require senders to authenticate by presenting `token` (see
+    /// `TcpSender::with_token`) before sending anything else, including
+    /// a version. The connection of a sender presenting a different
+    /// token, or none at all, is closed without a word, and nothing it
+    /// sent reaches the observer.
+    pub fn require_token<B>(mut self, token: B) -> Self
+    where
+        B: Into<Vec<u8>>,
+    {
+        self.config.token = Some(Token::new(token));
+        self
+    }
+
+    /// Set how a receiver connecting to a sender spaces out its
+    /// attempts at (re-)establishing the connection. Only relevant for
+    /// receivers created via `connect`.
+    pub fn backoff(mut self, backoff: Backoff) -> Self {
+        self.config.backoff = backoff;
+        self
+    }
+
+    /// Set the niceness of the threads accepting and processing
+    /// connections, e.g., to keep a busy receiver from starving more
+    /// important work. By default, the niceness of the thread creating
+    /// the receiver is inherited. Raising it above that usually
+    /// requires privileges; a failure to set it is logged.
+    ///
+    /// Note that niceness applies to individual threads on Linux only,
+    /// while other systems may apply it to the entire process.
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.config.nice = Some(nice);
+        self
+    }
+
+    /// Build the configured `TcpReceiver`.
+    pub fn build<T, D>(self) -> Result<TcpReceiver<T, D>, String>
+    where
+        T: Send + Debug + 'static,
+        D: Into<T> + Send + Debug + 'static,
+        BincodeCodec: Codec<D>,
+    {
+        self.build_with_codec(BincodeCodec)
+    }
+
+    /// Build the configured receiver, decoding messages using `codec`
+    /// instead of bincode. Control frames, such as tokens, version
+    /// announcements, and acknowledgements, are not affected by the
+    /// codec (see `Codec`).
+    pub fn build_with_codec<T, D, C>(self, codec: C) -> Result<TcpReceiver<T, D, C>, String>
+    where
+        T: Send + Debug + 'static,
+        D: Into<T> + Send + Debug + 'static,
+        C: Codec<D>,
+    {
+        let source = self.listen.into_source(&self.config)?;
+        TcpReceiver::with_config(source, self.config, codec)
+    }
+
+    /// Build the configured receiver as a `BorrowedTcpReceiver`,
+    /// relaying everything it receives to `observer`.
+    ///
+    /// Because transactions are not buffered by such a receiver, it
+    /// processes only a single connection at a time, regardless of
+    /// the configured maximum.
+    pub fn build_borrowed<F, O>(mut self, observer: O) -> Result<BorrowedTcpReceiver<F, O>, String>
+    where
+        F: for<'de> BorrowedItem<'de> + 'static,
+        O: for<'de> Observer<<F as BorrowedItem<'de>>::Item, String> + Debug + Send + 'static,
+    {
+        let listener = self
+            .listen
+            .into_listener(self.config.only_v6, self.config.backlog)?;
+        self.config.max_connections = Some(1);
+        BorrowedTcpReceiver::with_config(listener, self.config, observer)
+    }
+
+    /// Build the configured receiver as a `TcpDemuxReceiver`, routing
+    /// the messages of multiplexed streams to the observers subscribed
+    /// to them.
+    pub fn build_demux<T>(self) -> Result<TcpDemuxReceiver<T>, String>
+    where
+        T: DeserializeOwned + Send + Debug + 'static,
+    {
+        let source = self.listen.into_source(&self.config)?;
+        TcpDemuxReceiver::with_config(source, self.config)
+    }
+
+    /// Build the configured receiver as a `RingTcpReceiver`, reading
+    /// up to `slots` records at once and relaying them to `observer`.
+    pub fn build_ring<F, O>(
+        self,
+        slots: usize,
+        observer: O,
+    ) -> Result<RingTcpReceiver<F, O>, String>
+    where
+        F: for<'a> FixedLayout<'a> + 'static,
+        O: for<'a> Observer<<F as FixedLayout<'a>>::Item, String> + Send + 'static,
+    {
+        let listener = self
+            .listen
+            .into_listener(self.config.only_v6, self.config.backlog)?;
+        RingTcpReceiver::with_config(listener, self.config, slots, observer)
+    }
+
+    /// Build the configured receiver as a `TcpRelay`, forwarding
+    /// everything it receives to the receiver at `downstream`.
+    pub fn build_relay(self, downstream: SocketAddr) -> Result<TcpRelay, String> {
+        let listener = self
+            .listen
+            .into_listener(self.config.only_v6, self.config.backlog)?;
+        TcpRelay::with_config(listener, self.config, downstream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread::current;
+
+    use test_env_log::test;
+
+    use crate::observe::Observable;
+    use crate::observe::Observer;
+    use crate::MockObserver;
+    use crate::TcpSender;
+
+    /// Build a receiver with non-default options and transmit a
+    /// transaction to it.
+    #[test]
+    fn build_configured() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .max_connections(1)
+            .max_decode_failures(1)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        assert_eq!(mock.lock().unwrap().called_on_updates, 2);
+    }
+
+    /// Build a receiver with all options set via `TcpReceiver::builder`
+    /// and check that they take effect together.
+    #[test]
+    fn build_all_options() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::builder("127.0.0.1:0")
+            .max_connections(2)
+            .max_decode_failures(4)
+            .max_frame_size(64)
+            .restart_policy(RestartPolicy::Abort)
+            .shutdown_timeout(Duration::from_secs(1))
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2, 3].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        // A batch exceeding the maximum frame size gets the connection
+        // closed.
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new((0..16).collect::<Vec<_>>().into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        assert!(send.await_ack(2).is_err());
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 3);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// An observer remembering the sizes of the batches of updates it
+    /// received.
+    #[derive(Debug, Default)]
+    struct BatchObserver {
+        batches: Vec<usize>,
+        called_on_start: usize,
+        called_on_commit: usize,
+    }
+
+    impl Observer<u64, String> for BatchObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            self.called_on_start += 1;
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.called_on_commit += 1;
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            self.batches.push(updates.count());
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that large batches of updates are split up as configured,
+    /// while still being delivered as a single transaction.
+    #[test]
+    fn split_large_batches() {
+        let observer = Arc::new(Mutex::new(BatchObserver::default()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .max_batch_len(4)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(observer.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let sender = &mut send as &mut dyn Observer<u64, _>;
+        sender.on_start().unwrap();
+        sender
+            .on_updates(Box::new((0..10).collect::<Vec<_>>().into_iter()))
+            .unwrap();
+        sender.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let observer = observer.lock().unwrap();
+        assert_eq!(observer.batches, vec![4, 4, 2]);
+        assert_eq!(observer.called_on_start, 1);
+        assert_eq!(observer.called_on_commit, 1);
+    }
+
+    /// Check that an unresolvable address is reported when building.
+    #[test]
+    fn build_invalid_addr() {
+        let result = TcpReceiverBuilder::new("invalid address").build::<u64, u64>();
+        assert!(result.is_err());
+    }
+
+    /// Transmit a transaction to the given receiver, connecting to it
+    /// via `addr`.
+    fn transmit(recv: &mut TcpReceiver<u64, u64>, addr: SocketAddr) {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+        let _ = recv.unsubscribe(&());
+    }
+
+    /// Check that receivers can be bound to IPv6 as well as IPv4
+    /// loopback addresses.
+    #[test]
+    fn bind_loopback() {
+        for addr in &["[::1]:0", "127.0.0.1:0"] {
+            let mut recv = TcpReceiverBuilder::new(addr)
+                .only_v6(true)
+                .build::<u64, u64>()
+                .unwrap();
+            let addr = *recv.addr();
+            transmit(&mut recv, addr);
+        }
+    }
+
+    /// Check that `IPV6_V6ONLY` is honored when binding to the IPv6
+    /// wildcard address.
+    #[test]
+    fn bind_dual_stack() {
+        let mut recv = TcpReceiverBuilder::new("[::]:0")
+            .only_v6(false)
+            .build::<u64, u64>()
+            .unwrap();
+        let port = recv.addr().port();
+        transmit(&mut recv, SocketAddr::from(([127, 0, 0, 1], port)));
+        transmit(
+            &mut recv,
+            SocketAddr::from(([0, 0, 0, 0, 0, 0, 0, 1], port)),
+        );
+
+        let recv = TcpReceiverBuilder::new("[::]:0")
+            .only_v6(true)
+            .build::<u64, u64>()
+            .unwrap();
+        let port = recv.addr().port();
+        assert!(TcpStream::connect(("::1", port)).is_ok());
+        assert!(TcpStream::connect(("127.0.0.1", port)).is_err());
+    }
+
+    /// Check that the configured backlog limits the connections queued
+    /// on the listener, which Linux caps at one more than the backlog.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn listen_backlog() {
+        let recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .max_connections(1)
+            .backlog(1)
+            .build::<u64, u64>()
+            .unwrap();
+        let addr = *recv.addr();
+
+        // The first connection gets accepted and occupies the only
+        // slot, so that the ones after it stay queued.
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        send.wait_connected().unwrap();
+
+        let timeout = Duration::from_millis(250);
+        let queued = (0..4)
+            .map(|_| TcpStream::connect_timeout(&addr, timeout))
+            .filter_map(Result::ok)
+            .collect::<Vec<_>>();
+        assert_eq!(queued.len(), 2);
+    }
+
+    /// An observer remembering the name and niceness of the thread
+    /// committing a transaction.
+    #[derive(Debug, Default)]
+    struct ThreadObserver(Option<(Option<String>, i32)>);
+
+    impl Observer<u64, String> for ThreadObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            let name = current().name().map(String::from);
+            let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+            self.0 = Some((name, nice));
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that the configured thread name and niceness apply to the
+    /// threads processing connections (and that configuring their
+    /// stack size does not get in the way).
+    #[test]
+    fn thread_name_and_nice() {
+        // Unprivileged users may lower the priority of threads only.
+        let nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) } + 1;
+        let observer = Arc::new(Mutex::new(ThreadObserver::default()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .thread_name("recv-test")
+            .stack_size(4 << 20)
+            .nice(nice)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(observer.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let sender = &mut send as &mut dyn Observer<u64, _>;
+        sender.on_start().unwrap();
+        sender.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        sender.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let (name, niceness) = observer.lock().unwrap().0.clone().unwrap();
+        assert_eq!(name.as_deref(), Some("recv-test"));
+        assert_eq!(niceness, nice);
+    }
+}
@@ -0,0 +1,118 @@
+//! A module providing access to the sockets of the connections a
+//! receiver is processing.
+
+use std::collections::BTreeMap;
+use std::io::Result as IoResult;
+use std::net::SocketAddr;
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::poison::MutexExt;
+use crate::tcp_channel::socket::pending_bytes;
+use crate::tcp_channel::socket::set_linger;
+
+/// A handle to a connection a `TcpReceiver` is processing, allowing
+/// for inspecting and tweaking its socket.
+///
+/// The handle refers to a duplicate of the socket the connection is
+/// processed on, so changes take effect right away, on the live
+/// connection. Socket options are safe to change concurrently with
+/// the thread reading from the socket. Once the connection got closed,
+/// operations fail (or report stale information).
+#[derive(Clone, Debug)]
+pub struct Connection(Arc<TcpStream>);
+
+impl Connection {
+    /// Retrieve the address of the remote end of the connection.
+    pub fn peer_addr(&self) -> IoResult<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    /// Retrieve the address of the local end of the connection.
+    pub fn local_addr(&self) -> IoResult<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    /// Check whether Nagle's algorithm is disabled on the connection.
+    pub fn nodelay(&self) -> IoResult<bool> {
+        self.0.nodelay()
+    }
+
+    /// Disable (or enable) Nagle's algorithm on the connection, i.e.,
+    /// send small segments right away instead of coalescing them.
+    pub fn set_nodelay(&self, nodelay: bool) -> IoResult<()> {
+        self.0.set_nodelay(nodelay)
+    }
+
+    /// Retrieve the number of bytes that arrived on the connection
+    /// but have yet to be read by the receiver, i.e., that are sitting
+    /// in the kernel's receive buffer.
+    pub fn pending_bytes(&self) -> IoResult<usize> {
+        pending_bytes(self.0.as_raw_fd())
+    }
+
+    /// Set how long closing the connection lingers to send data still
+    /// queued (at second granularity), with `None` disabling lingering.
+    pub fn set_linger(&self, linger: Option<Duration>) -> IoResult<()> {
+        set_linger(self.0.as_raw_fd(), linger)
+    }
+}
+
+/// The connections a receiver is processing.
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    /// The connections, by the key they got registered under.
+    connections: Mutex<BTreeMap<u64, Connection>>,
+    /// The key to register the next connection under.
+    next: AtomicU64,
+}
+
+impl Connections {
+    /// Register the connection using `socket`, for as long as the
+    /// returned `Registration` is alive.
+    pub(crate) fn register(self: &Arc<Self>, socket: &TcpStream) -> IoResult<Registration> {
+        let connection = Connection(Arc::new(socket.try_clone()?));
+        let key = self.next.fetch_add(1, Ordering::Relaxed);
+        let _ = self.connections.lock_unpoisoned().insert(key, connection);
+        Ok(Registration {
+            connections: self.clone(),
+            key,
+        })
+    }
+
+    /// Retrieve the connections, in the order they got registered.
+    pub(crate) fn list(&self) -> Vec<Connection> {
+        self.connections
+            .lock_unpoisoned()
+            .values()
+            .cloned()
+            .collect()
+    }
+}
+
+/// The registration of a connection with `Connections`, which is
+/// revoked when dropped, even if the thread processing the connection
+/// panics. Otherwise the duplicate of the socket we hold on to would
+/// keep the connection from being closed.
+#[derive(Debug)]
+pub(crate) struct Registration {
+    /// The connections we registered with.
+    connections: Arc<Connections>,
+    /// The key we got registered under.
+    key: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        let _ = self
+            .connections
+            .connections
+            .lock_unpoisoned()
+            .remove(&self.key);
+    }
+}
@@ -0,0 +1,334 @@
+//! A module providing a receiver for several logical streams sharing
+//! the same connections.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use bincode::deserialize;
+use bincode::Result as BincodeResult;
+
+use log::trace;
+
+use serde::de::DeserializeOwned;
+
+use uid::Id;
+
+use crate::observe::Observable;
+use crate::observe::Observer;
+use crate::observe::ObserverBox;
+use crate::poison::MutexExt;
+use crate::tcp_channel::builder::Config;
+use crate::tcp_channel::message::Message;
+use crate::tcp_channel::receiver::relay;
+use crate::tcp_channel::receiver::Acceptor;
+use crate::tcp_channel::receiver::Dispatch;
+use crate::tcp_channel::receiver::Event;
+use crate::tcp_channel::receiver::RestartPolicy;
+use crate::tcp_channel::receiver::Session;
+use crate::tcp_channel::receiver::Source;
+use crate::tcp_channel::TcpReceiverBuilder;
+use crate::txnmux::TxnMux;
+
+/// The streams a `TcpDemuxReceiver` routes messages to, by stream ID.
+type Streams<T> = Mutex<BTreeMap<u64, TxnMux<T, String>>>;
+
+/// Retrieve the multiplexer of the stream with the given ID, creating
+/// it if necessary.
+fn stream<T>(
+    streams: &mut BTreeMap<u64, TxnMux<T, String>>,
+    stream_id: u64,
+) -> &mut TxnMux<T, String>
+where
+    T: Debug + Send + 'static,
+{
+    streams.entry(stream_id).or_insert_with(TxnMux::new)
+}
+
+/// A `Dispatch` decoding `Message::Framed` messages and relaying the
+/// contained messages to the streams they belong to.
+#[derive(Debug)]
+struct DemuxDispatch<T>
+where
+    T: Debug + Send,
+{
+    /// The streams we route messages to.
+    streams: Arc<Streams<T>>,
+    /// The observers buffering the transactions of the streams seen on
+    /// the connection, along with the state of each stream.
+    outlets: BTreeMap<u64, (ObserverBox<T, String>, Session)>,
+    /// How to handle a restarted transaction of a stream.
+    restart: RestartPolicy,
+}
+
+impl<T> Dispatch for DemuxDispatch<T>
+where
+    T: Debug + DeserializeOwned + Send + 'static,
+{
+    fn dispatch(
+        &mut self,
+        frame: &[u8],
+        session: &mut Session,
+    ) -> BincodeResult<(Event, Result<(), String>)> {
+        let (stream_id, inner) = match deserialize::<Message<T>>(frame)? {
+            Message::Framed { stream_id, inner } => (stream_id, *inner),
+            message => {
+                let e = format!("unexpected unframed {} message", message);
+                return Ok((Event::Framed, Err(e)));
+            }
+        };
+
+        let streams = &self.streams;
+        let restart = self.restart;
+        let max_batch_len = session.max_batch_len;
+        let empty_batches = session.empty_batches;
+        let (observer, state) = self.outlets.entry(stream_id).or_insert_with(|| {
+            // Each stream buffers the transactions of every connection
+            // separately, so that they are delivered atomically even if
+            // several connections contribute to it.
+            let observer = stream(&mut streams.lock_unpoisoned(), stream_id).create_observer();
+            let state = Session {
+                max_batch_len,
+                empty_batches,
+                ..Session::new(restart)
+            };
+            (observer, state)
+        });
+        state.frame_bytes = session.frame_bytes;
+        let dispatched = relay::<T, T, _>(inner, observer, state);
+
+        // The connection is in a transaction as long as any of its
+        // streams is, so that it gets aborted once the sender is gone.
+        session.open = self.outlets.values().any(|(_, state)| state.open);
+        Ok(dispatched)
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        // Every open stream gets aborted, even if another one failed to.
+        let mut result = Ok(());
+        for (observer, state) in self.outlets.values_mut() {
+            if state.open {
+                state.open = false;
+                result = result.and(observer.on_abort());
+            }
+        }
+        result
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        let mut result = Ok(());
+        for (observer, state) in self.outlets.values_mut() {
+            if !state.completed {
+                state.completed = true;
+                result = result.and(observer.on_completed());
+            }
+        }
+        result
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        let mut result = Ok(());
+        for (observer, state) in self.outlets.values_mut() {
+            if !state.completed {
+                state.completed = true;
+                result = result.and(observer.on_disconnected());
+            }
+        }
+        result
+    }
+}
+
+/// The receiving end of TCP channels carrying several logical streams
+/// over the same connection.
+///
+/// Senders wrap every message in a `Message::Framed`, tagged with the
+/// ID of the stream it belongs to, and the receiver routes the
+/// contained message to the observer subscribed to that stream.
+/// Transactions are per stream, so that the transactions of different
+/// streams may interleave on a connection, and each stream's
+/// transactions are delivered to its observer atomically, just as by a
+/// `TcpReceiver`. Every committed transaction, of any stream, is
+/// acknowledged, with the sequence number counting the commits on the
+/// connection. Messages for a stream nobody subscribed to are dropped.
+#[derive(Debug)]
+pub struct TcpDemuxReceiver<T>
+where
+    T: Debug + Send,
+{
+    /// The receiver's unique ID.
+    id: usize,
+    /// The machinery accepting connections and processing the data
+    /// arriving on them.
+    acceptor: Acceptor,
+    /// The streams we route messages to.
+    streams: Arc<Streams<T>>,
+}
+
+impl<T> TcpDemuxReceiver<T>
+where
+    T: Debug + DeserializeOwned + Send + 'static,
+{
+    /// Create a new demultiplexing receiver listening on `addr`.
+    pub fn new<A>(addr: A) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        TcpReceiverBuilder::new(addr).build_demux()
+    }
+
+    /// Create a new demultiplexing receiver with the given
+    /// configuration, accepting connections from `source`.
+    pub(crate) fn with_config(source: Source, config: Config) -> Result<Self, String> {
+        let id = Id::<()>::new().get();
+        trace!("TcpDemuxReceiver({})::new", id);
+
+        let streams = Arc::new(Mutex::new(BTreeMap::new()));
+        let copy = streams.clone();
+        let restart = config.restart;
+        let connect = move |_: &_| {
+            Some(DemuxDispatch {
+                streams: copy.clone(),
+                outlets: BTreeMap::new(),
+                restart,
+            })
+        };
+        let acceptor = Acceptor::new(id, source, config, connect)?;
+
+        Ok(Self {
+            id,
+            acceptor,
+            streams,
+        })
+    }
+
+    /// Retrieve the address we are listening on (or connecting to, for
+    /// a receiver created via `TcpReceiverBuilder::connect`).
+    pub fn addr(&self) -> &SocketAddr {
+        let addr = self.acceptor.addr();
+        trace!("TcpDemuxReceiver({})::addr: {}", self.id, addr);
+        addr
+    }
+
+    /// Subscribe `observer` to the stream with the given ID. A stream
+    /// has at most one observer subscribed at a time.
+    pub fn subscribe_stream(
+        &mut self,
+        stream_id: u64,
+        observer: ObserverBox<T, String>,
+    ) -> Result<(), ObserverBox<T, String>> {
+        trace!(
+            "TcpDemuxReceiver({})::subscribe_stream: {}",
+            self.id,
+            stream_id
+        );
+        stream(&mut self.streams.lock_unpoisoned(), stream_id).subscribe(observer)
+    }
+
+    /// Unsubscribe the observer subscribed to the stream with the given
+    /// ID, if any.
+    pub fn unsubscribe_stream(&mut self, stream_id: u64) -> Option<ObserverBox<T, String>> {
+        trace!(
+            "TcpDemuxReceiver({})::unsubscribe_stream: {}",
+            self.id,
+            stream_id
+        );
+        self.streams
+            .lock_unpoisoned()
+            .get_mut(&stream_id)
+            .and_then(|stream| stream.unsubscribe(&()))
+    }
+
+    /// Block until at least `count` commits, of any stream, have been
+    /// processed, failing if that did not happen within `timeout`.
+    pub fn wait_for_commit(&self, count: u64, timeout: Duration) -> Result<(), String> {
+        self.acceptor.counters().await_commits(count, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+    use std::net::TcpStream;
+
+    use test_env_log::test;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::tcp_channel::frame::read_frame;
+    use crate::tcp_channel::frame::write_frame;
+
+    /// Wrap `message` for the stream with the given ID.
+    fn framed(stream_id: u64, message: Message<u64>) -> Message<u64> {
+        Message::Framed {
+            stream_id,
+            inner: Box::new(message),
+        }
+    }
+
+    /// Check that two streams interleaved on a single connection are
+    /// delivered to the observers subscribed to them.
+    #[test]
+    fn interleaved_streams() {
+        let first = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let second = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpDemuxReceiver::<u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe_stream(1, Box::new(first.clone())).unwrap();
+        recv.subscribe_stream(2, Box::new(second.clone())).unwrap();
+        assert!(recv.subscribe_stream(2, Box::new(first.clone())).is_err());
+
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut frame = Vec::new();
+        let mut read = || {
+            assert!(read_frame(&mut reader, &mut frame, 64).unwrap());
+            deserialize::<Message<()>>(&frame).unwrap()
+        };
+
+        let messages = vec![
+            framed(1, Message::Start),
+            framed(2, Message::Start),
+            framed(1, Message::Updates(vec![1, 2])),
+            framed(2, Message::Updates(vec![10])),
+            // Nobody listens to the third stream.
+            framed(3, Message::Start),
+            framed(3, Message::Updates(vec![100])),
+            framed(3, Message::Commit),
+            framed(2, Message::Commit),
+            framed(1, Message::Updates(vec![3])),
+            framed(1, Message::Commit),
+        ];
+        for message in &messages {
+            write_frame(&mut socket, message).unwrap();
+        }
+        assert_eq!(read(), Message::Ack(1));
+        assert_eq!(read(), Message::Ack(2));
+        assert_eq!(read(), Message::Ack(3));
+
+        {
+            let first = first.lock().unwrap();
+            assert_eq!(first.received_updates, vec![1, 2, 3]);
+            assert_eq!(first.called_on_commit, 1);
+            let second = second.lock().unwrap();
+            assert_eq!(second.received_updates, vec![10]);
+            assert_eq!(second.called_on_commit, 1);
+        }
+
+        // Messages lacking a stream ID are refused.
+        write_frame(&mut socket, &Message::<u64>::Start).unwrap();
+        write_frame(&mut socket, &framed(2, Message::Start)).unwrap();
+        write_frame(&mut socket, &framed(2, Message::Updates(vec![11]))).unwrap();
+        write_frame(&mut socket, &framed(2, Message::Commit)).unwrap();
+        assert_eq!(read(), Message::Ack(4));
+        assert_eq!(second.lock().unwrap().received_updates, vec![10, 11]);
+        assert_eq!(first.lock().unwrap().called_on_start, 1);
+
+        assert!(recv.unsubscribe_stream(2).is_some());
+        assert!(recv.unsubscribe_stream(2).is_none());
+        assert!(recv.unsubscribe_stream(4).is_none());
+    }
+}
@@ -0,0 +1,684 @@
+//! A module providing a TCP relay, forwarding the messages received
+//! from senders to a downstream receiver without decoding the updates
+//! they contain.
+
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::mem::replace;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::thread::sleep;
+use std::time::Duration;
+
+use bincode::deserialize;
+use bincode::Result as BincodeResult;
+
+use log::debug;
+use log::trace;
+use log::warn;
+
+use uid::Id;
+use uuid::Uuid;
+
+use crate::observe::UpdatesObservable;
+use crate::tcp_channel::builder::Config;
+use crate::tcp_channel::frame::read_frame;
+use crate::tcp_channel::frame::write_frame;
+use crate::tcp_channel::message::Kind;
+use crate::tcp_channel::message::Message;
+use crate::tcp_channel::receiver::Acceptor;
+use crate::tcp_channel::receiver::ConnectionEvent;
+use crate::tcp_channel::receiver::Dispatch;
+use crate::tcp_channel::receiver::Event;
+use crate::tcp_channel::receiver::RestartPolicy;
+use crate::tcp_channel::receiver::Session;
+use crate::tcp_channel::receiver::Source;
+use crate::tcp_channel::TcpReceiverBuilder;
+
+/// An established connection to the downstream receiver.
+#[derive(Debug)]
+struct Link {
+    /// The buffered writing end of the connection.
+    writer: BufWriter<TcpStream>,
+    /// The reading end of the connection, providing acknowledgements.
+    reader: BufReader<TcpStream>,
+    /// The number of commits sent over this connection.
+    commits: u64,
+    /// The number of our transactions the receiver reported as
+    /// processed when the connection got established, if we asked for
+    /// it.
+    resumed: Option<u64>,
+}
+
+/// The outcome of waiting for the acknowledgement of a commit.
+#[derive(Debug)]
+enum Acked {
+    /// The commit got acknowledged.
+    Yes,
+    /// The receiver did not acknowledge the commit in time, i.e., its
+    /// observer most likely failed to commit.
+    No,
+}
+
+/// A `Dispatch` forwarding the raw frames received on a connection to
+/// a downstream receiver, over a connection of its own.
+#[derive(Debug)]
+struct Forward {
+    /// The unique ID of the relay we work for.
+    id: usize,
+    /// The address of the downstream receiver.
+    addr: SocketAddr,
+    /// The connection to the downstream receiver, if established.
+    link: Option<Link>,
+    /// The frames of the transaction in progress, to be replayed in
+    /// case the connection has to be re-established.
+    pending: Vec<Vec<u8>>,
+    /// How often to try (re-)establishing the connection.
+    attempts: usize,
+    /// The delay before the first reconnection attempt; doubled for
+    /// each subsequent one.
+    delay: Duration,
+    /// How long to wait for the acknowledgement of a commit.
+    ack_timeout: Duration,
+    /// The maximum size of an acknowledgement frame.
+    max_frame_size: usize,
+    /// Whether to resume after the transactions the downstream
+    /// receiver reports as processed when reconnecting.
+    resume: bool,
+    /// The identity we present to the downstream receiver for
+    /// resuming, unique to the connection we forward.
+    identity: String,
+    /// The version to announce to the downstream receiver, if any.
+    version: Option<String>,
+    /// The position of the last transaction we got acknowledged in the
+    /// downstream receiver's sequence of processed transactions; only
+    /// tracked if resuming.
+    sequence: Option<u64>,
+}
+
+impl Forward {
+    /// Create a new `Forward` relaying to the receiver at `addr`.
+    fn new(id: usize, addr: SocketAddr, config: &Config) -> Self {
+        Self {
+            id,
+            addr,
+            link: None,
+            pending: Vec::new(),
+            attempts: config.reconnect_attempts,
+            delay: config.reconnect_delay,
+            ack_timeout: config.ack_timeout,
+            max_frame_size: config.max_frame_size,
+            resume: config.resume,
+            identity: Uuid::new_v4().to_string(),
+            version: config.version.clone(),
+            sequence: None,
+        }
+    }
+
+    /// Connect to the downstream receiver.
+    fn connect(&self) -> Result<Link, String> {
+        let socket = TcpStream::connect(self.addr)
+            .map_err(|e| format!("failed to connect to {}: {}", self.addr, e))?;
+        // Frames are flushed explicitly and we wait for the
+        // acknowledgement of every commit, so do not let them linger.
+        socket
+            .set_nodelay(true)
+            .map_err(|e| format!("failed to disable Nagle's algorithm: {}", e))?;
+        socket
+            .set_read_timeout(Some(self.ack_timeout))
+            .map_err(|e| format!("failed to set read timeout: {}", e))?;
+        let reader = socket
+            .try_clone()
+            .map_err(|e| format!("failed to clone socket: {}", e))?;
+
+        debug!("TcpRelay({}): connected to {}", self.id, self.addr);
+        let mut link = Link {
+            writer: BufWriter::new(socket),
+            reader: BufReader::new(reader),
+            commits: 0,
+            resumed: None,
+        };
+        if let Some(version) = &self.version {
+            Self::write(&mut link, &[Self::frame(Message::Version(version.clone()))])?;
+        }
+        if self.resume {
+            Self::write(
+                &mut link,
+                &[Self::frame(Message::Identify(self.identity.clone()))],
+            )?;
+            link.resumed = Some(Self::await_resume(&mut link, self.max_frame_size)?);
+        }
+        Ok(link)
+    }
+
+    /// Check whether the downstream receiver, as reported on the fresh
+    /// connection `link`, processed a transaction beyond the last one
+    /// we got acknowledged, i.e., the one whose commit we sent before
+    /// the previous connection broke.
+    fn delivered(&mut self, link: &Link) -> bool {
+        match link.resumed {
+            Some(resumed) => {
+                // The first connection establishes our position in the
+                // receiver's sequence of transactions.
+                let sequence = *self.sequence.get_or_insert(resumed);
+                resumed > sequence
+            }
+            None => false,
+        }
+    }
+
+    /// Register the acknowledgement of a commit.
+    fn acknowledged(&mut self) {
+        if let Some(sequence) = self.sequence.as_mut() {
+            *sequence += 1;
+        }
+    }
+
+    /// Transmit `frames` to the downstream receiver, (re-)establishing
+    /// the connection as necessary and waiting for the acknowledgement
+    /// of a commit if `commit` is set.
+    ///
+    /// If `replay` is set, `frames` are expected to be the tail of
+    /// `pending`, i.e., on a fresh connection they are sent as part of
+    /// the replay of the open transaction.
+    fn transmit(&mut self, frames: &[Vec<u8>], replay: bool, commit: bool) -> Result<(), String> {
+        let mut delay = self.delay;
+        let mut error = String::new();
+
+        for attempt in 0..self.attempts {
+            if attempt > 0 {
+                sleep(delay);
+                delay *= 2;
+            }
+
+            let (mut link, frames) = match self.link.take() {
+                Some(link) => (link, frames),
+                None => match self.connect() {
+                    Ok(link) => {
+                        if self.delivered(&link) && commit {
+                            // The receiver processed the transaction
+                            // already, only its acknowledgement got
+                            // lost along with the connection.
+                            debug!(
+                                "TcpRelay({}): skipping replay of transaction processed by {}",
+                                self.id, self.addr
+                            );
+                            self.acknowledged();
+                            self.link = Some(link);
+                            return Ok(());
+                        }
+                        // On a fresh connection the open transaction is
+                        // replayed in its entirety, which includes
+                        // `frames`.
+                        if replay {
+                            (link, &self.pending[..])
+                        } else {
+                            (link, frames)
+                        }
+                    }
+                    Err(e) => {
+                        error = e;
+                        continue;
+                    }
+                },
+            };
+
+            let result = Self::write(&mut link, frames).and_then(|_| {
+                if commit {
+                    link.commits += 1;
+                    Self::await_ack(&mut link, self.max_frame_size)
+                } else {
+                    Ok(Acked::Yes)
+                }
+            });
+            match result {
+                Ok(acked) => {
+                    self.link = Some(link);
+                    return match acked {
+                        Acked::Yes => {
+                            if commit {
+                                self.acknowledged();
+                            }
+                            Ok(())
+                        }
+                        Acked::No => Err(format!(
+                            "commit was not acknowledged within {:?}",
+                            self.ack_timeout
+                        )),
+                    };
+                }
+                Err(e) => {
+                    warn!(
+                        "TcpRelay({}): connection to {} broke: {}",
+                        self.id, self.addr, e
+                    );
+                    error = e;
+                }
+            }
+        }
+        Err(format!(
+            "failed to relay to {} after {} attempts: {}",
+            self.addr, self.attempts, error
+        ))
+    }
+
+    /// Write and flush `frames`.
+    fn write(link: &mut Link, frames: &[Vec<u8>]) -> Result<(), String> {
+        for frame in frames {
+            link.writer
+                .write_all(frame)
+                .map_err(|e| format!("failed to send message: {}", e))?;
+        }
+        link.writer
+            .flush()
+            .map_err(|e| format!("failed to flush messages: {}", e))
+    }
+
+    /// Wait for the acknowledgement of the last commit sent over
+    /// `link`. Acknowledgements arriving late for earlier commits are
+    /// skipped.
+    fn await_ack(link: &mut Link, max_frame_size: usize) -> Result<Acked, String> {
+        let mut buffer = Vec::new();
+        loop {
+            match read_frame(&mut link.reader, &mut buffer, max_frame_size) {
+                Ok(true) => (),
+                Ok(false) => return Err("connection closed by receiver".to_string()),
+                Err(e) => match e.kind() {
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut => return Ok(Acked::No),
+                    ErrorKind::Interrupted => continue,
+                    _ => return Err(format!("failed to read acknowledgement: {}", e)),
+                },
+            }
+
+            match deserialize::<Message<()>>(&buffer) {
+                Ok(Message::Ack(sequence)) if sequence >= link.commits => return Ok(Acked::Yes),
+                Ok(Message::Ack(_)) | Ok(Message::Resume(_)) => (),
+                Ok(message) => return Err(format!("received unexpected {} message", message)),
+                Err(e) => return Err(format!("failed to decode acknowledgement: {}", e)),
+            }
+        }
+    }
+
+    /// Wait for the header a receiver replies to our identification
+    /// with, reporting the number of our transactions it processed.
+    fn await_resume(link: &mut Link, max_frame_size: usize) -> Result<u64, String> {
+        let mut buffer = Vec::new();
+        loop {
+            match read_frame(&mut link.reader, &mut buffer, max_frame_size) {
+                Ok(true) => (),
+                Ok(false) => return Err("connection closed by receiver".to_string()),
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(format!("failed to read resume header: {}", e)),
+            }
+
+            return match deserialize::<Message<()>>(&buffer) {
+                Ok(Message::Resume(sequence)) => Ok(sequence),
+                Ok(message) => Err(format!("received unexpected {} message", message)),
+                Err(e) => Err(format!("failed to decode resume header: {}", e)),
+            };
+        }
+    }
+
+    /// Encode a message without updates as a frame.
+    fn frame(message: Message<()>) -> Vec<u8> {
+        let mut frame = Vec::new();
+        // Encoding a message without payload into memory cannot fail.
+        write_frame(&mut frame, &message).unwrap();
+        frame
+    }
+
+    /// Forward the frame of a message of the given kind.
+    fn forward(&mut self, frame: Vec<u8>, kind: Kind) -> Result<(), String> {
+        match kind {
+            Kind::Start => {
+                self.pending = vec![frame];
+                self.transmit(&self.pending.clone(), true, false)
+            }
+            Kind::Updates | Kind::UpdateList | Kind::Snapshot => {
+                self.pending.push(frame);
+                let frames = [self.pending.last().unwrap().clone()];
+                self.transmit(&frames, true, false)
+            }
+            Kind::Commit => {
+                self.pending.push(frame);
+                let frames = [self.pending.last().unwrap().clone()];
+                let result = self.transmit(&frames, true, true);
+                self.pending.clear();
+                result
+            }
+            // A flush ends the partial transaction without a commit to
+            // await the acknowledgement of.
+            Kind::Flush => {
+                self.pending.push(frame);
+                let frames = [self.pending.last().unwrap().clone()];
+                let result = self.transmit(&frames, true, false);
+                self.pending.clear();
+                result
+            }
+            Kind::Abort => {
+                self.pending.clear();
+                // There is no need to re-establish a broken connection
+                // just to abort: the receiver aborts the transaction
+                // on its own once the connection is gone, and a fresh
+                // one has no transaction open.
+                if let Some(link) = self.link.as_mut() {
+                    if let Err(e) = Self::write(link, &[frame]) {
+                        warn!("TcpRelay({}): failed to forward abort: {}", self.id, e);
+                        self.link = None;
+                    }
+                }
+                Ok(())
+            }
+            Kind::Complete => self.transmit(&[frame], false, false),
+            Kind::Ack => Err("unexpected acknowledgement".to_string()),
+            Kind::Resume => Err("unexpected resume header".to_string()),
+            // The upstream sender's version got checked already, if we
+            // care, and we announce our own downstream.
+            Kind::Version => Ok(()),
+            Kind::Framed => Err("multiplexed streams cannot be relayed".to_string()),
+            Kind::Identify => Err("unexpected identification".to_string()),
+        }
+    }
+}
+
+impl Dispatch for Forward {
+    fn dispatch(
+        &mut self,
+        frame: &[u8],
+        session: &mut Session,
+    ) -> BincodeResult<(Event, Result<(), String>)> {
+        let kind = Kind::of(frame)?;
+        let event = match kind {
+            Kind::Start => {
+                if replace(&mut session.open, true) {
+                    match session.restart {
+                        RestartPolicy::Abort => {
+                            if let Err(e) = self.forward(Self::frame(Message::Abort), Kind::Abort) {
+                                let e = format!("failed to abort restarted transaction: {}", e);
+                                return Ok((Event::Start, Err(e)));
+                            }
+                        }
+                        RestartPolicy::Reject => {
+                            let e = "transaction restarted while still open".to_string();
+                            return Ok((Event::Restart, Err(e)));
+                        }
+                    }
+                }
+                Event::Start
+            }
+            Kind::Updates | Kind::UpdateList => Event::Updates,
+            Kind::Snapshot => {
+                if !session.open {
+                    let e = "snapshot outside of a transaction".to_string();
+                    return Ok((Event::Snapshot, Err(e)));
+                }
+                Event::Snapshot
+            }
+            Kind::Commit => {
+                session.open = false;
+                Event::Commit
+            }
+            Kind::Abort => {
+                session.open = false;
+                Event::Abort
+            }
+            Kind::Flush => {
+                session.open = false;
+                Event::Flush
+            }
+            Kind::Complete => {
+                session.completed = true;
+                Event::Complete
+            }
+            Kind::Ack => Event::Ack,
+            Kind::Resume => Event::Resume,
+            Kind::Version => Event::Version,
+            Kind::Framed => Event::Framed,
+            Kind::Identify => Event::Identify,
+        };
+
+        let mut forwarded = Vec::with_capacity(frame.len() + 4);
+        forwarded.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        forwarded.extend_from_slice(frame);
+        Ok((event, self.forward(forwarded, kind)))
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        self.forward(Self::frame(Message::Abort), Kind::Abort)
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        self.forward(Self::frame(Message::Complete), Kind::Complete)
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        // There is no message for a disconnect, so we signal completion
+        // downstream, just as the sender would have.
+        self.on_completed()
+    }
+}
+
+/// A relay accepting connections from `TcpSender`s and forwarding
+/// everything it receives to a downstream `TcpReceiver` (or another
+/// relay).
+///
+/// Messages are forwarded as they were received, without decoding
+/// the updates they contain, i.e., the relay is agnostic of the update
+/// type. Every connection accepted is forwarded over a connection of
+/// its own. A commit is acknowledged to the sender only once the
+/// downstream receiver acknowledged it, which makes acknowledgements
+/// end-to-end and propagates backpressure from the downstream receiver
+/// to the sender.
+///
+/// Should the downstream connection break, it is re-established and
+/// the transaction in progress is replayed on it, from its start. A
+/// transaction whose commit was sent but not acknowledged before the
+/// connection broke may hence be delivered twice, unless the relay is
+/// configured to resume (see `TcpReceiverBuilder::resume`), in which
+/// case the receiver's report of the transactions it processed is
+/// consulted first.
+#[derive(Debug)]
+pub struct TcpRelay {
+    /// The relay's unique ID.
+    id: usize,
+    /// The machinery accepting connections and processing the data
+    /// arriving on them.
+    acceptor: Acceptor,
+    /// The address of the downstream receiver.
+    downstream: SocketAddr,
+}
+
+impl TcpRelay {
+    /// Create a new relay listening on `addr` and forwarding to the
+    /// receiver at `downstream`.
+    pub fn new<A>(addr: A, downstream: SocketAddr) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        TcpReceiverBuilder::new(addr).build_relay(downstream)
+    }
+
+    /// Create a new relay with the given configuration, accepting
+    /// connections on `listener`.
+    pub(crate) fn with_config(
+        listener: TcpListener,
+        config: Config,
+        downstream: SocketAddr,
+    ) -> Result<Self, String> {
+        let id = Id::<()>::new().get();
+        trace!("TcpRelay({})::new: {}", id, downstream);
+
+        let copy = config.clone();
+        let connect = move |_: &_| Some(Forward::new(id, downstream, &copy));
+        let acceptor = Acceptor::new(id, Source::Listen(listener), config, connect)?;
+
+        Ok(Self {
+            id,
+            acceptor,
+            downstream,
+        })
+    }
+
+    /// Retrieve the address we are listening on.
+    pub fn addr(&self) -> &SocketAddr {
+        let addr = self.acceptor.addr();
+        trace!("TcpRelay({})::addr: {}", self.id, addr);
+        addr
+    }
+
+    /// Retrieve the address of the downstream receiver.
+    pub fn downstream(&self) -> &SocketAddr {
+        &self.downstream
+    }
+
+    /// Retrieve the number of transactions of the sender identifying
+    /// itself as `sender` committed and acknowledged downstream, as
+    /// announced to the sender for resumption.
+    pub fn last_processed_seq(&self, sender: &str) -> u64 {
+        self.acceptor.counters().processed(sender)
+    }
+
+    /// Create an observable emitting an event whenever a sender
+    /// connects or disconnects. See `TcpReceiver::connection_events`.
+    pub fn connection_events(&self) -> UpdatesObservable<ConnectionEvent, String> {
+        self.acceptor.connection_events()
+    }
+
+    /// Block until at least `count` commits have been forwarded (and
+    /// acknowledged downstream, if successful), failing if that did
+    /// not happen within `timeout`.
+    pub fn wait_for_commit(&self, count: u64, timeout: Duration) -> Result<(), String> {
+        self.acceptor.counters().await_commits(count, timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread::spawn;
+
+    use test_env_log::test;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::observe::Observable;
+    use crate::observe::Observer;
+    use crate::TcpReceiver;
+    use crate::TcpSender;
+
+    /// Send a transaction through a sender and wait for its end-to-end
+    /// acknowledgement.
+    fn transmit(send: &mut TcpSender<u64>, updates: Vec<u64>, sequence: u64) {
+        let observer = send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(updates.into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(sequence).unwrap();
+    }
+
+    /// Chain a receiver to a sender through two relays.
+    #[test]
+    fn relay_chain() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let second = TcpRelay::new("127.0.0.1:0", *recv.addr()).unwrap();
+        let first = TcpRelay::new("127.0.0.1:0", *second.addr()).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*first.addr()).unwrap();
+        transmit(&mut send, vec![3, 1, 2], 1);
+        transmit(&mut send, vec![5, 4], 2);
+
+        // The acknowledgements are end-to-end, so the receiver has
+        // seen both transactions already.
+        assert_eq!(recv.committed_count(), 2);
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_commit, 2);
+        assert_eq!(mock.received_updates, vec![3, 1, 2, 5, 4]);
+    }
+
+    /// Check that a relay keeps trying to connect to a downstream
+    /// receiver that is not (yet) available.
+    #[test]
+    fn relay_reconnect() {
+        // Reserve an address for the receiver, which is started only
+        // after the relay tried to connect to it already.
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let relay = TcpReceiverBuilder::new("127.0.0.1:0")
+            .reconnect(10, Duration::from_millis(50))
+            .build_relay(addr)
+            .unwrap();
+
+        let mut send = TcpSender::<u64>::new(*relay.addr()).unwrap();
+        let thread = spawn(move || {
+            transmit(&mut send, vec![1, 2], 1);
+            send
+        });
+
+        sleep(Duration::from_millis(100));
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new(addr).unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let _send = thread.join().unwrap();
+        assert_eq!(mock.lock().unwrap().received_updates, vec![1, 2]);
+    }
+
+    /// Encode a message as a frame.
+    fn frame(message: &Message<u64>) -> Vec<u8> {
+        let mut frame = Vec::new();
+        write_frame(&mut frame, message).unwrap();
+        frame
+    }
+
+    /// Check that a resuming relay does not replay a transaction the
+    /// downstream receiver processed already after reconnecting.
+    #[test]
+    fn relay_resume() {
+        for &resume in [false, true].iter() {
+            let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+            let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+            recv.subscribe(Box::new(mock.clone())).unwrap();
+
+            let config = Config {
+                resume,
+                ..Default::default()
+            };
+            let mut forward = Forward::new(0, *recv.addr(), &config);
+            let frames = vec![
+                (frame(&Message::Start), Kind::Start),
+                (frame(&Message::Updates(vec![1, 2])), Kind::Updates),
+                (frame(&Message::Commit), Kind::Commit),
+            ];
+            for (frame, kind) in frames.clone() {
+                forward.forward(frame, kind).unwrap();
+            }
+            assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+
+            // Pretend that the connection broke after the commit got
+            // sent but before its acknowledgement arrived.
+            forward.link = None;
+            forward.sequence = forward.sequence.map(|sequence| sequence - 1);
+            forward.pending = frames[..2].iter().map(|(f, _)| f.clone()).collect();
+            let (commit, kind) = frames[2].clone();
+            forward.forward(commit, kind).unwrap();
+
+            let expected = if resume { 1 } else { 2 };
+            assert_eq!(mock.lock().unwrap().called_on_commit, expected);
+            if resume {
+                assert_eq!(recv.last_processed_seq(&forward.identity), 1);
+            }
+        }
+    }
+}
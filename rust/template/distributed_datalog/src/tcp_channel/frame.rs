@@ -0,0 +1,205 @@
+//! A module providing the framing of messages sent over a TCP
+//! channel. Every message is preceded by its length, encoded as a
+//! little endian `u32`. Knowing the extent of a message up front
+//! allows for reading it in its entirety before decoding it, which in
+//! turn enables decoding into types borrowing from the read buffer.
+
+use std::convert::TryFrom;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Result as IoResult;
+use std::io::Write;
+
+use bincode::DefaultOptions;
+use bincode::ErrorKind as BincodeError;
+use bincode::Options;
+use bincode::Result as BincodeResult;
+
+use serde::Serialize;
+
+/// The size of the length prefix of a frame.
+const HEADER_SIZE: usize = 4;
+
+/// Write a message as a single frame.
+pub fn write_frame<W, T>(writer: &mut W, message: &T) -> BincodeResult<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+{
+    // The options `bincode::serialize` and friends use.
+    let options = DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes();
+    write_frame_with(writer, message, options)
+}
+
+/// Write a message as a single frame, encoded using bincode configured
+/// with `options`.
+pub fn write_frame_with<W, T, O>(writer: &mut W, message: &T, options: O) -> BincodeResult<()>
+where
+    W: Write,
+    T: Serialize + ?Sized,
+    O: Options + Copy,
+{
+    let size = options.serialized_size(message)?;
+    let size = u32::try_from(size).map_err(|_| {
+        BincodeError::Custom(format!(
+            "message of {} bytes is too large for a frame",
+            size
+        ))
+    })?;
+    writer.write_all(&size.to_le_bytes())?;
+    options.serialize_into(writer, message)
+}
+
+/// Convert an error encountered in the middle of a frame. Such an
+/// error leaves the stream positioned somewhere within the frame, so
+/// make sure that it is not mistaken for one after which the read can
+/// simply be retried.
+fn mid_frame(error: Error) -> Error {
+    match error.kind() {
+        ErrorKind::WouldBlock | ErrorKind::TimedOut => Error::new(
+            ErrorKind::UnexpectedEof,
+            format!("read timed out in the middle of a frame: {}", error),
+        ),
+        _ => error,
+    }
+}
+
+/// Fill `buffer` as far as possible, returning the number of bytes
+/// read, which is less than its length only if the stream ended.
+/// Interrupted reads are retried.
+fn read_full<R>(reader: &mut R, buffer: &mut [u8]) -> IoResult<usize>
+where
+    R: Read,
+{
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(e) if filled > 0 => return Err(mid_frame(e)),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Read a single frame into `buffer`, replacing its contents.
+///
+/// `Ok(false)` is returned if the stream ended cleanly, i.e., before
+/// the start of a frame. A stream ending (or a read timing out) in the
+/// middle of a frame is reported as an `UnexpectedEof` error, meaning
+/// that errors after which a read may be retried are only ever reported
+/// if no part of the frame was read. Frames exceeding `max_size` bytes
+/// are rejected with an `InvalidData` error, without reading their
+/// contents.
+pub fn read_frame<R>(reader: &mut R, buffer: &mut Vec<u8>, max_size: usize) -> IoResult<bool>
+where
+    R: Read,
+{
+    let mut header = [0; HEADER_SIZE];
+    match read_full(reader, &mut header)? {
+        0 => return Ok(false),
+        HEADER_SIZE => (),
+        _ => return Err(ErrorKind::UnexpectedEof.into()),
+    }
+
+    let size = u32::from_le_bytes(header) as usize;
+    if size > max_size {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "frame of {} bytes exceeds limit of {} bytes",
+                size, max_size
+            ),
+        ));
+    }
+
+    buffer.clear();
+    buffer.resize(size, 0);
+    match read_full(reader, buffer) {
+        Ok(n) if n == size => Ok(true),
+        Ok(_) => Err(ErrorKind::UnexpectedEof.into()),
+        // The header has been read already, so we are in the middle of
+        // the frame.
+        Err(e) => Err(mid_frame(e)),
+    }
+}
+
+/// A reader counting the bytes read through it.
+#[derive(Debug)]
+pub struct CountingReader<R> {
+    /// The reader we read from.
+    reader: R,
+    /// The number of bytes read so far.
+    count: u64,
+}
+
+impl<R> CountingReader<R> {
+    /// Create a new `CountingReader` reading from `reader`.
+    pub fn new(reader: R) -> Self {
+        Self { reader, count: 0 }
+    }
+
+    /// Retrieve the number of bytes read so far.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Retrieve the reader we read from.
+    pub fn get_ref(&self) -> &R {
+        &self.reader
+    }
+}
+
+impl<R> Read for CountingReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let n = self.reader.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use bincode::deserialize;
+
+    use crate::tcp_channel::Message;
+
+    /// Check that framed messages can be read back and that truncated
+    /// and oversized frames are detected.
+    #[test]
+    fn frame_roundtrip() {
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2, 3])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+
+        let mut reader = Cursor::new(data.clone());
+        let mut buffer = Vec::new();
+        assert!(read_frame(&mut reader, &mut buffer, 1024).unwrap());
+        let message = deserialize::<Message<u64>>(&buffer).unwrap();
+        assert_eq!(message, Message::Updates(vec![1, 2, 3]));
+        assert!(read_frame(&mut reader, &mut buffer, 1024).unwrap());
+        let message = deserialize::<Message<u64>>(&buffer).unwrap();
+        assert_eq!(message, Message::Commit);
+        assert!(!read_frame(&mut reader, &mut buffer, 1024).unwrap());
+
+        let mut reader = Cursor::new(&data[..6]);
+        let error = read_frame(&mut reader, &mut buffer, 1024).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::UnexpectedEof);
+
+        let mut reader = Cursor::new(&data);
+        let error = read_frame(&mut reader, &mut buffer, 4).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
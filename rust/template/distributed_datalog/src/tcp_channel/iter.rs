@@ -0,0 +1,234 @@
+//! A module providing a pull-based interface to the messages received
+//! by a `TcpReceiver`.
+
+use std::fmt::Debug;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::SyncSender;
+
+use log::trace;
+
+use crate::observe::Observable;
+use crate::observe::Observer;
+use crate::tcp_channel::codec::BincodeCodec;
+use crate::tcp_channel::codec::Codec;
+use crate::tcp_channel::Message;
+use crate::tcp_channel::TcpReceiver;
+
+/// The number of messages a `MessageIter` queues up before a receiver
+/// blocks, by default.
+pub(crate) const DEFAULT_ITER_CAPACITY: usize = 1024;
+
+/// The sending end of a bounded queue of messages.
+pub(crate) trait Queue<T>: Debug + Send {
+    /// Queue a message, blocking while the queue is full. The message
+    /// is handed back if the receiving end is gone.
+    fn push(&mut self, message: Message<T>) -> Result<(), Message<T>>;
+}
+
+impl<T> Queue<T> for SyncSender<Message<T>>
+where
+    T: Send,
+{
+    fn push(&mut self, message: Message<T>) -> Result<(), Message<T>> {
+        self.send(message).map_err(|e| e.0)
+    }
+}
+
+/// An `Observer` queueing all events it receives as `Message`s into a
+/// bounded queue.
+#[derive(Debug)]
+pub(crate) struct QueueObserver<Q> {
+    /// The queue we push messages to.
+    queue: Q,
+}
+
+impl<Q> QueueObserver<Q> {
+    /// Create a new `QueueObserver` pushing messages to `queue`.
+    pub fn new(queue: Q) -> Self {
+        Self { queue }
+    }
+
+    /// Queue a message, blocking while the queue is full.
+    fn send<T>(&mut self, message: Message<T>) -> Result<(), String>
+    where
+        Q: Queue<T>,
+    {
+        self.queue
+            .push(message)
+            .map_err(|message| format!("failed to queue {} message: consumer dropped", message))
+    }
+}
+
+impl<Q, T> Observer<T, String> for QueueObserver<Q>
+where
+    Q: Queue<T>,
+    T: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        self.send(Message::Start)
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        self.send(Message::Commit)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        self.send(Message::Updates(updates.collect()))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        self.send(Message::Snapshot(items.collect()))
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        self.send(Message::Abort)
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        self.send(Message::Complete)
+    }
+}
+
+/// An iterator over the messages a `TcpReceiver` receives, as created
+/// by `TcpReceiver::into_messages`.
+///
+/// Messages are queued up to a fixed capacity, beyond which the
+/// receiver blocks until they are consumed. Every committed transaction
+/// is yielded as a `Start` message, followed by a `Snapshot`, if the
+/// sender sent one, followed by `Updates`, followed by `Commit`. The
+/// iterator ends, i.e., `next` returns `None`, once a sender completed
+/// or disconnected; the `Complete` message itself is not yielded.
+/// Dropping the iterator stops the receiver.
+#[derive(Debug)]
+pub struct MessageIter<T, D, C = BincodeCodec>
+where
+    T: Debug + Send,
+    D: Debug + Send,
+{
+    /// The channel we receive messages from. Declared (and hence
+    /// dropped) ahead of `receiver`, so that threads blocked queueing a
+    /// message get unblocked before the receiver waits for them.
+    messages: Receiver<Message<T>>,
+    /// The receiver feeding us.
+    receiver: TcpReceiver<T, D, C>,
+    /// Whether the end of the stream was reached.
+    done: bool,
+}
+
+impl<T, D, C> MessageIter<T, D, C>
+where
+    T: Send + Debug + 'static,
+    D: Into<T> + Send + Debug + 'static,
+    C: Codec<D>,
+{
+    /// Create a new `MessageIter` over the messages `receiver`
+    /// receives, queueing up to `capacity` of them.
+    pub(crate) fn new(mut receiver: TcpReceiver<T, D, C>, capacity: usize) -> Self {
+        let (sender, messages) = sync_channel(capacity);
+        let _ = receiver.unsubscribe(&());
+        // We just made sure that no observer is subscribed.
+        receiver
+            .subscribe(Box::new(QueueObserver::new(sender)))
+            .unwrap();
+
+        Self {
+            messages,
+            receiver,
+            done: false,
+        }
+    }
+
+    /// Retrieve the receiver feeding us.
+    pub fn receiver(&self) -> &TcpReceiver<T, D, C> {
+        &self.receiver
+    }
+}
+
+impl<T, D, C> Iterator for MessageIter<T, D, C>
+where
+    T: Debug + Send,
+    D: Debug + Send,
+{
+    type Item = Message<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.messages.recv() {
+            Ok(Message::Complete) | Err(_) => {
+                trace!("MessageIter: end of stream");
+                self.done = true;
+                None
+            }
+            Ok(message) => Some(message),
+        }
+    }
+}
+
+impl<T, D, C> IntoIterator for TcpReceiver<T, D, C>
+where
+    T: Send + Debug + 'static,
+    D: Into<T> + Send + Debug + 'static,
+    C: Codec<D>,
+{
+    type Item = Message<T>;
+    type IntoIter = MessageIter<T, D, C>;
+
+    /// Turn the receiver into an iterator over the messages it
+    /// receives, with the default queue capacity. See
+    /// `TcpReceiver::into_messages` for details.
+    fn into_iter(self) -> Self::IntoIter {
+        MessageIter::new(self, DEFAULT_ITER_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::TcpSender;
+
+    /// Collect the messages of a couple of transactions via the
+    /// iterator.
+    #[test]
+    fn collect_messages() {
+        let recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let addr = *recv.addr();
+        let iter = recv.into_messages(8);
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(2).unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_completed().unwrap();
+
+        let messages = iter.collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![
+                Message::Start,
+                Message::Updates(vec![1, 2]),
+                Message::Commit,
+                Message::Start,
+                Message::Updates(vec![3]),
+                Message::Commit,
+            ]
+        );
+    }
+}
@@ -2,19 +2,135 @@ use std::collections::LinkedList;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
+use std::hash::Hash;
+use std::ops::Add;
+
+use bincode::deserialize;
+use bincode::Result as BincodeResult;
 
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::observe::Weighted;
+
+/// An update carrying an explicit weight (or multiplicity), mirroring
+/// the deltas used by differential dataflow: a positive weight denotes
+/// insertions, a negative one deletions.
+///
+/// Channels transferring weighted updates use it as their item type,
+/// i.e., `TcpSender<WeightedUpdate<V>>` and
+/// `TcpReceiver<WeightedUpdate<V>, WeightedUpdate<V>>`, which makes
+/// observers consume the weights alongside the values.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct WeightedUpdate<V, W = isize> {
+    /// The value being updated.
+    pub value: V,
+    /// The weight of the update.
+    pub weight: W,
+}
+
+impl<V, W> WeightedUpdate<V, W> {
+    /// Create a new `WeightedUpdate`.
+    pub fn new(value: V, weight: W) -> Self {
+        Self { value, weight }
+    }
+}
+
+impl<V, W> Weighted for WeightedUpdate<V, W>
+where
+    V: Eq + Hash,
+    W: Add<Output = W> + Copy + Default + PartialEq,
+{
+    type Value = V;
+    type Weight = W;
+
+    fn into_parts(self) -> (V, W) {
+        (self.value, self.weight)
+    }
+
+    fn from_parts(value: V, weight: W) -> Self {
+        Self::new(value, weight)
+    }
+}
+
+impl<V, W> From<(V, W)> for WeightedUpdate<V, W> {
+    fn from((value, weight): (V, W)) -> Self {
+        Self::new(value, weight)
+    }
+}
+
 /// An enum used for representing (and serializing/deserializing)
 /// messages sent through the channel.
+///
+/// `T` is the type of the individual updates. For weighted streams
+/// this is a `WeightedUpdate`.
+///
+/// Every message is sent as a frame of its own, encoded using bincode,
+/// i.e., as the index of its variant (a little endian `u32`) followed
+/// by its payload. That encoding is what peers agree on, so the enum
+/// may only be evolved in ways keeping peers of different versions
+/// compatible:
+/// - variants are only ever appended, which keeps the indices of the
+///   existing ones stable
+/// - payloads are only ever extended at their end, as the decoding of
+///   a message ignores any trailing bytes of its frame
+///
+/// Peers skip messages of a kind introduced after their time (see
+/// `Kind::unknown`), instead of considering them corrupted.
 #[derive(Debug, Deserialize, PartialEq, Serialize)]
 pub enum Message<T> {
+    /// The start of a transaction.
     Start,
+    /// A batch of updates.
     Updates(Vec<T>),
+    /// Multiple batches of updates.
     UpdateList(LinkedList<Vec<T>>),
+    /// The commit of the transaction in progress.
     Commit,
+    /// The end of the stream.
     Complete,
+    /// An acknowledgement sent back by the receiver after its observer
+    /// successfully committed the transaction with the given
+    /// (connection-local) sequence number.
+    Ack(u64),
+    /// The transaction in progress got abandoned without a commit.
+    Abort,
+    /// The reply of a receiver to a sender identifying itself (see
+    /// `Identify`): the number of transactions of that sender its
+    /// observer committed successfully so far, across all connections,
+    /// allowing the sender to resume after them upon reconnecting.
+    Resume(u64),
+    /// A snapshot of the full state of the sender, e.g., for
+    /// bootstrapping a receiver joining a long-running stream. It is
+    /// only valid within a transaction, ahead of any updates of that
+    /// transaction.
+    Snapshot(Vec<T>),
+    /// The version of the data a sender transmits, which it announces
+    /// first thing on a connection to a receiver expecting a version.
+    /// A receiver refusing the version replies with the one it
+    /// expects before closing the connection.
+    Version(String),
+    /// A message belonging to one of several logical streams
+    /// multiplexed over a single connection, identified by its stream
+    /// ID. Only a `TcpDemuxReceiver` accepts such messages, routing the
+    /// contained message to the observer subscribed to the stream.
+    /// Transactions are per stream, i.e., streams may interleave their
+    /// transactions freely.
+    Framed {
+        /// The ID of the stream the message belongs to.
+        stream_id: u64,
+        /// The message itself, which must not be framed in turn.
+        inner: Box<Message<T>>,
+    },
+    /// The updates of the transaction in progress were delivered ahead
+    /// of its commit. The transaction ends here; its remainder, if any,
+    /// follows as a new one.
+    Flush,
+    /// The identity of a sender wishing to resume after reconnecting,
+    /// announced ahead of any transaction on a connection. The receiver
+    /// replies with a `Resume` header. Only relays configured to resume
+    /// identify themselves (see `TcpReceiverBuilder::resume`).
+    Identify(String),
 }
 
 impl<T> Display for Message<T> {
@@ -25,7 +141,146 @@ impl<T> Display for Message<T> {
             Message::UpdateList(_) => "on_updates",
             Message::Commit => "on_commit",
             Message::Complete => "on_completed",
+            Message::Ack(_) => "ack",
+            Message::Abort => "on_abort",
+            Message::Resume(_) => "resume",
+            Message::Snapshot(_) => "on_snapshot",
+            Message::Version(_) => "version",
+            Message::Framed { .. } => "framed",
+            Message::Flush => "on_flush",
+            Message::Identify(_) => "identify",
         };
         formatter.write_str(s)
     }
 }
+
+/// The number of kinds of messages, i.e., of variants of `Message`.
+const KINDS: u32 = 13;
+
+/// The kind of a `Message`, which can be decoded from an encoded
+/// message without decoding (or even knowing the type of) the updates
+/// it may contain.
+///
+/// The variants have to be kept in the same order as those of
+/// `Message`, as they are identified by their index on the wire.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub(crate) enum Kind {
+    Start,
+    Updates,
+    UpdateList,
+    Commit,
+    Complete,
+    Ack,
+    Abort,
+    Resume,
+    Snapshot,
+    Version,
+    Framed,
+    Flush,
+    Identify,
+}
+
+impl Kind {
+    /// Determine the kind of the encoded message in `bytes`.
+    pub fn of(bytes: &[u8]) -> BincodeResult<Self> {
+        // Only the variant index gets decoded; the remaining bytes
+        // are ignored.
+        deserialize(bytes)
+    }
+
+    /// Retrieve the variant index of the encoded message in `bytes` if
+    /// it is not one of a known kind, as is the case for a message
+    /// introduced by a later version of the protocol. Because messages
+    /// are framed, such a message can simply be skipped.
+    pub fn unknown(bytes: &[u8]) -> Option<u32> {
+        deserialize::<u32>(bytes)
+            .ok()
+            .filter(|index| *index >= KINDS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use bincode::serialize;
+
+    /// Check that weighted updates survive a serialization round trip.
+    #[test]
+    fn weighted_roundtrip() {
+        let updates = vec![
+            WeightedUpdate::new("foo".to_string(), 1),
+            WeightedUpdate::new("bar".to_string(), -2),
+        ];
+        let message = Message::<WeightedUpdate<String>>::Updates(updates);
+        let bytes = serialize(&message).unwrap();
+        assert_eq!(deserialize::<Message<_>>(&bytes).unwrap(), message);
+    }
+
+    /// Check that the kind of every message is identified correctly.
+    #[test]
+    fn message_kind() {
+        let messages = vec![
+            (Message::Start, Kind::Start, 0u32),
+            (Message::Updates(vec![1u64, 2]), Kind::Updates, 1),
+            (
+                Message::UpdateList(vec![vec![3]].into_iter().collect()),
+                Kind::UpdateList,
+                2,
+            ),
+            (Message::Commit, Kind::Commit, 3),
+            (Message::Complete, Kind::Complete, 4),
+            (Message::Ack(42), Kind::Ack, 5),
+            (Message::Abort, Kind::Abort, 6),
+            (Message::Resume(7), Kind::Resume, 7),
+            (Message::Snapshot(vec![8]), Kind::Snapshot, 8),
+            (Message::Version("1.0".to_string()), Kind::Version, 9),
+            (
+                Message::Framed {
+                    stream_id: 3,
+                    inner: Box::new(Message::Commit),
+                },
+                Kind::Framed,
+                10,
+            ),
+            (Message::Flush, Kind::Flush, 11),
+            (Message::Identify("foo".to_string()), Kind::Identify, 12),
+        ];
+        assert_eq!(messages.len(), KINDS as usize);
+
+        for (message, kind, index) in messages {
+            let bytes = serialize(&message).unwrap();
+            // The variant indices are part of the wire protocol and
+            // must never change.
+            assert_eq!(bytes[..4], index.to_le_bytes(), "{:?}", message);
+            assert_eq!(Kind::of(&bytes).unwrap(), kind, "{:?}", message);
+            assert_eq!(Kind::unknown(&bytes), None, "{:?}", message);
+        }
+    }
+
+    /// Check that messages of a kind not known to us are recognized as
+    /// such, while their payload is left alone.
+    #[test]
+    fn unknown_kind() {
+        let bytes = serialize(&(KINDS, "payload")).unwrap();
+        assert!(Kind::of(&bytes).is_err());
+        assert_eq!(Kind::unknown(&bytes), Some(KINDS));
+
+        let bytes = serialize(&(42u32, 1u64)).unwrap();
+        assert_eq!(Kind::unknown(&bytes), Some(42));
+
+        // Truncated messages are not mistaken for unknown ones.
+        assert_eq!(Kind::unknown(&bytes[..2]), None);
+    }
+
+    /// Check that a message extended by a later version of the
+    /// protocol still decodes.
+    #[test]
+    fn extended_payload() {
+        let bytes = serialize(&(5u32, 42u64, "new field")).unwrap();
+        assert_eq!(
+            deserialize::<Message<u64>>(&bytes).unwrap(),
+            Message::Ack(42)
+        );
+    }
+}
@@ -1,11 +1,60 @@
 //! TCP implementation of an Observer/Observable channel.
 
+mod ack;
+mod backoff;
+mod borrowed;
+mod builder;
+mod codec;
+mod connection;
+mod demux;
+mod forward;
+mod frame;
+mod iter;
 mod message;
+mod net;
+mod prefetch;
+mod raw;
 mod receiver;
+mod ring;
 mod sender;
 mod socket;
+#[cfg(feature = "futures")]
+mod stream;
+mod token;
 mod txnbuf;
 
+pub use backoff::Backoff;
+pub use borrowed::BorrowedItem;
+pub use borrowed::BorrowedTcpReceiver;
+pub use builder::TcpReceiverBuilder;
+pub use codec::BincodeCodec;
+pub use codec::BincodeConfigCodec;
+pub use codec::Codec;
+pub use codec::CodecError;
+pub use connection::Connection;
+pub use demux::TcpDemuxReceiver;
+pub use forward::TcpRelay;
+pub use iter::MessageIter;
+pub use message::Message;
+pub use message::WeightedUpdate;
+pub use net::IpNet;
+pub use raw::RawBytes;
+pub(crate) use receiver::relay;
+pub use receiver::CloseError;
+pub use receiver::ConnectionEvent;
+pub use receiver::EmptyBatchPolicy;
+pub use receiver::EofPolicy;
+pub(crate) use receiver::Event;
+pub use receiver::ObserverSignal;
+pub use receiver::ReceiverError;
+pub use receiver::ReceiverMetrics;
+pub use receiver::RestartPolicy;
+pub(crate) use receiver::Session;
+pub use receiver::StallPolicy;
 pub use receiver::TcpReceiver;
+pub use ring::FixedLayout;
+pub use ring::RingTcpReceiver;
 pub use sender::TcpSender;
 pub use socket::Fd;
+#[cfg(feature = "futures")]
+pub use stream::MessageStream;
@@ -0,0 +1,146 @@
+//! A module providing IP networks, for restricting the peers a
+//! receiver accepts connections from.
+
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+
+/// An IPv4 or IPv6 network, i.e., an address prefix of a given length,
+/// such as `10.0.0.0/8` or `fd00::/8`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IpNet {
+    /// The address of the network, with all host bits cleared.
+    addr: IpAddr,
+    /// The length of the prefix, in bits.
+    prefix_len: u8,
+}
+
+impl IpNet {
+    /// Create a new `IpNet` comprising all addresses sharing the first
+    /// `prefix_len` bits with `addr`.
+    pub fn new(addr: IpAddr, prefix_len: u8) -> Result<Self, String> {
+        let max_len = match addr {
+            IpAddr::V4(..) => 32,
+            IpAddr::V6(..) => 128,
+        };
+        if prefix_len > max_len {
+            return Err(format!(
+                "prefix length {} exceeds {} bits of {}",
+                prefix_len, max_len, addr
+            ));
+        }
+
+        let addr = match addr {
+            IpAddr::V4(addr) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(prefix_len));
+                IpAddr::V4(Ipv4Addr::from(u32::from(addr) & mask.unwrap_or(0)))
+            }
+            IpAddr::V6(addr) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(prefix_len));
+                IpAddr::V6(Ipv6Addr::from(u128::from(addr) & mask.unwrap_or(0)))
+            }
+        };
+        Ok(Self { addr, prefix_len })
+    }
+
+    /// Retrieve the address of the network.
+    pub fn addr(&self) -> IpAddr {
+        self.addr
+    }
+
+    /// Retrieve the length of the prefix, in bits.
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Check whether `addr` is part of the network. IPv4 addresses
+    /// mapped into IPv6, as reported for IPv4 peers of a socket
+    /// accepting both, are treated as the IPv4 addresses they map.
+    pub fn contains(&self, addr: &IpAddr) -> bool {
+        let addr = match addr {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(*addr),
+            IpAddr::V4(..) => *addr,
+        };
+        match Self::new(addr, self.prefix_len) {
+            Ok(net) => net.addr == self.addr,
+            // An IPv6 prefix may exceed the length of IPv4 addresses.
+            Err(..) => false,
+        }
+    }
+}
+
+impl From<IpAddr> for IpNet {
+    /// Create an `IpNet` comprising just `addr`.
+    fn from(addr: IpAddr) -> Self {
+        let prefix_len = match addr {
+            IpAddr::V4(..) => 32,
+            IpAddr::V6(..) => 128,
+        };
+        Self { addr, prefix_len }
+    }
+}
+
+impl FromStr for IpNet {
+    type Err = String;
+
+    /// Parse a network in CIDR notation, e.g., `192.168.0.0/16`, or a
+    /// single address.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_addr = |addr: &str| {
+            addr.parse::<IpAddr>()
+                .map_err(|e| format!("invalid IP address {}: {}", addr, e))
+        };
+
+        match s.split_once('/') {
+            Some((addr, prefix_len)) => {
+                let prefix_len = prefix_len
+                    .parse()
+                    .map_err(|e| format!("invalid prefix length {}: {}", prefix_len, e))?;
+                Self::new(parse_addr(addr)?, prefix_len)
+            }
+            None => parse_addr(s).map(Self::from),
+        }
+    }
+}
+
+impl Display for IpNet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}/{}", self.addr, self.prefix_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that networks get parsed and match the addresses they
+    /// comprise.
+    #[test]
+    fn contains() {
+        let net = "10.1.2.3/8".parse::<IpNet>().unwrap();
+        assert_eq!(net.to_string(), "10.0.0.0/8");
+        assert!(net.contains(&"10.255.0.1".parse().unwrap()));
+        assert!(net.contains(&"::ffff:10.0.0.1".parse().unwrap()));
+        assert!(!net.contains(&"11.0.0.1".parse().unwrap()));
+        assert!(!net.contains(&"fd00::1".parse().unwrap()));
+
+        let net = "fd00::/8".parse::<IpNet>().unwrap();
+        assert!(net.contains(&"fd12::1".parse().unwrap()));
+        assert!(!net.contains(&"10.0.0.1".parse().unwrap()));
+
+        let net = "127.0.0.1".parse::<IpNet>().unwrap();
+        assert_eq!(net.prefix_len(), 32);
+        assert!(net.contains(&"127.0.0.1".parse().unwrap()));
+        assert!(!net.contains(&"127.0.0.2".parse().unwrap()));
+
+        let net = "0.0.0.0/0".parse::<IpNet>().unwrap();
+        assert!(net.contains(&"192.168.1.1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<IpNet>().is_err());
+        assert!("10.0.0/8".parse::<IpNet>().is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! A module providing the reading of the frames arriving on a
+//! connection, optionally ahead of their processing.
+
+use std::io::BufReader;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result as IoResult;
+use std::net::TcpStream;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::sync_channel;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread::current;
+use std::thread::Builder;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::tcp_channel::frame::read_frame;
+use crate::tcp_channel::frame::CountingReader;
+
+/// The result of reading a frame ahead: the frame along with the number
+/// of bytes it took up on the wire, `None` once the stream ended, or
+/// the error the read failed with.
+type Prefetched = IoResult<Option<(Vec<u8>, u64)>>;
+
+/// A thread reading frames ahead into a bounded queue.
+#[derive(Debug)]
+pub(crate) struct Prefetcher {
+    /// The queue of frames read ahead.
+    queue: Option<Receiver<Prefetched>>,
+    /// The number of bytes of the frames queued.
+    pending: Arc<AtomicUsize>,
+    /// The thread reading frames.
+    thread: Option<JoinHandle<()>>,
+    /// The socket the thread reads a duplicate of. We hold on to it,
+    /// so that its file descriptor, which the processing thread refers
+    /// to, stays valid for as long as we are alive.
+    _socket: TcpStream,
+}
+
+impl Prefetcher {
+    /// Start a thread reading frames of at most `max_size` bytes from
+    /// `socket`, for as long as at most `count` of them are queued.
+    fn new(socket: TcpStream, count: usize, max_size: usize) -> IoResult<Self> {
+        let mut reader = CountingReader::new(BufReader::new(socket.try_clone()?));
+        let (sender, queue) = sync_channel(count);
+        let pending = Arc::new(AtomicUsize::new(0));
+        let copy = pending.clone();
+        let prefetch = move || loop {
+            let read = reader.count();
+            let mut frame = Vec::new();
+            let prefetched = match read_frame(&mut reader, &mut frame, max_size) {
+                Ok(true) => {
+                    let _ = copy.fetch_add(frame.len(), Ordering::SeqCst);
+                    Ok(Some((frame, reader.count() - read)))
+                }
+                Ok(false) => Ok(None),
+                Err(e) => match e.kind() {
+                    // No read timeout is set on the socket, but play
+                    // it safe.
+                    ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut => {
+                        continue
+                    }
+                    _ => Err(e),
+                },
+            };
+            let done = !matches!(prefetched, Ok(Some(..)));
+            // The processing thread is gone if the queue is.
+            if sender.send(prefetched).is_err() || done {
+                break;
+            }
+        };
+
+        let mut builder = Builder::new();
+        if let Some(name) = current().name() {
+            builder = builder.name(format!("{}-prefetch", name));
+        }
+        let thread = builder.spawn(prefetch)?;
+
+        Ok(Self {
+            queue: Some(queue),
+            pending,
+            thread: Some(thread),
+            _socket: socket,
+        })
+    }
+
+    /// Retrieve the next frame, waiting at most `timeout` for it, if
+    /// set.
+    fn next(&mut self, frame: &mut Vec<u8>, timeout: Option<Duration>) -> IoResult<Option<u64>> {
+        let queue = match &self.queue {
+            Some(queue) => queue,
+            None => return Ok(None),
+        };
+        let prefetched = match timeout {
+            Some(timeout) => queue.recv_timeout(timeout),
+            None => queue.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match prefetched {
+            Ok(Ok(Some((prefetched, bytes)))) => {
+                let _ = self.pending.fetch_sub(prefetched.len(), Ordering::SeqCst);
+                *frame = prefetched;
+                Ok(Some(bytes))
+            }
+            Ok(Ok(None)) => Ok(None),
+            Ok(Err(e)) => Err(e),
+            Err(RecvTimeoutError::Timeout) => {
+                Err(Error::new(ErrorKind::TimedOut, "no frame arrived in time"))
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                Err(Error::other("prefetching thread exited unexpectedly"))
+            }
+        }
+    }
+}
+
+impl Drop for Prefetcher {
+    fn drop(&mut self) {
+        // The thread exits once it fails to queue a frame, or once the
+        // connection got closed, which is the case when we are done
+        // processing it.
+        self.queue = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The source of the frames arriving on a connection.
+#[derive(Debug)]
+pub(crate) enum Frames {
+    /// Frames of at most the given size are read as they get
+    /// processed.
+    Direct(CountingReader<BufReader<TcpStream>>, usize),
+    /// Frames are read ahead of their processing.
+    Prefetch(Prefetcher),
+}
+
+impl Frames {
+    /// Create a new `Frames` object reading frames of at most
+    /// `max_size` bytes from `socket`, with up to `prefetch` of them
+    /// being read ahead.
+    pub(crate) fn new(socket: TcpStream, prefetch: usize, max_size: usize) -> IoResult<Self> {
+        if prefetch == 0 {
+            let reader = CountingReader::new(BufReader::new(socket));
+            Ok(Frames::Direct(reader, max_size))
+        } else {
+            Prefetcher::new(socket, prefetch, max_size).map(Frames::Prefetch)
+        }
+    }
+
+    /// Check whether frames are read as they get processed, in which
+    /// case read timeouts set on the socket apply.
+    pub(crate) fn is_direct(&self) -> bool {
+        matches!(self, Frames::Direct(..))
+    }
+
+    /// Read the next frame into `frame`, as `read_frame` does,
+    /// reporting the number of bytes it took up on the wire. When
+    /// reading ahead, we wait at most `timeout` for a frame, if set,
+    /// instead of relying on read timeouts.
+    pub(crate) fn next(
+        &mut self,
+        frame: &mut Vec<u8>,
+        timeout: Option<Duration>,
+    ) -> IoResult<Option<u64>> {
+        match self {
+            Frames::Direct(reader, max_size) => {
+                let read = reader.count();
+                let more = read_frame(reader, frame, *max_size)?;
+                Ok(Some(reader.count() - read).filter(|_| more))
+            }
+            Frames::Prefetch(prefetcher) => prefetcher.next(frame, timeout),
+        }
+    }
+
+    /// Retrieve the number of bytes read but not yet handed out.
+    pub(crate) fn buffered(&self) -> usize {
+        match self {
+            Frames::Direct(reader, _) => reader.get_ref().buffer().len(),
+            Frames::Prefetch(prefetcher) => prefetcher.pending.load(Ordering::SeqCst),
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! A module providing the passing through of encoded messages, for
+//! forwarding updates without decoding them (or even knowing their
+//! type).
+
+use std::convert::TryFrom;
+use std::io::Write;
+
+use bincode::deserialize;
+use bincode::ErrorKind as BincodeError;
+use bincode::Result as BincodeResult;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::tcp_channel::frame::write_frame;
+use crate::tcp_channel::message::Kind;
+use crate::tcp_channel::message::Message;
+
+/// An encoded message carrying updates or a snapshot, including the
+/// discriminant identifying the kind of message.
+///
+/// A `TcpReceiver<RawBytes, RawBytes>` relays every such message it
+/// receives to its observer as a single item, without decoding the
+/// updates contained, while transactions are delimited as usual. A
+/// `TcpSender<RawBytes>` in turn sends each item as the message it
+/// contains, unchanged. Together, they allow for building a forwarder
+/// that works regardless of the type of the updates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RawBytes(Vec<u8>);
+
+impl RawBytes {
+    /// Retrieve the encoded message.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Convert the object into the encoded message.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+/// A type whose messages can be decoded from a frame.
+pub trait Decode: Sized {
+    /// Decode the message contained in `frame`.
+    fn decode(frame: &[u8]) -> BincodeResult<Message<Self>>;
+}
+
+impl<D> Decode for D
+where
+    D: DeserializeOwned,
+{
+    fn decode(frame: &[u8]) -> BincodeResult<Message<Self>> {
+        deserialize(frame)
+    }
+}
+
+impl Decode for RawBytes {
+    fn decode(frame: &[u8]) -> BincodeResult<Message<Self>> {
+        let raw = || vec![RawBytes(frame.to_vec())];
+        match Kind::of(frame)? {
+            Kind::Updates | Kind::UpdateList => Ok(Message::Updates(raw())),
+            Kind::Snapshot => Ok(Message::Snapshot(raw())),
+            // The transaction boundaries of multiplexed streams would
+            // get lost in a single item.
+            Kind::Framed => Err(BincodeError::Custom(
+                "framed messages cannot be passed through".to_string(),
+            )
+            .into()),
+            // Other messages carry no updates, so their encoding is the
+            // same for any type.
+            _ => Ok(convert(&deserialize::<Message<()>>(frame)?).unwrap()),
+        }
+    }
+}
+
+/// A type whose messages can be encoded as frames.
+pub trait Encode: Sized {
+    /// Write `message` to `writer`, framed.
+    fn encode<W>(writer: &mut W, message: &Message<Self>) -> BincodeResult<()>
+    where
+        W: Write;
+}
+
+impl<T> Encode for T
+where
+    T: Serialize,
+{
+    fn encode<W>(writer: &mut W, message: &Message<Self>) -> BincodeResult<()>
+    where
+        W: Write,
+    {
+        write_frame(writer, message)
+    }
+}
+
+impl Encode for RawBytes {
+    fn encode<W>(writer: &mut W, message: &Message<Self>) -> BincodeResult<()>
+    where
+        W: Write,
+    {
+        match message {
+            Message::Updates(items) | Message::Snapshot(items) => write_raw(writer, items),
+            Message::UpdateList(list) => list.iter().try_for_each(|items| write_raw(writer, items)),
+            Message::Framed { .. } => Err(BincodeError::Custom(
+                "framed messages cannot be passed through".to_string(),
+            )
+            .into()),
+            // Messages without updates are encoded just the same for
+            // any type.
+            _ => write_frame(writer, &convert::<_, ()>(message).unwrap()),
+        }
+    }
+}
+
+/// Convert a message not carrying any updates into one of a different
+/// type. `None` is returned for messages carrying updates.
+fn convert<U, V>(message: &Message<U>) -> Option<Message<V>> {
+    match message {
+        Message::Start => Some(Message::Start),
+        Message::Commit => Some(Message::Commit),
+        Message::Complete => Some(Message::Complete),
+        Message::Ack(sequence) => Some(Message::Ack(*sequence)),
+        Message::Abort => Some(Message::Abort),
+        Message::Flush => Some(Message::Flush),
+        Message::Resume(sequence) => Some(Message::Resume(*sequence)),
+        Message::Version(version) => Some(Message::Version(version.clone())),
+        Message::Identify(identity) => Some(Message::Identify(identity.clone())),
+        Message::Updates(_)
+        | Message::UpdateList(_)
+        | Message::Snapshot(_)
+        | Message::Framed { .. } => None,
+    }
+}
+
+/// Write the encoded messages in `items` as frames of their own.
+fn write_raw<W>(writer: &mut W, items: &[RawBytes]) -> BincodeResult<()>
+where
+    W: Write,
+{
+    for RawBytes(bytes) in items {
+        let size = u32::try_from(bytes.len()).map_err(|_| {
+            BincodeError::Custom(format!(
+                "message of {} bytes is too large for a frame",
+                bytes.len()
+            ))
+        })?;
+        writer.write_all(&size.to_le_bytes())?;
+        writer.write_all(bytes)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use test_env_log::test;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::await_expected;
+    use crate::observe::Observable;
+    use crate::observe::Observer;
+    use crate::tcp_channel::frame::read_frame;
+    use crate::TcpReceiver;
+    use crate::TcpSender;
+
+    /// Check that messages pass through `RawBytes` unchanged.
+    #[test]
+    fn passthrough() {
+        let messages = vec![
+            Message::Start,
+            Message::Snapshot(vec![1u64]),
+            Message::Updates(vec![2, 3]),
+            Message::UpdateList(vec![vec![4], vec![5, 6]].into_iter().collect()),
+            Message::Commit,
+            Message::Abort,
+            Message::Complete,
+        ];
+        let mut data = Vec::new();
+        for message in &messages {
+            u64::encode(&mut data, message).unwrap();
+        }
+
+        let mut reader = Cursor::new(data.clone());
+        let mut buffer = Vec::new();
+        let mut output = Vec::new();
+        while read_frame(&mut reader, &mut buffer, 1024).unwrap() {
+            let message = RawBytes::decode(&buffer).unwrap();
+            if let Message::Updates(items) | Message::Snapshot(items) = &message {
+                assert_eq!(items.len(), 1);
+                assert_eq!(items[0].as_bytes(), &buffer[..]);
+            }
+            RawBytes::encode(&mut output, &message).unwrap();
+        }
+        assert_eq!(output, data);
+    }
+
+    /// Forward updates through a receiver and sender passing through
+    /// the encoded messages.
+    #[test]
+    fn forward() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut proxy = TcpReceiver::<RawBytes, RawBytes>::new("127.0.0.1:0").unwrap();
+        // Connect senders up front, so that transactions are not
+        // merged while buffered.
+        let mut forward = TcpSender::<RawBytes>::new(*recv.addr()).unwrap();
+        forward.wait_connected().unwrap();
+        proxy.subscribe(Box::new(forward)).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*proxy.addr()).unwrap();
+        send.wait_connected().unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_snapshot(Box::new(vec![1].into_iter())).unwrap();
+        observer
+            .on_updates(Box::new(vec![2, 3].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![4].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(2).unwrap();
+
+        await_expected(|| {
+            let on_commit = mock.lock().unwrap().called_on_commit;
+            assert_eq!(on_commit, 2);
+        });
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 4]);
+    }
+}
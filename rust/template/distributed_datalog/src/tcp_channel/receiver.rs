@@ -1,37 +1,94 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Debug;
-use std::io::BufReader;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::Error;
 use std::io::ErrorKind;
+use std::io::Result as IoResult;
+use std::io::Write;
+use std::iter::once;
+use std::marker::PhantomData;
+use std::mem::replace;
+use std::mem::size_of;
 use std::net::SocketAddr;
 use std::net::TcpListener;
 use std::net::TcpStream;
 use std::net::ToSocketAddrs;
 use std::os::unix::io::AsRawFd;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::io::IntoRawFd;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::channel;
 use std::sync::Arc;
+use std::sync::Condvar;
 use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::thread::sleep;
 use std::thread::spawn;
+use std::thread::Builder;
 use std::thread::JoinHandle;
+use std::thread::Result as ThreadResult;
+use std::time::Duration;
+use std::time::Instant;
 
-use bincode::deserialize_from;
-use bincode::ErrorKind as BincodeError;
+use bincode::deserialize;
+use bincode::Result as BincodeResult;
 
+use libc::c_int;
 use libc::c_uint;
+use libc::c_void;
+use libc::getsockopt;
+use libc::socklen_t;
+use libc::SOL_SOCKET;
+use libc::SO_ACCEPTCONN;
 
 use log::debug;
 use log::error;
 use log::trace;
+use log::warn;
 
 use uid::Id;
 
-use serde::de::DeserializeOwned;
-
 use crate::observe::Observable;
 use crate::observe::Observer;
 use crate::observe::ObserverBox;
+use crate::observe::OptionalObserver;
 use crate::observe::SharedObserver;
+use crate::observe::TransactionStats;
+use crate::observe::UpdatesObservable;
+use crate::poison::MutexExt;
+use crate::tcp_channel::builder::bind_listener;
+use crate::tcp_channel::builder::Config;
+use crate::tcp_channel::codec::BincodeCodec;
+use crate::tcp_channel::codec::BincodeConfigCodec;
+use crate::tcp_channel::codec::Codec;
+use crate::tcp_channel::connection::Connection;
+use crate::tcp_channel::connection::Connections;
+use crate::tcp_channel::frame::write_frame;
+use crate::tcp_channel::iter::MessageIter;
+use crate::tcp_channel::message::Kind;
 use crate::tcp_channel::message::Message;
+use crate::tcp_channel::net::IpNet;
+use crate::tcp_channel::prefetch::Frames;
+use crate::tcp_channel::socket::await_accept;
+use crate::tcp_channel::socket::await_wake;
+use crate::tcp_channel::socket::connect_once;
+use crate::tcp_channel::socket::set_nice;
 use crate::tcp_channel::socket::Fd;
 use crate::tcp_channel::socket::ShutdownExt;
+use crate::tcp_channel::socket::Wake;
+#[cfg(feature = "futures")]
+use crate::tcp_channel::stream::MessageStream;
+use crate::tcp_channel::Backoff;
+use crate::tcp_channel::TcpReceiverBuilder;
+use crate::txnmux::on_updates_batched;
+use crate::txnmux::SubscribePolicy;
 use crate::txnmux::TxnMux;
 
 /// A struct representing both an `Observer` and an `Observable` that
@@ -67,6 +124,10 @@ where
     fn unsubscribe(&mut self, _subscription: &Self::Subscription) -> Option<ObserverBox<T, E>> {
         self.0.take()
     }
+
+    fn subscriber_count(&self) -> usize {
+        usize::from(self.0.is_some())
+    }
 }
 
 impl<T, E> Observer<T, E> for Passthrough<T, E>
@@ -82,410 +143,4236 @@ where
         self.0.as_mut().map_or(Ok(()), |o| o.on_commit())
     }
 
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.0
+            .as_mut()
+            .map_or(Ok(()), |o| o.on_commit_with_stats(stats))
+    }
+
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
         self.0.as_mut().map_or(Ok(()), |o| o.on_updates(updates))
     }
 
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.0.as_mut().map_or(Ok(()), |o| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.0.as_mut().map_or(Ok(()), |o| o.on_abort())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.0.as_mut().map_or(Ok(()), |o| o.on_flush())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         self.0.as_mut().map_or(Ok(()), |o| o.on_completed())
     }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.0.as_mut().map_or(Ok(()), |o| o.on_disconnected())
+    }
 }
 
-/// The receiving end of a TCP channel has an address
-/// and streams data to an observer.
+/// A limit on the number of connections processed concurrently.
 #[derive(Debug)]
-pub struct TcpReceiver<T, D>
-where
-    T: Debug + Send,
-    D: Debug + Send,
-{
-    /// The TCP receiver's unique ID.
-    id: usize,
-    /// The address we are listening on.
-    addr: SocketAddr,
-    /// Our listener file descriptor state; shared with the thread
-    /// accepting connections.
-    fd: Arc<Fd>,
-    /// Handle to the thread accepting a connection and processing data.
-    thread: Option<JoinHandle<Result<(), String>>>,
-    /// The transaction multiplexer we use to ensure serialization of
-    /// transactions from all accepted connections.
-    txnmux: Arc<Mutex<TxnMux<T, String>>>,
-    _phantom: std::marker::PhantomData<D>,
+struct ConnectionLimit {
+    /// The maximum number of concurrent connections, if any.
+    max: Option<usize>,
+    /// The number of connections currently being processed.
+    active: Mutex<usize>,
+    /// A condition variable signaled whenever a connection finishes.
+    cond: Condvar,
 }
 
-/// `T` - type received from the network.  This type is not required to implement `Deserialize`.
-/// `D` - a "wrapper" type that implements `Deserialize` and that can be converted into `T`.
-///
-/// `T` and `D` can be the same type.
-///
-/// Using two separate type arguments supports the use case when `Deserialize` implementation
-/// resides outside the crate that declares `T` and is defined over a wrapper type, without
-/// introducing a separate filter to perform the conversion.
-impl<T, D> TcpReceiver<T, D>
-where
-    T: Send + Debug + 'static,
-    D: DeserializeOwned + Into<T> + Send + Debug,
-{
-    /// Create a new TCP receiver with no observer.
-    ///
-    /// `addr` may have a port set (by setting it to 0). In such a case
-    /// the system will assign a port that is free. To retrieve this
-    /// assigned port (in the form of the full `SocketAddr`), use the
-    /// `addr` method.
-    pub fn new<A>(addr: A) -> Result<Self, String>
-    where
-        A: ToSocketAddrs,
-    {
-        let id = Id::<()>::new().get();
-        trace!("TcpReceiver({})::new", id);
+impl ConnectionLimit {
+    fn new(max: Option<usize>) -> Self {
+        Self {
+            max,
+            active: Mutex::new(0),
+            cond: Condvar::new(),
+        }
+    }
 
-        let listener =
-            TcpListener::bind(addr).map_err(|e| format!("failed to bind TCP socket: {}", e))?;
-        // We want to allow for auto-assigned ports, by letting the user
-        // specify a `SocketAddr` with port 0. In this case, after
-        // actually binding to an address, we need to update the port we
-        // got assigned in `addr`, but for simplicity we just copy the
-        // entire thing.
-        let addr = listener
-            .local_addr()
-            .map_err(|e| format!("failed to inquire local address: {}", e))?;
-        let fd = c_uint::try_from(listener.as_raw_fd()).unwrap();
-        let fd = Arc::new(Fd::new_unowned(fd));
-        let txnmux = Arc::new(Mutex::new(TxnMux::new()));
-        let thread = Some(Self::accept(id, listener, fd.clone(), txnmux.clone()));
+    /// Block until another connection may be processed, returning a
+    /// permit for it. `None` is returned if `wake` got woken up while
+    /// waiting.
+    fn acquire(self: &Arc<Self>, wake: &Wake) -> Option<ConnectionPermit> {
+        let mut active = self.active.lock_unpoisoned();
+        if let Some(max) = self.max {
+            while *active >= max {
+                if wake.is_woken() {
+                    return None;
+                }
+                active = self
+                    .cond
+                    .wait(active)
+                    .unwrap_or_else(PoisonError::into_inner);
+            }
+        }
+        *active += 1;
+        Some(ConnectionPermit(self.clone()))
+    }
 
-        Ok(Self {
-            id,
-            addr,
-            fd,
-            thread,
-            txnmux,
-            _phantom: std::marker::PhantomData,
-        })
+    /// Wake up a thread waiting in `acquire`, e.g., so that it notices
+    /// a shutdown.
+    fn wake(&self) {
+        let _guard = self.active.lock_unpoisoned();
+        self.cond.notify_all();
     }
+}
 
-    /// Accept a connection (in a non-blocking manner), read data from
-    /// it, and dispatch that to the transaction multiplexer.
-    fn accept(
-        id: usize,
-        listener: TcpListener,
-        fd: Arc<Fd>,
-        txnmux: Arc<Mutex<TxnMux<T, String>>>,
-    ) -> JoinHandle<Result<(), String>> {
-        spawn(move || {
-            let mut handles = Vec::new();
-            loop {
-                let socket = match listener.accept() {
-                    Ok((socket, _)) => {
-                        debug!("TcpReceiver({}): accepted connection", id);
-                        socket
-                    }
-                    Err(e) => {
-                        // The user may have dropped the receiver shortly after
-                        // us accepting a connection. If that is the case do not
-                        // continue.
-                        if fd.is_shutdown() {
-                            break;
-                        }
-                        error!("TcpReceiver({}): failed to accept connection: {}", id, e);
-                        continue;
-                    }
-                };
+/// A permit to process a connection, handing back its slot to the
+/// `ConnectionLimit` once dropped.
+#[derive(Debug)]
+struct ConnectionPermit(Arc<ConnectionLimit>);
 
-                let passthrough = Arc::new(Mutex::new(Passthrough::new()));
-                let observable = Box::new(passthrough.clone());
-                if txnmux.lock().unwrap().add_observable(observable).is_err() {
-                    error!(
-                        "TcpReceiver({}): failed to register connection {} with TxnMux",
-                        id,
-                        socket.as_raw_fd()
-                    );
-                    continue;
-                }
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        *self.0.active.lock_unpoisoned() -= 1;
+        self.0.cond.notify_all();
+    }
+}
 
-                let fd = c_uint::try_from(socket.as_raw_fd()).unwrap();
-                let fd = Arc::new(Fd::new_unowned(fd));
-                let copy = fd.clone();
-                let thread = spawn(move || Self::process(id, socket, copy, passthrough));
-                handles.push((thread, fd));
-            }
+/// Counters tracking the flow of messages through a receiver.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    /// The number of messages received, across all connections.
+    received: AtomicUsize,
+    /// The number of messages dispatched to the observer (or
+    /// discarded because they could not be decoded).
+    dispatched: AtomicUsize,
+    /// The highest number of messages received but not yet dispatched
+    /// we have seen.
+    high_water_mark: AtomicUsize,
+    /// The number of commits the observer processed successfully for
+    /// each sender that identified itself, across all connections.
+    processed: Mutex<HashMap<String, u64>>,
+    /// The number of commits delivered to the observer.
+    commits: Mutex<u64>,
+    /// A condition variable signaled whenever a commit got delivered.
+    committed: Condvar,
+    /// The number of bytes read from connections but not yet
+    /// dispatched to the observer.
+    buffered: AtomicUsize,
+    /// The number of connections accepted.
+    connections: Mutex<u64>,
+    /// A condition variable signaled whenever a connection got
+    /// accepted.
+    connected: Condvar,
+    /// The counts accumulated since the metrics were last taken.
+    interval: IntervalCounters,
+}
 
-            // We only exit above loop when the receiver is dropped and
-            // in this case we intend to stop and join all the
-            // processing threads we started.
-            for (thread, fd) in handles.into_iter().rev() {
-                if let Err(e) = fd.shutdown() {
-                    error!(
-                        "TcpReceiver({}): failed to shut down TcpReceiver file descriptor: {}",
-                        id, e
-                    );
-                }
-                // It should be safe for us to continue even in case of
-                // shutdown failure because all ways in which shutdown
-                // can fail are somehow related to an invalid socket
-                // (except for EINVAL, but that is trivial to rule out)
-                // and so the thread we wait on should have died anyway.
-                let _result = thread.join();
-                debug_assert!(_result.is_ok(), "processing thread panicked: {:?}", _result);
-            }
-            Ok(())
-        })
-    }
+/// Counters accumulating the flow of messages through a receiver since
+/// they were last reset, see `TcpReceiver::take_metrics`.
+#[derive(Debug, Default)]
+struct IntervalCounters {
+    /// The number of messages received.
+    received: AtomicUsize,
+    /// The number of messages dispatched.
+    dispatched: AtomicUsize,
+    /// The number of commits delivered.
+    commits: AtomicU64,
+    /// The highest number of messages received but not yet dispatched.
+    high_water_mark: AtomicUsize,
+}
 
-    /// Process data from a `TcpSender`, relaying messages to a
-    /// connected `Observer`, if any, or dropping them.
-    fn process(
-        id: usize,
-        socket: TcpStream,
-        fd: Arc<Fd>,
-        mut observer: SharedObserver<Passthrough<T, String>>,
-    ) -> Result<(), String> {
-        let mut reader = BufReader::new(socket);
-        loop {
-            let mut message: Message<D> = match deserialize_from(&mut reader) {
-                Ok(m) => m,
-                Err(e) => {
-                    if fd.is_shutdown() {
-                        return Ok(());
-                    }
-                    match *e {
-                        // It is possible that the sender was actually
-                        // closed and in this case there is nothing more
-                        // for us to do. So return early.
-                        BincodeError::Io(ref e) if e.kind() == ErrorKind::UnexpectedEof => {
-                            if let Err(e) = fd.shutdown() {
-                                error!("TcpReceiver({}): failed to shut down socket: {}", id, e);
-                            }
-                            return Ok(());
-                        }
-                        _ => error!("TcpReceiver({}): failed to deserialize message: {}", id, e),
-                    }
-                    continue;
-                }
-            };
+/// A snapshot of the metrics of a `TcpReceiver`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReceiverMetrics {
+    /// The number of messages received, across all connections.
+    pub messages_received: usize,
+    /// The number of messages dispatched to the observer.
+    pub messages_dispatched: usize,
+    /// The number of commits delivered to the observer.
+    pub commits: u64,
+    /// The highest number of messages received but not yet dispatched.
+    pub queue_high_water_mark: usize,
+}
 
-            let result = match message {
-                Message::Start => observer.on_start(),
-                Message::Updates(ref mut updates) => {
-                    observer.on_updates(Box::new(updates.drain(..).map(|u| u.into())))
-                }
-                Message::UpdateList(ref mut updates) => observer.on_updates(Box::new(
-                    updates.split_off(0).into_iter().flatten().map(|u| u.into()),
-                )),
-                Message::Commit => observer.on_commit(),
-                Message::Complete => observer.on_completed(),
-            };
+/// The bytes a connection read but has yet to dispatch, as accounted
+/// for in `Counters::buffered`. They are removed from there once the
+/// connection is done with.
+#[derive(Debug)]
+struct Buffered<'c> {
+    /// The counters we account for bytes in.
+    counters: &'c Counters,
+    /// The bytes we accounted for.
+    bytes: usize,
+}
 
-            if let Err(e) = result {
-                error!(
-                    "TcpReceiver({}): observer {:?} failed to process {} event: {}",
-                    id, observer, message, e
-                );
-            }
-        }
+impl<'c> Buffered<'c> {
+    /// Create a new `Buffered` object accounting for bytes in
+    /// `counters`.
+    fn new(counters: &'c Counters) -> Self {
+        Self { counters, bytes: 0 }
     }
 
-    /// Retrieve the address we are listening on.
-    pub fn addr(&self) -> &SocketAddr {
-        trace!("TcpReceiver({})::addr: {}", self.id, &self.addr);
-        &self.addr
+    /// Set the number of bytes read but not yet dispatched.
+    fn set(&mut self, bytes: usize) {
+        if bytes > self.bytes {
+            let _ = self
+                .counters
+                .buffered
+                .fetch_add(bytes - self.bytes, Ordering::SeqCst);
+        } else {
+            let _ = self
+                .counters
+                .buffered
+                .fetch_sub(self.bytes - bytes, Ordering::SeqCst);
+        }
+        self.bytes = bytes;
     }
 }
 
-impl<T, D> Drop for TcpReceiver<T, D>
-where
-    T: Debug + Send,
-    D: Debug + Send,
-{
+impl Drop for Buffered<'_> {
     fn drop(&mut self) {
-        // Note that we only ever shut down the file descriptor, but
-        // don't close it. The close will happen once the "acceptor"
-        // thread wakes up, sees that we are shut down, and exits,
-        // dropping the TcpListener/TcpStream in the process.
-        if let Err(e) = self.fd.shutdown() {
-            error!("failed to shut down TcpReceiver file descriptor: {}", e);
+        self.set(0)
+    }
+}
+
+/// Block until `counter` reached at least `count` or the timeout
+/// expired, reporting whether the former happened.
+fn await_count(counter: &Mutex<u64>, cond: &Condvar, count: u64, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    let mut current = counter.lock_unpoisoned();
+    while *current < count {
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
         }
+        current = cond
+            .wait_timeout(current, deadline - now)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0;
+    }
+    true
+}
 
-        if let Some(t) = self.thread.take() {
-            match t.join() {
-                Ok(Ok(())) => (),
-                Ok(Err(e)) => error!("TcpReceiver({}) accept thread failed: {}", self.id, e),
-                Err(e) => error!("TcpReceiver({}) thread has panicked: {:?}", self.id, e),
-            }
-        };
+impl Counters {
+    /// Register the receipt of a message.
+    fn receive(&self) {
+        let dispatched = self.dispatched.load(Ordering::SeqCst);
+        let received = self.received.fetch_add(1, Ordering::SeqCst) + 1;
+        let depth = received.saturating_sub(dispatched);
+        let _ = self.high_water_mark.fetch_max(depth, Ordering::SeqCst);
+        let _ = self.interval.received.fetch_add(1, Ordering::SeqCst);
+        let _ = self
+            .interval
+            .high_water_mark
+            .fetch_max(depth, Ordering::SeqCst);
+    }
 
-        // The remaining members will be destroyed automatically, no
-        // need to bother here.
+    /// Register the dispatch of a message to the observer.
+    fn dispatch(&self) {
+        let _ = self.dispatched.fetch_add(1, Ordering::SeqCst);
+        let _ = self.interval.dispatched.fetch_add(1, Ordering::SeqCst);
     }
-}
 
-impl<T, D> Observable<T, String> for TcpReceiver<T, D>
-where
-    T: Debug + Send + 'static,
-    D: Debug + Send,
-{
-    type Subscription = ();
+    /// Register the successful processing of a commit by the observer,
+    /// on behalf of the given sender, if it identified itself.
+    fn process(&self, sender: Option<&str>) {
+        if let Some(sender) = sender {
+            let mut processed = self.processed.lock_unpoisoned();
+            match processed.get_mut(sender) {
+                Some(count) => *count += 1,
+                None => {
+                    let _ = processed.insert(sender.to_string(), 1);
+                }
+            }
+        }
+    }
 
-    /// An observer subscribes to the receiving end of a TCP channel to
-    /// listen to incoming data.
-    fn subscribe(
-        &mut self,
-        observer: ObserverBox<T, String>,
-    ) -> Result<Self::Subscription, ObserverBox<T, String>> {
-        trace!("TcpReceiver({})::subscribe", self.id);
+    /// Retrieve the number of commits the observer processed
+    /// successfully on behalf of the given sender.
+    pub(crate) fn processed(&self, sender: &str) -> u64 {
+        self.processed
+            .lock_unpoisoned()
+            .get(sender)
+            .copied()
+            .unwrap_or(0)
+    }
 
-        self.txnmux.lock().unwrap().subscribe(observer)
+    /// Register the delivery of a commit to the observer.
+    fn commit(&self) {
+        *self.commits.lock_unpoisoned() += 1;
+        let _ = self.interval.commits.fetch_add(1, Ordering::SeqCst);
+        self.committed.notify_all();
     }
 
-    /// Unsubscribe a previously subscribed `Observer` based on a
-    /// subscription.
-    fn unsubscribe(&mut self, subscription: &Self::Subscription) -> Option<ObserverBox<T, String>> {
-        trace!("TcpReceiver({})::unsubscribe", self.id);
+    /// Block until at least `count` commits have been delivered or the
+    /// timeout expired.
+    pub(crate) fn await_commits(&self, count: u64, timeout: Duration) -> Result<(), String> {
+        if await_count(&self.commits, &self.committed, count, timeout) {
+            Ok(())
+        } else {
+            Err(format!(
+                "timed out waiting for commit {} (seen {})",
+                count,
+                *self.commits.lock_unpoisoned()
+            ))
+        }
+    }
 
-        self.txnmux.lock().unwrap().unsubscribe(subscription)
+    /// Retrieve the number of bytes read from connections but not yet
+    /// dispatched to the observer.
+    fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::SeqCst)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Register the acceptance of a connection.
+    fn connect(&self) {
+        *self.connections.lock_unpoisoned() += 1;
+        self.connected.notify_all();
+    }
 
-    use std::io::ErrorKind;
-    use std::io::Read;
-    use std::net::TcpStream;
+    /// Retrieve the number of connections accepted.
+    fn connections(&self) -> u64 {
+        *self.connections.lock_unpoisoned()
+    }
 
-    use test_env_log::test;
+    /// Block until at least `count` connections have been accepted or
+    /// the timeout expired, reporting whether the former happened.
+    fn await_connections(&self, count: u64, timeout: Duration) -> bool {
+        await_count(&self.connections, &self.connected, count, timeout)
+    }
 
-    use crate::await_expected;
-    use crate::MockObserver;
-    use crate::TcpSender;
+    /// Retrieve the number of messages received but not yet
+    /// dispatched.
+    fn depth(&self) -> usize {
+        // Load the dispatched count first so that we never see more
+        // messages dispatched than received.
+        let dispatched = self.dispatched.load(Ordering::SeqCst);
+        let received = self.received.load(Ordering::SeqCst);
+        received.saturating_sub(dispatched)
+    }
 
-    /// Drop a `TcpReceiver`.
-    #[test]
-    fn drop() {
-        let _recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+    /// Retrieve the metrics accumulated since the receiver got created.
+    fn metrics(&self) -> ReceiverMetrics {
+        ReceiverMetrics {
+            messages_received: self.received.load(Ordering::SeqCst),
+            messages_dispatched: self.dispatched.load(Ordering::SeqCst),
+            commits: *self.commits.lock_unpoisoned(),
+            queue_high_water_mark: self.high_water_mark.load(Ordering::SeqCst),
+        }
     }
 
-    /// Connect to a `TcpReceiver`.
-    #[test]
-    fn accept() {
-        let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
-        {
-            let _send = TcpStream::connect(recv.addr()).unwrap();
+    /// Retrieve the metrics accumulated since they were last taken,
+    /// resetting them.
+    fn take_metrics(&self) -> ReceiverMetrics {
+        let interval = &self.interval;
+        ReceiverMetrics {
+            messages_received: interval.received.swap(0, Ordering::SeqCst),
+            messages_dispatched: interval.dispatched.swap(0, Ordering::SeqCst),
+            commits: interval.commits.swap(0, Ordering::SeqCst),
+            // The messages still queued count towards the next
+            // interval's high water mark.
+            queue_high_water_mark: interval
+                .high_water_mark
+                .swap(self.depth(), Ordering::SeqCst),
         }
     }
+}
 
-    /// Check that the listener socket is cleaned up properly when a
-    /// `TcpReceiver` is dropped but has never accepted a connection.
-    #[test]
-    fn never_accepted() {
-        let test = || {
-            let addr = {
-                let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
-                recv.addr().clone()
-            };
+/// An event in the life of a connection to a receiver, as emitted
+/// through `TcpReceiver::connection_events`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectionEvent {
+    /// A sender with the given address connected.
+    Connected(SocketAddr),
+    /// The connection to the sender with the given address was closed,
+    /// due to the contained error, if any.
+    Disconnected(SocketAddr, Option<String>),
+}
 
-            TcpStream::connect(addr)
-        };
+/// An error a receiver encountered, as reported to the callback
+/// registered using `TcpReceiverBuilder::on_error`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReceiverError {
+    /// Accepting a connection failed.
+    Accept(String),
+    /// A message failed to decode.
+    Decode(String),
+    /// A connection from a peer not on the allowlist got rejected.
+    Rejected(SocketAddr),
+    /// The observer failed to process an event.
+    Observer {
+        /// The event the observer failed to process.
+        event: String,
+        /// The error the observer reported.
+        error: String,
+    },
+}
 
-        // There is a teeny-tiny chance that a test running in parallel
-        // gets assigned the very same port we were using between the
-        // drop of the `TcpReceiver` and the `TcpStream::connect`. To
-        // prevent this unlikely collision from causing a flaky test we
-        // retry if we did indeed succeed to connect.
-        for _ in 1..10 {
-            match test() {
-                Ok(_) => continue,
-                Err(e) => {
-                    if e.kind() == ErrorKind::ConnectionRefused {
-                        break;
-                    } else {
-                        panic!("unexpected error")
-                    }
-                }
+impl Display for ReceiverError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            ReceiverError::Accept(error) => write!(f, "failed to accept connection: {}", error),
+            ReceiverError::Decode(error) => write!(f, "failed to deserialize message: {}", error),
+            ReceiverError::Rejected(peer) => write!(f, "rejected connection from {}", peer),
+            ReceiverError::Observer { event, error } => {
+                write!(f, "observer failed to process {} event: {}", event, error)
             }
         }
     }
+}
 
-    /// Check that senders are properly cleaned up when a `TcpReceiver`
-    /// is dropped.
-    #[test]
-    fn sender_cleanup() {
-        let mut send = {
-            let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
-            let send = TcpStream::connect(recv.addr()).unwrap();
-            send
-        };
+/// The callback errors a receiver encountered are reported to.
+#[derive(Clone)]
+pub(crate) struct ErrorCallback(Arc<dyn Fn(ReceiverError) + Send + Sync>);
 
-        let buffer = &mut [0; 32];
-        let result = send.read_exact(buffer);
-        let error = result.unwrap_err().kind();
-        assert!(error == ErrorKind::ConnectionReset || error == ErrorKind::UnexpectedEof);
+impl ErrorCallback {
+    /// Create a new `ErrorCallback` invoking `callback`.
+    pub(crate) fn new<F>(callback: F) -> Self
+    where
+        F: Fn(ReceiverError) + Send + Sync + 'static,
+    {
+        Self(Arc::new(callback))
     }
+}
 
-    /// Test the connection of multiple senders to a `TcpReceiver`.
-    #[test]
-    fn multiple_senders() {
-        let mock = Arc::new(Mutex::new(MockObserver::new()));
-        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
-        let addr = recv.addr();
-        let mut send1 = TcpSender::<u64>::new(*addr).unwrap();
+impl Debug for ErrorCallback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("ErrorCallback")
+    }
+}
+
+/// An error encountered while closing a receiver, as reported by
+/// `TcpReceiver::close`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CloseError {
+    /// Shutting down the listener socket (or waking up the thread
+    /// accepting connections) failed.
+    Socket(String),
+    /// The thread accepting connections failed.
+    Thread(String),
+    /// The thread accepting connections panicked.
+    Panicked(String),
+    /// The thread accepting connections did not exit within the
+    /// shutdown timeout and got detached.
+    Timeout(Duration),
+}
+
+impl Display for CloseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CloseError::Socket(error) => write!(f, "failed to shut down socket: {}", error),
+            CloseError::Thread(error) => write!(f, "accept thread failed: {}", error),
+            CloseError::Panicked(error) => write!(f, "accept thread has panicked: {}", error),
+            CloseError::Timeout(timeout) => {
+                write!(f, "accept thread did not exit within {:?}", timeout)
+            }
+        }
+    }
+}
+
+/// Report `error` to the callback configured in `config`, if any.
+///
+/// Callers must not hold any locks, so that the callback is free to
+/// interact with the receiver.
+pub(crate) fn report(config: &Config, error: ReceiverError) {
+    if let Some(callback) = &config.on_error {
+        (callback.0)(error)
+    }
+}
+
+/// Check whether connections from `peer` are to be accepted as per the
+/// allowlist in `config`, if any.
+pub(crate) fn is_allowed(config: &Config, peer: &SocketAddr) -> bool {
+    match &config.allowlist {
+        Some(allow) => allow.iter().any(|net| net.contains(&peer.ip())),
+        None => true,
+    }
+}
+
+/// A handle for an observer subscribed to a receiver to signal it
+/// upstream, retrieved via `TcpReceiver::signal`.
+///
+/// Signals travel out of band, i.e., next to the results an observer
+/// reports, and are acted upon once the event being processed when
+/// raising one got handled. That event is accounted for according to
+/// its result, meaning that a commit reported as successful is still
+/// acknowledged to the sender. Note that a `TcpReceiver` delivers
+/// transactions only on commit, so that is when its observer gets to
+/// signal anything.
+#[derive(Clone, Debug, Default)]
+pub struct ObserverSignal {
+    /// Whether stopping the receiver was requested.
+    stop: Arc<AtomicBool>,
+}
+
+impl ObserverSignal {
+    /// Close the connection and stop the receiver, as if it was
+    /// dropped: a transaction still open is aborted, without the
+    /// observer being notified of completion, other connections are
+    /// closed as well, and no new ones are accepted.
+    ///
+    /// Because the receiver no longer listens, a `TcpRelay` feeding it
+    /// fails to reconnect and gives up once it has exhausted its
+    /// configured attempts.
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::SeqCst)
+    }
+
+    /// Check whether stopping the receiver was requested, clearing the
+    /// request.
+    fn take_stop(&self) -> bool {
+        self.stop.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// The means of stopping a receiver from one of the threads processing
+/// its connections, as requested through `ObserverSignal::stop`.
+#[derive(Debug)]
+struct Stop {
+    /// The file descriptor state of the listener, if any.
+    listener: Option<Arc<Fd>>,
+    /// The object used for stopping the thread accepting connections.
+    wake: Arc<Wake>,
+    /// The signal raised by the observer.
+    signal: ObserverSignal,
+}
+
+impl Stop {
+    /// Shut down the listener and stop accepting connections, which
+    /// in turn closes all connections being processed.
+    fn stop(&self, id: usize) {
+        if let Some(listener) = &self.listener {
+            if let Err(e) = listener.shutdown() {
+                error!("TcpReceiver({}): failed to shut down listener: {}", id, e);
+            }
+        }
+        if let Err(e) = self.wake.wake() {
+            error!(
+                "TcpReceiver({}): failed to stop accepting connections: {}",
+                id, e
+            );
+        }
+    }
+}
+
+/// The observer slot connection events are emitted to.
+type ConnectionEvents = SharedObserver<OptionalObserver<ObserverBox<ConnectionEvent, String>>>;
+
+/// Emit `event` to the observer subscribed to connection events, if
+/// any, as a transaction of its own.
+fn notify(id: usize, events: &ConnectionEvents, event: ConnectionEvent) {
+    let mut observer = events.lock_unpoisoned();
+    let result = observer
+        .on_start()
+        .and_then(|_| observer.on_updates(Box::new(once(event.clone()))))
+        .and_then(|_| observer.on_commit());
+
+    if let Err(e) = result {
+        error!(
+            "TcpReceiver({}): observer failed to process connection event {:?}: {}",
+            id, event, e
+        );
+    }
+}
+
+/// The policy for handling a `Start` message arriving while a
+/// transaction is still open, as happens if a sender restarts the
+/// protocol, e.g., after reconnecting.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RestartPolicy {
+    /// Abort the open transaction on behalf of the sender and start
+    /// the new one. This is the default.
+    Abort,
+    /// Consider the restart a protocol violation: the open transaction
+    /// is aborted and the stream of messages ended with an error.
+    Reject,
+}
+
+/// The policy for what the observer learns about a sender closing its
+/// connection cleanly, i.e., between messages. A connection breaking
+/// otherwise, e.g., in the middle of a message, always aborts the
+/// transaction left open, if any, but is signaled to the observer
+/// only under `EmitDisconnected`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EofPolicy {
+    /// Abort the transaction left open, if any, and signal the
+    /// disconnect (see `Observer::on_disconnected`), unless the sender
+    /// signaled completion already. Observers treat a disconnect as
+    /// completion by default. This is the default.
+    EmitDisconnected,
+    /// Abort the transaction left open, if any, but do not signal the
+    /// disconnect, e.g., because the sender is expected to reconnect.
+    EmitAbort,
+    /// Neither abort nor complete anything. A transaction left open is
+    /// never delivered to the observer of a `TcpReceiver`, as that
+    /// only happens on commit, while the observer of a
+    /// `BorrowedTcpReceiver` is left to deal with it.
+    Nothing,
+}
+
+/// The policy for handling a transaction that was not committed (or
+/// aborted) within the configured transaction timeout, e.g., because
+/// the sender got stuck or its host crashed without the connection
+/// being closed. Either way, the transaction gets aborted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StallPolicy {
+    /// Keep the connection open, so that the sender can start over with
+    /// a new transaction. If it continues the one that timed out
+    /// instead, its connection is closed with an error, so that it has
+    /// to reconnect and replay the transaction. This is the default.
+    Abort,
+    /// Close the connection right away, treating the sender as if it
+    /// went away: the configured `EofPolicy` decides whether the
+    /// observer is notified of completion.
+    Disconnect,
+}
+
+/// The policy for handling a batch of updates without any, as some
+/// senders emit for transactions that did not change anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EmptyBatchPolicy {
+    /// Do not pass the batch on to the observer. The transaction it is
+    /// part of is still started and committed, so that the observer of
+    /// a `TcpReceiver` sees a transaction without any updates without
+    /// an `on_updates` call. This is the default.
+    Suppress,
+    /// Pass the batch on to the observer, as an `on_updates` call
+    /// without any updates. The observer of a `TcpReceiver` sees one
+    /// such call for a transaction without any updates.
+    Forward,
+}
+
+/// The state of a stream of messages relayed to an observer.
+#[derive(Debug)]
+pub(crate) struct Session {
+    /// Whether a transaction is in progress.
+    pub open: bool,
+    /// Whether the sender signaled completion.
+    pub completed: bool,
+    /// How to handle a restarted transaction.
+    pub restart: RestartPolicy,
+    /// The size of the message being relayed, including its framing,
+    /// if it was read from the wire.
+    pub frame_bytes: u64,
+    /// The statistics of the transaction in progress.
+    pub stats: TransactionStats,
+    /// The maximum number of updates passed to an observer in a single
+    /// `on_updates` call, if any.
+    pub max_batch_len: Option<usize>,
+    /// How to handle a batch without any updates.
+    pub empty_batches: EmptyBatchPolicy,
+}
+
+impl Session {
+    /// Create a new `Session` using the given restart policy.
+    pub fn new(restart: RestartPolicy) -> Self {
+        Self {
+            open: false,
+            completed: false,
+            restart,
+            frame_bytes: 0,
+            stats: TransactionStats::default(),
+            max_batch_len: None,
+            empty_batches: EmptyBatchPolicy::Suppress,
+        }
+    }
+
+    /// Check whether a batch of `len` updates is to be passed on to the
+    /// observer.
+    fn forward_batch(&self, len: usize) -> bool {
+        len > 0 || self.empty_batches == EmptyBatchPolicy::Forward
+    }
+}
+
+/// The kind of event a message received on a connection represents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Event {
+    Start,
+    Updates,
+    Snapshot,
+    Commit,
+    Abort,
+    Flush,
+    Complete,
+    Ack,
+    Resume,
+    Version,
+    Identify,
+    /// A transaction got restarted while open, which was rejected.
+    Restart,
+    /// A message of a multiplexed stream arrived where none was
+    /// expected, or vice versa.
+    Framed,
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        let name = match self {
+            Event::Start => "on_start",
+            Event::Updates => "on_updates",
+            Event::Snapshot => "on_snapshot",
+            Event::Commit => "on_commit",
+            Event::Abort => "on_abort",
+            Event::Flush => "on_flush",
+            Event::Complete => "on_completed",
+            Event::Ack => "ack",
+            Event::Resume => "resume",
+            Event::Version => "version",
+            Event::Identify => "identify",
+            Event::Restart => "restart",
+            Event::Framed => "framed",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Relay a message to an observer, converting the contained updates
+/// as necessary and keeping track of the state of `session`. Returned
+/// are the kind of event the message represents and the result of the
+/// observer processing it.
+///
+/// A `Start` message arriving while a transaction is open is handled
+/// according to the session's `RestartPolicy`, so that the observer
+/// never sees two transactions starting without the first one ending.
+/// A rejected restart is not relayed but reported as an
+/// `Event::Restart`, with the transaction left open.
+pub(crate) fn relay<U, V, O>(
+    message: Message<U>,
+    observer: &mut O,
+    session: &mut Session,
+) -> (Event, Result<(), String>)
+where
+    U: Into<V>,
+    V: Send,
+    O: Observer<V, String> + ?Sized,
+{
+    session.stats.bytes += session.frame_bytes;
+
+    match message {
+        Message::Start => {
+            // A (re-)started transaction starts out with the bytes of
+            // its `Start` message.
+            session.stats = TransactionStats {
+                bytes: session.frame_bytes,
+                ..TransactionStats::default()
+            };
+            if !replace(&mut session.open, true) {
+                return (Event::Start, observer.on_start());
+            }
+            match session.restart {
+                RestartPolicy::Abort => {
+                    let aborted = observer
+                        .on_abort()
+                        .map_err(|e| format!("failed to abort restarted transaction: {}", e));
+                    let started = observer.on_start();
+                    (Event::Start, aborted.and(started))
+                }
+                RestartPolicy::Reject => (
+                    Event::Restart,
+                    Err("transaction restarted while still open".to_string()),
+                ),
+            }
+        }
+        Message::Updates(updates) => {
+            let len = updates.len();
+            if !session.forward_batch(len) {
+                return (Event::Updates, Ok(()));
+            }
+            session.stats.items += len;
+            let updates = updates.into_iter().map(Into::into);
+            (
+                Event::Updates,
+                on_updates_batched(observer, updates, len, session.max_batch_len),
+            )
+        }
+        Message::UpdateList(updates) => {
+            let len = updates.iter().map(Vec::len).sum::<usize>();
+            if !session.forward_batch(len) {
+                return (Event::Updates, Ok(()));
+            }
+            session.stats.items += len;
+            let updates = updates.into_iter().flatten().map(Into::into);
+            (
+                Event::Updates,
+                on_updates_batched(observer, updates, len, session.max_batch_len),
+            )
+        }
+        Message::Commit => {
+            session.open = false;
+            (Event::Commit, observer.on_commit_with_stats(session.stats))
+        }
+        Message::Abort => {
+            session.open = false;
+            (Event::Abort, observer.on_abort())
+        }
+        Message::Flush => {
+            session.open = false;
+            (Event::Flush, observer.on_flush())
+        }
+        Message::Complete => {
+            session.completed = true;
+            (Event::Complete, observer.on_completed())
+        }
+        Message::Ack(_) => (Event::Ack, Err("unexpected acknowledgement".to_string())),
+        Message::Resume(_) => (Event::Resume, Err("unexpected resume header".to_string())),
+        // Only a receiver expecting a version cares about it, and that
+        // checks it before relaying anything.
+        Message::Version(_) => (Event::Version, Ok(())),
+        // Multiplexed streams are the business of a `TcpDemuxReceiver`.
+        Message::Framed { .. } => (Event::Framed, Err("unexpected framed message".to_string())),
+        // A receiver handles identification before relaying anything.
+        Message::Identify(_) => (
+            Event::Identify,
+            Err("unexpected identification".to_string()),
+        ),
+        Message::Snapshot(items) => {
+            if !session.open {
+                let e = "snapshot outside of a transaction".to_string();
+                return (Event::Snapshot, Err(e));
+            }
+            session.stats.items += items.len();
+            (
+                Event::Snapshot,
+                observer.on_snapshot(Box::new(items.into_iter().map(Into::into))),
+            )
+        }
+    }
+}
+
+/// An object decoding the frames received on a connection and
+/// dispatching the contained messages to an observer.
+pub(crate) trait Dispatch: Debug + Send {
+    /// Decode the message contained in `frame` and dispatch it, as
+    /// part of the given session.
+    fn dispatch(
+        &mut self,
+        frame: &[u8],
+        session: &mut Session,
+    ) -> BincodeResult<(Event, Result<(), String>)>;
+
+    /// Whether the kind of the messages dispatched can be told using
+    /// `Kind::of`, without decoding them (see
+    /// `Codec::has_fixint_tags`).
+    fn has_fixint_tags(&self) -> bool {
+        true
+    }
+
+    /// Abort the transaction in progress.
+    fn on_abort(&mut self) -> Result<(), String>;
+
+    /// Signal completion, as requested by the sender.
+    fn on_completed(&mut self) -> Result<(), String>;
+
+    /// Signal that a sender went away without signaling completion.
+    fn on_disconnected(&mut self) -> Result<(), String>;
+}
+
+/// A `Dispatch` decoding messages into owned data, relaying them to the
+/// `Passthrough` of a connection.
+#[derive(Debug)]
+struct OwnedDispatch<T, D, C> {
+    observer: SharedObserver<Passthrough<T, String>>,
+    codec: C,
+    _phantom: PhantomData<fn() -> D>,
+}
+
+impl<T, D, C> Dispatch for OwnedDispatch<T, D, C>
+where
+    T: Debug + Send,
+    D: Into<T> + Debug,
+    C: Codec<D>,
+{
+    fn dispatch(
+        &mut self,
+        frame: &[u8],
+        session: &mut Session,
+    ) -> BincodeResult<(Event, Result<(), String>)> {
+        let message = self.codec.decode(frame)?;
+        Ok(relay::<D, T, _>(message, &mut self.observer, session))
+    }
+
+    fn has_fixint_tags(&self) -> bool {
+        self.codec.has_fixint_tags()
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        self.observer.on_abort()
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        self.observer.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        self.observer.on_disconnected()
+    }
+}
+
+/// Join `thread`, waiting for at most `timeout`. If the thread did not
+/// exit in time, `None` is returned and the thread is left running,
+/// detached.
+fn join_timeout<T>(thread: JoinHandle<T>, timeout: Duration) -> Option<ThreadResult<T>>
+where
+    T: Send + 'static,
+{
+    let (sender, receiver) = channel();
+    // The watchdog outlives us in case of a timeout, but exits as soon
+    // as the thread does.
+    let _ = spawn(move || sender.send(thread.join()));
+    receiver.recv_timeout(timeout).ok()
+}
+
+/// Where an `Acceptor` gets the connections it processes from.
+#[derive(Debug)]
+pub(crate) enum Source {
+    /// Accept connections on a listener socket.
+    Listen(TcpListener),
+    /// Connect to the sender listening on the given address, one
+    /// connection at a time, re-establishing it whenever it drops.
+    Dial(SocketAddr),
+}
+
+/// A function starting a thread processing the connections from a
+/// `Source`, for (re-)starting an `Acceptor`.
+type SpawnFn = dyn FnMut(Source, Option<Arc<Fd>>, Arc<Wake>) -> Result<JoinHandle<Result<Source, String>>, String>
+    + Send;
+
+/// A wrapper around a `SpawnFn` making it `Debug`.
+struct Spawn(Box<SpawnFn>);
+
+impl Debug for Spawn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_tuple("Spawn").finish()
+    }
+}
+
+/// The machinery shared by receivers for accepting connections on a
+/// listener socket (or establishing one to a sender) and processing the
+/// data arriving on them.
+#[derive(Debug)]
+pub(crate) struct Acceptor {
+    /// The unique ID of the receiver we work for.
+    id: usize,
+    /// The address we are listening on or connecting to.
+    addr: SocketAddr,
+    /// Whether we connect to a sender instead of listening.
+    dial: bool,
+    /// Our listener file descriptor state, if listening; shared with
+    /// the thread accepting connections.
+    fd: Option<Arc<Fd>>,
+    /// The object used for stopping the thread accepting connections.
+    wake: Arc<Wake>,
+    /// Handle to the thread accepting a connection and processing data,
+    /// handing back the source of connections once it exits.
+    thread: Option<JoinHandle<Result<Source, String>>>,
+    /// The limit on the number of concurrently processed connections.
+    limit: Arc<ConnectionLimit>,
+    /// Counters tracking the messages received and dispatched.
+    counters: Arc<Counters>,
+    /// The connections being processed.
+    connections: Arc<Connections>,
+    /// The observer slot connection events are emitted to.
+    events: ConnectionEvents,
+    /// The signal raised by the observer to stop us.
+    signal: ObserverSignal,
+    /// The configuration of the receiver we work for.
+    config: Arc<Config>,
+    /// The function starting the thread accepting connections.
+    spawn: Spawn,
+}
+
+impl Acceptor {
+    /// Start accepting connections from `source`. For every accepted
+    /// connection `connect` is invoked to create the `Dispatch` to use
+    /// for it; a connection for which it returns `None` is dropped.
+    pub(crate) fn new<C, P>(
+        id: usize,
+        source: Source,
+        config: Config,
+        connect: C,
+    ) -> Result<Self, String>
+    where
+        C: FnMut(&TcpStream) -> Option<P> + Send + 'static,
+        P: Dispatch + 'static,
+    {
+        // We want to allow for auto-assigned ports, by letting the user
+        // specify a `SocketAddr` with port 0. In this case, after
+        // actually binding to an address, we need to update the port we
+        // got assigned in `addr`, but for simplicity we just copy the
+        // entire thing.
+        let (addr, dial) = match &source {
+            Source::Listen(listener) => {
+                let addr = listener
+                    .local_addr()
+                    .map_err(|e| format!("failed to inquire local address: {}", e))?;
+                (addr, false)
+            }
+            Source::Dial(addr) => (*addr, true),
+        };
+        // We only ever maintain a single connection to a sender.
+        let max_connections = if dial {
+            Some(1)
+        } else {
+            config.max_connections
+        };
+        let limit = Arc::new(ConnectionLimit::new(max_connections));
+        let counters = Arc::new(Counters::default());
+        let connections = Arc::new(Connections::default());
+        let events = ConnectionEvents::default();
+        let signal = ObserverSignal::default();
+        let config = Arc::new(config);
+        let name = config
+            .thread_name
+            .clone()
+            .unwrap_or_else(|| format!("tcp-recv-{}", addr));
+        // Every accepting thread we start (see `resume`) gets its own
+        // reference to `connect`.
+        let connect = Arc::new(Mutex::new(connect));
+        let spawn = {
+            let limit = limit.clone();
+            let counters = counters.clone();
+            let connections = connections.clone();
+            let events = events.clone();
+            let signal = signal.clone();
+            let config = config.clone();
+            move |source, fd, wake| {
+                Self::accept(
+                    id,
+                    name.clone(),
+                    source,
+                    fd,
+                    wake,
+                    limit.clone(),
+                    counters.clone(),
+                    connections.clone(),
+                    events.clone(),
+                    signal.clone(),
+                    config.clone(),
+                    connect.clone(),
+                )
+            }
+        };
+
+        let mut spawn = Spawn(Box::new(spawn));
+        let (fd, wake, thread) = Self::start(&mut spawn, source)?;
+
+        Ok(Self {
+            id,
+            addr,
+            dial,
+            fd,
+            wake,
+            thread: Some(thread),
+            limit,
+            counters,
+            connections,
+            events,
+            signal,
+            config,
+            spawn,
+        })
+    }
+
+    /// Start a thread accepting connections from `source` using
+    /// `spawn`, returning the objects for stopping it along with its
+    /// handle.
+    #[allow(clippy::type_complexity)]
+    fn start(
+        spawn: &mut Spawn,
+        source: Source,
+    ) -> Result<
+        (
+            Option<Arc<Fd>>,
+            Arc<Wake>,
+            JoinHandle<Result<Source, String>>,
+        ),
+        String,
+    > {
+        let fd = match &source {
+            Source::Listen(listener) => {
+                // Listeners created externally may have been set to
+                // non-blocking mode, but we rely on `accept` blocking.
+                listener
+                    .set_nonblocking(false)
+                    .map_err(|e| format!("failed to make TCP socket blocking: {}", e))?;
+                let fd = c_uint::try_from(listener.as_raw_fd()).unwrap();
+                Some(Arc::new(Fd::new_unowned(fd)))
+            }
+            Source::Dial(..) => None,
+        };
+        let wake = Arc::new(Wake::new().map_err(|e| format!("failed to create pipe: {}", e))?);
+        let thread = (spawn.0)(source, fd.clone(), wake.clone())?;
+        Ok((fd, wake, thread))
+    }
+
+    /// Accept a connection (in a non-blocking manner) or establish one,
+    /// read data from it, and dispatch that using the `Dispatch`
+    /// created for it.
+    ///
+    /// Once `wake` got woken up, we stop accepting connections, close
+    /// the ones being processed, and hand back the source.
+    ///
+    /// The accepting thread as well as the processing threads it starts
+    /// are called `name` and run with the configured niceness.
+    #[allow(clippy::too_many_arguments)]
+    fn accept<C, P>(
+        id: usize,
+        name: String,
+        source: Source,
+        fd: Option<Arc<Fd>>,
+        wake: Arc<Wake>,
+        limit: Arc<ConnectionLimit>,
+        counters: Arc<Counters>,
+        connections: Arc<Connections>,
+        events: ConnectionEvents,
+        signal: ObserverSignal,
+        config: Arc<Config>,
+        connect: Arc<Mutex<C>>,
+    ) -> Result<JoinHandle<Result<Source, String>>, String>
+    where
+        C: FnMut(&TcpStream) -> Option<P> + Send + 'static,
+        P: Dispatch + 'static,
+    {
+        let builder = Self::thread_builder(&name, &config);
+        let accept = move || {
+            // Threads inherit the niceness of the thread creating them,
+            // so the processing threads are covered as well.
+            if let Some(nice) = config.nice {
+                if let Err(e) = set_nice(nice) {
+                    error!("TcpReceiver({}): failed to set niceness: {}", id, e);
+                }
+            }
+
+            let stop = Arc::new(Stop {
+                listener: fd.clone(),
+                wake: wake.clone(),
+                signal: signal.clone(),
+            });
+            let mut handles = Vec::new();
+            // The index of the next retry at connecting to a sender, if
+            // any attempt was made already.
+            let mut retry = None;
+            loop {
+                let permit = match limit.acquire(&wake) {
+                    Some(permit) => permit,
+                    None => break,
+                };
+
+                let (socket, peer) = match &source {
+                    Source::Listen(listener) => {
+                        match await_accept(listener.as_raw_fd(), &wake) {
+                            Ok(true) => (),
+                            Ok(false) => break,
+                            // We can still accept connections, we just
+                            // may not notice a wake up while blocked
+                            // doing so.
+                            Err(e) => error!("TcpReceiver({}): failed to poll listener: {}", id, e),
+                        }
+
+                        match listener.accept() {
+                            Ok((_socket, peer)) if !is_allowed(&config, &peer) => {
+                                // The socket gets closed as it goes out
+                                // of scope.
+                                warn!("TcpReceiver({}): rejected connection from {}", id, peer);
+                                report(&config, ReceiverError::Rejected(peer));
+                                continue;
+                            }
+                            Ok((socket, peer)) => {
+                                debug!("TcpReceiver({}): accepted connection from {}", id, peer);
+                                (socket, peer)
+                            }
+                            Err(e) => {
+                                // The user may have dropped the receiver
+                                // shortly after us accepting a connection.
+                                // If that is the case do not continue.
+                                if matches!(&fd, Some(fd) if fd.is_shutdown()) {
+                                    break;
+                                }
+                                error!("TcpReceiver({}): failed to accept connection: {}", id, e);
+                                report(&config, ReceiverError::Accept(e.to_string()));
+                                continue;
+                            }
+                        }
+                    }
+                    Source::Dial(addr) => {
+                        match Self::dial(id, addr, &config.backoff, &mut retry, &wake) {
+                            Some(socket) => (socket, *addr),
+                            None => break,
+                        }
+                    }
+                };
+                // Only a connection we can dispatch from counts as
+                // established; otherwise the socket gets closed as it
+                // goes out of scope, without any event reported.
+                let dispatch = match (*connect.lock_unpoisoned())(&socket) {
+                    Some(dispatch) => dispatch,
+                    None => continue,
+                };
+                counters.connect();
+                notify(id, &events, ConnectionEvent::Connected(peer));
+
+                let registration = match connections.register(&socket) {
+                    Ok(registration) => Some(registration),
+                    Err(e) => {
+                        error!("TcpReceiver({}): failed to register connection: {}", id, e);
+                        None
+                    }
+                };
+                let fd = c_uint::try_from(socket.as_raw_fd()).unwrap();
+                let fd = Arc::new(Fd::new_unowned(fd));
+                let copy = fd.clone();
+                let builder = Self::thread_builder(&name, &config);
+                let counters = counters.clone();
+                let events = events.clone();
+                let config = config.clone();
+                let stop = stop.clone();
+                let disconnect = events.clone();
+                let process = move || {
+                    let result =
+                        Self::process(id, socket, copy, dispatch, &counters, &config, &stop);
+                    drop(registration);
+                    if let Err(ref e) = result {
+                        error!("TcpReceiver({}): closed connection: {}", id, e);
+                    }
+                    let error = result.as_ref().err().cloned();
+                    notify(id, &events, ConnectionEvent::Disconnected(peer, error));
+                    drop(permit);
+                    result
+                };
+                match builder.spawn(process) {
+                    Ok(thread) => handles.push((thread, fd)),
+                    Err(e) => {
+                        let error = format!("failed to spawn processing thread: {}", e);
+                        error!("TcpReceiver({}): {}", id, error);
+                        notify(
+                            id,
+                            &disconnect,
+                            ConnectionEvent::Disconnected(peer, Some(error)),
+                        );
+                    }
+                }
+            }
+
+            // We only exit above loop when the receiver is dropped,
+            // paused, or hands off its listener and in all cases we
+            // intend to stop and join all the processing threads we
+            // started.
+            for (thread, fd) in handles.into_iter().rev() {
+                if let Err(e) = fd.shutdown() {
+                    error!(
+                        "TcpReceiver({}): failed to shut down TcpReceiver file descriptor: {}",
+                        id, e
+                    );
+                }
+                // It should be safe for us to continue even in case of
+                // shutdown failure because all ways in which shutdown
+                // can fail are somehow related to an invalid socket
+                // (except for EINVAL, but that is trivial to rule out)
+                // and so the thread we wait on should have died anyway.
+                // A thread may have panicked in the observer, which
+                // does not keep us from shutting down the others.
+                if let Err(e) = thread.join() {
+                    error!("TcpReceiver({}): processing thread panicked: {:?}", id, e);
+                }
+            }
+            Ok(source)
+        };
+
+        builder
+            .spawn(accept)
+            .map_err(|e| format!("failed to spawn accepting thread: {}", e))
+    }
+
+    /// Create the builder for a thread accepting or processing
+    /// connections, named `name` and configured as per `config`.
+    fn thread_builder(name: &str, config: &Config) -> Builder {
+        let builder = Builder::new().name(name.to_string());
+        match config.stack_size {
+            Some(size) => builder.stack_size(size),
+            None => builder,
+        }
+    }
+
+    /// Connect to the sender listening on `addr`, retrying as per
+    /// `backoff` until we succeed or `wake` got woken up, in which case
+    /// `None` is returned. `retry` tracks the index of the next retry;
+    /// after a connection got established, re-establishing it once it
+    /// dropped counts as a retry as well.
+    fn dial(
+        id: usize,
+        addr: &SocketAddr,
+        backoff: &Backoff,
+        retry: &mut Option<u32>,
+        wake: &Wake,
+    ) -> Option<TcpStream> {
+        loop {
+            if let Some(retry) = *retry {
+                let delay = backoff.delay(retry);
+                match await_wake(wake, delay) {
+                    Ok(true) => return None,
+                    Ok(false) => (),
+                    Err(e) => {
+                        error!("TcpReceiver({}): failed to poll wake up: {}", id, e);
+                        sleep(delay);
+                    }
+                }
+            }
+
+            match connect_once(addr, wake) {
+                Ok(Some(socket)) => {
+                    debug!("TcpReceiver({}): connected to {}", id, addr);
+                    *retry = Some(0);
+                    return Some(socket);
+                }
+                Ok(None) => return None,
+                Err(e) => {
+                    debug!("TcpReceiver({}): failed to connect to {}: {}", id, addr, e);
+                    *retry = Some(retry.map_or(0, |retry| retry.saturating_add(1)));
+                }
+            }
+        }
+    }
+
+    /// Process data from a `TcpSender`, dispatching messages to an
+    /// observer.
+    ///
+    /// Upon connection, the sender is told the number of transactions
+    /// committed successfully so far, so that it can resume after them
+    /// when reconnecting. Every message is read into a buffer that is
+    /// reused for the lifetime of the connection, before being decoded.
+    /// Every transaction successfully committed by the observer is
+    /// acknowledged to the sender. A connection on which we repeatedly
+    /// fail to decode messages is considered corrupted and closed with
+    /// an error, as is one announcing a message exceeding the
+    /// configured maximum frame size. If we require a token, the sender
+    /// has to present it first thing, and is only told where to resume
+    /// once it did. If we expect a version, the sender has to announce
+    /// it next, or it is refused. A transaction not committed within
+    /// the configured transaction timeout, if any, is aborted and
+    /// handled according to the configured `StallPolicy`.
+    ///
+    /// If the sender closes the connection in between messages, the
+    /// configured `EofPolicy` decides whether a transaction left open
+    /// is aborted and the observer notified of completion. If it does so in
+    /// the middle of a message, the transaction is aborted and an error
+    /// reported. The same happens if the sender restarts a transaction
+    /// that is still open and the configured `RestartPolicy` rejects
+    /// that. An observer signaling to stop via `ObserverSignal::stop`
+    /// gets the connection closed and the receiver stopped using
+    /// `stop`.
+    fn process<P>(
+        id: usize,
+        socket: TcpStream,
+        fd: Arc<Fd>,
+        mut dispatch: P,
+        counters: &Counters,
+        config: &Config,
+        stop: &Stop,
+    ) -> Result<(), String>
+    where
+        P: Dispatch,
+    {
+        let mut writer = socket
+            .try_clone()
+            .map_err(|e| format!("failed to clone socket: {}", e))?;
+        let mut frames = match Frames::new(socket, config.prefetch, config.max_frame_size) {
+            Ok(frames) => frames,
+            Err(e) => {
+                Self::close(id, &fd);
+                return Err(format!("failed to start reading ahead: {}", e));
+            }
+        };
+        // The token the sender has yet to present, if we require one.
+        let mut unauthenticated = config.token.as_ref();
+        // The identity of the sender, if it identified itself.
+        let mut sender = None;
+        // Whether we can tell the kind of messages without decoding
+        // them; otherwise only control frames get classified.
+        let tagged = dispatch.has_fixint_tags();
+        // Whether we have yet to dispatch a message, i.e., whether
+        // the sender may still identify itself.
+        let mut fresh = true;
+        // The buffer holding the message being processed.
+        let mut frame = Vec::new();
+        // The number of commits we have seen on this connection.
+        let mut commits = 0;
+        // The number of messages we failed to decode in a row.
+        let mut failures = 0;
+        // The state of the stream of messages.
+        let mut session = Session {
+            max_batch_len: config.max_batch_len,
+            empty_batches: config.empty_batches,
+            ..Session::new(config.restart)
+        };
+        // The version the sender has yet to announce, if we expect one.
+        let mut unverified = config.version.as_ref();
+        // The bytes we read but did not yet dispatch.
+        let mut buffered = Buffered::new(counters);
+        // When the transaction in progress started.
+        let mut started = None;
+        // Whether the transaction in progress timed out, with the
+        // sender yet to move on from it.
+        let mut stalled = false;
+        // The read timeout currently set on the socket.
+        let mut read_timeout = None;
+        loop {
+            buffered.set(frames.buffered());
+
+            let deadline = config
+                .transaction_timeout
+                .and_then(|timeout| started.map(|started: Instant| started + timeout));
+            let remaining = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        warn!("TcpReceiver({}): transaction timed out", id);
+                        started = None;
+                        Self::abort(id, &mut dispatch, &mut session);
+                        match config.stall {
+                            StallPolicy::Abort => {
+                                stalled = true;
+                                continue;
+                            }
+                            StallPolicy::Disconnect => {
+                                Self::hang_up(id, &mut dispatch, &mut session, config.eof);
+                                Self::close(id, &fd);
+                                return Err("transaction timed out".to_string());
+                            }
+                        }
+                    }
+                    Some(deadline - now)
+                }
+                None => None,
+            };
+            // Reads time out once the transaction in progress is due,
+            // so that we get to abort it even if the sender went
+            // silent.
+            if frames.is_direct() && remaining != read_timeout {
+                if let Err(e) = writer.set_read_timeout(remaining) {
+                    Self::disconnect(id, &mut dispatch, &mut session, config.eof);
+                    Self::close(id, &fd);
+                    return Err(format!("failed to set read timeout: {}", e));
+                }
+                read_timeout = remaining;
+            }
+
+            match frames.next(&mut frame, remaining) {
+                Ok(Some(bytes)) => {
+                    counters.receive();
+                    session.frame_bytes = bytes;
+                    buffered.set(frames.buffered() + frame.len());
+                }
+                Ok(None) => {
+                    if fd.is_shutdown() {
+                        Self::abort(id, &mut dispatch, &mut session);
+                        return Ok(());
+                    }
+                    // A sender that did not even authenticate or
+                    // announce its version has nothing to tell the
+                    // observer.
+                    if unauthenticated.is_some() || unverified.is_some() {
+                        Self::close(id, &fd);
+                        return Ok(());
+                    }
+                    Self::hang_up(id, &mut dispatch, &mut session, config.eof);
+                    Self::close(id, &fd);
+                    return Ok(());
+                }
+                Err(e) => {
+                    if fd.is_shutdown() {
+                        Self::abort(id, &mut dispatch, &mut session);
+                        return Ok(());
+                    }
+                    match e.kind() {
+                        // We have seen the beginning of a message, so
+                        // the sender must have died while sending it.
+                        ErrorKind::UnexpectedEof => {
+                            Self::disconnect(id, &mut dispatch, &mut session, config.eof);
+                            Self::close(id, &fd);
+                            return Err("connection closed in the middle of a message".to_string());
+                        }
+                        // The read got interrupted or timed out before
+                        // any data arrived; just try again.
+                        ErrorKind::WouldBlock | ErrorKind::Interrupted | ErrorKind::TimedOut => {
+                            continue
+                        }
+                        // Any other I/O error means the connection is
+                        // broken (or the sender misbehaves) and retrying
+                        // will not do any good.
+                        _ => {
+                            Self::disconnect(id, &mut dispatch, &mut session, config.eof);
+                            Self::close(id, &fd);
+                            return Err(format!("failed to read message: {}", e));
+                        }
+                    }
+                }
+            }
+
+            if let Some(token) = unauthenticated.take() {
+                counters.dispatch();
+                if !token.matches(&frame) {
+                    Self::close(id, &fd);
+                    return Err("sender presented an invalid token".to_string());
+                }
+                continue;
+            }
+
+            if let Some(version) = unverified.take() {
+                counters.dispatch();
+                if let Err(e) = Self::verify(&frame, version) {
+                    // Tell the sender what we expect before hanging up
+                    // on it, so that it can report the mismatch.
+                    let _ = Self::reply(&mut writer, Message::Version(version.clone()));
+                    Self::close(id, &fd);
+                    return Err(e);
+                }
+                continue;
+            }
+
+            // Identifications are control frames, but with a codec not
+            // encoding kinds like they are, we cannot tell them apart
+            // from other messages past the start of the stream.
+            if (tagged || fresh) && matches!(Kind::of(&frame), Ok(Kind::Identify)) {
+                counters.dispatch();
+                // Only commits from the very start of a connection are
+                // attributed to the sender.
+                if sender.is_some() || commits > 0 || session.open {
+                    Self::close(id, &fd);
+                    return Err("sender identified itself in the middle of its stream".to_string());
+                }
+                let identity = match Self::identity(&frame) {
+                    Ok(identity) => identity,
+                    Err(e) => {
+                        Self::close(id, &fd);
+                        return Err(e);
+                    }
+                };
+                let processed = counters.processed(&identity);
+                if let Err(e) = Self::reply(&mut writer, Message::Resume(processed)) {
+                    Self::close(id, &fd);
+                    return Err(e);
+                }
+                sender = Some(identity);
+                continue;
+            }
+
+            if stalled && !tagged {
+                counters.dispatch();
+                Self::close(id, &fd);
+                return Err(
+                    "cannot tell whether the sender moved on from a transaction that timed out"
+                        .to_string(),
+                );
+            }
+
+            if stalled {
+                match Kind::of(&frame) {
+                    Ok(Kind::Start) | Ok(Kind::Complete) => stalled = false,
+                    // The sender gave up on the transaction, too.
+                    Ok(Kind::Abort) => {
+                        stalled = false;
+                        counters.dispatch();
+                        continue;
+                    }
+                    // Relaying the rest of the transaction would have
+                    // the observer commit a fragment of it. We cannot
+                    // tell what a multiplexed message continues, so
+                    // err on the side of caution with those, too.
+                    Ok(Kind::Updates) | Ok(Kind::UpdateList) | Ok(Kind::Snapshot)
+                    | Ok(Kind::Commit) | Ok(Kind::Flush) | Ok(Kind::Framed) => {
+                        counters.dispatch();
+                        Self::close(id, &fd);
+                        return Err("sender continued a transaction that timed out".to_string());
+                    }
+                    _ => (),
+                }
+            }
+
+            fresh = false;
+            let (event, result) = match dispatch.dispatch(&frame, &mut session) {
+                Ok(dispatched) => {
+                    failures = 0;
+                    dispatched
+                }
+                Err(e) => {
+                    // A message we cannot decode is discarded, but
+                    // still accounted for, so that it does not count
+                    // towards the queue depth forever.
+                    counters.dispatch();
+                    // A message introduced by a later version of the
+                    // protocol is skipped, without being held against
+                    // the sender.
+                    if let Some(index) = Kind::unknown(&frame).filter(|_| tagged) {
+                        debug!(
+                            "TcpReceiver({}): skipping message of unknown kind {}",
+                            id, index
+                        );
+                        continue;
+                    }
+                    error!("TcpReceiver({}): failed to deserialize message: {}", id, e);
+                    report(config, ReceiverError::Decode(e.to_string()));
+                    failures += 1;
+                    if failures >= config.max_decode_failures {
+                        Self::abort(id, &mut dispatch, &mut session);
+                        Self::close(id, &fd);
+                        return Err(format!(
+                            "failed to deserialize {} consecutive messages",
+                            failures
+                        ));
+                    }
+                    continue;
+                }
+            };
+
+            if !session.open {
+                started = None
+            } else if started.is_none() || event == Event::Start {
+                started = Some(Instant::now())
+            }
+
+            let result = match event {
+                Event::Commit => {
+                    commits += 1;
+                    // Account for the commit before acknowledging it,
+                    // so that a sender reconnecting because the
+                    // acknowledgement got lost does not replay it.
+                    let result = result.map(|_| counters.process(sender.as_deref()));
+                    counters.commit();
+                    result.and_then(|_| Self::reply(&mut writer, Message::Ack(commits)))
+                }
+                Event::Restart => {
+                    counters.dispatch();
+                    Self::abort(id, &mut dispatch, &mut session);
+                    Self::close(id, &fd);
+                    return result;
+                }
+                Event::Start
+                | Event::Updates
+                | Event::Snapshot
+                | Event::Abort
+                | Event::Flush
+                | Event::Complete
+                | Event::Ack
+                | Event::Resume
+                | Event::Version
+                | Event::Identify
+                | Event::Framed => result,
+            };
+            counters.dispatch();
+
+            if let Err(e) = result {
+                error!(
+                    "TcpReceiver({}): observer {:?} failed to process {} event: {}",
+                    id, dispatch, event, e
+                );
+                let error = ReceiverError::Observer {
+                    event: event.to_string(),
+                    error: e,
+                };
+                report(config, error);
+            }
+
+            // The event is accounted for, so act upon a signal raised
+            // while processing it.
+            if stop.signal.take_stop() {
+                debug!("TcpReceiver({}): observer signaled to stop", id);
+                Self::abort(id, &mut dispatch, &mut session);
+                Self::close(id, &fd);
+                stop.stop(id);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Check that the message in `frame` announces `version`.
+    fn verify(frame: &[u8], version: &str) -> Result<(), String> {
+        match deserialize::<Message<()>>(frame) {
+            Ok(Message::Version(announced)) if announced == version => Ok(()),
+            Ok(Message::Version(announced)) => Err(format!(
+                "sender announced version {} instead of {}",
+                announced, version
+            )),
+            Ok(message) => Err(format!(
+                "sender did not announce version {}, but sent {}",
+                version, message
+            )),
+            Err(e) => Err(format!("failed to decode version announcement: {}", e)),
+        }
+    }
+
+    /// Retrieve the identity of a sender from its identification in
+    /// `frame`.
+    fn identity(frame: &[u8]) -> Result<String, String> {
+        match deserialize::<Message<()>>(frame) {
+            Ok(Message::Identify(identity)) => Ok(identity),
+            Ok(message) => Err(format!("expected identification, but got {}", message)),
+            Err(e) => Err(format!("failed to decode identification: {}", e)),
+        }
+    }
+
+    /// Abort the transaction in progress on a connection, if any.
+    fn abort<P>(id: usize, dispatch: &mut P, session: &mut Session)
+    where
+        P: Dispatch,
+    {
+        if replace(&mut session.open, false) {
+            if let Err(e) = dispatch.on_abort() {
+                error!(
+                    "TcpReceiver({}): observer {:?} failed to process on_abort event: {}",
+                    id, dispatch, e
+                );
+            }
+        }
+    }
+
+    /// Tell the observer about the sender going away, as the given
+    /// `EofPolicy` mandates.
+    fn hang_up<P>(id: usize, dispatch: &mut P, session: &mut Session, eof: EofPolicy)
+    where
+        P: Dispatch,
+    {
+        match eof {
+            EofPolicy::EmitDisconnected | EofPolicy::EmitAbort => {
+                Self::disconnect(id, dispatch, session, eof)
+            }
+            EofPolicy::Nothing => (),
+        }
+    }
+
+    /// Tell the observer about the connection to the sender breaking:
+    /// abort the transaction left open, if any, and signal the
+    /// disconnect if the given `EofPolicy` asks for it.
+    fn disconnect<P>(id: usize, dispatch: &mut P, session: &mut Session, eof: EofPolicy)
+    where
+        P: Dispatch,
+    {
+        Self::abort(id, dispatch, session);
+        // The sender went away without signaling completion, so do it
+        // on its behalf, telling the two apart.
+        if eof == EofPolicy::EmitDisconnected && !session.completed {
+            if let Err(e) = dispatch.on_disconnected() {
+                error!(
+                    "TcpReceiver({}): observer {:?} failed to process on_disconnected event: {}",
+                    id, dispatch, e
+                );
+            }
+        }
+    }
+
+    /// Shut down the connection represented by the given file
+    /// descriptor.
+    fn close(id: usize, fd: &Fd) {
+        if let Err(e) = fd.shutdown() {
+            error!("TcpReceiver({}): failed to shut down socket: {}", id, e);
+        }
+    }
+
+    /// Send a message without updates, such as an acknowledgement, to
+    /// the sender.
+    fn reply(writer: &mut TcpStream, message: Message<()>) -> Result<(), String> {
+        // Assemble the frame up front so that it goes out with a single
+        // write.
+        let mut buffer = Vec::new();
+        write_frame(&mut buffer, &message)
+            .map_err(|e| format!("failed to encode {} message: {}", message, e))?;
+        writer
+            .write_all(&buffer)
+            .map_err(|e| format!("failed to send {} message: {}", message, e))
+    }
+
+    /// Retrieve the address we are listening on.
+    pub(crate) fn addr(&self) -> &SocketAddr {
+        &self.addr
+    }
+
+    /// Retrieve the counters tracking the messages received and
+    /// dispatched.
+    pub(crate) fn counters(&self) -> &Arc<Counters> {
+        &self.counters
+    }
+
+    /// Retrieve the connections being processed.
+    pub(crate) fn connections(&self) -> Vec<Connection> {
+        self.connections.list()
+    }
+
+    /// Retrieve the signal the observer may raise to stop us.
+    pub(crate) fn signal(&self) -> &ObserverSignal {
+        &self.signal
+    }
+
+    /// Create an observable for the events in the life of the
+    /// connections we accept.
+    pub(crate) fn connection_events(&self) -> UpdatesObservable<ConnectionEvent, String> {
+        UpdatesObservable {
+            observer: self.events.clone(),
+        }
+    }
+
+    /// Stop accepting connections and hand out the listener socket,
+    /// without shutting it down. Connections being processed are
+    /// closed, while those not yet accepted stay queued on the socket.
+    pub(crate) fn into_listener(mut self) -> Result<TcpListener, String> {
+        match self.stop_accepting()? {
+            Source::Listen(listener) => Ok(listener),
+            Source::Dial(..) => Err("not listening for connections".to_string()),
+        }
+    }
+
+    /// Stop accepting connections and close the listener socket, while
+    /// retaining everything else, e.g., the counters. Connections being
+    /// processed are closed, as are those not yet accepted. Pausing an
+    /// already paused acceptor has no effect.
+    pub(crate) fn pause(&mut self) -> Result<(), String> {
+        if self.thread.is_some() {
+            let _source = self.stop_accepting()?;
+        }
+        Ok(())
+    }
+
+    /// Resume accepting connections after a `pause`, on a listener
+    /// socket newly bound to the address we listened on before (or by
+    /// connecting to the sender anew). This
+    /// also restarts an acceptor that stopped because an observer
+    /// signaled so. Resuming a running acceptor has no effect.
+    pub(crate) fn resume(&mut self) -> Result<(), String> {
+        if self.thread.is_some() {
+            if !self.wake.is_woken() {
+                return Ok(());
+            }
+            // We got stopped on behalf of an observer; the listener was
+            // shut down and has to be closed before binding anew.
+            self.pause()?;
+        }
+
+        let source = if self.dial {
+            Source::Dial(self.addr)
+        } else {
+            Source::Listen(bind_listener(self.addr, &self.config)?)
+        };
+        let (fd, wake, thread) = Self::start(&mut self.spawn, source)?;
+        self.fd = fd;
+        self.wake = wake;
+        self.thread = Some(thread);
+        Ok(())
+    }
+
+    /// Wake up the accepting thread and wait for it to exit, handing
+    /// back the source of connections.
+    fn stop_accepting(&mut self) -> Result<Source, String> {
+        self.wake
+            .wake()
+            .map_err(|e| format!("failed to stop accepting connections: {}", e))?;
+        // The acceptor thread may be waiting for a connection slot to
+        // become available; make sure it notices the wake up.
+        self.limit.wake();
+
+        // With the thread gone, dropping us no longer shuts down the
+        // listener.
+        let thread = self
+            .thread
+            .take()
+            .ok_or_else(|| "accept thread is gone".to_string())?;
+        match join_timeout(thread, self.config.shutdown_timeout) {
+            Some(Ok(result)) => result,
+            Some(Err(e)) => Err(format!("accept thread has panicked: {:?}", e)),
+            None => Err(format!(
+                "accept thread did not exit within {:?}",
+                self.config.shutdown_timeout
+            )),
+        }
+    }
+
+    /// Shut down the listener socket and wait for the accepting thread
+    /// to exit. Closing is attempted in full even if a step fails, with
+    /// the first failure being reported and any later ones logged.
+    /// Closing an acceptor that is not running has no effect.
+    pub(crate) fn shutdown(&mut self) -> Result<(), CloseError> {
+        // If the listener got handed off there is nothing left to stop.
+        let t = match self.thread.take() {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+
+        let mut errors = Vec::new();
+        // Note that we only ever shut down the file descriptor, but
+        // don't close it. The close will happen once the "acceptor"
+        // thread wakes up, sees that we are shut down, and exits,
+        // handing back the TcpListener for us to drop.
+        if let Some(fd) = &self.fd {
+            if let Err(e) = fd.shutdown() {
+                errors.push(CloseError::Socket(e.to_string()));
+            }
+        }
+        if let Err(e) = self.wake.wake() {
+            errors.push(CloseError::Socket(format!(
+                "failed to wake up accept thread: {}",
+                e
+            )));
+        }
+        // The acceptor thread may be waiting for a connection slot to
+        // become available; make sure it notices the shutdown.
+        self.limit.wake();
+
+        match join_timeout(t, self.config.shutdown_timeout) {
+            Some(Ok(Ok(_source))) => (),
+            Some(Ok(Err(e))) => errors.push(CloseError::Thread(e)),
+            Some(Err(e)) => errors.push(CloseError::Panicked(format!("{:?}", e))),
+            None => errors.push(CloseError::Timeout(self.config.shutdown_timeout)),
+        }
+
+        let mut errors = errors.into_iter();
+        match errors.next() {
+            Some(error) => {
+                for e in errors {
+                    error!("TcpReceiver({}) failed to close: {}", self.id, e);
+                }
+                Err(error)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Acceptor {
+    fn drop(&mut self) {
+        if let Err(e) = self.shutdown() {
+            error!("TcpReceiver({}) failed to close: {}", self.id, e);
+        }
+
+        // The remaining members will be destroyed automatically, no
+        // need to bother here.
+    }
+}
+
+/// The receiving end of a TCP channel has an address
+/// and streams data to an observer.
+///
+/// A receiver accepts connections from multiple senders and processes
+/// them concurrently, optionally up to a limit. Connections exceeding
+/// the limit are left queued (in the listen backlog of the socket)
+/// until a connection being processed is closed. Transactions from
+/// different connections never interleave: each connection's
+/// transaction is buffered until its commit and only then delivered to
+/// the observer as a whole. The completion of any sender is forwarded
+/// to the observer as is. Messages are decoded using the `Codec` `C`.
+#[derive(Debug)]
+pub struct TcpReceiver<T, D, C = BincodeCodec>
+where
+    T: Debug + Send,
+    D: Debug + Send,
+{
+    /// The TCP receiver's unique ID.
+    id: usize,
+    /// The machinery accepting connections and processing the data
+    /// arriving on them.
+    acceptor: Acceptor,
+    /// The transaction multiplexer we use to ensure serialization of
+    /// transactions from all accepted connections.
+    txnmux: Arc<Mutex<TxnMux<T, String>>>,
+    /// A counter incremented whenever an observer got subscribed or
+    /// unsubscribed, identifying the current subscription. Only ever
+    /// changed with `txnmux` locked.
+    generation: Arc<AtomicU64>,
+    _phantom: PhantomData<(D, C)>,
+}
+
+/// `T` - type received from the network.  This type is not required to implement `Deserialize`.
+/// `D` - a "wrapper" type that implements `Deserialize` and that can be converted into `T`.
+///
+/// `T` and `D` can be the same type. Both being `RawBytes` makes the
+/// receiver pass through the encoded messages instead of decoding them.
+///
+/// Using two separate type arguments supports the use case when `Deserialize` implementation
+/// resides outside the crate that declares `T` and is defined over a wrapper type, without
+/// introducing a separate filter to perform the conversion.
+impl<T, D> TcpReceiver<T, D>
+where
+    T: Send + Debug + 'static,
+    D: Into<T> + Send + Debug + 'static,
+    BincodeCodec: Codec<D>,
+{
+    /// Create a new TCP receiver with no observer.
+    ///
+    /// `addr` may have a port set (by setting it to 0). In such a case
+    /// the system will assign a port that is free. To retrieve this
+    /// assigned port (in the form of the full `SocketAddr`), use the
+    /// `addr` method.
+    pub fn new<A>(addr: A) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::builder(addr).build()
+    }
+
+    /// Create a builder for a TCP receiver listening on `addr`, which
+    /// allows for setting all the options a receiver supports.
+    pub fn builder<A>(addr: A) -> TcpReceiverBuilder
+    where
+        A: ToSocketAddrs,
+    {
+        TcpReceiverBuilder::new(addr)
+    }
+
+    /// Create a new TCP receiver with no observer, only accepting
+    /// connections from senders announcing `version`, as created by
+    /// `TcpSender::new_versioned`. Any other sender is refused with an
+    /// error, instead of having its data decoded into garbage because
+    /// it has a different idea of the format of the updates.
+    pub fn new_versioned<A, S>(addr: A, version: S) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+        S: Into<String>,
+    {
+        Self::builder(addr).version(version).build()
+    }
+
+    /// Create a new TCP receiver with no observer that, instead of
+    /// listening, connects to a sender listening on `addr`, e.g., for
+    /// traversing a NAT. Data are received over that connection just
+    /// like over an accepted one. Whenever the connection drops or
+    /// cannot be established, it is retried, backing off exponentially.
+    /// Use `TcpReceiverBuilder::connect` for configuring the backoff.
+    ///
+    /// The peer has to speak the sender side of the protocol on the
+    /// connections it accepts.
+    pub fn connect<A>(addr: A) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        TcpReceiverBuilder::connect(addr).build()
+    }
+
+    /// Create a new TCP receiver with no observer, processing at most
+    /// `max_connections` connections concurrently.
+    pub fn with_max_connections<A>(addr: A, max_connections: usize) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::builder(addr).max_connections(max_connections).build()
+    }
+
+    /// Create a new TCP receiver with no observer, only accepting
+    /// connections from peers whose address is part of one of the
+    /// networks in `allow`.
+    pub fn with_allowlist<A>(addr: A, allow: Vec<IpNet>) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        Self::builder(addr).allowlist(allow).build()
+    }
+
+    /// Create a new TCP receiver with no observer, decoding messages
+    /// using bincode configured with `options`. Senders have to use the
+    /// same options (see `TcpSender::with_bincode_config`).
+    pub fn with_bincode_config<A, O>(
+        addr: A,
+        options: O,
+    ) -> Result<TcpReceiver<T, D, BincodeConfigCodec<O>>, String>
+    where
+        A: ToSocketAddrs,
+        BincodeConfigCodec<O>: Codec<D>,
+    {
+        Self::builder(addr).build_with_codec(BincodeConfigCodec::new(options))
+    }
+
+    /// Create a new TCP receiver with no observer, handling observers
+    /// subscribing in addition to one subscribed already as `policy`
+    /// dictates.
+    pub fn with_subscribe_policy<A>(addr: A, policy: SubscribePolicy) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+        T: Clone,
+    {
+        let mut receiver = Self::new(addr)?;
+        receiver.set_subscribe_policy(policy);
+        Ok(receiver)
+    }
+
+    /// Create a new TCP receiver with no observer, using an already
+    /// bound listener socket.
+    ///
+    /// This allows for the listener to be set up externally, e.g., as
+    /// part of socket activation or with custom socket options.
+    pub fn from_listener(listener: TcpListener) -> Result<Self, String> {
+        TcpReceiverBuilder::from_listener(listener).build()
+    }
+
+    /// Create a new TCP receiver with no observer, adopting a listener
+    /// socket from a raw file descriptor, e.g., one inherited as part
+    /// of socket activation or handed off by another receiver via
+    /// `into_raw_listener`.
+    ///
+    /// The socket has to be bound and listening already.
+    ///
+    /// # Safety
+    /// The receiver takes ownership of `fd` and closes it once dropped
+    /// (or right away, in case of an error). The caller must ensure
+    /// that `fd` is open and not owned by anybody else.
+    pub unsafe fn from_raw_fd(fd: RawFd) -> Result<Self, String> {
+        let listener = TcpListener::from_raw_fd(fd);
+
+        let mut listening: c_int = 0;
+        let mut len = size_of::<c_int>() as socklen_t;
+        let result = getsockopt(
+            fd,
+            SOL_SOCKET,
+            SO_ACCEPTCONN,
+            &mut listening as *mut c_int as *mut c_void,
+            &mut len,
+        );
+        if result != 0 {
+            let error = Error::last_os_error();
+            return Err(format!("failed to inquire socket state: {}", error));
+        }
+        if listening == 0 {
+            return Err(format!("file descriptor {} is not listening", fd));
+        }
+
+        Self::from_listener(listener)
+    }
+}
+
+impl<T, D, C> TcpReceiver<T, D, C>
+where
+    T: Send + Debug + 'static,
+    D: Into<T> + Send + Debug + 'static,
+    C: Codec<D>,
+{
+    /// Stop accepting connections and hand out the listener socket as
+    /// a raw file descriptor, e.g., for another process to take over
+    /// via `from_raw_fd` as part of a restart without downtime.
+    ///
+    /// The socket is neither shut down nor closed, so connection
+    /// attempts arriving in the meantime queue up in its listen backlog
+    /// for the new owner to accept. Connections already accepted are
+    /// closed, aborting the transactions in progress on them; their
+    /// senders have to reconnect.
+    ///
+    /// Ownership of the file descriptor passes to the caller. It can be
+    /// sent to another process over a Unix domain socket (using
+    /// `SCM_RIGHTS`) or inherited by a child process; for the latter,
+    /// note that sockets are created with `FD_CLOEXEC` set, which has
+    /// to be cleared before calling `exec`. Both mechanisms are
+    /// specific to Unix systems.
+    pub fn into_raw_listener(self) -> Result<RawFd, String> {
+        trace!("TcpReceiver({})::into_raw_listener", self.id);
+        self.acceptor.into_listener().map(IntoRawFd::into_raw_fd)
+    }
+
+    /// Stop accepting connections and unbind from our address (or stop
+    /// connecting to the sender, for a receiver created via `connect`),
+    /// without dropping the receiver, e.g., to shed load or while
+    /// reconfiguring the observer. Everything else, including the subscribed observer
+    /// and the metrics, stays in place until `resume` is called.
+    ///
+    /// Connections already accepted are closed, aborting the
+    /// transactions in progress on them. Connections racing with the
+    /// pause, i.e., those already established by the kernel but not yet
+    /// accepted by us, are reset when the listener socket is closed.
+    /// Either way, senders have to reconnect. Pausing a paused receiver
+    /// has no effect.
+    pub fn pause(&mut self) -> Result<(), String> {
+        trace!("TcpReceiver({})::pause", self.id);
+        self.acceptor.pause()
+    }
+
+    /// Resume accepting connections after a `pause`, by binding to the
+    /// address we listened on before. That is also the case for a
+    /// receiver created from an existing listener socket. Resuming
+    /// fails if the address got taken in the meantime, leaving the
+    /// receiver paused. A receiver stopped on behalf of its observer
+    /// (see `ObserverSignal::stop`) gets restarted, while resuming a
+    /// running receiver has no effect.
+    pub fn resume(&mut self) -> Result<(), String> {
+        trace!("TcpReceiver({})::resume", self.id);
+        self.acceptor.resume()
+    }
+
+    /// Close the receiver, reporting whether doing so went cleanly.
+    ///
+    /// Dropping a receiver closes it as well, but merely logs any
+    /// failure. Closing the listener socket and waiting for the
+    /// thread accepting connections to exit is attempted in full even
+    /// if a step fails; the first failure is reported. As with
+    /// dropping, we wait at most for the configured shutdown timeout
+    /// for the thread to exit.
+    pub fn close(mut self) -> Result<(), CloseError> {
+        trace!("TcpReceiver({})::close", self.id);
+        self.acceptor.shutdown()
+    }
+
+    /// Create a new TCP receiver with the given configuration,
+    /// accepting connections from `source` and decoding messages using
+    /// `codec`.
+    pub(crate) fn with_config(source: Source, config: Config, codec: C) -> Result<Self, String> {
+        let id = Id::<()>::new().get();
+        trace!("TcpReceiver({})::new", id);
+
+        let mut txnmux = TxnMux::new();
+        txnmux.set_max_batch_len(config.max_batch_len);
+        txnmux.set_suppress_empty_batches(config.empty_batches == EmptyBatchPolicy::Suppress);
+        let txnmux = Arc::new(Mutex::new(txnmux));
+        let copy = txnmux.clone();
+        let connect = move |socket: &TcpStream| {
+            let passthrough = Arc::new(Mutex::new(Passthrough::new()));
+            let observable = Box::new(passthrough.clone());
+            if copy.lock_unpoisoned().add_observable(observable).is_err() {
+                error!(
+                    "TcpReceiver({}): failed to register connection {} with TxnMux",
+                    id,
+                    socket.as_raw_fd()
+                );
+                return None;
+            }
+            Some(OwnedDispatch::<T, D, C> {
+                observer: passthrough,
+                codec: codec.clone(),
+                _phantom: PhantomData,
+            })
+        };
+        let acceptor = Acceptor::new(id, source, config, connect)?;
+
+        Ok(Self {
+            id,
+            acceptor,
+            txnmux,
+            generation: Arc::new(AtomicU64::new(0)),
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Retrieve the address we are listening on (or connecting to, for
+    /// a receiver created via `connect`).
+    pub fn addr(&self) -> &SocketAddr {
+        let addr = self.acceptor.addr();
+        trace!("TcpReceiver({})::addr: {}", self.id, addr);
+        addr
+    }
+
+    /// Retrieve the number of messages received but not yet
+    /// dispatched to the observer.
+    pub fn queue_depth(&self) -> usize {
+        self.acceptor.counters().depth()
+    }
+
+    /// Retrieve the number of bytes received but not yet dispatched
+    /// to the observer, across all connections, as a measure of lag.
+    ///
+    /// Included are the bytes waiting in the kernel's receive buffers
+    /// of the connections, those read into our own buffers, and those
+    /// of the messages being dispatched. Transactions buffered until
+    /// their commit are not included, as they are kept decoded.
+    pub fn pending_bytes(&self) -> IoResult<usize> {
+        self.acceptor.connections().iter().try_fold(
+            self.acceptor.counters().buffered(),
+            |pending, connection| Ok(pending + connection.pending_bytes()?),
+        )
+    }
+
+    /// Retrieve the highest queue depth seen so far.
+    pub fn queue_high_water_mark(&self) -> usize {
+        self.acceptor
+            .counters()
+            .high_water_mark
+            .load(Ordering::SeqCst)
+    }
+
+    /// Retrieve the total number of messages received, across all
+    /// connections.
+    pub fn messages_received(&self) -> usize {
+        self.acceptor.counters().received.load(Ordering::SeqCst)
+    }
+
+    /// Retrieve the total number of messages dispatched to the
+    /// observer. Together with `messages_received` this allows for
+    /// computing how far dispatch is lagging behind.
+    pub fn messages_dispatched(&self) -> usize {
+        self.acceptor.counters().dispatched.load(Ordering::SeqCst)
+    }
+
+    /// Retrieve the number of commits delivered to the observer, across
+    /// all connections.
+    pub fn committed_count(&self) -> u64 {
+        *self.acceptor.counters().commits.lock_unpoisoned()
+    }
+
+    /// Retrieve the metrics accumulated since the receiver got created.
+    pub fn metrics(&self) -> ReceiverMetrics {
+        self.acceptor.counters().metrics()
+    }
+
+    /// Retrieve the metrics accumulated since the last call (or since
+    /// the receiver got created), resetting them.
+    ///
+    /// This is meant for collectors computing rates per interval: each
+    /// counter is read and reset with a single atomic operation, so
+    /// every message and commit is accounted for in exactly one
+    /// interval, even while they keep arriving. Resetting does not
+    /// affect `metrics` or any of the other accessors.
+    pub fn take_metrics(&self) -> ReceiverMetrics {
+        self.acceptor.counters().take_metrics()
+    }
+
+    /// Retrieve the number of transactions of the sender identifying
+    /// itself as `sender` the observer committed successfully, across
+    /// all connections. This is the sequence number of the last
+    /// transaction processed, as announced to the sender for
+    /// resumption when it reconnects. Only relays identify themselves
+    /// (see `TcpReceiverBuilder::resume`); a `TcpSender` does not.
+    pub fn last_processed_seq(&self, sender: &str) -> u64 {
+        self.acceptor.counters().processed(sender)
+    }
+
+    /// Block until at least `count` commits have been delivered to the
+    /// observer, failing if that did not happen within `timeout`.
+    pub fn wait_for_commit(&self, count: u64, timeout: Duration) -> Result<(), String> {
+        self.acceptor.counters().await_commits(count, timeout)
+    }
+
+    /// Retrieve handles to the connections currently being processed,
+    /// in the order they were accepted, for inspecting or tweaking
+    /// their sockets. A connection that got closed in the meantime
+    /// is no longer listed, while handles retrieved earlier remain
+    /// valid, if useless.
+    pub fn connections(&self) -> Vec<Connection> {
+        self.acceptor.connections()
+    }
+
+    /// Retrieve the handle an observer may use to signal the receiver,
+    /// e.g., to stop it. It is meant to be handed to the observer upon
+    /// subscription.
+    pub fn signal(&self) -> ObserverSignal {
+        self.acceptor.signal().clone()
+    }
+
+    /// Create an observable emitting an event whenever a sender
+    /// connects or disconnects, for monitoring purposes. Every event is
+    /// emitted as a transaction of its own, independent of the data
+    /// delivered to the observer subscribed via `subscribe`.
+    ///
+    /// All observables created this way share a single subscription.
+    pub fn connection_events(&self) -> UpdatesObservable<ConnectionEvent, String> {
+        self.acceptor.connection_events()
+    }
+
+    /// Turn the receiver into an iterator over the messages it
+    /// receives, for code that would rather pull data than have it
+    /// pushed to an `Observer`. Up to `capacity` messages are queued;
+    /// beyond that the threads processing connections block until the
+    /// iterator catches up. Any observer subscribed is unsubscribed.
+    ///
+    /// The iterator ends once a sender completed or disconnected. See
+    /// `MessageIter` for details.
+    pub fn into_messages(self, capacity: usize) -> MessageIter<T, D, C> {
+        trace!("TcpReceiver({})::into_messages", self.id);
+        MessageIter::new(self, capacity)
+    }
+
+    /// Turn the receiver into a `Stream` of the messages it receives,
+    /// for consumption by asynchronous code. Up to `capacity` messages
+    /// are queued; beyond that the threads processing connections
+    /// block until the stream is polled again. Any observer subscribed
+    /// is unsubscribed.
+    ///
+    /// The stream ends once a sender completed or disconnected. See
+    /// `MessageStream` for details.
+    #[cfg(feature = "futures")]
+    pub fn into_stream(self, capacity: usize) -> MessageStream<T, D, C> {
+        trace!("TcpReceiver({})::into_stream", self.id);
+        MessageStream::new(self, capacity)
+    }
+
+    /// Forcibly deliver the updates of all transactions still in
+    /// progress to the observer, ahead of their commits, returning the
+    /// number of updates flushed. See `TxnMux::flush` for details.
+    pub fn flush(&self) -> Result<usize, String> {
+        trace!("TcpReceiver({})::flush", self.id);
+        self.txnmux.lock_unpoisoned().flush()
+    }
+
+    /// Subscribe an observer to the boundaries of the transactions
+    /// delivered, i.e., to all events but `on_updates`, e.g., for
+    /// informing a coordinator while the data go to the observer
+    /// subscribed via `subscribe`.
+    ///
+    /// The lifecycle observer is invoked right after the subscribed
+    /// observer processed an event successfully, on the same thread.
+    /// Hence, it sees transaction boundaries in the very order the
+    /// subscribed observer does and learns about a commit only once
+    /// the subscribed observer completed it. See
+    /// `TxnMux::subscribe_lifecycle` for details.
+    pub fn subscribe_lifecycle(
+        &mut self,
+        observer: ObserverBox<(), String>,
+    ) -> Result<(), ObserverBox<(), String>> {
+        trace!("TcpReceiver({})::subscribe_lifecycle", self.id);
+        self.txnmux.lock_unpoisoned().subscribe_lifecycle(observer)
+    }
+
+    /// Unsubscribe the lifecycle observer, if any.
+    pub fn unsubscribe_lifecycle(&mut self) -> Option<ObserverBox<(), String>> {
+        trace!("TcpReceiver({})::unsubscribe_lifecycle", self.id);
+        self.txnmux.lock_unpoisoned().unsubscribe_lifecycle()
+    }
+
+    /// Set what happens to an observer subscribing while another one
+    /// is subscribed already, which is to reject it by default. See
+    /// `SubscribePolicy` for details.
+    pub fn set_subscribe_policy(&mut self, policy: SubscribePolicy)
+    where
+        T: Clone,
+    {
+        self.txnmux.lock_unpoisoned().set_subscribe_policy(policy)
+    }
+
+    /// Subscribe `observer`, delivering the current state as produced
+    /// by `snapshot` to it before any live transaction.
+    ///
+    /// This allows for an observer to join a stream that has been
+    /// going on for a while: it first receives a synthetic transaction
+    /// containing the snapshot (via `on_snapshot`), followed by the
+    /// transactions received from then on. See
+    /// `TxnMux::subscribe_with_snapshot` for details.
+    pub fn subscribe_with_snapshot<F>(
+        &mut self,
+        observer: ObserverBox<T, String>,
+        snapshot: F,
+    ) -> Result<(), ObserverBox<T, String>>
+    where
+        F: FnOnce() -> Vec<T>,
+    {
+        trace!("TcpReceiver({})::subscribe_with_snapshot", self.id);
+
+        let mut txnmux = self.txnmux.lock_unpoisoned();
+        txnmux.subscribe_with_snapshot(observer, snapshot)?;
+        let _ = self.generation.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Subscribe `observer`, giving up on it unless a sender shows up
+    /// within `timeout`: if by then no connection got accepted and no
+    /// message received, the observer is unsubscribed again and handed
+    /// to `on_timeout`, on a thread of its own.
+    ///
+    /// The decision is made with the subscription locked, so a sender
+    /// arriving right at the deadline either keeps the observer
+    /// subscribed or finds it gone, but never gets it taken away in
+    /// the middle of a message. Nothing happens on timeout if the
+    /// observer got unsubscribed explicitly in the meantime.
+    pub fn subscribe_with_timeout<F>(
+        &mut self,
+        observer: ObserverBox<T, String>,
+        timeout: Duration,
+        on_timeout: F,
+    ) -> Result<(), ObserverBox<T, String>>
+    where
+        F: FnOnce(ObserverBox<T, String>) + Send + 'static,
+    {
+        trace!(
+            "TcpReceiver({})::subscribe_with_timeout: {:?}",
+            self.id,
+            timeout
+        );
+
+        let counters = self.acceptor.counters().clone();
+        let connections = counters.connections();
+        let received = counters.received.load(Ordering::SeqCst);
+        self.subscribe(observer)?;
+        let generation = self.generation.load(Ordering::SeqCst);
+
+        let id = self.id;
+        let txnmux = self.txnmux.clone();
+        let current = self.generation.clone();
+        let _ = spawn(move || {
+            let _ = counters.await_connections(connections + 1, timeout);
+
+            let observer = {
+                let mut txnmux = txnmux.lock_unpoisoned();
+                let active = counters.connections() > connections
+                    || counters.received.load(Ordering::SeqCst) > received;
+                if active || current.load(Ordering::SeqCst) != generation {
+                    return;
+                }
+                let _ = current.fetch_add(1, Ordering::SeqCst);
+                txnmux.unsubscribe(&())
+            };
+
+            if let Some(observer) = observer {
+                debug!(
+                    "TcpReceiver({}): no sender within {:?}, unsubscribing observer",
+                    id, timeout
+                );
+                on_timeout(observer)
+            }
+        });
+        Ok(())
+    }
+}
+
+impl<T, D, C> Observable<T, String> for TcpReceiver<T, D, C>
+where
+    T: Debug + Send + 'static,
+    D: Debug + Send,
+    C: Debug + Send,
+{
+    type Subscription = ();
+
+    /// An observer subscribes to the receiving end of a TCP channel to
+    /// listen to incoming data.
+    fn subscribe(
+        &mut self,
+        observer: ObserverBox<T, String>,
+    ) -> Result<Self::Subscription, ObserverBox<T, String>> {
+        trace!("TcpReceiver({})::subscribe", self.id);
+
+        let mut txnmux = self.txnmux.lock_unpoisoned();
+        txnmux.subscribe(observer)?;
+        let _ = self.generation.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Unsubscribe a previously subscribed `Observer` based on a
+    /// subscription.
+    fn unsubscribe(&mut self, subscription: &Self::Subscription) -> Option<ObserverBox<T, String>> {
+        trace!("TcpReceiver({})::unsubscribe", self.id);
+
+        let mut txnmux = self.txnmux.lock_unpoisoned();
+        let _ = self.generation.fetch_add(1, Ordering::SeqCst);
+        txnmux.unsubscribe(subscription)
+    }
+
+    fn subscriber_count(&self) -> usize {
+        self.txnmux.lock_unpoisoned().subscriber_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::BufReader;
+    use std::io::ErrorKind;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::Shutdown;
+    use std::net::TcpStream;
+    use std::os::unix::io::IntoRawFd;
+    use std::panic::AssertUnwindSafe;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::mpsc::Receiver;
+    use std::thread::sleep;
+
+    use bincode::serialized_size;
+
+    use test_env_log::test;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::await_expected;
+    use crate::tcp_channel::builder::DEFAULT_MAX_DECODE_FAILURES;
+    use crate::tcp_channel::frame::read_frame;
+    use crate::MockObserver;
+    use crate::TcpSender;
+    use crate::TimingObserver;
+    use crate::WeightedUpdate;
+
+    /// Drop a `TcpReceiver`.
+    #[test]
+    fn drop() {
+        let _recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+    }
+
+    /// Check that closing a `TcpReceiver` reports success.
+    #[test]
+    fn close() {
+        let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+        assert_eq!(recv.close(), Ok(()));
+    }
+
+    /// Check that closing a `TcpReceiver` surfaces a failure of the
+    /// thread accepting connections.
+    #[test]
+    fn close_failed_thread() {
+        let mut recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+        recv.pause().unwrap();
+        // Substitute a failing thread for the one stopped, with no
+        // listener socket to shut down.
+        recv.acceptor.fd = None;
+        recv.acceptor.thread = Some(spawn(|| Err("broken".to_string())));
+
+        assert_eq!(recv.close(), Err(CloseError::Thread("broken".to_string())));
+    }
+
+    /// Connect to a `TcpReceiver`.
+    #[test]
+    fn accept() {
+        let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+        {
+            let _send = TcpStream::connect(recv.addr()).unwrap();
+        }
+    }
+
+    /// Check that the listener socket is cleaned up properly when a
+    /// `TcpReceiver` is dropped but has never accepted a connection.
+    #[test]
+    fn never_accepted() {
+        let test = || {
+            let addr = {
+                let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+                recv.addr().clone()
+            };
+
+            TcpStream::connect(addr)
+        };
+
+        // There is a teeny-tiny chance that a test running in parallel
+        // gets assigned the very same port we were using between the
+        // drop of the `TcpReceiver` and the `TcpStream::connect`. To
+        // prevent this unlikely collision from causing a flaky test we
+        // retry if we did indeed succeed to connect.
+        for _ in 1..10 {
+            match test() {
+                Ok(_) => continue,
+                Err(e) => {
+                    if e.kind() == ErrorKind::ConnectionRefused {
+                        break;
+                    } else {
+                        panic!("unexpected error")
+                    }
+                }
+            }
+        }
+    }
+
+    /// Check that senders are properly cleaned up when a `TcpReceiver`
+    /// is dropped.
+    #[test]
+    fn sender_cleanup() {
+        let mut send = {
+            let recv = TcpReceiver::<(), ()>::new("127.0.0.1:0").unwrap();
+            let send = TcpStream::connect(recv.addr()).unwrap();
+            send
+        };
+
+        let buffer = &mut [0; 32];
+        let result = send.read_exact(buffer);
+        let error = result.unwrap_err().kind();
+        assert!(error == ErrorKind::ConnectionReset || error == ErrorKind::UnexpectedEof);
+    }
+
+    /// Test the connection of multiple senders to a `TcpReceiver`.
+    #[test]
+    fn multiple_senders() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let addr = recv.addr();
+        let mut send1 = TcpSender::<u64>::new(*addr).unwrap();
         let mut send2 = TcpSender::<u64>::new(*addr).unwrap();
         let mut send3 = TcpSender::<u64>::new(*addr).unwrap();
 
         recv.subscribe(Box::new(mock.clone())).unwrap();
 
-        let t1 = spawn(move || {
-            let observer1 = &mut send1 as &mut dyn Observer<u64, _>;
-            let updates1 = vec![1u64];
-            observer1.on_start().unwrap();
-            observer1
-                .on_updates(Box::new(updates1.into_iter()))
-                .unwrap();
-            observer1.on_commit().unwrap();
-            send1.wait_connected().unwrap();
+        let t1 = spawn(move || {
+            let observer1 = &mut send1 as &mut dyn Observer<u64, _>;
+            let updates1 = vec![1u64];
+            observer1.on_start().unwrap();
+            observer1
+                .on_updates(Box::new(updates1.into_iter()))
+                .unwrap();
+            observer1.on_commit().unwrap();
+            send1.wait_connected().unwrap();
+        });
+
+        let t2 = spawn(move || {
+            let observer2 = &mut send2 as &mut dyn Observer<u64, _>;
+            let updates2 = vec![2u64, 3, 4];
+            observer2.on_start().unwrap();
+            observer2
+                .on_updates(Box::new(updates2.into_iter()))
+                .unwrap();
+            observer2.on_commit().unwrap();
+            send2.wait_connected().unwrap();
+        });
+
+        let t3 = spawn(move || {
+            let observer3 = &mut send3 as &mut dyn Observer<u64, _>;
+            let updates3 = vec![5u64, 6, 7, 8];
+            observer3.on_start().unwrap();
+            observer3
+                .on_updates(Box::new(updates3.into_iter()))
+                .unwrap();
+            observer3.on_commit().unwrap();
+            send3.wait_connected().unwrap();
+        });
+
+        t1.join().unwrap();
+        t2.join().unwrap();
+        t3.join().unwrap();
+
+        await_expected(|| {
+            let (on_start, on_updates, on_commit) = {
+                let guard = mock.lock().unwrap();
+                (
+                    guard.called_on_start,
+                    guard.called_on_updates,
+                    guard.called_on_commit,
+                )
+            };
+
+            assert_eq!(on_start, 3);
+            assert_eq!(on_updates, 8);
+            assert_eq!(on_commit, 3);
+        });
+    }
+
+    /// Transmit weighted updates and check that the observer sees the
+    /// values along with their weights.
+    #[test]
+    fn weighted_updates() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::new()));
+        let mut recv =
+            TcpReceiver::<WeightedUpdate<u64>, WeightedUpdate<u64>>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut send = TcpSender::<WeightedUpdate<u64>>::new(*recv.addr()).unwrap();
+        let updates = vec![WeightedUpdate::new(1, 1), WeightedUpdate::new(2, -1)];
+        {
+            let observer = &mut send as &mut dyn Observer<WeightedUpdate<u64>, _>;
+            observer.on_start().unwrap();
+            observer
+                .on_updates(Box::new(updates.clone().into_iter()))
+                .unwrap();
+            observer.on_commit().unwrap();
+        }
+
+        await_expected(|| {
+            let received = mock.lock().unwrap().received_updates.clone();
+            assert_eq!(received, updates);
+        });
+    }
+
+    /// Check that connections exceeding the limit are only processed
+    /// once a previous connection got closed.
+    #[test]
+    fn max_connections() {
+        fn transmit(send: &mut TcpSender<u64>, updates: Vec<u64>) {
+            let observer = send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(updates.into_iter())).unwrap();
+            observer.on_commit().unwrap();
+        }
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::with_max_connections("127.0.0.1:0", 1).unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut send1 = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        transmit(&mut send1, vec![1, 2]);
+        send1.wait_connected().unwrap();
+        send1.await_ack(1).unwrap();
+
+        let mut send2 = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        transmit(&mut send2, vec![3, 4, 5]);
+        send2.wait_connected().unwrap();
+
+        // The second connection should not be served while the first
+        // one is still open.
+        sleep(Duration::from_millis(50));
+        let on_updates = mock.lock().unwrap().called_on_updates;
+        assert_eq!(on_updates, 2);
+
+        // Note that `drop` is shadowed by the test of the same name.
+        std::mem::drop(send1);
+        send2.await_ack(1).unwrap();
+
+        let (on_updates, on_commit) = {
+            let guard = mock.lock().unwrap();
+            (guard.called_on_updates, guard.called_on_commit)
+        };
+        assert_eq!(on_updates, 5);
+        assert_eq!(on_commit, 2);
+    }
+
+    /// Check that a connection on which we only receive garbage gets
+    /// closed instead of being read from indefinitely.
+    #[test]
+    fn persistent_garbage() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut stream = TcpStream::connect(recv.addr()).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+
+        // Every frame contains an `Updates` message claiming way more
+        // updates than it carries. Note that a message with an invalid
+        // tag would be skipped, as that of a kind yet unknown to us.
+        let mut garbage = Vec::new();
+        for _ in 0..DEFAULT_MAX_DECODE_FAILURES {
+            garbage.extend_from_slice(&12u32.to_le_bytes());
+            garbage.extend_from_slice(&1u32.to_le_bytes());
+            garbage.extend_from_slice(&[0xff; 8]);
+        }
+        stream.write_all(&garbage).unwrap();
+
+        // The receiver should close the connection once it gave up.
+        // Any more data we send after that may cause a reset.
+        let mut buffer = [0; 16];
+        match stream.read(&mut buffer) {
+            Ok(n) => assert_eq!(n, 0),
+            Err(e) => assert_eq!(e.kind(), ErrorKind::ConnectionReset),
+        }
+
+        // The receiver is still accepting new connections.
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let (on_updates, on_commit) = {
+            let guard = mock.lock().unwrap();
+            (guard.called_on_updates, guard.called_on_commit)
+        };
+        assert_eq!(on_updates, 1);
+        assert_eq!(on_commit, 1);
+    }
+
+    /// Check that a versioned receiver only accepts senders announcing
+    /// the version it expects.
+    #[test]
+    fn versioned() {
+        /// Send a transaction, returning whether it got acknowledged.
+        fn transmit(mut send: TcpSender<u64>) -> bool {
+            let observer = &mut send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+            observer.on_commit().unwrap();
+            send.wait_connected().unwrap();
+            send.await_ack(1).is_ok()
+        }
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new_versioned("127.0.0.1:0", "v2").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        let addr = *recv.addr();
+
+        assert!(transmit(TcpSender::new_versioned(addr, "v2").unwrap()));
+        assert!(!transmit(TcpSender::new_versioned(addr, "v1").unwrap()));
+        assert!(!transmit(TcpSender::new(addr).unwrap()));
+
+        // Only the accepted sender completes the stream, once its
+        // connection is closed.
+        await_expected(|| {
+            let on_completed = mock.lock().unwrap().called_on_completed;
+            assert_eq!(on_completed, 1);
+        });
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+
+        // A receiver not expecting any version ignores the announcement.
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        assert!(transmit(
+            TcpSender::new_versioned(*recv.addr(), "v1").unwrap()
+        ));
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Check that a receiver requiring a token only accepts senders
+    /// presenting it.
+    #[test]
+    fn token() {
+        /// Send a transaction, returning whether it got acknowledged.
+        fn transmit(mut send: TcpSender<u64>) -> bool {
+            let observer = &mut send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+            observer.on_commit().unwrap();
+            send.wait_connected().unwrap();
+            send.await_ack(1).is_ok()
+        }
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::builder("127.0.0.1:0")
+            .require_token("secret")
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        let addr = *recv.addr();
+
+        assert!(transmit(TcpSender::with_token(addr, "secret").unwrap()));
+        assert!(!transmit(TcpSender::with_token(addr, "secreT").unwrap()));
+        assert!(!transmit(TcpSender::with_token(addr, "").unwrap()));
+        assert!(!transmit(TcpSender::new(addr).unwrap()));
+
+        // Nothing sent by the rejected senders reaches the observer.
+        await_expected(|| {
+            let on_completed = mock.lock().unwrap().called_on_completed;
+            assert_eq!(on_completed, 1);
+        });
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 1);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Check that the socket of a live connection can be inspected and
+    /// tweaked.
+    #[test]
+    fn connections() {
+        // `await_expected` insists on inspecting unwind safe state only.
+        let recv = AssertUnwindSafe(TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap());
+        assert!(recv.connections().is_empty());
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+        await_expected(|| assert_eq!(recv.connections().len(), 1));
+
+        let connection = recv.connections().remove(0);
+        assert_eq!(connection.local_addr().unwrap(), *recv.addr());
+        assert!(connection.peer_addr().unwrap().ip().is_loopback());
+
+        connection.set_nodelay(true).unwrap();
+        assert!(connection.nodelay().unwrap());
+        connection.set_nodelay(false).unwrap();
+        assert!(!connection.nodelay().unwrap());
+        connection.set_linger(Some(Duration::from_secs(1))).unwrap();
+        connection.set_linger(None).unwrap();
+
+        // The connection still works after the tweaks.
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+
+        std::mem::drop(send);
+        await_expected(|| assert!(recv.connections().is_empty()));
+    }
+
+    /// Check that an observer joining a stream late is bootstrapped
+    /// with a snapshot before receiving live transactions.
+    #[test]
+    fn subscribe_with_snapshot() {
+        let early = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let late = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(early.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+
+        // Only a single observer can be subscribed at a time.
+        assert!(recv
+            .subscribe_with_snapshot(Box::new(late.clone()), Vec::new)
+            .is_err());
+        assert!(recv.unsubscribe(&()).is_some());
+
+        let state = early.lock().unwrap().received_updates.clone();
+        recv.subscribe_with_snapshot(Box::new(late.clone()), || state)
+            .unwrap();
+        {
+            let late = late.lock().unwrap();
+            assert_eq!(late.received_updates, vec![1, 2]);
+            assert_eq!(late.called_on_start, 1);
+            assert_eq!(late.called_on_commit, 1);
+        }
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(2).unwrap();
+
+        let late = late.lock().unwrap();
+        assert_eq!(late.received_updates, vec![1, 2, 3]);
+        assert_eq!(late.called_on_start, 2);
+        assert_eq!(late.called_on_commit, 2);
+    }
+
+    /// Check that a `TcpReceiver` can be created from a listener bound
+    /// externally.
+    #[test]
+    fn from_listener() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::from_listener(listener).unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        assert_eq!(*recv.addr(), addr);
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        assert_eq!(mock.lock().unwrap().called_on_updates, 2);
+    }
+
+    /// Check that a `TcpReceiver` can adopt a listener socket from a
+    /// raw file descriptor.
+    #[test]
+    fn from_raw_fd() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let fd = listener.into_raw_fd();
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = unsafe { TcpReceiver::<u64, u64>::from_raw_fd(fd) }.unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        assert_eq!(*recv.addr(), addr);
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        assert_eq!(mock.lock().unwrap().called_on_updates, 1);
+    }
+
+    /// Check that a socket that is not listening is rejected.
+    #[test]
+    fn from_raw_fd_not_listening() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let fd = socket.into_raw_fd();
+
+        let result = unsafe { TcpReceiver::<u64, u64>::from_raw_fd(fd) };
+        assert!(result.is_err());
+    }
+
+    /// Write the given bytes to the receiver, close our end of the
+    /// connection, and wait for the receiver to close its end.
+    fn send_and_close(recv: &TcpReceiver<u64, u64>, data: &[u8]) {
+        let mut stream = TcpStream::connect(recv.addr()).unwrap();
+        stream.write_all(data).unwrap();
+        stream.shutdown(Shutdown::Write).unwrap();
+
+        let mut buffer = Vec::new();
+        let _ = stream.read_to_end(&mut buffer).unwrap();
+    }
+
+    /// Check that messages of a kind introduced by a later version of
+    /// the protocol, as well as extended payloads, are handled
+    /// gracefully, without desynchronizing the stream of messages.
+    #[test]
+    fn future_messages() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        // Every decoding failure would close the connection.
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .max_decode_failures(1)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+        write_frame(&mut data, &(42u32, "future", vec![3u64])).unwrap();
+        write_frame(&mut data, &(Message::Updates(vec![4u64]), 5u64)).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates, vec![1, 2, 4]);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.called_on_completed, 1);
+        assert_eq!(recv.messages_received(), 5);
+    }
+
+    /// Check that a flushed transaction is delivered ahead of its
+    /// commit and continued afterwards.
+    #[test]
+    fn flush_mid_transaction() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Flush).unwrap();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![3u64])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_updates, 3);
+        assert_eq!(mock.called_on_flush, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.called_on_abort, 0);
+    }
+
+    /// Check that a transaction left open by a sender closing the
+    /// connection is aborted and completion is signaled.
+    #[test]
+    fn eof_mid_transaction() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_updates, 0);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that completion is signaled on a sender closing the
+    /// connection only if so configured.
+    #[test]
+    fn eof_policy() {
+        let cases = [
+            (EofPolicy::EmitDisconnected, 1),
+            (EofPolicy::EmitAbort, 0),
+            (EofPolicy::Nothing, 0),
+        ];
+
+        for (eof, completed) in &cases {
+            let mock = Arc::new(Mutex::new(MockObserver::new()));
+            let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+                .eof_policy(*eof)
+                .build::<u64, u64>()
+                .unwrap();
+            recv.subscribe(Box::new(mock.clone())).unwrap();
+
+            let mut data = Vec::new();
+            write_frame(&mut data, &Message::<u64>::Start).unwrap();
+            write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+            write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+            write_frame(&mut data, &Message::<u64>::Start).unwrap();
+            send_and_close(&recv, &data);
+
+            let mock = *mock.lock().unwrap();
+            assert_eq!(mock.called_on_commit, 1, "{:?}", eof);
+            assert_eq!(mock.called_on_completed, *completed, "{:?}", eof);
+            assert_eq!(mock.called_on_disconnected, *completed, "{:?}", eof);
+        }
+    }
+
+    /// Check that a sender signaling completion explicitly is told
+    /// apart from one disconnecting.
+    #[test]
+    fn complete_not_disconnected() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        write_frame(&mut data, &Message::<u64>::Complete).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_completed, 1);
+        assert_eq!(mock.called_on_disconnected, 0);
+    }
+
+    /// Check that batches without any updates only reach the observer
+    /// if so configured.
+    #[test]
+    fn empty_batches() {
+        let cases = [
+            (EmptyBatchPolicy::Suppress, 1),
+            (EmptyBatchPolicy::Forward, 2),
+        ];
+
+        for (policy, batches) in &cases {
+            let timing = Arc::new(Mutex::new(TimingObserver::new(MockObserver::new())));
+            let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+                .empty_batch_policy(*policy)
+                .build::<u64, u64>()
+                .unwrap();
+            recv.subscribe(Box::new(timing.clone())).unwrap();
+
+            let mut data = Vec::new();
+            write_frame(&mut data, &Message::<u64>::Start).unwrap();
+            write_frame(&mut data, &Message::Updates(Vec::<u64>::new())).unwrap();
+            write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+            write_frame(&mut data, &Message::<u64>::Start).unwrap();
+            write_frame(&mut data, &Message::Updates(vec![1u64])).unwrap();
+            write_frame(&mut data, &Message::Updates(Vec::<u64>::new())).unwrap();
+            write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+            send_and_close(&recv, &data);
+
+            let timing = timing.lock().unwrap();
+            let timings = timing.timings();
+            assert_eq!(timings.on_commit.count, 2, "{:?}", policy);
+            assert_eq!(timings.on_updates.count, *batches, "{:?}", policy);
+        }
+    }
+
+    /// Check that a message cut short by the sender closing the
+    /// connection is signaled as a disconnect, not as completion.
+    #[test]
+    fn truncated_message() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        let start = data.len();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+        data.truncate(start + 6);
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_updates, 0);
+        assert_eq!(mock.called_on_disconnected, 1);
+        // Completion is signaled only through the disconnect.
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that a transaction restarted while open is aborted before
+    /// the new one starts, by default.
+    #[test]
+    fn restart_aborted() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![3u64])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 1);
+        assert_eq!(mock.called_on_commit, 1);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that a transaction restarted while open closes the
+    /// connection if so configured.
+    #[test]
+    fn restart_rejected() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .restart_policy(RestartPolicy::Reject)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![3u64])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_updates, 0);
+        assert_eq!(mock.called_on_commit, 0);
+        assert_eq!(mock.called_on_completed, 0);
+    }
+
+    /// Check that transactions not committed in time are aborted, as
+    /// the configured `StallPolicy` mandates.
+    #[test]
+    fn transaction_timeout() {
+        let timeout = Duration::from_millis(100);
+        let stalled = || {
+            let mut data = Vec::new();
+            write_frame(&mut data, &Message::<u64>::Start).unwrap();
+            write_frame(&mut data, &Message::Updates(vec![1u64])).unwrap();
+            data
+        };
+
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .transaction_timeout(timeout, StallPolicy::Abort)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut frame = Vec::new();
+        let mut read = || {
+            if read_frame(&mut reader, &mut frame, 64).unwrap() {
+                Some(deserialize::<Message<()>>(&frame).unwrap())
+            } else {
+                None
+            }
+        };
+
+        // The sender may start over after a transaction timed out...
+        socket.write_all(&stalled()).unwrap();
+        sleep(timeout * 3);
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![2u64])).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        socket.write_all(&data).unwrap();
+        assert_eq!(read(), Some(Message::Ack(1)));
+
+        // ... but not continue it.
+        socket.write_all(&stalled()).unwrap();
+        sleep(timeout * 3);
+        write_frame(&mut socket, &Message::<u64>::Commit).unwrap();
+        assert_eq!(read(), None);
+        {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.received_updates, vec![2]);
+            assert_eq!(mock.called_on_commit, 1);
+            assert_eq!(mock.called_on_completed, 0);
+        }
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .transaction_timeout(timeout, StallPolicy::Disconnect)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        // The sender gets disconnected without closing its end.
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        socket.write_all(&stalled()).unwrap();
+        let mut buffer = Vec::new();
+        let _ = socket.read_to_end(&mut buffer).unwrap();
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_commit, 0);
+        assert_eq!(mock.called_on_completed, 1);
+    }
+
+    /// Check that errors are reported to the callback registered for
+    /// them.
+    #[test]
+    fn error_callback() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let copy = errors.clone();
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .on_error(move |error| copy.lock().unwrap().push(error))
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(MockObserver::new())).unwrap();
+
+        // An `Updates` message claiming more updates than it carries,
+        // followed by one the receiver does not expect.
+        let mut data = Vec::new();
+        data.extend_from_slice(&12u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&[0xff; 8]);
+        write_frame(&mut data, &Message::<u64>::Ack(1)).unwrap();
+        send_and_close(&recv, &data);
+
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], ReceiverError::Decode(_)));
+        assert_eq!(
+            errors[1],
+            ReceiverError::Observer {
+                event: "ack".to_string(),
+                error: "unexpected acknowledgement".to_string(),
+            }
+        );
+    }
+
+    /// Check that connections from peers not on the allowlist get
+    /// rejected, and reported as such.
+    #[test]
+    fn allowlist() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let copy = errors.clone();
+        let recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .allowlist(vec!["10.0.0.0/8".parse().unwrap()])
+            .on_error(move |error| copy.lock().unwrap().push(error))
+            .build::<u64, u64>()
+            .unwrap();
+
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        let peer = socket.local_addr().unwrap();
+        // The receiver hangs up without reading anything.
+        let mut buffer = [0; 1];
+        assert!(matches!(socket.read(&mut buffer), Ok(0) | Err(_)));
+        await_expected(|| assert_eq!(*errors.lock().unwrap(), vec![ReceiverError::Rejected(peer)]));
+        assert_eq!(recv.connections().len(), 0);
+
+        // `await_expected` insists on inspecting unwind safe state only.
+        let recv = AssertUnwindSafe(
+            TcpReceiver::<u64, u64>::with_allowlist(
+                "127.0.0.1:0",
+                vec!["127.0.0.0/8".parse().unwrap()],
+            )
+            .unwrap(),
+        );
+        let _socket = TcpStream::connect(recv.addr()).unwrap();
+        await_expected(|| assert_eq!(recv.connections().len(), 1));
+    }
+
+    /// Check that messages read ahead of their processing are
+    /// dispatched as usual.
+    #[test]
+    fn prefetch() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .prefetch(2)
+            .transaction_timeout(Duration::from_millis(100), StallPolicy::Disconnect)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        for i in 0..4u64 {
+            write_frame(&mut data, &Message::<u64>::Start).unwrap();
+            write_frame(&mut data, &Message::Updates(vec![i, i + 10])).unwrap();
+            write_frame(&mut data, &Message::Updates(vec![i + 20])).unwrap();
+            write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        }
+        send_and_close(&recv, &data);
+
+        {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.received_updates.len(), 12);
+            assert_eq!(mock.received_updates[..3], [0, 10, 20]);
+            assert_eq!(mock.called_on_commit, 4);
+            assert_eq!(mock.called_on_completed, 1);
+        }
+        assert_eq!(recv.messages_dispatched(), 16);
+
+        // Transactions still time out while waiting for messages.
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        socket.write_all(&data).unwrap();
+        let mut buffer = Vec::new();
+        let _ = socket.read_to_end(&mut buffer).unwrap();
+        assert_eq!(mock.lock().unwrap().called_on_commit, 4);
+    }
+
+    /// Check that a connection announcing a frame exceeding the maximum
+    /// frame size is closed without reading the frame, signaling the
+    /// disconnect.
+    #[test]
+    fn oversized_frame() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .max_frame_size(16)
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(vec![1u64, 2, 3])).unwrap();
+        send_and_close(&recv, &data);
+
+        let mock = *mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 0);
+        assert_eq!(mock.called_on_updates, 0);
+        assert_eq!(mock.called_on_disconnected, 1);
+    }
+
+    /// Check that messages received and dispatched are counted.
+    #[test]
+    fn message_counters() {
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(MockObserver::new())).unwrap();
+        assert_eq!(recv.messages_received(), 0);
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+
+        // The acknowledgement may arrive before the commit is counted
+        // as dispatched.
+        let counters = recv.acceptor.counters.clone();
+        await_expected(move || {
+            assert_eq!(counters.dispatched.load(Ordering::SeqCst), 3);
+        });
+        assert_eq!(recv.messages_received(), 3);
+        assert_eq!(recv.queue_depth(), 0);
+        assert_eq!(recv.queue_high_water_mark(), 1);
+
+        let metrics = ReceiverMetrics {
+            messages_received: 3,
+            messages_dispatched: 3,
+            commits: 1,
+            queue_high_water_mark: 1,
+        };
+        assert_eq!(recv.metrics(), metrics);
+        assert_eq!(recv.take_metrics(), metrics);
+        assert_eq!(recv.take_metrics(), ReceiverMetrics::default());
+        assert_eq!(recv.metrics(), metrics);
+    }
+
+    /// Check that we can wait for commits to be delivered.
+    #[test]
+    fn commit_count() {
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(MockObserver::new())).unwrap();
+
+        let timeout = Duration::from_secs(10);
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+
+        for i in 0..3 {
+            let observer = &mut send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(vec![i].into_iter())).unwrap();
+            observer.on_commit().unwrap();
+        }
+
+        recv.wait_for_commit(3, timeout).unwrap();
+        assert_eq!(recv.committed_count(), 3);
+        assert!(recv.wait_for_commit(4, Duration::from_millis(10)).is_err());
+    }
+
+    /// Check that a sender identifying itself is told the number of its
+    /// transactions processed so far, not counting those of others.
+    #[test]
+    fn resume_header() {
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(MockObserver::new())).unwrap();
+
+        // Identify as `sender`, commit `count` transactions, and return
+        // the resume header along with the connection.
+        let transmit = |sender: &str, count| {
+            let mut socket = TcpStream::connect(recv.addr()).unwrap();
+            let mut reader = BufReader::new(socket.try_clone().unwrap());
+            let mut frame = Vec::new();
+            write_frame(&mut socket, &Message::<u64>::Identify(sender.to_string())).unwrap();
+            assert!(read_frame(&mut reader, &mut frame, 64).unwrap());
+            let resumed = deserialize::<Message<()>>(&frame).unwrap();
+
+            for i in 1..=count {
+                write_frame(&mut socket, &Message::<u64>::Start).unwrap();
+                write_frame(&mut socket, &Message::<u64>::Commit).unwrap();
+                assert!(read_frame(&mut reader, &mut frame, 64).unwrap());
+                assert_eq!(deserialize::<Message<()>>(&frame).unwrap(), Message::Ack(i));
+            }
+            resumed
+        };
+
+        assert_eq!(transmit("foo", 2), Message::Resume(0));
+        assert_eq!(transmit("bar", 1), Message::Resume(0));
+        assert_eq!(recv.last_processed_seq("foo"), 2);
+        assert_eq!(recv.last_processed_seq("bar"), 1);
+        assert_eq!(transmit("foo", 0), Message::Resume(2));
+        assert_eq!(recv.last_processed_seq("baz"), 0);
+    }
+
+    /// Check that a sender identifying itself after its first
+    /// transaction is refused.
+    #[test]
+    fn identify_late() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        write_frame(&mut data, &Message::<u64>::Identify("foo".to_string())).unwrap();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        send_and_close(&recv, &data);
+
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+        assert_eq!(recv.last_processed_seq("foo"), 0);
+    }
+
+    /// Check that an observer subscribed with a timeout is handed back
+    /// if no sender shows up in time.
+    #[test]
+    fn subscribe_timeout_expired() {
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let (sender, receiver) = channel();
+        let observer = Box::new(MockObserver::new());
+        recv.subscribe_with_timeout(observer, Duration::from_millis(50), move |observer| {
+            sender.send(observer).unwrap()
+        })
+        .unwrap();
+
+        let _observer = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+        // The subscription is free again.
+        recv.subscribe(Box::new(MockObserver::new())).unwrap();
+    }
+
+    /// Check that an observer subscribed with a timeout stays
+    /// subscribed if a sender shows up in time.
+    #[test]
+    fn subscribe_timeout_beaten() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let (sender, receiver) = channel();
+        let timeout = Duration::from_millis(200);
+        recv.subscribe_with_timeout(Box::new(mock.clone()), timeout, move |observer| {
+            sender.send(observer).unwrap()
+        })
+        .unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+        assert!(receiver.recv_timeout(timeout * 2).is_err());
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Check that a listener handed off by one receiver can be adopted
+    /// by another, which then receives data.
+    #[test]
+    fn hand_off_listener() {
+        let recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let addr = *recv.addr();
+        let fd = recv.into_raw_listener().unwrap();
+
+        // Connections attempted in between queue up.
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        send.wait_connected().unwrap();
+
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = unsafe { TcpReceiver::<u64, u64>::from_raw_fd(fd) }.unwrap();
+        assert_eq!(*recv.addr(), addr);
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+        assert_eq!(mock.lock().unwrap().called_on_commit, 1);
+    }
+
+    /// Check that a paused receiver refuses connections and that it
+    /// receives data on the same address again once resumed.
+    #[test]
+    fn pause_resume() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        let addr = *recv.addr();
+
+        let transmit = |addr| {
+            let mut send = TcpSender::<u64>::new(addr).unwrap();
+            send.wait_connected().unwrap();
+            let observer = &mut send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+            observer.on_commit().unwrap();
+            send.await_ack(1).unwrap();
+        };
+        transmit(addr);
+
+        recv.pause().unwrap();
+        recv.pause().unwrap();
+        assert!(TcpStream::connect(addr).is_err());
+
+        recv.resume().unwrap();
+        recv.resume().unwrap();
+        assert_eq!(*recv.addr(), addr);
+        transmit(addr);
+
+        assert_eq!(mock.lock().unwrap().called_on_commit, 2);
+        assert_eq!(recv.committed_count(), 2);
+    }
+
+    /// Play the sender on a connection accepted on `listener`: identify,
+    /// transmit a single transaction containing `updates`, wait for it
+    /// to be acknowledged, and close the connection. Returns the number
+    /// of our transactions the receiver reported as processed.
+    fn serve(listener: &TcpListener, updates: Vec<u64>) -> u64 {
+        let (mut socket, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(socket.try_clone().unwrap());
+        let mut frame = Vec::new();
+        let mut read = || {
+            assert!(read_frame(&mut reader, &mut frame, 64).unwrap());
+            deserialize::<Message<()>>(&frame).unwrap()
+        };
+
+        write_frame(&mut socket, &Message::<u64>::Identify("sender".to_string())).unwrap();
+        let resume = match read() {
+            Message::Resume(resume) => resume,
+            message => panic!("unexpected message: {}", message),
+        };
+        write_frame(&mut socket, &Message::<u64>::Start).unwrap();
+        write_frame(&mut socket, &Message::Updates(updates)).unwrap();
+        write_frame(&mut socket, &Message::<u64>::Commit).unwrap();
+        assert_eq!(read(), Message::Ack(1));
+        resume
+    }
+
+    /// Check that a receiver connecting to a listening sender receives
+    /// data and reconnects once the connection dropped.
+    #[test]
+    fn connect_to_sender() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiverBuilder::connect(addr)
+            .backoff(Backoff {
+                initial: Duration::from_millis(10),
+                max: Duration::from_millis(50),
+                ..Default::default()
+            })
+            .build::<u64, u64>()
+            .unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        assert_eq!(*recv.addr(), addr);
+
+        assert_eq!(serve(&listener, vec![1, 2]), 0);
+        // The receiver redials after we closed the connection.
+        assert_eq!(serve(&listener, vec![3]), 1);
+
+        // It keeps retrying while nobody is listening.
+        std::mem::drop(listener);
+        sleep(Duration::from_millis(100));
+        let listener = TcpListener::bind(addr).unwrap();
+        assert_eq!(serve(&listener, vec![4]), 2);
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 4]);
+        assert_eq!(mock.called_on_commit, 3);
+    }
+
+    /// Check that connection events are emitted when a sender connects
+    /// and disconnects.
+    #[test]
+    fn connection_events() {
+        let mock = Arc::new(Mutex::new(UpdatesMockObserver::<ConnectionEvent>::new()));
+        let recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.connection_events()
+            .subscribe(Box::new(mock.clone()))
+            .unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+        await_expected(|| {
+            let received = mock.lock().unwrap().received_updates.clone();
+            assert_eq!(received.len(), 1);
         });
 
-        let t2 = spawn(move || {
-            let observer2 = &mut send2 as &mut dyn Observer<u64, _>;
-            let updates2 = vec![2u64, 3, 4];
-            observer2.on_start().unwrap();
-            observer2
-                .on_updates(Box::new(updates2.into_iter()))
-                .unwrap();
-            observer2.on_commit().unwrap();
-            send2.wait_connected().unwrap();
+        let peer = match &mock.lock().unwrap().received_updates[0] {
+            ConnectionEvent::Connected(peer) => *peer,
+            event => panic!("unexpected connection event: {:?}", event),
+        };
+
+        std::mem::drop(send);
+        await_expected(|| {
+            let received = mock.lock().unwrap().received_updates.clone();
+            let expected = vec![
+                ConnectionEvent::Connected(peer),
+                ConnectionEvent::Disconnected(peer, None),
+            ];
+            assert_eq!(received, expected);
         });
+        assert_eq!(mock.lock().unwrap().called_on_commit, 2);
+    }
 
-        let t3 = spawn(move || {
-            let observer3 = &mut send3 as &mut dyn Observer<u64, _>;
-            let updates3 = vec![5u64, 6, 7, 8];
-            observer3.on_start().unwrap();
-            observer3
-                .on_updates(Box::new(updates3.into_iter()))
+    /// Check that a lifecycle observer learns about transaction
+    /// boundaries alongside the subscribed observer.
+    #[test]
+    fn lifecycle_observer() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let lifecycle = Arc::new(Mutex::new(MockObserver::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        recv.subscribe_lifecycle(Box::new(lifecycle.clone()))
+            .unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let lifecycle = *lifecycle.lock().unwrap();
+        assert_eq!(lifecycle.called_on_start, 1);
+        assert_eq!(lifecycle.called_on_updates, 0);
+        assert_eq!(lifecycle.called_on_commit, 1);
+        assert_eq!(mock.lock().unwrap().called_on_updates, 2);
+    }
+
+    /// An observer panicking on its first commit.
+    #[derive(Debug, Default)]
+    struct PanickingObserver {
+        /// Set once the observer panicked.
+        panicked: bool,
+        /// The number of commits that went through.
+        committed: usize,
+    }
+
+    impl Observer<u64, String> for PanickingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            if !replace(&mut self.panicked, true) {
+                panic!("observer failed to commit")
+            }
+            self.committed += 1;
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that a panic in the observer, which poisons the locks held
+    /// at the time, neither wedges the receiver for other senders nor
+    /// prevents it from being dropped.
+    #[test]
+    fn observer_panic() {
+        fn transmit(addr: &SocketAddr) -> Result<(), String> {
+            let mut send = TcpSender::<u64>::new(*addr).unwrap();
+            let observer = &mut send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+            observer.on_commit().unwrap();
+            send.wait_connected().unwrap();
+            send.await_ack(1)
+        }
+
+        let observer = Arc::new(Mutex::new(PanickingObserver::default()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(observer.clone())).unwrap();
+
+        assert!(transmit(recv.addr()).is_err());
+        assert!(observer.is_poisoned());
+
+        assert_eq!(transmit(recv.addr()), Ok(()));
+        assert_eq!(observer.lock_unpoisoned().committed, 1);
+
+        let start = Instant::now();
+        std::mem::drop(recv);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    /// Check that a snapshot sent ahead of the first transaction's
+    /// updates is delivered as such, followed by further updates.
+    #[test]
+    fn snapshot_then_updates() {
+        let recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let addr = *recv.addr();
+        let messages = recv.into_messages(16);
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_snapshot(Box::new(vec![1, 2, 3].into_iter()))
+            .unwrap();
+        observer.on_updates(Box::new(vec![4].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![5].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        observer.on_completed().unwrap();
+
+        assert_eq!(
+            messages.collect::<Vec<_>>(),
+            vec![
+                Message::Start,
+                Message::Snapshot(vec![1, 2, 3]),
+                Message::Updates(vec![4]),
+                Message::Commit,
+                Message::Start,
+                Message::Updates(vec![5]),
+                Message::Commit,
+            ]
+        );
+    }
+
+    /// Check that a snapshot outside of a transaction is rejected.
+    #[test]
+    fn snapshot_outside_transaction() {
+        let mut observer = MockObserver::new();
+        let mut session = Session::new(RestartPolicy::Abort);
+        let (event, result) =
+            relay::<u64, u64, _>(Message::Snapshot(vec![1]), &mut observer, &mut session);
+        assert_eq!(event, Event::Snapshot);
+        assert!(result.is_err());
+        assert_eq!(observer.called_on_updates, 0);
+    }
+
+    /// An observer remembering the statistics of the transactions it
+    /// committed.
+    #[derive(Debug, Default)]
+    struct StatsObserver(Vec<TransactionStats>);
+
+    impl Observer<u64, String> for StatsObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            panic!("commit without statistics")
+        }
+
+        fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), String> {
+            self.0.push(stats);
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that the items and bytes of a transaction are accounted
+    /// for and delivered with its commit.
+    #[test]
+    fn transaction_stats() {
+        let stats = Arc::new(Mutex::new(StatsObserver::default()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(stats.clone())).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+
+        let start = Instant::now();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(1).unwrap();
+
+        let size = |message: &Message<u64>| serialized_size(message).unwrap() + 4;
+        let bytes = size(&Message::Start)
+            + size(&Message::Updates(vec![1, 2]))
+            + size(&Message::Updates(vec![3]))
+            + size(&Message::Commit);
+
+        let stats = stats.lock().unwrap();
+        assert_eq!(stats.0.len(), 1);
+        assert_eq!(stats.0[0].items, 3);
+        assert_eq!(stats.0[0].bytes, bytes);
+        assert!(stats.0[0].started_at >= start);
+    }
+
+    /// An observer signaling to stop on its first commit.
+    #[derive(Debug, Default)]
+    struct StoppingObserver {
+        /// The signal to raise.
+        signal: ObserverSignal,
+        /// The number of commits seen.
+        committed: usize,
+        /// The number of aborts seen.
+        aborted: usize,
+    }
+
+    impl Observer<u64, String> for StoppingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.committed += 1;
+            self.signal.stop();
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_abort(&mut self) -> Result<(), String> {
+            self.aborted += 1;
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            panic!("stopped observer got notified of completion")
+        }
+    }
+
+    /// Check that an observer signaling to stop gets the commit
+    /// acknowledged, but the connection closed and the receiver
+    /// stopped.
+    #[test]
+    fn observer_stop() {
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let observer = Arc::new(Mutex::new(StoppingObserver {
+            signal: recv.signal(),
+            ..Default::default()
+        }));
+        recv.subscribe(Box::new(observer.clone())).unwrap();
+        let addr = *recv.addr();
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let sender = &mut send as &mut dyn Observer<u64, _>;
+        sender.on_start().unwrap();
+        sender.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        sender.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        assert_eq!(send.await_ack(1), Ok(()));
+
+        let sender = &mut send as &mut dyn Observer<u64, _>;
+        let _ = sender.on_start();
+        let _ = sender.on_updates(Box::new(vec![2].into_iter()));
+        let _ = sender.on_commit();
+        assert!(send.await_ack(2).is_err());
+
+        await_expected(|| assert!(TcpStream::connect(addr).is_err()));
+
+        let observer = observer.lock().unwrap();
+        assert_eq!(observer.committed, 1);
+        assert_eq!(observer.aborted, 0);
+    }
+
+    /// An observer blocking in `on_updates` until released.
+    #[derive(Debug)]
+    struct BlockingObserver(Receiver<()>);
+
+    impl Observer<u64, String> for BlockingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            self.0.recv().map_err(|e| e.to_string())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that the bytes piling up while the observer is busy are
+    /// reported as pending.
+    #[test]
+    fn pending_bytes() {
+        let (release, blocked) = channel();
+        // `await_expected` insists on inspecting unwind safe state only.
+        let mut recv = AssertUnwindSafe(TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap());
+        recv.subscribe(Box::new(BlockingObserver(blocked))).unwrap();
+        assert_eq!(recv.pending_bytes().unwrap(), 0);
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        send.wait_connected().unwrap();
+        // The first transaction blocks the observer, so that the second
+        // one piles up.
+        for updates in [vec![1], (0..1000).collect()] {
+            let observer = &mut send as &mut dyn Observer<u64, _>;
+            observer.on_start().unwrap();
+            observer.on_updates(Box::new(updates.into_iter())).unwrap();
+            observer.on_commit().unwrap();
+        }
+        await_expected(|| assert!(recv.pending_bytes().unwrap() >= 8000));
+
+        release.send(()).unwrap();
+        release.send(()).unwrap();
+        send.await_ack(2).unwrap();
+        await_expected(|| assert_eq!(recv.pending_bytes().unwrap(), 0));
+    }
+
+    /// An observer getting stuck committing for a while.
+    #[derive(Debug)]
+    struct StuckObserver {
+        /// Set once the observer is stuck.
+        stuck: Arc<AtomicBool>,
+    }
+
+    impl Observer<u64, String> for StuckObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.stuck.store(true, Ordering::SeqCst);
+            sleep(Duration::from_secs(3));
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that dropping a receiver does not wait for a thread stuck
+    /// in the observer for longer than the shutdown timeout.
+    #[test]
+    fn drop_stuck() {
+        let stuck = Arc::new(AtomicBool::new(false));
+        let mut recv = TcpReceiverBuilder::new("127.0.0.1:0")
+            .shutdown_timeout(Duration::from_millis(100))
+            .build::<u64, u64>()
+            .unwrap();
+        let observer = StuckObserver {
+            stuck: stuck.clone(),
+        };
+        recv.subscribe(Box::new(observer)).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        await_expected(|| assert!(stuck.load(Ordering::SeqCst)));
+
+        let start = Instant::now();
+        // Note that `drop` refers to the test of the same name here.
+        std::mem::drop(recv);
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    /// Create the data of a single transaction carrying `updates`.
+    fn transaction(updates: Vec<u64>) -> Vec<u8> {
+        let mut data = Vec::new();
+        write_frame(&mut data, &Message::<u64>::Start).unwrap();
+        write_frame(&mut data, &Message::Updates(updates)).unwrap();
+        write_frame(&mut data, &Message::<u64>::Commit).unwrap();
+        data
+    }
+
+    /// Check that by default an observer subscribing in addition to
+    /// one subscribed already is rejected.
+    #[test]
+    fn subscribe_reject() {
+        let first = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let second = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(first.clone())).unwrap();
+        assert!(recv.subscribe(Box::new(second.clone())).is_err());
+        assert_eq!(recv.subscriber_count(), 1);
+
+        send_and_close(&recv, &transaction(vec![1, 2]));
+
+        assert_eq!(first.lock().unwrap().received_updates, vec![1, 2]);
+        assert_eq!(second.lock().unwrap().received_updates, Vec::<u64>::new());
+    }
+
+    /// Check that an observer subscribing may displace the one
+    /// subscribed already, which gets completed.
+    #[test]
+    fn subscribe_replace() {
+        let first = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let second = Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new()));
+        let mut recv =
+            TcpReceiver::<u64, u64>::with_subscribe_policy("127.0.0.1:0", SubscribePolicy::Replace)
                 .unwrap();
-            observer3.on_commit().unwrap();
-            send3.wait_connected().unwrap();
-        });
+        recv.subscribe(Box::new(first.clone())).unwrap();
+        send_and_close(&recv, &transaction(vec![1]));
+        let completed = first.lock().unwrap().called_on_completed;
 
-        t1.join().unwrap();
-        t2.join().unwrap();
-        t3.join().unwrap();
+        recv.subscribe(Box::new(second.clone())).unwrap();
+        assert_eq!(recv.subscriber_count(), 1);
+        assert_eq!(first.lock().unwrap().called_on_completed, completed + 1);
 
-        await_expected(|| {
-            let (on_start, on_updates, on_commit) = {
-                let guard = mock.lock().unwrap();
-                (
-                    guard.called_on_start,
-                    guard.called_on_updates,
-                    guard.called_on_commit,
-                )
-            };
+        send_and_close(&recv, &transaction(vec![2, 3]));
 
-            assert_eq!(on_start, 3);
-            assert_eq!(on_updates, 8);
-            assert_eq!(on_commit, 3);
-        });
+        let first = first.lock().unwrap();
+        assert_eq!(first.received_updates, vec![1]);
+        assert_eq!(first.called_on_commit, 1);
+        assert_eq!(second.lock().unwrap().received_updates, vec![2, 3]);
+    }
+
+    /// Check that transactions may be broadcast to all observers
+    /// subscribed.
+    #[test]
+    fn subscribe_broadcast() {
+        let observers = (0..3)
+            .map(|_| Arc::new(Mutex::new(UpdatesMockObserver::<u64>::new())))
+            .collect::<Vec<_>>();
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.set_subscribe_policy(SubscribePolicy::Broadcast);
+        recv.subscribe(Box::new(observers[0].clone())).unwrap();
+        send_and_close(&recv, &transaction(vec![1]));
+
+        for observer in &observers[1..] {
+            recv.subscribe(Box::new(observer.clone())).unwrap();
+        }
+        assert_eq!(recv.subscriber_count(), 3);
+
+        send_and_close(&recv, &transaction(vec![2, 3]));
+
+        assert_eq!(observers[0].lock().unwrap().received_updates, vec![1, 2, 3]);
+        for observer in &observers[1..] {
+            let observer = observer.lock().unwrap();
+            assert_eq!(observer.received_updates, vec![2, 3]);
+            assert_eq!(observer.called_on_start, 1);
+            assert_eq!(observer.called_on_commit, 1);
+        }
+
+        assert!(recv.unsubscribe(&()).is_some());
+        assert_eq!(recv.subscriber_count(), 0);
     }
 }
@@ -0,0 +1,482 @@
+//! A module providing a TCP receiver for records of a fixed binary
+//! layout, handing them to the observer straight from the buffer they
+//! were read into.
+
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::net::ToSocketAddrs;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread::Builder;
+use std::thread::JoinHandle;
+
+use log::debug;
+use log::error;
+use log::trace;
+use log::warn;
+
+use uid::Id;
+
+use crate::observe::Observer;
+use crate::observe::SharedObserver;
+use crate::poison::MutexExt;
+use crate::tcp_channel::builder::Config;
+use crate::tcp_channel::receiver::is_allowed;
+use crate::tcp_channel::receiver::report;
+use crate::tcp_channel::socket::await_accept;
+use crate::tcp_channel::socket::Wake;
+use crate::tcp_channel::ReceiverError;
+use crate::tcp_channel::TcpReceiverBuilder;
+
+/// A family of record types of a fixed binary layout, viewed in place,
+/// borrowing from the bytes they are stored in for any lifetime of
+/// those.
+///
+/// For example, records made up of a little endian `u64` key followed
+/// by eight bytes of payload may be viewed as follows:
+/// ```ignore
+/// struct Record;
+///
+/// impl<'a> FixedLayout<'a> for Record {
+///     const SIZE: usize = 16;
+///     type Item = (u64, &'a [u8]);
+///
+///     fn view(record: &'a [u8]) -> Self::Item {
+///         let (key, payload) = record.split_at(8);
+///         (u64::from_le_bytes(key.try_into().unwrap()), payload)
+///     }
+/// }
+/// ```
+pub trait FixedLayout<'a> {
+    /// The size of a record, in bytes.
+    const SIZE: usize;
+    /// The record type, borrowing from data of lifetime `'a`.
+    type Item: Send;
+
+    /// View the `SIZE` bytes of a record as an item.
+    fn view(record: &'a [u8]) -> Self::Item;
+}
+
+/// Retrieve the size of a record of the layout `F`.
+fn record_size<F>() -> usize
+where
+    F: for<'a> FixedLayout<'a>,
+{
+    <F as FixedLayout<'static>>::SIZE
+}
+
+/// The receiving end of a TCP channel carrying records of a fixed
+/// binary layout, for latency critical paths.
+///
+/// Senders write records back to back, without any framing or
+/// transactions, e.g., straight to a `TcpStream`. Instead of being
+/// buffered and decoded, records are read from the socket directly
+/// into a pre-allocated buffer of a given number of slots and viewed
+/// in place as per their `FixedLayout`. All records that arrived
+/// complete with a single read are delivered to the observer as a
+/// transaction of their own, after which their slots get reused; hence
+/// the observer has to be provided up front and accept records of any
+/// lifetime, just as for a `BorrowedTcpReceiver`. A sender closing the
+/// connection is signaled as a disconnect (see
+/// `Observer::on_disconnected`), discarding a record left incomplete.
+///
+/// Connections are processed one after the other, in the order they
+/// were accepted. Of the options of a `TcpReceiverBuilder`, only those
+/// concerning the listener socket, the allowlist, the error callback,
+/// and the thread name apply.
+pub struct RingTcpReceiver<F, O> {
+    /// The TCP receiver's unique ID.
+    id: usize,
+    /// The address we are listening on.
+    addr: SocketAddr,
+    /// The observer all connections relay their records to.
+    observer: SharedObserver<O>,
+    /// The object used for stopping the thread processing connections.
+    wake: Arc<Wake>,
+    /// The thread accepting and processing connections.
+    thread: Option<JoinHandle<()>>,
+    /// The number of records delivered to the observer.
+    records: Arc<AtomicU64>,
+    _phantom: PhantomData<fn() -> F>,
+}
+
+impl<F, O> RingTcpReceiver<F, O>
+where
+    F: for<'a> FixedLayout<'a> + 'static,
+    O: for<'a> Observer<<F as FixedLayout<'a>>::Item, String> + Send + 'static,
+{
+    /// Create a new TCP receiver listening on `addr`, reading up to
+    /// `slots` records at once and relaying them to `observer`.
+    pub fn new<A>(addr: A, slots: usize, observer: O) -> Result<Self, String>
+    where
+        A: ToSocketAddrs,
+    {
+        TcpReceiverBuilder::new(addr).build_ring(slots, observer)
+    }
+
+    /// Create a new TCP receiver with the given configuration,
+    /// accepting connections on `listener`.
+    pub(crate) fn with_config(
+        listener: TcpListener,
+        config: Config,
+        slots: usize,
+        observer: O,
+    ) -> Result<Self, String> {
+        let id = Id::<()>::new().get();
+        trace!("RingTcpReceiver({})::new", id);
+
+        if slots == 0 || record_size::<F>() == 0 {
+            return Err("records and the number of slots must not be empty".to_string());
+        }
+
+        let addr = listener
+            .local_addr()
+            .map_err(|e| format!("failed to inquire local address: {}", e))?;
+        let observer = Arc::new(Mutex::new(observer));
+        let wake = Arc::new(Wake::new().map_err(|e| format!("failed to create pipe: {}", e))?);
+        let records = Arc::new(AtomicU64::new(0));
+
+        let name = config
+            .thread_name
+            .clone()
+            .unwrap_or_else(|| format!("tcp-ring-{}", addr));
+        let ring = Ring::<F, O> {
+            id,
+            slab: vec![0; slots * record_size::<F>()],
+            observer: observer.clone(),
+            records: records.clone(),
+            _phantom: PhantomData,
+        };
+        let copy = wake.clone();
+        let thread = Builder::new()
+            .name(name)
+            .spawn(move || ring.run(listener, &config, &copy))
+            .map_err(|e| format!("failed to spawn receiving thread: {}", e))?;
+
+        Ok(Self {
+            id,
+            addr,
+            observer,
+            wake,
+            thread: Some(thread),
+            records,
+            _phantom: PhantomData,
+        })
+    }
+}
+
+impl<F, O> RingTcpReceiver<F, O> {
+    /// Retrieve the address we are listening on.
+    pub fn addr(&self) -> &SocketAddr {
+        trace!("RingTcpReceiver({})::addr: {}", self.id, self.addr);
+        &self.addr
+    }
+
+    /// Retrieve the observer we relay records to.
+    pub fn observer(&self) -> &SharedObserver<O> {
+        &self.observer
+    }
+
+    /// Retrieve the number of records delivered to the observer.
+    pub fn records_received(&self) -> u64 {
+        self.records.load(Ordering::SeqCst)
+    }
+}
+
+impl<F, O> Debug for RingTcpReceiver<F, O> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("RingTcpReceiver")
+            .field("id", &self.id)
+            .field("addr", &self.addr)
+            .field("records", &self.records)
+            .finish()
+    }
+}
+
+impl<F, O> Drop for RingTcpReceiver<F, O> {
+    fn drop(&mut self) {
+        if let Err(e) = self.wake.wake() {
+            error!(
+                "RingTcpReceiver({}): failed to wake up receiving thread: {}",
+                self.id, e
+            );
+            // The thread may never notice; better leave it detached.
+            return;
+        }
+        if let Some(thread) = self.thread.take() {
+            if let Err(e) = thread.join() {
+                error!(
+                    "RingTcpReceiver({}): receiving thread has panicked: {:?}",
+                    self.id, e
+                );
+            }
+        }
+    }
+}
+
+/// The state of the thread receiving records.
+struct Ring<F, O> {
+    /// The unique ID of the receiver we work for.
+    id: usize,
+    /// The buffer records get read into.
+    slab: Vec<u8>,
+    /// The observer we relay records to.
+    observer: SharedObserver<O>,
+    /// The number of records delivered to the observer.
+    records: Arc<AtomicU64>,
+    _phantom: PhantomData<fn() -> F>,
+}
+
+impl<F, O> Ring<F, O>
+where
+    F: for<'a> FixedLayout<'a>,
+    O: for<'a> Observer<<F as FixedLayout<'a>>::Item, String> + Send,
+{
+    /// Accept connections on `listener` and process them, one at a
+    /// time, until `wake` got woken up.
+    fn run(mut self, listener: TcpListener, config: &Config, wake: &Wake) {
+        loop {
+            match await_accept(listener.as_raw_fd(), wake) {
+                Ok(true) => (),
+                Ok(false) => break,
+                // We can still accept connections, we just may not
+                // notice a wake up while blocked doing so.
+                Err(e) => error!(
+                    "RingTcpReceiver({}): failed to poll listener: {}",
+                    self.id, e
+                ),
+            }
+
+            match listener.accept() {
+                Ok((_socket, peer)) if !is_allowed(config, &peer) => {
+                    warn!(
+                        "RingTcpReceiver({}): rejected connection from {}",
+                        self.id, peer
+                    );
+                    report(config, ReceiverError::Rejected(peer));
+                }
+                Ok((socket, peer)) => {
+                    debug!(
+                        "RingTcpReceiver({}): accepted connection from {}",
+                        self.id, peer
+                    );
+                    if !self.process(socket, wake) {
+                        break;
+                    }
+                }
+                Err(e) => {
+                    error!(
+                        "RingTcpReceiver({}): failed to accept connection: {}",
+                        self.id, e
+                    );
+                    report(config, ReceiverError::Accept(e.to_string()));
+                }
+            }
+        }
+    }
+
+    /// Read records from `socket` and deliver them, until the sender
+    /// goes away, in which case `true` is returned, or until `wake` got
+    /// woken up.
+    fn process(&mut self, mut socket: TcpStream, wake: &Wake) -> bool {
+        let size = record_size::<F>();
+        // The number of bytes of an incomplete record at the start of
+        // the slab.
+        let mut filled = 0;
+
+        loop {
+            // A socket that is readable has data pending or got closed,
+            // just as a listener has connections pending.
+            match await_accept(socket.as_raw_fd(), wake) {
+                Ok(true) => (),
+                Ok(false) => return false,
+                Err(e) => error!("RingTcpReceiver({}): failed to poll socket: {}", self.id, e),
+            }
+
+            let read = match socket.read(&mut self.slab[filled..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    error!(
+                        "RingTcpReceiver({}): failed to read from socket: {}",
+                        self.id, e
+                    );
+                    break;
+                }
+            };
+
+            filled += read;
+            let complete = filled - filled % size;
+            if complete > 0 {
+                self.deliver(complete);
+                self.slab.copy_within(complete..filled, 0);
+                filled -= complete;
+            }
+        }
+
+        if filled > 0 {
+            warn!(
+                "RingTcpReceiver({}): discarding incomplete record of {} bytes",
+                self.id, filled
+            );
+        }
+        let mut observer = self.observer.lock_unpoisoned();
+        if let Err(e) = Observer::<<F as FixedLayout<'_>>::Item, _>::on_disconnected(&mut *observer)
+        {
+            error!(
+                "RingTcpReceiver({}): observer failed to process on_disconnected event: {}",
+                self.id, e
+            );
+        }
+        true
+    }
+
+    /// Deliver the records in the first `len` bytes of the slab as a
+    /// transaction.
+    fn deliver(&self, len: usize) {
+        let size = record_size::<F>();
+        let slab = &self.slab[..len];
+        let mut observer = self.observer.lock_unpoisoned();
+        let observer = &mut *observer as &mut dyn Observer<<F as FixedLayout<'_>>::Item, String>;
+
+        let result = observer
+            .on_start()
+            .map_err(|e| ("on_start", e))
+            .and_then(|_| {
+                let records = slab.chunks_exact(size).map(F::view);
+                observer
+                    .on_updates(Box::new(records))
+                    .map_err(|e| ("on_updates", e))
+            })
+            .and_then(|_| observer.on_commit().map_err(|e| ("on_commit", e)));
+
+        match result {
+            Ok(()) => {
+                let _ = self
+                    .records
+                    .fetch_add((len / size) as u64, Ordering::SeqCst);
+            }
+            Err((event, e)) => {
+                error!(
+                    "RingTcpReceiver({}): observer failed to process {} event: {}",
+                    self.id, event, e
+                );
+                if event != "on_commit" {
+                    let _ = observer.on_abort();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::TryInto;
+    use std::io::Write;
+    use std::thread::sleep;
+    use std::time::Duration;
+
+    use crate::await_expected;
+
+    /// Records made up of a key and four bytes of payload.
+    struct Record;
+
+    impl<'a> FixedLayout<'a> for Record {
+        const SIZE: usize = 12;
+        type Item = (u64, &'a [u8]);
+
+        fn view(record: &'a [u8]) -> Self::Item {
+            let (key, payload) = record.split_at(8);
+            (u64::from_le_bytes(key.try_into().unwrap()), payload)
+        }
+    }
+
+    /// An observer remembering the records it received.
+    #[derive(Debug, Default)]
+    struct RecordObserver {
+        records: Vec<(u64, Vec<u8>)>,
+        commits: usize,
+        disconnects: usize,
+    }
+
+    impl<'a> Observer<(u64, &'a [u8]), String> for RecordObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.commits += 1;
+            Ok(())
+        }
+
+        fn on_updates<'b>(
+            &mut self,
+            updates: Box<dyn Iterator<Item = (u64, &'a [u8])> + 'b>,
+        ) -> Result<(), String> {
+            self.records
+                .extend(updates.map(|(key, payload)| (key, payload.to_vec())));
+            Ok(())
+        }
+
+        fn on_disconnected(&mut self) -> Result<(), String> {
+            self.disconnects += 1;
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that records are delivered intact, even when arriving in
+    /// pieces and exceeding the number of slots.
+    #[test]
+    fn receive_records() {
+        let recv =
+            RingTcpReceiver::<Record, _>::new("127.0.0.1:0", 4, RecordObserver::default()).unwrap();
+
+        let mut data = Vec::new();
+        for key in 0..10u64 {
+            data.extend_from_slice(&key.to_le_bytes());
+            data.extend_from_slice(&[key as u8; 4]);
+        }
+        // A record cut short, which never gets delivered.
+        data.extend_from_slice(&[0xff; 5]);
+
+        let mut socket = TcpStream::connect(recv.addr()).unwrap();
+        socket.set_nodelay(true).unwrap();
+        for chunk in data.chunks(7) {
+            socket.write_all(chunk).unwrap();
+            sleep(Duration::from_millis(1));
+        }
+        drop(socket);
+
+        let observer = recv.observer().clone();
+        await_expected(move || {
+            // Don't poison the lock by panicking while holding it.
+            let disconnects = observer.lock().unwrap().disconnects;
+            assert_eq!(disconnects, 1)
+        });
+        assert_eq!(recv.records_received(), 10);
+
+        let observer = recv.observer().lock().unwrap();
+        let expected = (0..10u64)
+            .map(|key| (key, vec![key as u8; 4]))
+            .collect::<Vec<_>>();
+        assert_eq!(observer.records, expected);
+        assert!(observer.commits >= 3);
+    }
+}
@@ -1,6 +1,10 @@
 use std::fmt::Debug;
+use std::io::BufReader;
 use std::io::BufWriter;
 use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Write;
+use std::net::Shutdown;
 use std::net::SocketAddr;
 use std::net::TcpStream;
 use std::sync::Arc;
@@ -8,21 +12,39 @@ use std::sync::Mutex;
 use std::thread::spawn;
 use std::thread::JoinHandle;
 
+use bincode::deserialize;
 use log::debug;
 use log::error;
 use log::trace;
-use serde::Serialize;
 use uid::Id;
 
 use crate::observe::Observer;
+use crate::tcp_channel::ack::Acks;
+use crate::tcp_channel::codec::BincodeCodec;
+use crate::tcp_channel::codec::BincodeConfigCodec;
+use crate::tcp_channel::codec::Codec;
+use crate::tcp_channel::frame::read_frame;
+use crate::tcp_channel::frame::write_frame;
+use crate::tcp_channel::message::Kind;
+use crate::tcp_channel::message::Message;
 use crate::tcp_channel::socket::Cancelable;
 use crate::tcp_channel::socket::Socket;
+use crate::tcp_channel::token::Token;
 use crate::tcp_channel::txnbuf::TxnBuf;
 
+/// The maximum size of a frame containing an acknowledgement.
+const MAX_ACK_FRAME_SIZE: usize = 1024;
+
 /// The sending end of a TCP channel with a specified address and a TCP
-/// connection.
+/// connection, encoding messages using the `Codec` `C`.
+///
+/// A `TcpSender` never identifies itself to the receiver and hence
+/// does not resume after the transactions the receiver reports as
+/// processed: it connects only once, as reconnecting is out of scope,
+/// and merges the transactions it buffers while connecting into one.
+/// Only relays resume (see `TcpReceiverBuilder::resume`).
 #[derive(Debug)]
-pub struct TcpSender<T>
+pub struct TcpSender<T, C = BincodeCodec>
 where
     T: Debug,
 {
@@ -30,72 +52,264 @@ where
     id: usize,
     /// The buffer we use for buffering transactions or pushing them out
     /// over the wire.
-    buffer: Arc<Mutex<TxnBuf<BufWriter<TcpStream>, T>>>,
+    buffer: Arc<Mutex<TxnBuf<BufWriter<TcpStream>, T, C>>>,
     /// A cancellation handle we can use for canceling an ongoing
     /// connect.
     cancel: Cancelable,
     /// The thread attempting to establish a connection to a receiver.
-    thread: Option<JoinHandle<Result<(), String>>>,
+    /// Once connected, it hands out the thread reading
+    /// acknowledgements.
+    thread: Option<JoinHandle<Result<JoinHandle<()>, String>>>,
+    /// The thread reading acknowledgements from the receiver.
+    reader: Option<JoinHandle<()>>,
+    /// The acknowledgements of transactions we committed.
+    acks: Arc<Acks>,
+    /// The maximum number of updates to send in a single message, if
+    /// any.
+    max_chunk_len: Option<usize>,
 }
 
 impl<T> TcpSender<T>
 where
-    T: Debug + Send + Serialize + 'static,
+    T: Debug + Send + 'static,
+    BincodeCodec: Codec<T>,
 {
     /// Create a new `TcpSender`, connecting to the given address.
     pub fn new(addr: SocketAddr) -> Result<Self, Error> {
+        Self::with_config(addr, None, None, None, BincodeCodec)
+    }
+
+    /// Create a new `TcpSender`, connecting to the given address and
+    /// announcing `version` as the version of the data sent, to be
+    /// checked by a receiver created via `TcpReceiver::new_versioned`.
+    ///
+    /// A receiver expecting a different version refuses the connection,
+    /// which is logged as an error and makes `await_ack` fail.
+    pub fn new_versioned<S>(addr: SocketAddr, version: S) -> Result<Self, Error>
+    where
+        S: Into<String>,
+    {
+        Self::with_config(addr, None, Some(version.into()), None, BincodeCodec)
+    }
+
+    /// Create a new `TcpSender`, connecting to the given address and
+    /// authenticating by presenting `token`, as required by a receiver
+    /// configured via `TcpReceiverBuilder::require_token`.
+    ///
+    /// A receiver requiring a different token closes the connection,
+    /// which makes `await_ack` fail. The receiver not requiring any
+    /// token in turn discards it as a message it cannot decode.
+    pub fn with_token<B>(addr: SocketAddr, token: B) -> Result<Self, Error>
+    where
+        B: Into<Vec<u8>>,
+    {
+        Self::with_config(addr, None, None, Some(Token::new(token)), BincodeCodec)
+    }
+
+    /// Create a new `TcpSender`, connecting to the given address and
+    /// sending at most `max_chunk_len` updates per message.
+    ///
+    /// The updates of a single `on_updates` call are split up into
+    /// chunks as necessary, so that even a very large transaction is
+    /// never serialized in its entirety. The receiver relays all chunks
+    /// between a start and a commit as the same transaction.
+    pub fn with_max_chunk_len(addr: SocketAddr, max_chunk_len: usize) -> Result<Self, Error> {
+        if max_chunk_len == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "maximum chunk length must not be zero",
+            ));
+        }
+        Self::with_config(addr, Some(max_chunk_len), None, None, BincodeCodec)
+    }
+
+    /// Create a new `TcpSender`, connecting to the given address and
+    /// encoding messages using bincode configured with `options`. The
+    /// receiver has to use the same options (see
+    /// `TcpReceiver::with_bincode_config`).
+    pub fn with_bincode_config<O>(
+        addr: SocketAddr,
+        options: O,
+    ) -> Result<TcpSender<T, BincodeConfigCodec<O>>, Error>
+    where
+        BincodeConfigCodec<O>: Codec<T>,
+    {
+        TcpSender::with_codec(addr, BincodeConfigCodec::new(options))
+    }
+}
+
+impl<T, C> TcpSender<T, C>
+where
+    T: Debug + Send + 'static,
+    C: Codec<T>,
+{
+    /// Create a new `TcpSender`, connecting to the given address and
+    /// encoding messages using `codec` instead of bincode. The
+    /// receiver has to use the same codec, e.g., by being built via
+    /// `TcpReceiverBuilder::build_with_codec`. The codec only applies
+    /// to the messages making up transactions; the version and token
+    /// announced as well as the acknowledgements read are always
+    /// encoded using bincode.
+    pub fn with_codec(addr: SocketAddr, codec: C) -> Result<Self, Error> {
+        Self::with_config(addr, None, None, None, codec)
+    }
+
+    /// Create a new `TcpSender` with the given maximum chunk length,
+    /// version to announce, token to present, and codec.
+    fn with_config(
+        addr: SocketAddr,
+        max_chunk_len: Option<usize>,
+        version: Option<String>,
+        token: Option<Token>,
+        codec: C,
+    ) -> Result<Self, Error> {
         let id = Id::<()>::new().get();
         trace!("TcpSender({})::new({})", id, addr);
 
         let buffer = Arc::new(Mutex::new(TxnBuf::default()));
-        let socket = Socket::new()?;
+        let acks = Arc::new(Acks::default());
+        let socket = Socket::new(&addr)?;
         let cancel = socket.to_cancelable();
-        let thread = Some(Self::connect(id, socket, addr, buffer.clone()));
+        let thread = Some(Self::connect(
+            id,
+            socket,
+            addr,
+            version,
+            token,
+            codec,
+            buffer.clone(),
+            acks.clone(),
+        ));
 
         Ok(Self {
             id,
             buffer,
             cancel,
             thread,
+            reader: None,
+            acks,
+            max_chunk_len,
         })
     }
 
     /// Start a thread attempting to connect to the given address.
+    #[allow(clippy::too_many_arguments)]
     fn connect(
         id: usize,
         socket: Socket,
         addr: SocketAddr,
-        buffer: Arc<Mutex<TxnBuf<BufWriter<TcpStream>, T>>>,
-    ) -> JoinHandle<Result<(), String>> {
+        version: Option<String>,
+        token: Option<Token>,
+        codec: C,
+        buffer: Arc<Mutex<TxnBuf<BufWriter<TcpStream>, T, C>>>,
+        acks: Arc<Acks>,
+    ) -> JoinHandle<Result<JoinHandle<()>, String>> {
         spawn(move || {
             let stream = socket
                 .connect(&addr)
                 .map_err(|e| format!("TcpSender({}): failed to connect to {}: {}", id, addr, e))?;
             debug!("TcpSender({}): connected to {}", id, addr);
+            // Frames are flushed explicitly on commit, after which we
+            // may wait for the acknowledgement, so do not let them
+            // linger.
+            stream.set_nodelay(true).map_err(|e| {
+                format!(
+                    "TcpSender({}): failed to disable Nagle's algorithm: {}",
+                    id, e
+                )
+            })?;
+
+            let reader = stream.try_clone().map_err(|e| {
+                format!(
+                    "TcpSender({}): failed to clone connection to {}: {}",
+                    id, addr, e
+                )
+            })?;
+
+            let mut writer = BufWriter::new(stream);
+            // The token and the version go out ahead of anything else,
+            // flushed along with the cached transactions.
+            if let Some(token) = token {
+                token
+                    .write(&mut writer)
+                    .map_err(|e| format!("TcpSender({}): failed to present token: {}", id, e))?;
+            }
+            if let Some(version) = version {
+                write_frame(&mut writer, &Message::<()>::Version(version))
+                    .map_err(|e| format!("TcpSender({}): failed to announce version: {}", id, e))?;
+            }
 
             let buffer = &mut buffer.lock().unwrap();
-            buffer
-                .set_mode_passthrough(BufWriter::new(stream))
-                .map_err(|e| {
-                    format!(
-                        "TcpSender({}): failed to flush cached transactions: {}",
-                        id, e
-                    )
-                })?;
-            Ok(())
+            let committed = buffer.set_mode_passthrough(writer, codec).map_err(|e| {
+                format!(
+                    "TcpSender({}): failed to flush cached transactions: {}",
+                    id, e
+                )
+            })?;
+            acks.flush(committed);
+
+            Ok(spawn(move || Self::read_acks(id, reader, acks)))
         })
     }
 }
 
-impl<T> TcpSender<T>
+impl<T, C> TcpSender<T, C>
 where
     T: Debug,
 {
+    /// Read acknowledgements sent by the receiver until the connection
+    /// is closed.
+    fn read_acks(id: usize, socket: TcpStream, acks: Arc<Acks>) {
+        let mut reader = BufReader::new(socket);
+        let mut buffer = Vec::new();
+        loop {
+            let result = match read_frame(&mut reader, &mut buffer, MAX_ACK_FRAME_SIZE) {
+                Ok(true) => {
+                    // Skip messages introduced by a later version of
+                    // the protocol.
+                    if let Some(index) = Kind::unknown(&buffer) {
+                        trace!(
+                            "TcpSender({}): skipping message of unknown kind {}",
+                            id,
+                            index
+                        );
+                        continue;
+                    }
+                    deserialize(&buffer).map(Some).map_err(|e| e.to_string())
+                }
+                Ok(false) => Ok(None),
+                Err(e) => Err(e.to_string()),
+            };
+
+            match result {
+                Ok(Some(Message::<()>::Ack(sequence))) => {
+                    trace!("TcpSender({}): received ack {}", id, sequence);
+                    acks.ack(sequence)
+                }
+                // The receiver only tells us its version to refuse us.
+                Ok(Some(Message::<()>::Version(version))) => {
+                    error!(
+                        "TcpSender({}): receiver refused connection, expecting version {}",
+                        id, version
+                    );
+                    break;
+                }
+                Ok(Some(message)) => error!("TcpSender({}): received unexpected {}", id, message),
+                Ok(None) => break,
+                Err(e) => {
+                    error!("TcpSender({}): failed to read acknowledgement: {}", id, e);
+                    break;
+                }
+            }
+        }
+        acks.close();
+    }
+
     /// Block until a connection is established.
     pub fn wait_connected(&mut self) -> Result<(), String> {
         if let Some(t) = self.thread.take() {
             match t.join() {
-                Ok(result) => result,
+                Ok(result) => result.map(|reader| self.reader = Some(reader)),
                 Err(e) => Err(format!(
                     "TcpSender({}) thread has panicked: {:?}",
                     self.id, e
@@ -105,16 +319,43 @@ where
             Ok(())
         }
     }
+
+    /// Retrieve the sequence number of the last transaction committed.
+    ///
+    /// Transactions are numbered consecutively, starting at one.
+    pub fn sequence(&self) -> u64 {
+        self.acks.sequence()
+    }
+
+    /// Retrieve the sequence number of the last transaction
+    /// acknowledged by the receiver.
+    pub fn acked(&self) -> u64 {
+        self.acks.acked()
+    }
+
+    /// Block until the receiver acknowledged that its observer
+    /// committed the transaction with the given sequence number.
+    ///
+    /// An error is reported if the receiver failed to commit the
+    /// transaction or if the connection was closed before the
+    /// acknowledgement arrived.
+    pub fn await_ack(&self, sequence: u64) -> Result<(), String> {
+        trace!("TcpSender({})::await_ack({})", self.id, sequence);
+        self.acks
+            .await_ack(sequence)
+            .map_err(|e| format!("TcpSender({}): {}", self.id, e))
+    }
 }
 
 /// `TcpSender` can be an observer for any type `V` that can be converted to `T`.
 /// This way we can support scenarios where `T` is a wrapper that implements the
 /// `Serialize` trait for another type without having to insert an additional
 /// transformer in the chain.
-impl<T, V> Observer<V, String> for TcpSender<T>
+impl<T, V, C> Observer<V, String> for TcpSender<T, C>
 where
-    T: Debug + Send + Serialize + From<V> + 'static,
+    T: Debug + Send + From<V> + 'static,
     V: Send,
+    C: Codec<T>,
 {
     /// Perform some action before data starts coming in.
     fn on_start(&mut self) -> Result<(), String> {
@@ -122,28 +363,72 @@ where
         self.buffer.lock().unwrap().on_start()
     }
 
-    /// Send a series of items over the TCP channel.
+    /// Send a series of items over the TCP channel, split up into
+    /// chunks of the configured maximum length, if any.
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = V> + 'a>) -> Result<(), String> {
         trace!("TcpSender({})::on_updates", self.id);
-        self.buffer
-            .lock()
-            .unwrap()
-            .on_updates(Box::new(updates.map(T::from)))
+        let mut buffer = self.buffer.lock().unwrap();
+        let updates = updates.map(T::from);
+
+        match self.max_chunk_len {
+            None => buffer.on_updates(Box::new(updates)),
+            Some(max_chunk_len) => {
+                let mut updates = updates.peekable();
+                while updates.peek().is_some() {
+                    buffer.on_updates(Box::new(updates.by_ref().take(max_chunk_len)))?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Send a snapshot over the TCP channel, as a single message.
+    ///
+    /// A snapshot has to be sent within a transaction, ahead of any
+    /// updates of that transaction. While not yet connected, only the
+    /// first transaction may carry one.
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = V> + 'a>) -> Result<(), String> {
+        trace!("TcpSender({})::on_snapshot", self.id);
+        let mut buffer = self.buffer.lock().unwrap();
+        buffer.on_snapshot(Box::new(items.map(T::from)))
     }
 
     /// Flush the TCP stream and signal the commit.
     fn on_commit(&mut self) -> Result<(), String> {
         trace!("TcpSender({})::on_commit", self.id);
-        self.buffer.lock().unwrap().on_commit()
+        let mut buffer = self.buffer.lock().unwrap();
+        // Register the commit before sending it, so that we are
+        // guaranteed to know about it once the acknowledgement arrives.
+        let sent = matches!(*buffer, TxnBuf::Writer(..));
+        let _ = self.acks.commit(sent);
+        buffer.on_commit()
+    }
+
+    /// Flush the TCP stream and signal that the transaction in progress
+    /// got abandoned, or discard it if not yet connected.
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("TcpSender({})::on_abort", self.id);
+        self.buffer.lock().unwrap().on_abort()
+    }
+
+    /// Flush the TCP stream and signal the end of a partial
+    /// transaction, which the next one continues.
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("TcpSender({})::on_flush", self.id);
+        self.buffer.lock().unwrap().on_flush()
     }
 
     fn on_completed(&mut self) -> Result<(), String> {
         trace!("TcpSender({})::on_completed", self.id);
         self.buffer.lock().unwrap().on_completed()
     }
+
+    fn describe(&self) -> String {
+        "tcp".to_string()
+    }
 }
 
-impl<T> Drop for TcpSender<T>
+impl<T, C> Drop for TcpSender<T, C>
 where
     T: Debug,
 {
@@ -154,6 +439,33 @@ where
         if let Err(e) = self.wait_connected() {
             error!("{}", e);
         }
+
+        // Only shut down the sending side of the connection. The
+        // receiver will close it once it has seen all our data, which
+        // in turn terminates the thread reading acknowledgements.
+        // A broken connection may end in the middle of a message, which
+        // the receiver reports as an error once it sees the shutdown.
+        let result = match &mut *self.buffer.lock().unwrap() {
+            TxnBuf::Writer(writer, _) => Some(
+                writer
+                    .flush()
+                    .and_then(|_| writer.get_ref().shutdown(Shutdown::Write)),
+            ),
+            TxnBuf::Broken(writer, _) => Some(writer.get_ref().shutdown(Shutdown::Write)),
+            TxnBuf::Updates { .. } => None,
+        };
+        if let Some(Err(e)) = result {
+            error!(
+                "TcpSender({}): failed to shut down connection: {}",
+                self.id, e
+            );
+        }
+
+        if let Some(reader) = self.reader.take() {
+            if let Err(e) = reader.join() {
+                error!("TcpSender({}) ack thread has panicked: {:?}", self.id, e);
+            }
+        }
     }
 }
 
@@ -161,6 +473,14 @@ where
 mod tests {
     use super::*;
 
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::sync::mpsc::channel;
+    use std::sync::mpsc::Receiver;
+    use std::thread::sleep;
+    use std::time::Duration;
+
     use test_env_log::test;
 
     use crate::await_expected;
@@ -216,4 +536,123 @@ mod tests {
             assert_eq!(on_updates, 3);
         });
     }
+
+    /// Check that updates get sent in chunks of the configured maximum
+    /// length, as part of a single transaction.
+    #[test]
+    fn chunked_updates() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut send =
+            TcpSender::<u64>::with_max_chunk_len(listener.local_addr().unwrap(), 2).unwrap();
+        let (socket, _) = listener.accept().unwrap();
+        send.wait_connected().unwrap();
+
+        {
+            let send = &mut send as &mut dyn Observer<u64, _>;
+            send.on_start().unwrap();
+            send.on_updates(Box::new(1..=5)).unwrap();
+            send.on_updates(Box::new(vec![6].into_iter())).unwrap();
+            send.on_commit().unwrap();
+        }
+
+        let expected = vec![
+            Message::Start,
+            Message::Updates(vec![1, 2]),
+            Message::Updates(vec![3, 4]),
+            Message::Updates(vec![5]),
+            Message::Updates(vec![6]),
+            Message::Commit,
+        ];
+        let mut reader = BufReader::new(socket);
+        let mut frame = Vec::new();
+        for expected in expected {
+            assert!(read_frame(&mut reader, &mut frame, usize::MAX).unwrap());
+            assert_eq!(deserialize::<Message<u64>>(&frame).unwrap(), expected);
+        }
+
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let mock = SharedObserver::new(Mutex::new(MockObserver::new()));
+        recv.subscribe(Box::new(mock.clone())).unwrap();
+        let mut send = TcpSender::<u64>::with_max_chunk_len(*recv.addr(), 3).unwrap();
+        {
+            let send = &mut send as &mut dyn Observer<u64, _>;
+            send.on_start().unwrap();
+            send.on_updates(Box::new(0..10)).unwrap();
+            send.on_commit().unwrap();
+        }
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 10);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// An observer blocking in `on_commit` until signaled.
+    #[derive(Debug)]
+    struct BlockingObserver {
+        release: Receiver<()>,
+        committed: Arc<AtomicBool>,
+    }
+
+    impl Observer<u64, String> for BlockingObserver {
+        fn on_start(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_commit(&mut self) -> Result<(), String> {
+            self.release.recv().unwrap();
+            self.committed.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+
+        fn on_updates<'a>(
+            &mut self,
+            _updates: Box<dyn Iterator<Item = u64> + 'a>,
+        ) -> Result<(), String> {
+            Ok(())
+        }
+
+        fn on_completed(&mut self) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    /// Check that a transaction is only acknowledged after the
+    /// receiving observer committed it.
+    #[test]
+    fn ack_after_commit() {
+        let (release, receiver) = channel();
+        let committed = Arc::new(AtomicBool::new(false));
+        let observer = BlockingObserver {
+            release: receiver,
+            committed: committed.clone(),
+        };
+
+        let mut recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        recv.subscribe(Box::new(observer)).unwrap();
+
+        let mut send = TcpSender::<u64>::new(*recv.addr()).unwrap();
+        {
+            let send = &mut send as &mut dyn Observer<u64, _>;
+            send.on_start().unwrap();
+            send.on_updates(Box::new(vec![1, 2, 3].into_iter()))
+                .unwrap();
+            send.on_commit().unwrap();
+        }
+        send.wait_connected().unwrap();
+
+        let sequence = send.sequence();
+        assert_eq!(sequence, 1);
+
+        // Give the receiver some time to (erroneously) acknowledge the
+        // transaction before its observer committed.
+        sleep(Duration::from_millis(50));
+        assert_eq!(send.acked(), 0);
+
+        release.send(()).unwrap();
+        send.await_ack(sequence).unwrap();
+        assert!(committed.load(Ordering::SeqCst));
+    }
 }
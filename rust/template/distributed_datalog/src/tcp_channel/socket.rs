@@ -13,16 +13,22 @@ use std::convert::TryInto;
 use std::io::Error;
 use std::io::ErrorKind;
 use std::mem::forget;
+use std::mem::size_of_val;
+use std::net::Shutdown;
 use std::net::SocketAddr;
+use std::net::TcpListener;
 use std::net::TcpStream;
 use std::os::unix::io::AsRawFd;
 use std::os::unix::io::FromRawFd;
 use std::os::unix::io::IntoRawFd;
 use std::os::unix::io::RawFd;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
 
 use log::error;
 
@@ -50,14 +56,8 @@ fn cvt<T: IsMinusOne>(t: T) -> Result<T, Error> {
 
 fn into_inner(addr: &SocketAddr) -> (*const libc::sockaddr, libc::socklen_t) {
     match addr {
-        SocketAddr::V4(ref a) => (
-            a as *const _ as *const _,
-            std::mem::size_of_val(a) as libc::socklen_t,
-        ),
-        SocketAddr::V6(ref a) => (
-            a as *const _ as *const _,
-            std::mem::size_of_val(a) as libc::socklen_t,
-        ),
+        SocketAddr::V4(ref a) => (a as *const _ as *const _, size_of_val(a) as libc::socklen_t),
+        SocketAddr::V6(ref a) => (a as *const _ as *const _, size_of_val(a) as libc::socklen_t),
     }
 }
 
@@ -159,8 +159,11 @@ pub trait ShutdownExt {
 }
 
 const FD_CLOSED: usize = 1 << (0usize.count_zeros() - 1);
-const FD_SHUTDOWN: usize = FD_CLOSED >> 1;
-const FD_UNOWNED: usize = FD_SHUTDOWN >> 1;
+const FD_SHUTDOWN_READ: usize = FD_CLOSED >> 1;
+const FD_UNOWNED: usize = FD_SHUTDOWN_READ >> 1;
+const FD_SHUTDOWN_WRITE: usize = FD_UNOWNED >> 1;
+const FD_SHUTDOWN: usize = FD_SHUTDOWN_READ | FD_SHUTDOWN_WRITE;
+const FD_FLAGS: usize = FD_CLOSED | FD_SHUTDOWN | FD_UNOWNED;
 
 #[derive(Debug)]
 pub struct Fd(AtomicUsize);
@@ -176,7 +179,8 @@ impl Fd {
         T: Into<libc::c_uint>,
     {
         debug_assert_eq!(FD_CLOSED.count_ones(), 1);
-        debug_assert_eq!(FD_SHUTDOWN.count_ones(), 1);
+        debug_assert_eq!(FD_SHUTDOWN_READ.count_ones(), 1);
+        debug_assert_eq!(FD_SHUTDOWN_WRITE.count_ones(), 1);
         debug_assert_eq!(FD_UNOWNED.count_ones(), 1);
 
         let fd = usize::try_from(fd.into()).unwrap();
@@ -215,7 +219,7 @@ impl Fd {
         if fd & (FD_CLOSED | FD_UNOWNED) != 0 {
             Ok(())
         } else {
-            let fd = fd & !FD_SHUTDOWN;
+            let fd = fd & !FD_FLAGS;
             cvt(unsafe { libc::close(fd.try_into().unwrap()) }).map(|_| ())
         }
     }
@@ -224,27 +228,50 @@ impl Fd {
     pub fn is_closed(&self) -> bool {
         self.0.load(Ordering::SeqCst) & FD_CLOSED != 0
     }
+
+    /// Shut down the given direction(s) of the socket, leaving the
+    /// other one usable, e.g., to tell the peer that we are done
+    /// sending while still reading what it has to say.
+    ///
+    /// Each direction is shut down at most once, no matter whether
+    /// that actually succeeded; shutting it down again, as part of
+    /// `Shutdown::Both` or on its own, has no effect. Only once both
+    /// directions are shut down is the object considered shut down.
+    pub fn shutdown_mode(&self, how: Shutdown) -> Result<(), Error> {
+        let flags = match how {
+            Shutdown::Read => FD_SHUTDOWN_READ,
+            Shutdown::Write => FD_SHUTDOWN_WRITE,
+            Shutdown::Both => FD_SHUTDOWN,
+        };
+        let fd = self.0.fetch_or(flags, Ordering::SeqCst);
+        if fd & FD_CLOSED != 0 {
+            return Ok(());
+        }
+
+        let how = match flags & !fd {
+            FD_SHUTDOWN_READ => libc::SHUT_RD,
+            FD_SHUTDOWN_WRITE => libc::SHUT_WR,
+            FD_SHUTDOWN => libc::SHUT_RDWR,
+            _ => return Ok(()),
+        };
+        let fd = fd & !FD_FLAGS;
+        cvt(unsafe { libc::shutdown(fd.try_into().unwrap(), how) }).map(|_| ())
+    }
 }
 
 impl ShutdownExt for Fd {
     fn shutdown(&self) -> Result<(), Error> {
-        let fd = self.0.fetch_or(FD_SHUTDOWN, Ordering::SeqCst);
-        if fd & (FD_SHUTDOWN | FD_CLOSED) != 0 {
-            Ok(())
-        } else {
-            let fd = fd & !FD_UNOWNED;
-            cvt(unsafe { libc::shutdown(fd.try_into().unwrap(), libc::SHUT_RDWR) }).map(|_| ())
-        }
+        self.shutdown_mode(Shutdown::Both)
     }
 
     fn is_shutdown(&self) -> bool {
-        self.0.load(Ordering::SeqCst) & FD_SHUTDOWN != 0
+        self.0.load(Ordering::SeqCst) & FD_SHUTDOWN == FD_SHUTDOWN
     }
 }
 
 impl AsRawFd for Fd {
     fn as_raw_fd(&self) -> RawFd {
-        let fd = self.0.load(Ordering::SeqCst) & !(FD_SHUTDOWN | FD_CLOSED | FD_UNOWNED);
+        let fd = self.0.load(Ordering::SeqCst) & !FD_FLAGS;
         // It's always safe to unwrap because we created the object from
         // what is essentially a RawFd to begin with, it's just that we
         // store it in something potentially larger.
@@ -272,24 +299,300 @@ impl Drop for Fd {
     }
 }
 
-/// Create a socket file descriptor.
-fn socket() -> Result<Fd, Error> {
+/// Create a socket file descriptor for the address family of `addr`.
+fn socket(addr: &SocketAddr) -> Result<Fd, Error> {
+    let family = match addr {
+        SocketAddr::V4(..) => libc::AF_INET,
+        SocketAddr::V6(..) => libc::AF_INET6,
+    };
+
     unsafe {
-        let fd = cvt(libc::socket(libc::AF_INET, libc::SOCK_STREAM, 0))?;
+        let fd = cvt(libc::socket(family, libc::SOCK_STREAM, 0))?;
         let oldflags = cvt(libc::fcntl(fd, libc::F_GETFD, 0))?;
         let _ = cvt(libc::fcntl(fd, libc::F_SETFD, oldflags | libc::O_CLOEXEC))?;
         Ok(Fd::new(fd as libc::c_uint))
     }
 }
 
+/// Set an integer socket option.
+fn set_option(
+    fd: RawFd,
+    level: libc::c_int,
+    name: libc::c_int,
+    value: libc::c_int,
+) -> Result<(), Error> {
+    let len = size_of_val(&value) as libc::socklen_t;
+    let value = &value as *const libc::c_int as *const libc::c_void;
+    cvt(unsafe { libc::setsockopt(fd, level, name, value, len) }).map(|_| ())
+}
+
+/// Create a listener socket bound to `addr`.
+///
+/// For an IPv6 address, `only_v6` controls whether the socket accepts
+/// IPv6 connections only (`true`) or IPv4 ones as well (`false`),
+/// i.e., whether it is dual-stack. `None` retains the system's default
+/// behavior, which is what `TcpListener::bind` provides.
+pub fn bind(addr: &SocketAddr, only_v6: Option<bool>) -> Result<TcpListener, Error> {
+    let fd = socket(addr)?;
+    let raw = fd.as_raw_fd();
+    // Mirror `TcpListener::bind`, which allows for rebinding an address
+    // still in TIME_WAIT state.
+    set_option(raw, libc::SOL_SOCKET, libc::SO_REUSEADDR, 1)?;
+    if let (SocketAddr::V6(..), Some(only_v6)) = (addr, only_v6) {
+        set_option(
+            raw,
+            libc::IPPROTO_IPV6,
+            libc::IPV6_V6ONLY,
+            only_v6 as libc::c_int,
+        )?;
+    }
+
+    let (addrp, len) = into_inner(addr);
+    let _ = cvt(unsafe { libc::bind(raw, addrp, len) })?;
+    let _ = cvt(unsafe { libc::listen(raw, 128) })?;
+    Ok(unsafe { TcpListener::from_raw_fd(fd.into_raw_fd()) })
+}
+
+/// An object for waking up a thread blocked in `await_accept`, based
+/// on a pipe. Once woken up, it stays that way.
+#[derive(Debug)]
+pub struct Wake {
+    /// The read end of the pipe.
+    read: Fd,
+    /// The write end of the pipe.
+    write: Fd,
+    /// Whether we have been woken up.
+    woken: AtomicBool,
+}
+
+impl Wake {
+    /// Create a new `Wake` object.
+    pub fn new() -> Result<Self, Error> {
+        let mut fds = [0; 2];
+        let _ = cvt(unsafe { libc::pipe(fds.as_mut_ptr()) })?;
+        let read = Fd::new(libc::c_uint::try_from(fds[0]).unwrap());
+        let write = Fd::new(libc::c_uint::try_from(fds[1]).unwrap());
+
+        for fd in &fds {
+            let oldflags = cvt(unsafe { libc::fcntl(*fd, libc::F_GETFD, 0) })?;
+            let _ = cvt(unsafe { libc::fcntl(*fd, libc::F_SETFD, oldflags | libc::O_CLOEXEC) })?;
+        }
+
+        Ok(Self {
+            read,
+            write,
+            woken: AtomicBool::new(false),
+        })
+    }
+
+    /// Wake up the thread waiting in `await_accept`, if any.
+    pub fn wake(&self) -> Result<(), Error> {
+        if self.woken.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+
+        let byte = 0u8;
+        let ptr = &byte as *const u8 as *const libc::c_void;
+        cvt(unsafe { libc::write(self.write.as_raw_fd(), ptr, 1) }).map(|_| ())
+    }
+
+    /// Check whether we have been woken up.
+    pub fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::SeqCst)
+    }
+}
+
+/// Block until a connection is pending on the given listener socket or
+/// `wake` got woken up, reporting whether the former is the case.
+pub fn await_accept(listener: RawFd, wake: &Wake) -> Result<bool, Error> {
+    let mut pollfds = [
+        libc::pollfd {
+            fd: listener,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: wake.read.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+    let count = pollfds.len().try_into().unwrap();
+
+    loop {
+        match unsafe { libc::poll(pollfds.as_mut_ptr(), count, -1) } {
+            -1 => {
+                let err = Error::last_os_error();
+                if err.kind() != ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            _ => {
+                if pollfds[1].revents != 0 || wake.is_woken() {
+                    return Ok(false);
+                }
+                if pollfds[0].revents != 0 {
+                    return Ok(true);
+                }
+            }
+        }
+    }
+}
+
+/// Block until `wake` got woken up or `timeout` elapsed, reporting
+/// whether the former is the case.
+pub fn await_wake(wake: &Wake, timeout: Duration) -> Result<bool, Error> {
+    let mut pollfds = [libc::pollfd {
+        fd: wake.read.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+    let count = pollfds.len().try_into().unwrap();
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        // Round up, so that we do not spin once less than a millisecond
+        // is left.
+        let millis = (remaining + Duration::from_nanos(999_999)).as_millis();
+        let millis = libc::c_int::try_from(millis).unwrap_or(libc::c_int::MAX);
+        match unsafe { libc::poll(pollfds.as_mut_ptr(), count, millis) } {
+            -1 => {
+                let err = Error::last_os_error();
+                if err.kind() != ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+            0 => {
+                if Instant::now() >= deadline {
+                    return Ok(wake.is_woken());
+                }
+            }
+            _ => return Ok(true),
+        }
+    }
+}
+
+/// Make a single attempt at connecting to `addr`, giving up early if
+/// `wake` got woken up. `None` is returned in the latter case.
+///
+/// Unlike `Socket::connect`, a refused connection is reported as an
+/// error instead of being retried.
+pub fn connect_once(addr: &SocketAddr, wake: &Wake) -> Result<Option<TcpStream>, Error> {
+    let fd = socket(addr)?;
+    let raw = fd.as_raw_fd();
+    set_nonblocking(raw, true)?;
+    let result = unsafe {
+        let (addrp, len) = into_inner(addr);
+        cvt(libc::connect(raw, addrp, len))
+    };
+
+    match result {
+        Ok(_) => (),
+        Err(ref e) if e.raw_os_error() == Some(libc::EINPROGRESS) => {
+            let mut pollfds = [
+                libc::pollfd {
+                    fd: raw,
+                    events: libc::POLLOUT,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: wake.read.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            let count = pollfds.len().try_into().unwrap();
+
+            loop {
+                match unsafe { libc::poll(pollfds.as_mut_ptr(), count, -1) } {
+                    -1 => {
+                        let err = Error::last_os_error();
+                        if err.kind() != ErrorKind::Interrupted {
+                            return Err(err);
+                        }
+                    }
+                    _ => {
+                        if pollfds[1].revents != 0 || wake.is_woken() {
+                            return Ok(None);
+                        }
+                        if pollfds[0].revents != 0 {
+                            break;
+                        }
+                    }
+                }
+            }
+
+            // The outcome of the connection attempt is reported through
+            // the pending error of the socket.
+            let mut error: libc::c_int = 0;
+            let mut len = size_of_val(&error) as libc::socklen_t;
+            let _ = cvt(unsafe {
+                libc::getsockopt(
+                    raw,
+                    libc::SOL_SOCKET,
+                    libc::SO_ERROR,
+                    &mut error as *mut libc::c_int as *mut libc::c_void,
+                    &mut len,
+                )
+            })?;
+            if error != 0 {
+                return Err(Error::from_raw_os_error(error));
+            }
+        }
+        Err(e) => return Err(e),
+    }
+
+    set_nonblocking(raw, false)?;
+    Ok(Some(unsafe { TcpStream::from_raw_fd(fd.into_raw_fd()) }))
+}
+
+/// Set the maximum number of connections queued on the given listener
+/// socket before new ones get refused (or dropped), as capped by the
+/// system. Calling `listen` again on a listening socket just adjusts
+/// its backlog.
+pub fn set_backlog(listener: RawFd, backlog: libc::c_int) -> Result<(), Error> {
+    cvt(unsafe { libc::listen(listener, backlog) }).map(|_| ())
+}
+
+/// Set how long closing the given socket lingers to send data still
+/// queued, with `None` disabling lingering, i.e., restoring the default
+/// of closing in the background.
+pub fn set_linger(socket: RawFd, linger: Option<Duration>) -> Result<(), Error> {
+    let value = libc::linger {
+        l_onoff: libc::c_int::from(linger.is_some()),
+        l_linger: linger.map_or(0, |linger| {
+            libc::c_int::try_from(linger.as_secs()).unwrap_or(libc::c_int::MAX)
+        }),
+    };
+    let len = size_of_val(&value) as libc::socklen_t;
+    let value = &value as *const libc::linger as *const libc::c_void;
+    cvt(unsafe { libc::setsockopt(socket, libc::SOL_SOCKET, libc::SO_LINGER, value, len) })
+        .map(|_| ())
+}
+
+/// Retrieve the number of bytes received on the given socket that have
+/// yet to be read.
+pub fn pending_bytes(socket: RawFd) -> Result<usize, Error> {
+    let mut pending: libc::c_int = 0;
+    let _ = cvt(unsafe { libc::ioctl(socket, libc::FIONREAD, &mut pending) })?;
+    Ok(usize::try_from(pending).unwrap_or(0))
+}
+
+/// Set the niceness of the calling thread (on Linux; of the calling
+/// process elsewhere).
+pub fn set_nice(nice: libc::c_int) -> Result<(), Error> {
+    cvt(unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) }).map(|_| ())
+}
+
 /// An object representing a socket.
 #[derive(Debug)]
 pub struct Socket(Arc<Fd>);
 
 impl Socket {
-    /// Create a new `Socket` object.
-    pub fn new() -> Result<Self, Error> {
-        Ok(Self(Arc::new(socket()?)))
+    /// Create a new `Socket` object for connecting to `addr`.
+    pub fn new(addr: &SocketAddr) -> Result<Self, Error> {
+        Ok(Self(Arc::new(socket(addr)?)))
     }
 
     /// Connect the socket to the given address.
@@ -337,15 +640,13 @@ mod tests {
 
     use std::io::Read;
     use std::io::Write;
-    use std::net::TcpListener;
     use std::thread::sleep;
     use std::thread::spawn;
-    use std::time::Duration;
 
     /// Test the closing on an `Fd`.
     #[test]
     fn close() {
-        let socket = Socket::new().unwrap();
+        let socket = Socket::new(&"127.0.0.1:0".parse().unwrap()).unwrap();
         let fd = socket.0;
         let raw = fd.as_raw_fd();
 
@@ -368,7 +669,7 @@ mod tests {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let addr = listener.local_addr().unwrap();
 
-        let fd = socket().unwrap();
+        let fd = socket(&addr).unwrap();
         connect(&fd, &addr).unwrap();
 
         assert!(!fd.is_shutdown());
@@ -382,13 +683,43 @@ mod tests {
         assert!(fd.is_shutdown());
     }
 
+    /// Check that shutting down the write direction of a connection
+    /// leaves its read direction intact.
+    #[test]
+    fn shutdown_write() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut peer, _) = listener.accept().unwrap();
+        let fd = Fd::new_unowned(libc::c_uint::try_from(stream.as_raw_fd()).unwrap());
+
+        stream.write_all(b"done").unwrap();
+        assert!(fd.shutdown_mode(Shutdown::Write).is_ok());
+        assert!(!fd.is_shutdown());
+        assert!(stream.write_all(b"more").is_err());
+
+        let mut data = Vec::new();
+        let _ = peer.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"done");
+
+        peer.write_all(b"ack").unwrap();
+        let mut data = [0; 3];
+        stream.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"ack");
+
+        // Shutting down both directions now only affects the read
+        // direction.
+        assert!(fd.shutdown().is_ok());
+        assert!(fd.is_shutdown());
+        assert_eq!(stream.read(&mut data).unwrap(), 0);
+    }
+
     /// Test that we can establish a connection.
     #[test]
     fn connect_immediately() {
         let listener = TcpListener::bind("127.0.0.1:0").unwrap();
         let addr = listener.local_addr().unwrap();
 
-        let socket = Socket::new().unwrap();
+        let socket = Socket::new(&addr).unwrap();
         let _ = socket.connect(&addr).unwrap();
     }
 
@@ -416,7 +747,7 @@ mod tests {
 
             {
                 let sock_addr = ADDR.parse().unwrap();
-                let socket = Socket::new().unwrap();
+                let socket = Socket::new(&sock_addr).unwrap();
                 let mut stream = socket.connect(&sock_addr).unwrap();
                 let _ = stream.write_all(&MESSAGE).unwrap();
             }
@@ -436,7 +767,7 @@ mod tests {
         const ADDR: &str = "127.0.0.1:5004";
 
         let sock_addr = ADDR.parse().unwrap();
-        let socket = Socket::new().unwrap();
+        let socket = Socket::new(&sock_addr).unwrap();
         let cancelable = socket.to_cancelable();
 
         let thread = spawn(move || {
@@ -456,7 +787,7 @@ mod tests {
         const ADDR: &str = "127.0.0.1:5005";
 
         let sock_addr = ADDR.parse().unwrap();
-        let socket = Socket::new().unwrap();
+        let socket = Socket::new(&sock_addr).unwrap();
         let cancelable = socket.to_cancelable();
 
         let thread1 = spawn(move || {
@@ -482,4 +813,32 @@ mod tests {
         thread1.join().unwrap();
         thread2.join().unwrap();
     }
+
+    /// Check that a single connection attempt succeeds if somebody is
+    /// listening and fails if nobody is.
+    #[test]
+    fn connect_once_wakeable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let wake = Wake::new().unwrap();
+
+        let stream = connect_once(&addr, &wake).unwrap().unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+
+        drop(listener);
+        let err = connect_once(&addr, &wake).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ECONNREFUSED));
+    }
+
+    /// Check that waiting for a wake up times out.
+    #[test]
+    fn await_wake_timeout() {
+        let wake = Wake::new().unwrap();
+        let start = Instant::now();
+        assert!(!await_wake(&wake, Duration::from_millis(20)).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        wake.wake().unwrap();
+        assert!(await_wake(&wake, Duration::from_secs(60)).unwrap());
+    }
 }
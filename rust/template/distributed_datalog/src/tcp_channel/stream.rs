@@ -0,0 +1,185 @@
+//! A module providing an asynchronous, `futures` based interface to
+//! the messages received by a `TcpReceiver`.
+
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+
+use futures::channel::mpsc::channel;
+use futures::channel::mpsc::Receiver;
+use futures::channel::mpsc::Sender;
+use futures::channel::mpsc::TrySendError;
+use futures::executor::block_on;
+use futures::future::poll_fn;
+use futures::ready;
+use futures::Stream;
+
+use log::trace;
+
+use crate::observe::Observable;
+use crate::tcp_channel::codec::BincodeCodec;
+use crate::tcp_channel::codec::Codec;
+use crate::tcp_channel::iter::Queue;
+use crate::tcp_channel::iter::QueueObserver;
+use crate::tcp_channel::Message;
+use crate::tcp_channel::TcpReceiver;
+
+impl<T> Queue<T> for Sender<Message<T>>
+where
+    T: Send,
+{
+    fn push(&mut self, message: Message<T>) -> Result<(), Message<T>> {
+        // The threads processing connections are no tasks, so they
+        // just block until there is room.
+        match block_on(poll_fn(|cx| self.poll_ready(cx))) {
+            Ok(()) => self.try_send(message).map_err(TrySendError::into_inner),
+            Err(_) => Err(message),
+        }
+    }
+}
+
+/// A `Stream` of the messages a `TcpReceiver` receives, as created by
+/// `TcpReceiver::into_stream`.
+///
+/// This is the asynchronous counterpart of a `MessageIter`, yielding
+/// the same messages: connections are still processed on threads of
+/// their own, which queue up messages to a fixed capacity, beyond which
+/// they block until the stream is polled. The stream ends once a sender
+/// completed or disconnected; the `Complete` message itself is not
+/// yielded. Dropping the stream stops the receiver.
+#[derive(Debug)]
+pub struct MessageStream<T, D, C = BincodeCodec>
+where
+    T: Debug + Send,
+    D: Debug + Send,
+{
+    /// The channel we receive messages from. Declared (and hence
+    /// dropped) ahead of `receiver`, so that threads blocked queueing a
+    /// message get unblocked before the receiver waits for them.
+    messages: Receiver<Message<T>>,
+    /// The receiver feeding us.
+    receiver: TcpReceiver<T, D, C>,
+    /// Whether the end of the stream was reached.
+    done: bool,
+}
+
+impl<T, D, C> MessageStream<T, D, C>
+where
+    T: Send + Debug + 'static,
+    D: Into<T> + Send + Debug + 'static,
+    C: Codec<D>,
+{
+    /// Create a new `MessageStream` of the messages `receiver`
+    /// receives, queueing up to `capacity` of them.
+    pub(crate) fn new(mut receiver: TcpReceiver<T, D, C>, capacity: usize) -> Self {
+        // The channel has room for one message per sender on top of
+        // its buffer, and we have a single sender.
+        let (sender, messages) = channel(capacity.saturating_sub(1));
+        let _ = receiver.unsubscribe(&());
+        // We just made sure that no observer is subscribed.
+        receiver
+            .subscribe(Box::new(QueueObserver::new(sender)))
+            .unwrap();
+
+        Self {
+            messages,
+            receiver,
+            done: false,
+        }
+    }
+
+    /// Retrieve the receiver feeding us.
+    pub fn receiver(&self) -> &TcpReceiver<T, D, C> {
+        &self.receiver
+    }
+}
+
+// We never pin any of our fields.
+impl<T, D, C> Unpin for MessageStream<T, D, C>
+where
+    T: Debug + Send,
+    D: Debug + Send,
+{
+}
+
+impl<T, D, C> Stream for MessageStream<T, D, C>
+where
+    T: Debug + Send,
+    D: Debug + Send,
+{
+    type Item = Message<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+
+        match ready!(Pin::new(&mut this.messages).poll_next(cx)) {
+            Some(Message::Complete) | None => {
+                trace!("MessageStream: end of stream");
+                this.done = true;
+                Poll::Ready(None)
+            }
+            Some(message) => Poll::Ready(Some(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::StreamExt;
+
+    use crate::observe::Observer;
+    use crate::TcpSender;
+
+    /// Collect the messages of a couple of transactions via the stream.
+    #[test]
+    fn collect_messages() {
+        let recv = TcpReceiver::<u64, u64>::new("127.0.0.1:0").unwrap();
+        let addr = *recv.addr();
+        let stream = recv.into_stream(8);
+
+        let mut send = TcpSender::<u64>::new(addr).unwrap();
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_commit().unwrap();
+        send.wait_connected().unwrap();
+        send.await_ack(1).unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+        send.await_ack(2).unwrap();
+
+        let observer = &mut send as &mut dyn Observer<u64, _>;
+        observer.on_completed().unwrap();
+
+        let messages = block_on(async {
+            let mut messages = Vec::new();
+            let mut stream = stream;
+            while let Some(message) = stream.next().await {
+                messages.push(message);
+            }
+            messages
+        });
+        assert_eq!(
+            messages,
+            vec![
+                Message::Start,
+                Message::Updates(vec![1, 2]),
+                Message::Commit,
+                Message::Start,
+                Message::Updates(vec![3]),
+                Message::Commit,
+            ]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+//! A module providing the authentication of senders by means of a
+//! pre-shared token. A sender presents the token as the very first
+//! frame on a connection, verbatim and ahead of any message, and the
+//! receiver hangs up on a sender presenting anything else.
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::fmt::Formatter;
+use std::fmt::Result as FmtResult;
+use std::hint::black_box;
+use std::io::Error;
+use std::io::ErrorKind;
+use std::io::Result as IoResult;
+use std::io::Write;
+
+/// A token shared between senders and a receiver.
+#[derive(Clone)]
+pub struct Token(Vec<u8>);
+
+impl Token {
+    /// Create a new `Token` from the given bytes.
+    pub fn new<B>(token: B) -> Self
+    where
+        B: Into<Vec<u8>>,
+    {
+        Self(token.into())
+    }
+
+    /// Present the token by writing it to `writer`, framed.
+    pub fn write<W>(&self, writer: &mut W) -> IoResult<()>
+    where
+        W: Write,
+    {
+        let size = u32::try_from(self.0.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "token is too large for a frame"))?;
+        writer.write_all(&size.to_le_bytes())?;
+        writer.write_all(&self.0)
+    }
+
+    /// Check whether `presented` is the token.
+    ///
+    /// The comparison takes the same time no matter how many leading
+    /// bytes match, so that the token cannot be guessed byte by byte.
+    /// Only its length may be learned from the time taken.
+    pub fn matches(&self, presented: &[u8]) -> bool {
+        if presented.len() != self.0.len() {
+            return false;
+        }
+        let diff = self
+            .0
+            .iter()
+            .zip(presented)
+            .fold(0, |diff, (a, b)| black_box(diff | (a ^ b)));
+        diff == 0
+    }
+}
+
+impl Debug for Token {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        // Make sure to never leak the token into logs.
+        f.write_str("Token(..)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::tcp_channel::frame::read_frame;
+
+    /// Check that a token only matches itself.
+    #[test]
+    fn matches() {
+        let token = Token::new("secret");
+        assert!(token.matches(b"secret"));
+        assert!(!token.matches(b"secreT"));
+        assert!(!token.matches(b"secret!"));
+        assert!(!token.matches(b""));
+        assert!(Token::new("").matches(b""));
+    }
+
+    /// Check that a token is written as a frame of its own.
+    #[test]
+    fn write() {
+        let token = Token::new(vec![1, 2, 3]);
+        let mut data = Vec::new();
+        token.write(&mut data).unwrap();
+
+        let mut buffer = Vec::new();
+        assert!(read_frame(&mut Cursor::new(data), &mut buffer, 16).unwrap());
+        assert!(token.matches(&buffer));
+        assert_eq!(format!("{:?}", token), "Token(..)");
+    }
+}
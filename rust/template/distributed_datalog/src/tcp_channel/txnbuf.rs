@@ -6,11 +6,11 @@ use std::collections::LinkedList;
 use std::fmt::Debug;
 use std::io::Write;
 use std::mem::replace;
-
-use bincode::serialize_into;
-use serde::Serialize;
+use std::mem::take;
 
 use crate::observe::Observer;
+use crate::tcp_channel::codec::BincodeCodec;
+use crate::tcp_channel::codec::Codec;
 use crate::tcp_channel::message::Message;
 
 /// A type representing the updates of a transaction.
@@ -18,7 +18,7 @@ type Transaction<T> = LinkedList<Vec<T>>;
 
 /// A buffer for transactions.
 #[derive(Debug)]
-pub enum TxnBuf<W, T>
+pub enum TxnBuf<W, T, C = BincodeCodec>
 where
     W: Debug,
     T: Debug,
@@ -30,74 +30,183 @@ where
         complete: Transaction<T>,
         /// The transaction currently in progress.
         ongoing: Option<Transaction<T>>,
+        /// The snapshot the first transaction started with, if any.
+        /// It belongs to `complete` once that is non-empty and to
+        /// `ongoing` otherwise.
+        snapshot: Option<Vec<T>>,
+        /// The transaction in progress got flushed and the next
+        /// `on_start` continues it.
+        flushed: bool,
         /// We have received an `on_completed` event.
         on_completed: bool,
     },
     /// A writer is present and we no longer need to buffer
-    /// transactions.
-    Writer(W),
+    /// transactions, which we encode using the contained codec.
+    Writer(W, C),
+    /// Writing to the writer failed, possibly after part of a message
+    /// went out. Nothing else may be written to it, as the receiver
+    /// could not tell where the next message starts.
+    ///
+    /// This state is final: reconnecting is out of scope, so every
+    /// further event fails and a new `TcpSender` is needed to establish
+    /// a fresh connection.
+    Broken(W, String),
 }
 
-impl<W, T> TxnBuf<W, T>
+impl<W, T, C> TxnBuf<W, T, C>
 where
     W: Debug + Send + Write,
-    T: Debug + Send + Serialize,
+    T: Debug + Send,
+    C: Codec<T>,
 {
     /// Convert the `TxnBuf` into the `Writer` variant.
     ///
+    /// On success, the return value indicates whether a (merged)
+    /// transaction was committed as part of flushing buffered data.
+    ///
     /// An error return indicates a failure to flush all buffered
-    /// transactions. The objects is in an undefined state afterwards.
-    pub fn set_mode_passthrough(&mut self, mut writer: W) -> Result<(), String> {
+    /// transactions. The object is in the `Broken` state afterwards.
+    /// A `TxnBuf` that is broken already stays so, as reconnecting is
+    /// not supported, and the new writer is dropped with an error.
+    pub fn set_mode_passthrough(&mut self, mut writer: W, codec: C) -> Result<bool, String> {
         match self {
             TxnBuf::Updates {
                 complete,
                 ongoing,
+                snapshot,
+                flushed,
                 on_completed,
             } => {
-                Self::handle_txn(&mut writer, replace(complete, LinkedList::new()))?;
-                Self::handle_partial_txn(&mut writer, ongoing.take())?;
-                if *on_completed {
-                    Self::handle_msg(&mut writer, &Message::<T>::Complete)?;
+                let complete = replace(complete, LinkedList::new());
+                let snapshot = snapshot.take();
+                let ongoing = ongoing.take();
+                let flushed = *flushed;
+                let on_completed = *on_completed;
+                let flush = || -> Result<bool, String> {
+                    // The snapshot, if any, goes with the first
+                    // transaction sent.
+                    let mut snapshot = snapshot;
+                    let committed = !complete.is_empty()
+                        && Self::handle_txn(&codec, &mut writer, snapshot.take(), complete)?;
+                    Self::handle_partial_txn(&codec, &mut writer, snapshot, ongoing)?;
+                    // The transaction following continues the partial
+                    // one, so the receiver has to see the latter end.
+                    if flushed {
+                        Self::handle_msg(&codec, &mut writer, &Message::<T>::Flush)?;
+                    }
+                    if on_completed {
+                        Self::handle_msg(&codec, &mut writer, &Message::<T>::Complete)?;
+                    }
+                    writer.flush().map_err(|e| e.to_string())?;
+                    Ok(committed)
+                };
+
+                match flush() {
+                    Ok(committed) => {
+                        *self = TxnBuf::Writer(writer, codec);
+                        Ok(committed)
+                    }
+                    Err(e) => {
+                        *self = TxnBuf::Broken(writer, e.clone());
+                        Err(e)
+                    }
                 }
-                writer.flush().map_err(|e| e.to_string())?;
-                *self = TxnBuf::Writer(writer);
-                Ok(())
             }
             TxnBuf::Writer(..) => panic!("TxnBuf is already a Writer variant"),
+            TxnBuf::Broken(_, error) => Err(format!(
+                "connection is broken and cannot be reestablished: {}",
+                error
+            )),
         }
     }
 
-    /// Send a full transaction.
-    fn handle_txn(writer: &mut W, txn: Transaction<T>) -> Result<(), String> {
+    /// Send a full transaction, if it is not empty.
+    fn handle_txn(
+        codec: &C,
+        writer: &mut W,
+        snapshot: Option<Vec<T>>,
+        txn: Transaction<T>,
+    ) -> Result<bool, String> {
         if !txn.is_empty() {
-            Self::handle_msg(writer, &Message::<T>::Start)?;
-            Self::handle_msg(writer, &Message::UpdateList(txn))?;
-            Self::handle_msg(writer, &Message::<T>::Commit)?;
+            Self::handle_msg(codec, writer, &Message::<T>::Start)?;
+            if let Some(snapshot) = snapshot {
+                Self::handle_msg(codec, writer, &Message::Snapshot(snapshot))?;
+            }
+            Self::handle_msg(codec, writer, &Message::UpdateList(txn))?;
+            Self::handle_msg(codec, writer, &Message::<T>::Commit)?;
+            Ok(true)
+        } else {
+            Ok(false)
         }
-        Ok(())
     }
 
     /// Send a partial transaction.
-    fn handle_partial_txn(writer: &mut W, txn: Option<Transaction<T>>) -> Result<(), String> {
+    fn handle_partial_txn(
+        codec: &C,
+        writer: &mut W,
+        snapshot: Option<Vec<T>>,
+        txn: Option<Transaction<T>>,
+    ) -> Result<(), String> {
         if let Some(updates) = txn {
             // If there is a partial transaction that means that we
-            // received a transaction start and potentially updates, but
-            // no commit yet.
-            Self::handle_msg(writer, &Message::<T>::Start)?;
+            // received a transaction start and potentially a snapshot
+            // and updates, but no commit yet.
+            Self::handle_msg(codec, writer, &Message::<T>::Start)?;
+            if let Some(snapshot) = snapshot {
+                Self::handle_msg(codec, writer, &Message::Snapshot(snapshot))?;
+            }
             if !updates.is_empty() {
-                Self::handle_msg(writer, &Message::UpdateList(updates))?;
+                Self::handle_msg(codec, writer, &Message::UpdateList(updates))?;
             }
         }
         Ok(())
     }
 
     /// Send a single message.
-    fn handle_msg(writer: &mut W, msg: &Message<T>) -> Result<(), String> {
-        serialize_into(writer, msg).map_err(|e| e.to_string())
+    fn handle_msg(codec: &C, writer: &mut W, msg: &Message<T>) -> Result<(), String> {
+        let frames = Self::encode(codec, msg)?;
+        writer.write_all(&frames).map_err(|e| e.to_string())
+    }
+
+    /// Encode a single message in full before any of it gets written,
+    /// so that a failure to encode it leaves the writer untouched.
+    fn encode(codec: &C, msg: &Message<T>) -> Result<Vec<u8>, String> {
+        let mut frames = Vec::new();
+        codec.encode(msg, &mut frames).map_err(|e| e.to_string())?;
+        Ok(frames)
+    }
+
+    /// Send a single message while in `Writer` mode, optionally
+    /// flushing the writer afterwards.
+    ///
+    /// If writing fails we switch over to `Broken` mode, as an unknown
+    /// part of the message may have been written already.
+    fn send(&mut self, msg: &Message<T>, flush: bool) -> Result<(), String> {
+        let result = match self {
+            TxnBuf::Writer(writer, codec) => {
+                let frames = Self::encode(codec, msg)?;
+                writer
+                    .write_all(&frames)
+                    .and_then(|()| if flush { writer.flush() } else { Ok(()) })
+            }
+            TxnBuf::Broken(_, error) => {
+                return Err(format!("connection is broken: {}", error));
+            }
+            TxnBuf::Updates { .. } => unreachable!(),
+        };
+
+        result.map_err(|e| {
+            let error = e.to_string();
+            let state = take(self);
+            if let TxnBuf::Writer(writer, _) = state {
+                *self = TxnBuf::Broken(writer, error.clone());
+            }
+            error
+        })
     }
 }
 
-impl<W, T> Default for TxnBuf<W, T>
+impl<W, T, C> Default for TxnBuf<W, T, C>
 where
     W: Debug,
     T: Debug,
@@ -106,27 +215,32 @@ where
         TxnBuf::Updates {
             complete: LinkedList::default(),
             ongoing: None,
+            snapshot: None,
+            flushed: false,
             on_completed: false,
         }
     }
 }
 
-impl<W, T> Observer<T, String> for TxnBuf<W, T>
+impl<W, T, C> Observer<T, String> for TxnBuf<W, T, C>
 where
     W: Debug + Send + Write,
-    T: Debug + Send + Serialize,
+    T: Debug + Send,
+    C: Codec<T>,
 {
     /// Perform some action before data starts coming in.
     fn on_start(&mut self) -> Result<(), String> {
         match self {
-            TxnBuf::Updates { ongoing, .. } => {
+            TxnBuf::Updates {
+                ongoing, flushed, ..
+            } => {
                 if ongoing.is_none() {
                     *ongoing = Some(LinkedList::new());
-                } else {
+                } else if !replace(flushed, false) {
                     panic!("received multiple on_start events")
                 }
             }
-            TxnBuf::Writer(writer) => Self::handle_msg(writer, &Message::<T>::Start)?,
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => self.send(&Message::<T>::Start, false)?,
         }
         Ok(())
     }
@@ -141,8 +255,33 @@ where
                     panic!("on_updates was not preceded by an on_start event")
                 }
             }
-            TxnBuf::Writer(writer) => {
-                Self::handle_msg(writer, &Message::Updates(updates.collect()))?
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => {
+                self.send(&Message::Updates(updates.collect()), false)?
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a snapshot over the TCP channel. While buffering, only the
+    /// first transaction may carry a snapshot, ahead of any updates.
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        match self {
+            TxnBuf::Updates {
+                complete,
+                ongoing,
+                snapshot,
+                ..
+            } => match ongoing {
+                Some(transaction) => {
+                    if !complete.is_empty() || !transaction.is_empty() || snapshot.is_some() {
+                        return Err("snapshot does not precede all updates".to_string());
+                    }
+                    *snapshot = Some(items.collect())
+                }
+                None => panic!("on_snapshot was not preceded by an on_start event"),
+            },
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => {
+                self.send(&Message::Snapshot(items.collect()), false)?
             }
         }
         Ok(())
@@ -152,19 +291,70 @@ where
     fn on_commit(&mut self) -> Result<(), String> {
         match self {
             TxnBuf::Updates {
-                complete, ongoing, ..
+                complete,
+                ongoing,
+                snapshot,
+                ..
             } => {
                 if let Some(transaction) = ongoing {
+                    // Make sure that the transaction carrying the
+                    // snapshot is sent even if it has no updates.
+                    if snapshot.is_some() && complete.is_empty() && transaction.is_empty() {
+                        transaction.push_back(Vec::new());
+                    }
                     complete.append(transaction);
                     *ongoing = None
                 } else {
                     panic!("on_commit was not preceded by an on_start event")
                 }
             }
-            TxnBuf::Writer(writer) => {
-                Self::handle_msg(writer, &Message::<T>::Commit)?;
-                writer.flush().map_err(|e| e.to_string())?
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => self.send(&Message::<T>::Commit, true)?,
+        }
+        Ok(())
+    }
+
+    /// Signal that the transaction in progress got abandoned. While
+    /// buffering, we discard what we buffered of it, including a part
+    /// that got flushed and the snapshot it started with, if any.
+    fn on_abort(&mut self) -> Result<(), String> {
+        match self {
+            TxnBuf::Updates {
+                complete,
+                ongoing,
+                snapshot,
+                flushed,
+                ..
+            } => {
+                if ongoing.take().is_some() {
+                    // The snapshot belongs to the transaction in
+                    // progress unless an earlier one got committed.
+                    if complete.is_empty() {
+                        *snapshot = None;
+                    }
+                    *flushed = false;
+                } else {
+                    panic!("on_abort was not preceded by an on_start event")
+                }
             }
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => self.send(&Message::<T>::Abort, true)?,
+        }
+        Ok(())
+    }
+
+    /// Signal the end of a partial transaction. While buffering, we
+    /// merely hold on to it for the next transaction to continue.
+    fn on_flush(&mut self) -> Result<(), String> {
+        match self {
+            TxnBuf::Updates {
+                ongoing, flushed, ..
+            } => {
+                if ongoing.is_some() {
+                    *flushed = true
+                } else {
+                    panic!("on_flush was not preceded by an on_start event")
+                }
+            }
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => self.send(&Message::<T>::Flush, true)?,
         }
         Ok(())
     }
@@ -172,10 +362,7 @@ where
     fn on_completed(&mut self) -> Result<(), String> {
         match self {
             TxnBuf::Updates { on_completed, .. } => *on_completed = true,
-            TxnBuf::Writer(writer) => {
-                Self::handle_msg(writer, &Message::<T>::Complete)?;
-                writer.flush().map_err(|e| e.to_string())?;
-            }
+            TxnBuf::Writer(..) | TxnBuf::Broken(..) => self.send(&Message::<T>::Complete, true)?,
         }
         Ok(())
     }
@@ -185,7 +372,9 @@ where
 mod tests {
     use super::*;
 
-    use bincode::deserialize_from;
+    use bincode::deserialize;
+
+    use crate::tcp_channel::frame::read_frame;
 
     /// Test caching of transactions in a `TxnBuf`.
     #[test]
@@ -196,22 +385,25 @@ mod tests {
         {
             let mut buffer = TxnBuf::default();
             f(&mut buffer).unwrap();
-            buffer.set_mode_passthrough(Vec::new()).unwrap();
+            let _ = buffer
+                .set_mode_passthrough(Vec::new(), BincodeCodec)
+                .unwrap();
 
             match buffer {
-                TxnBuf::Writer(buf) => {
+                TxnBuf::Writer(buf, _) => {
                     let mut slice = buf.as_slice();
+                    let mut frame = Vec::new();
                     for expected in expected {
-                        let msg = deserialize_from::<_, Message<u64>>(&mut slice).unwrap();
+                        assert!(read_frame(&mut slice, &mut frame, usize::MAX).unwrap());
+                        let msg = deserialize::<Message<u64>>(&frame).unwrap();
                         assert_eq!(msg, expected);
                     }
 
                     // Make sure we did not have any additional messages
                     // in the reader.
-                    let result = deserialize_from::<_, Message<u64>>(&mut slice);
-                    assert!(result.is_err(), result)
+                    assert!(!read_frame(&mut slice, &mut frame, usize::MAX).unwrap());
                 }
-                TxnBuf::Updates { .. } => unreachable!(),
+                TxnBuf::Updates { .. } | TxnBuf::Broken(..) => unreachable!(),
             }
         }
 
@@ -247,5 +439,127 @@ mod tests {
             buffer.on_start()?;
             Ok(())
         });
+
+        let expected = vec![
+            Message::Start,
+            Message::UpdateList(vec![vec![3]].into_iter().collect()),
+            Message::Commit,
+        ];
+        test(expected, |buffer| {
+            buffer.on_start()?;
+            buffer.on_snapshot(Box::new(vec![1].into_iter()))?;
+            buffer.on_updates(Box::new(vec![2].into_iter()))?;
+            buffer.on_flush()?;
+            buffer.on_start()?;
+            buffer.on_abort()?;
+
+            buffer.on_start()?;
+            buffer.on_updates(Box::new(vec![3].into_iter()))?;
+            buffer.on_commit()?;
+            Ok(())
+        });
+
+        let expected = vec![
+            Message::Start,
+            Message::Snapshot(vec![1, 2]),
+            Message::UpdateList(vec![vec![], vec![3]].into_iter().collect()),
+            Message::Commit,
+        ];
+        test(expected, |buffer| {
+            buffer.on_start()?;
+            buffer.on_snapshot(Box::new(vec![1, 2].into_iter()))?;
+            buffer.on_commit()?;
+
+            buffer.on_start()?;
+            buffer.on_updates(Box::new(vec![3].into_iter()))?;
+            buffer.on_commit()?;
+            Ok(())
+        });
+        test(vec![Message::Start, Message::Snapshot(vec![4])], |buffer| {
+            buffer.on_start()?;
+            buffer.on_snapshot(Box::new(vec![4].into_iter()))
+        });
+    }
+
+    /// Check that a snapshot following updates is rejected while
+    /// buffering.
+    #[test]
+    fn late_snapshot() {
+        let mut buffer = TxnBuf::<Vec<u8>, u64>::default();
+        buffer.on_start().unwrap();
+        buffer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        assert!(buffer.on_snapshot(Box::new(vec![2].into_iter())).is_err());
+    }
+
+    /// A writer accepting a limited number of bytes, writing as much of
+    /// the data provided as possible before failing.
+    #[derive(Debug)]
+    struct ShortWriter {
+        data: Vec<u8>,
+        limit: usize,
+    }
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let count = buf.len().min(self.limit - self.data.len());
+            if count == 0 && !buf.is_empty() {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.data.extend_from_slice(&buf[..count]);
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Check that a short write breaks the connection, with nothing
+    /// written after the partial message.
+    #[test]
+    fn short_write() {
+        let writer = ShortWriter {
+            data: Vec::new(),
+            limit: 64,
+        };
+        let mut buffer = TxnBuf::<_, u64>::default();
+        let _ = buffer.set_mode_passthrough(writer, BincodeCodec).unwrap();
+
+        buffer.on_start().unwrap();
+        buffer.on_updates(Box::new(vec![1, 2].into_iter())).unwrap();
+        assert!(buffer.on_updates(Box::new(0..10)).is_err());
+        assert!(matches!(buffer, TxnBuf::Broken(..)));
+
+        let error = buffer.on_commit().unwrap_err();
+        assert!(error.contains("broken"), "{}", error);
+        assert!(buffer.on_updates(Box::new(vec![3].into_iter())).is_err());
+
+        let writer = ShortWriter {
+            data: Vec::new(),
+            limit: 64,
+        };
+        let error = buffer
+            .set_mode_passthrough(writer, BincodeCodec)
+            .unwrap_err();
+        assert!(error.contains("broken"), "{}", error);
+        assert!(matches!(buffer, TxnBuf::Broken(..)));
+
+        match buffer {
+            TxnBuf::Broken(writer, _) => {
+                let mut slice = writer.data.as_slice();
+                let mut frame = Vec::new();
+                for expected in &[Message::Start, Message::Updates(vec![1, 2])] {
+                    assert!(read_frame(&mut slice, &mut frame, usize::MAX).unwrap());
+                    let msg = deserialize::<Message<u64>>(&frame).unwrap();
+                    assert_eq!(&msg, expected);
+                }
+
+                // All that follows is part of the message that failed to
+                // go out.
+                assert!(!slice.is_empty());
+                assert!(read_frame(&mut slice, &mut frame, usize::MAX).is_err());
+            }
+            TxnBuf::Updates { .. } | TxnBuf::Writer(..) => unreachable!(),
+        }
     }
 }
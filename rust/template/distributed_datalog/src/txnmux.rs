@@ -1,8 +1,14 @@
 use std::any::Any;
+use std::cmp::min;
 use std::collections::{BTreeMap, LinkedList};
 use std::fmt::Debug;
-use std::mem::replace;
+use std::mem::take;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
 
+use log::debug;
+use log::error;
 use log::trace;
 use uid::Id;
 
@@ -12,9 +18,40 @@ use crate::observe::Observer;
 use crate::observe::ObserverBox;
 use crate::observe::OptionalObserver;
 use crate::observe::SharedObserver;
+use crate::observe::TransactionStats;
+use crate::poison::MutexExt;
+
+/// Pass the `len` items of `updates` on to `observer`, split into
+/// batches of at most `max_batch_len` items, if set and not zero.
+pub(crate) fn on_updates_batched<O, T, E, I>(
+    observer: &mut O,
+    mut updates: I,
+    len: usize,
+    max_batch_len: Option<usize>,
+) -> Result<(), E>
+where
+    O: Observer<T, E> + ?Sized,
+    T: Send,
+    E: Send,
+    I: Iterator<Item = T>,
+{
+    match max_batch_len {
+        Some(max) if max > 0 && len > max => {
+            let mut remaining = len;
+            while remaining > 0 {
+                let batch = min(remaining, max);
+                observer.on_updates(Box::new(updates.by_ref().take(batch)))?;
+                remaining -= batch;
+            }
+            Ok(())
+        }
+        _ => observer.on_updates(Box::new(updates)),
+    }
+}
 
 /// Wrapper around a `SharedObserver` that stores updates and pushes them
-/// forward only when an `on_commit` is received.
+/// forward only when an `on_commit` is received (or they get flushed
+/// forcibly).
 #[derive(Debug)]
 struct CachingObserver<O, T> {
     /// The observer's unique ID.
@@ -24,11 +61,21 @@ struct CachingObserver<O, T> {
     observer: SharedObserver<O>,
     /// The data we accumulated so far.
     data: Option<LinkedList<Vec<T>>>,
+    /// The snapshot received as part of the transaction in progress,
+    /// if any.
+    snapshot: Option<Vec<T>>,
+    /// The maximum number of updates pushed to the observer at once, if
+    /// any.
+    max_batch_len: Option<usize>,
+    /// Whether to skip the `on_updates` call for a transaction without
+    /// any updates.
+    suppress_empty: bool,
 }
 
 impl<O, T> CachingObserver<O, T> {
-    /// Create a new `CachingObserver` wrapping the provided observer.
-    pub fn new(observer: SharedObserver<O>) -> Self {
+    /// Create a new `CachingObserver` wrapping the provided observer,
+    /// pushing at most `max_batch_len` updates to it at once.
+    pub fn new(observer: SharedObserver<O>, max_batch_len: Option<usize>) -> Self {
         let id = Id::<()>::new().get();
         trace!("CachingObserver({})::new", id);
 
@@ -36,7 +83,78 @@ impl<O, T> CachingObserver<O, T> {
             id,
             observer,
             data: None,
+            snapshot: None,
+            max_batch_len,
+            suppress_empty: false,
+        }
+    }
+}
+
+impl<O, T> CachingObserver<O, T>
+where
+    T: Send + Debug,
+{
+    /// Push the data accumulated for the transaction in progress, if
+    /// any, forward ahead of its commit, as a partial transaction ended
+    /// by `on_flush`. Returned is the number of updates flushed.
+    fn flush<E>(&mut self) -> Result<usize, E>
+    where
+        O: Observer<T, E>,
+        E: Send,
+    {
+        trace!("CachingObserver({})::flush", self.id);
+
+        let updates = match self.data {
+            Some(ref mut data)
+                if self.snapshot.is_some() || data.iter().any(|batch| !batch.is_empty()) =>
+            {
+                take(data)
+            }
+            _ => return Ok(0),
+        };
+        let snapshot = self.snapshot.take();
+        let len = updates.iter().map(Vec::len).sum::<usize>();
+        let count = len + snapshot.as_ref().map_or(0, Vec::len);
+
+        let mut guard = self.observer.lock_unpoisoned();
+        guard.on_start()?;
+        if let Some(snapshot) = snapshot {
+            guard.on_snapshot(Box::new(snapshot.into_iter()))?;
+        }
+        let updates = updates.into_iter().flatten();
+        on_updates_batched(&mut *guard, updates, len, self.max_batch_len)?;
+        guard.on_flush()?;
+        Ok(count)
+    }
+
+    /// Push the data accumulated for the transaction in progress to the
+    /// observer and commit it, along with its statistics, if any.
+    fn commit<E>(&mut self, stats: Option<TransactionStats>) -> Result<(), E>
+    where
+        O: Observer<T, E>,
+        E: Send,
+    {
+        if let Some(ref mut data) = self.data.take() {
+            let updates = take(data);
+            let len = updates.iter().map(Vec::len).sum::<usize>();
+            let snapshot = self.snapshot.take();
+            let mut guard = self.observer.lock_unpoisoned();
+            guard.on_start()?;
+            if let Some(snapshot) = snapshot {
+                guard.on_snapshot(Box::new(snapshot.into_iter()))?;
+            }
+            if len > 0 || !self.suppress_empty {
+                let updates = updates.into_iter().flatten();
+                on_updates_batched(&mut *guard, updates, len, self.max_batch_len)?;
+            }
+            match stats {
+                Some(stats) => guard.on_commit_with_stats(stats)?,
+                None => guard.on_commit()?,
+            }
+        } else {
+            panic!("on_commit was not preceded by an on_start event")
         }
+        Ok(())
     }
 }
 
@@ -59,17 +177,12 @@ where
 
     fn on_commit(&mut self) -> Result<(), E> {
         trace!("CachingObserver({})::on_commit", self.id);
+        self.commit(None)
+    }
 
-        if let Some(ref mut data) = self.data.take() {
-            let updates = replace(data, LinkedList::new());
-            let mut guard = self.observer.lock().unwrap();
-            guard.on_start()?;
-            guard.on_updates(Box::new(updates.into_iter().flatten()))?;
-            guard.on_commit()?;
-        } else {
-            panic!("on_commit was not preceded by an on_start event")
-        }
-        Ok(())
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        trace!("CachingObserver({})::on_commit_with_stats", self.id);
+        self.commit(Some(stats))
     }
 
     fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
@@ -83,12 +196,277 @@ where
         Ok(())
     }
 
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        trace!("CachingObserver({})::on_snapshot", self.id);
+
+        if self.data.is_some() {
+            self.snapshot.get_or_insert_with(Vec::new).extend(items);
+        } else {
+            panic!("on_snapshot was not preceded by an on_start event")
+        }
+        Ok(())
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        trace!("CachingObserver({})::on_abort", self.id);
+
+        // Nothing has been pushed to the observer yet, so we only have
+        // to discard the data we accumulated.
+        self.data = None;
+        self.snapshot = None;
+        Ok(())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        trace!("CachingObserver({})::on_flush", self.id);
+        // The upstream continues the transaction with a new `on_start`.
+        let _ = self.flush()?;
+        self.data = None;
+        Ok(())
+    }
+
     fn on_completed(&mut self) -> Result<(), E> {
         trace!("CachingObserver({})::on_completed", self.id);
         self.observer.on_completed()
     }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        trace!("CachingObserver({})::on_disconnected", self.id);
+        self.observer.on_disconnected()
+    }
+}
+
+/// The observers a `TxnMux` delivers transactions to: the one
+/// subscribed to it, if any, and a lifecycle observer, if any, which
+/// is informed about all events but `on_updates`.
+///
+/// Every event is forwarded to the lifecycle observer right after the
+/// main observer processed it successfully; an event the main observer
+/// failed to process is not forwarded.
+#[derive(Debug)]
+struct Outlet<T, E> {
+    /// The observer subscribed to the `TxnMux`.
+    observer: OptionalObserver<ObserverBox<T, E>>,
+    /// The number of observers subscribed, which exceeds one if they
+    /// were combined into a single `observer` for broadcasting.
+    subscribers: usize,
+    /// The observer interested in transaction boundaries only.
+    lifecycle: OptionalObserver<ObserverBox<(), E>>,
+}
+
+impl<T, E> Outlet<T, E> {
+    /// Make `observer` the only one subscribed.
+    fn set(&mut self, observer: ObserverBox<T, E>) {
+        self.observer = Some(observer);
+        self.subscribers = 1;
+    }
+
+    /// Remove the observer subscribed, if any.
+    fn take(&mut self) -> OptionalObserver<ObserverBox<T, E>> {
+        self.subscribers = 0;
+        self.observer.take()
+    }
+}
+
+impl<T, E> Default for Outlet<T, E> {
+    fn default() -> Self {
+        Self {
+            observer: None,
+            subscribers: 0,
+            lifecycle: None,
+        }
+    }
+}
+
+impl<T, E> Observer<T, E> for Outlet<T, E>
+where
+    T: Debug + Send,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.observer.on_start()?;
+        self.lifecycle.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.observer.on_commit()?;
+        self.lifecycle.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.observer.on_commit_with_stats(stats)?;
+        self.lifecycle.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_updates(updates)
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.observer.on_snapshot(items)
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.observer.on_abort()?;
+        self.lifecycle.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.observer.on_flush()?;
+        self.lifecycle.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.observer.on_completed()?;
+        self.lifecycle.on_completed()
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.observer.on_disconnected()?;
+        self.lifecycle.on_disconnected()
+    }
+}
+
+/// What happens to an observer subscribing to a `TxnMux` (or a
+/// `TcpReceiver`) that already has one subscribed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SubscribePolicy {
+    /// Refuse the new observer, handing it back. This is the default.
+    Reject,
+    /// Displace the observer subscribed in favor of the new one. As
+    /// transactions are delivered in one go, the old observer has seen
+    /// only complete transactions and the new one sees all those
+    /// committed from then on. The displaced observer is informed via
+    /// `on_completed` that no more transactions follow and is dropped
+    /// afterwards; unsubscribe it first to keep it.
+    Replace,
+    /// Deliver all transactions to every observer subscribed, in the
+    /// order they subscribed in. An error reported by one of them does
+    /// not prevent delivery to the others; the first error is reported
+    /// once all of them were notified. Unsubscribing removes all
+    /// observers at once, handing them out combined into one.
+    Broadcast,
+}
+
+/// A `SubscribePolicy` along with the means of implementing it.
+#[derive(Debug)]
+enum Policy<T> {
+    Reject,
+    Replace,
+    /// Broadcast transactions, copying updates using the contained
+    /// function.
+    Broadcast(fn(&T) -> T),
+}
+
+// Deriving would require `#[default]`, which our toolchain predates.
+#[allow(unknown_lints, clippy::derivable_impls)]
+impl<T> Default for Policy<T> {
+    fn default() -> Self {
+        Policy::Reject
+    }
+}
+
+/// An `Observer` forwarding all events to each of a set of observers,
+/// as used for broadcasting transactions.
+#[derive(Debug)]
+struct FanOut<T, E> {
+    /// The observers we forward events to.
+    observers: Vec<ObserverBox<T, E>>,
+    /// The function copying updates for all but the last observer.
+    clone: fn(&T) -> T,
+}
+
+impl<T, E> FanOut<T, E>
+where
+    T: 'static,
+{
+    /// Invoke `f` on all observers, reporting the first error, if any.
+    fn fan_out<F>(&mut self, f: F) -> Result<(), E>
+    where
+        F: FnMut(&mut ObserverBox<T, E>) -> Result<(), E>,
+    {
+        self.observers.iter_mut().map(f).fold(Ok(()), Result::and)
+    }
+
+    /// Hand a copy of `items` to all observers using `f`.
+    fn fan_out_items<'a, F>(
+        &mut self,
+        items: Box<dyn Iterator<Item = T> + 'a>,
+        mut f: F,
+    ) -> Result<(), E>
+    where
+        F: FnMut(&mut ObserverBox<T, E>, Box<dyn Iterator<Item = T>>) -> Result<(), E>,
+    {
+        let clone = self.clone;
+        let mut remaining = self.observers.len();
+        let mut items = Some(items.collect::<Vec<_>>());
+        self.fan_out(|observer| {
+            remaining -= 1;
+            let items = if remaining == 0 {
+                items.take().unwrap_or_default()
+            } else {
+                items.iter().flatten().map(clone).collect()
+            };
+            f(observer, Box::new(items.into_iter()))
+        })
+    }
+}
+
+impl<T, E> Observer<T, E> for FanOut<T, E>
+where
+    T: Debug + Send + 'static,
+    E: Debug + Send,
+{
+    fn on_start(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_start())
+    }
+
+    fn on_commit(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_commit())
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), E> {
+        self.fan_out(|o| o.on_commit_with_stats(stats))
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.fan_out_items(updates, |o, updates| o.on_updates(updates))
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), E> {
+        self.fan_out_items(items, |o, items| o.on_snapshot(items))
+    }
+
+    fn on_abort(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_abort())
+    }
+
+    fn on_flush(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_flush())
+    }
+
+    fn on_completed(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_completed())
+    }
+
+    fn on_disconnected(&mut self) -> Result<(), E> {
+        self.fan_out(|o| o.on_disconnected())
+    }
+
+    fn describe(&self) -> String {
+        let observers = self
+            .observers
+            .iter()
+            .map(|o| o.describe())
+            .collect::<Vec<_>>();
+        format!("broadcast({})", observers.join(", "))
+    }
 }
 
+/// The `CachingObserver` used by a `TxnMux` for each of its
+/// observables.
+type Cache<T, E> = CachingObserver<Outlet<T, E>, T>;
+
 /// A multiplexer for transactions. In a nutshell, this is an object
 /// that tracks a set of `Observable`s and implements the `Observable`
 /// interface (i.e., can be subscribed to) itself. It will ensure that
@@ -108,8 +486,18 @@ where
     counter: usize,
     /// The observables we track and our subscriptions to them.
     subscriptions: BTreeMap<usize, (ObservableBox<T, E>, Box<dyn Any + Send>)>,
-    /// A reference to the `Observer` subscribed to us, if any.
-    observer: SharedObserver<OptionalObserver<ObserverBox<T, E>>>,
+    /// The observers we deliver transactions to.
+    observer: SharedObserver<Outlet<T, E>>,
+    /// The `CachingObserver`s we handed out, for flushing them.
+    caches: Vec<Weak<Mutex<Cache<T, E>>>>,
+    /// The maximum number of updates delivered to the observer at once,
+    /// if any.
+    max_batch_len: Option<usize>,
+    /// Whether transactions without any updates are delivered without
+    /// an `on_updates` call.
+    suppress_empty: bool,
+    /// What happens to an observer subscribing while another one is.
+    policy: Policy<T>,
 }
 
 impl<T, E> TxnMux<T, E>
@@ -126,10 +514,47 @@ where
             id,
             counter: 0,
             subscriptions: BTreeMap::new(),
-            observer: SharedObserver::default(),
+            observer: Arc::new(Mutex::new(Outlet::default())),
+            caches: Vec::new(),
+            max_batch_len: None,
+            suppress_empty: false,
+            policy: Policy::Reject,
         }
     }
 
+    /// Set what happens to an observer subscribing while another one
+    /// is subscribed already. Broadcasting transactions involves
+    /// copying their updates, hence the `Clone` bound. Subscribing
+    /// along with a snapshot always requires the absence of other
+    /// observers.
+    pub fn set_subscribe_policy(&mut self, policy: SubscribePolicy)
+    where
+        T: Clone,
+    {
+        self.policy = match policy {
+            SubscribePolicy::Reject => Policy::Reject,
+            SubscribePolicy::Replace => Policy::Replace,
+            SubscribePolicy::Broadcast => Policy::Broadcast(T::clone),
+        };
+    }
+
+    /// Set the maximum number of updates delivered to the observer in a
+    /// single `on_updates` call, if any. The updates of a larger
+    /// transaction are split up, but still delivered between a single
+    /// `on_start` and `on_commit`. Only applies to observables added
+    /// and observers created afterwards.
+    pub fn set_max_batch_len(&mut self, max_batch_len: Option<usize>) {
+        self.max_batch_len = max_batch_len;
+    }
+
+    /// Set whether a transaction without any updates is delivered to
+    /// the observer without an `on_updates` call, as opposed to one
+    /// passing no updates, which is the default. Only applies to
+    /// observables added and observers created afterwards.
+    pub fn set_suppress_empty_batches(&mut self, suppress: bool) {
+        self.suppress_empty = suppress;
+    }
+
     /// Increment the counter so it represents a new unique id.
     /// Then return the new value.
     pub fn get_counter(&mut self) -> usize {
@@ -147,9 +572,14 @@ where
         // Each observable gets its own `CachingObserver`, which will
         // take care of applying transactions in one go (serialized by
         // the shared observer's lock).
-        let cacher = CachingObserver::new(self.observer.clone());
+        let cacher = Arc::new(Mutex::new(CachingObserver {
+            suppress_empty: self.suppress_empty,
+            ..CachingObserver::new(self.observer.clone(), self.max_batch_len)
+        }));
+        let cache = Arc::downgrade(&cacher);
         match observable.subscribe_any(Box::new(cacher)) {
             Ok(subscription) => {
+                self.caches.push(cache);
                 let id = self.get_counter();
                 let _ = self.subscriptions.insert(id, (observable, subscription));
                 Ok(id)
@@ -167,7 +597,103 @@ where
     /// Creates and adds an `Observer` to which the multiplexer is subscribed.
     pub fn create_observer(&mut self) -> ObserverBox<T, E> {
         trace!("TxnMux({})::create_observer", self.id);
-        Box::new(CachingObserver::new(self.observer.clone()))
+        let cacher = Arc::new(Mutex::new(CachingObserver {
+            suppress_empty: self.suppress_empty,
+            ..CachingObserver::new(self.observer.clone(), self.max_batch_len)
+        }));
+        self.caches.push(Arc::downgrade(&cacher));
+        Box::new(cacher)
+    }
+
+    /// Forcibly push the updates buffered for all transactions in
+    /// progress forward, ahead of their commits, e.g., as part of a
+    /// shutdown. Returned is the total number of updates flushed.
+    ///
+    /// Each partial transaction is delivered to the observer as one of
+    /// its own, ended by `on_flush` instead of `on_commit`. Updates
+    /// arriving afterwards are buffered and delivered as usual.
+    pub fn flush(&mut self) -> Result<usize, E> {
+        trace!("TxnMux({})::flush", self.id);
+
+        self.caches.retain(|cache| cache.strong_count() > 0);
+        let mut flushed = 0;
+        for cache in &self.caches {
+            if let Some(cache) = cache.upgrade() {
+                flushed += cache.lock_unpoisoned().flush()?;
+            }
+        }
+        Ok(flushed)
+    }
+
+    /// Subscribe `observer`, bootstrapping it with the items produced
+    /// by `snapshot` first.
+    ///
+    /// The snapshot is delivered to the observer alone, as a synthetic
+    /// transaction of its own, and the observer subscribed right after,
+    /// all while holding the lock serializing transactions. Hence, no
+    /// transaction falls between the snapshot being taken and the
+    /// observer receiving live transactions. If the observer fails to
+    /// process the snapshot, it is handed back without being subscribed.
+    pub fn subscribe_with_snapshot<F>(
+        &mut self,
+        mut observer: ObserverBox<T, E>,
+        snapshot: F,
+    ) -> Result<(), ObserverBox<T, E>>
+    where
+        F: FnOnce() -> Vec<T>,
+    {
+        trace!("TxnMux({})::subscribe_with_snapshot", self.id);
+
+        let mut guard = self.observer.lock_unpoisoned();
+        if guard.observer.is_some() {
+            return Err(observer);
+        }
+
+        let result = observer.on_start().and_then(|_| {
+            observer.on_snapshot(Box::new(snapshot().into_iter()))?;
+            observer.on_commit()
+        });
+        if let Err(e) = result {
+            error!("TxnMux({}): failed to deliver snapshot: {:?}", self.id, e);
+            let _ = observer.on_abort();
+            return Err(observer);
+        }
+
+        guard.set(observer);
+        Ok(())
+    }
+
+    /// Subscribe an observer to the boundaries of the transactions
+    /// we deliver, i.e., to all events but `on_updates`, alongside the
+    /// observer subscribed to us.
+    ///
+    /// Each event is delivered to the lifecycle observer right after
+    /// the subscribed observer processed it successfully, on the same
+    /// thread and while still holding the lock serializing
+    /// transactions. Hence, the lifecycle observer sees the boundaries
+    /// of all transactions in exactly the order they are delivered in
+    /// and learns about a commit only once the commit is complete.
+    /// Events the subscribed observer failed to process are not
+    /// forwarded.
+    pub fn subscribe_lifecycle(
+        &mut self,
+        observer: ObserverBox<(), E>,
+    ) -> Result<(), ObserverBox<(), E>> {
+        trace!("TxnMux({})::subscribe_lifecycle", self.id);
+
+        let mut guard = self.observer.lock_unpoisoned();
+        if guard.lifecycle.is_some() {
+            Err(observer)
+        } else {
+            let _ = guard.lifecycle.replace(observer);
+            Ok(())
+        }
+    }
+
+    /// Unsubscribe the lifecycle observer, if any.
+    pub fn unsubscribe_lifecycle(&mut self) -> Option<ObserverBox<(), E>> {
+        trace!("TxnMux({})::unsubscribe_lifecycle", self.id);
+        self.observer.lock_unpoisoned().lifecycle.take()
     }
 
     /// For testing: Checks that the given id exists in the
@@ -204,18 +730,44 @@ where
     ) -> Result<Self::Subscription, ObserverBox<T, E>> {
         trace!("TxnMux({})::subscribe", self.id);
 
-        let mut guard = self.observer.lock().unwrap();
-        if guard.is_some() {
-            Err(observer)
-        } else {
-            let _ = guard.replace(observer);
-            Ok(())
+        let mut guard = self.observer.lock_unpoisoned();
+        match (guard.observer.take(), &self.policy) {
+            (None, _) => guard.set(observer),
+            (Some(subscribed), Policy::Reject) => {
+                guard.observer = Some(subscribed);
+                return Err(observer);
+            }
+            (Some(mut subscribed), Policy::Replace) => {
+                debug!("TxnMux({}): replacing {}", self.id, subscribed.describe());
+                if let Err(e) = subscribed.on_completed() {
+                    error!(
+                        "TxnMux({}): failed to complete displaced observer: {:?}",
+                        self.id, e
+                    );
+                }
+                guard.set(observer);
+            }
+            (Some(subscribed), Policy::Broadcast(clone)) => {
+                // Additional observers nest fan-outs, which preserves
+                // the order of delivery.
+                let subscribers = guard.subscribers;
+                guard.set(Box::new(FanOut {
+                    observers: vec![subscribed, observer],
+                    clone: *clone,
+                }));
+                guard.subscribers = subscribers + 1;
+            }
         }
+        Ok(())
     }
 
     fn unsubscribe(&mut self, _subscription: &Self::Subscription) -> Option<ObserverBox<T, E>> {
         trace!("TxnMux({})::unsubscribe", self.id);
-        self.observer.lock().unwrap().take()
+        self.observer.lock_unpoisoned().take()
+    }
+
+    fn subscriber_count(&self) -> usize {
+        self.observer.lock_unpoisoned().subscribers
     }
 }
 
@@ -223,16 +775,13 @@ where
 mod tests {
     use super::*;
 
-    use std::sync::Arc;
-    use std::sync::Mutex;
-
     use crate::observe::MockObserver;
 
     /// Test caching of transactions via a `CachingObserver`.
     #[test]
     fn transaction_caching() {
         let mock = Arc::new(Mutex::new(Some(MockObserver::new())));
-        let observer = &mut CachingObserver::new(mock.clone()) as &mut dyn Observer<_, ()>;
+        let observer = &mut CachingObserver::new(mock.clone(), None) as &mut dyn Observer<_, ()>;
 
         assert_eq!(observer.on_start(), Ok(()));
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_start, 0);
@@ -248,4 +797,97 @@ mod tests {
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_updates, 6);
         assert_eq!(mock.lock().unwrap().as_ref().unwrap().called_on_commit, 1);
     }
+
+    /// Check that an aborted transaction is never pushed forward by a
+    /// `CachingObserver`.
+    #[test]
+    fn transaction_abort() {
+        let mock = Arc::new(Mutex::new(Some(MockObserver::new())));
+        let observer = &mut CachingObserver::new(mock.clone(), None) as &mut dyn Observer<_, ()>;
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new([1, 3, 2].iter())), Ok(()));
+        assert_eq!(observer.on_abort(), Ok(()));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(observer.on_updates(Box::new([4].iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = mock.lock().unwrap().unwrap();
+        assert_eq!(mock.called_on_start, 1);
+        assert_eq!(mock.called_on_updates, 1);
+        assert_eq!(mock.called_on_abort, 0);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Check that buffered updates of a transaction in progress can be
+    /// flushed forcibly and that the remainder is delivered on commit.
+    #[test]
+    fn transaction_flush() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let mut mux = TxnMux::<u64, ()>::new();
+        mux.subscribe(Box::new(mock.clone())).unwrap();
+        let mut observer = mux.create_observer();
+
+        assert_eq!(mux.flush(), Ok(0));
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 3, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(mux.flush(), Ok(3));
+        assert_eq!(mux.flush(), Ok(0));
+        {
+            let mock = mock.lock().unwrap();
+            assert_eq!(mock.called_on_start, 1);
+            assert_eq!(mock.called_on_updates, 3);
+            assert_eq!(mock.called_on_flush, 1);
+            assert_eq!(mock.called_on_commit, 0);
+        }
+
+        assert_eq!(observer.on_updates(Box::new(vec![4].into_iter())), Ok(()));
+        assert_eq!(observer.on_commit(), Ok(()));
+
+        let mock = mock.lock().unwrap();
+        assert_eq!(mock.called_on_start, 2);
+        assert_eq!(mock.called_on_updates, 4);
+        assert_eq!(mock.called_on_flush, 1);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Check that a lifecycle observer is informed about transaction
+    /// boundaries but not updates.
+    #[test]
+    fn transaction_lifecycle() {
+        let mock = Arc::new(Mutex::new(MockObserver::new()));
+        let lifecycle = Arc::new(Mutex::new(MockObserver::new()));
+        let mut mux = TxnMux::<u64, ()>::new();
+        mux.subscribe(Box::new(mock.clone())).unwrap();
+        mux.subscribe_lifecycle(Box::new(lifecycle.clone()))
+            .unwrap();
+        assert!(mux
+            .subscribe_lifecycle(Box::new(MockObserver::new()))
+            .is_err());
+        let mut observer = mux.create_observer();
+
+        assert_eq!(observer.on_start(), Ok(()));
+        assert_eq!(
+            observer.on_updates(Box::new(vec![1, 3, 2].into_iter())),
+            Ok(())
+        );
+        assert_eq!(lifecycle.lock().unwrap().called_on_start, 0);
+        assert_eq!(observer.on_commit(), Ok(()));
+        assert_eq!(observer.on_completed(), Ok(()));
+
+        {
+            let lifecycle = lifecycle.lock().unwrap();
+            assert_eq!(lifecycle.called_on_start, 1);
+            assert_eq!(lifecycle.called_on_updates, 0);
+            assert_eq!(lifecycle.called_on_commit, 1);
+            assert_eq!(lifecycle.called_on_completed, 1);
+        }
+        assert_eq!(mock.lock().unwrap().called_on_updates, 3);
+        assert!(mux.unsubscribe_lifecycle().is_some());
+    }
 }
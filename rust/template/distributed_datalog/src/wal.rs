@@ -0,0 +1,377 @@
+//! A module providing a write-ahead log for observer events, allowing
+//! for the state of an observer to be recovered after a crash.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+
+use bincode::deserialize_from;
+use bincode::serialize_into;
+use bincode::ErrorKind as BincodeError;
+
+use log::trace;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use uid::Id;
+
+use crate::observe::Observer;
+use crate::observe::TransactionStats;
+use crate::tcp_channel::Message;
+
+/// An `Observer` durably logging all events to an append-only file
+/// before forwarding them to an inner observer.
+///
+/// The log is synced to disk on every commit, meaning that every
+/// transaction the inner observer has seen committed can be recovered
+/// using `replay`.
+#[derive(Debug)]
+pub struct WalObserver<O> {
+    /// The observer's unique ID.
+    id: usize,
+    /// The observer we forward events to.
+    observer: O,
+    /// The log file we write to.
+    log: BufWriter<File>,
+}
+
+impl<O> WalObserver<O> {
+    /// Create a new `WalObserver` appending events to `file` before
+    /// forwarding them to `observer`.
+    ///
+    /// The file should be opened in append mode, so that an existing
+    /// log is extended rather than overwritten.
+    pub fn new(observer: O, file: File) -> Self {
+        let id = Id::<()>::new().get();
+        trace!("WalObserver({})::new", id);
+
+        Self {
+            id,
+            observer,
+            log: BufWriter::new(file),
+        }
+    }
+
+    /// Append a message to the log.
+    fn log<T>(&mut self, message: &Message<T>) -> Result<(), String>
+    where
+        T: Serialize,
+    {
+        serialize_into(&mut self.log, message)
+            .map_err(|e| format!("failed to log '{}' event: {}", message, e))
+    }
+
+    /// Flush the log and sync it to disk.
+    fn sync(&mut self) -> Result<(), String> {
+        self.log
+            .flush()
+            .and_then(|_| self.log.get_ref().sync_data())
+            .map_err(|e| format!("failed to sync log: {}", e))
+    }
+}
+
+impl<O, T> Observer<T, String> for WalObserver<O>
+where
+    O: Observer<T, String>,
+    T: Send + Serialize,
+{
+    fn on_start(&mut self) -> Result<(), String> {
+        trace!("WalObserver({})::on_start", self.id);
+
+        self.log(&Message::<T>::Start)?;
+        self.observer.on_start()
+    }
+
+    fn on_commit(&mut self) -> Result<(), String> {
+        trace!("WalObserver({})::on_commit", self.id);
+
+        self.log(&Message::<T>::Commit)?;
+        self.sync()?;
+        self.observer.on_commit()
+    }
+
+    fn on_commit_with_stats(&mut self, stats: TransactionStats) -> Result<(), String> {
+        trace!("WalObserver({})::on_commit_with_stats", self.id);
+
+        self.log(&Message::<T>::Commit)?;
+        self.sync()?;
+        self.observer.on_commit_with_stats(stats)
+    }
+
+    fn on_updates<'a>(&mut self, updates: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("WalObserver({})::on_updates", self.id);
+
+        let message = Message::Updates(updates.collect());
+        self.log(&message)?;
+
+        match message {
+            Message::Updates(updates) => self.observer.on_updates(Box::new(updates.into_iter())),
+            _ => unreachable!(),
+        }
+    }
+
+    fn on_snapshot<'a>(&mut self, items: Box<dyn Iterator<Item = T> + 'a>) -> Result<(), String> {
+        trace!("WalObserver({})::on_snapshot", self.id);
+
+        let message = Message::Snapshot(items.collect());
+        self.log(&message)?;
+
+        match message {
+            Message::Snapshot(items) => self.observer.on_snapshot(Box::new(items.into_iter())),
+            _ => unreachable!(),
+        }
+    }
+
+    fn on_abort(&mut self) -> Result<(), String> {
+        trace!("WalObserver({})::on_abort", self.id);
+
+        self.log(&Message::<T>::Abort)?;
+        self.observer.on_abort()
+    }
+
+    fn on_flush(&mut self) -> Result<(), String> {
+        trace!("WalObserver({})::on_flush", self.id);
+
+        self.log(&Message::<T>::Flush)?;
+        self.sync()?;
+        self.observer.on_flush()
+    }
+
+    fn on_completed(&mut self) -> Result<(), String> {
+        trace!("WalObserver({})::on_completed", self.id);
+
+        self.log(&Message::<T>::Complete)?;
+        self.sync()?;
+        self.observer.on_completed()
+    }
+
+    /// A disconnect is not logged, as it is not replayed either, but
+    /// the log is synced all the same.
+    fn on_disconnected(&mut self) -> Result<(), String> {
+        trace!("WalObserver({})::on_disconnected", self.id);
+
+        self.sync()?;
+        self.observer.on_disconnected()
+    }
+
+    fn describe(&self) -> String {
+        format!("wal -> {}", self.observer.describe())
+    }
+}
+
+/// A transaction read from the log but not yet committed.
+struct Transaction<T> {
+    /// The snapshot of the transaction, if any.
+    snapshot: Option<Vec<T>>,
+    /// The batches of updates of the transaction.
+    batches: Vec<Vec<T>>,
+}
+
+/// Replay the transactions recorded in a log written by a
+/// `WalObserver` to the given observer, returning the number of
+/// transactions replayed.
+///
+/// Only committed transactions are replayed. A transaction that was
+/// aborted or not committed by the end of the log (e.g., because of a
+/// crash) is discarded, as is a record cut short at the end of the log.
+/// A transaction that got flushed is continued by the one following it
+/// and replayed along with that, as a single transaction. A snapshot is
+/// replayed as such, ahead of the transaction's updates. Completion
+/// events are not replayed, as the observer is meant to continue
+/// processing events afterwards.
+pub fn replay<R, O, T>(log: R, observer: &mut O) -> Result<usize, String>
+where
+    R: Read,
+    O: Observer<T, String>,
+    T: Send + DeserializeOwned,
+{
+    let mut reader = BufReader::new(log);
+    // The transaction in progress, if any.
+    let mut ongoing = None;
+    // Whether the transaction in progress got flushed, meaning that the
+    // next one continues it.
+    let mut flushed = false;
+    let mut replayed = 0;
+
+    loop {
+        let message = match deserialize_from(&mut reader) {
+            Ok(message) => message,
+            Err(e) => match *e {
+                BincodeError::Io(ref e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                _ => return Err(format!("failed to read log: {}", e)),
+            },
+        };
+
+        match message {
+            Message::Start => {
+                if !flushed {
+                    ongoing = Some(Transaction {
+                        snapshot: None,
+                        batches: Vec::new(),
+                    })
+                }
+                flushed = false;
+            }
+            Message::Updates(updates) => match &mut ongoing {
+                Some(txn) => txn.batches.push(updates),
+                None => return Err("log contains updates outside of a transaction".to_string()),
+            },
+            Message::UpdateList(updates) => match &mut ongoing {
+                Some(txn) => txn.batches.extend(updates),
+                None => return Err("log contains updates outside of a transaction".to_string()),
+            },
+            Message::Snapshot(items) => match &mut ongoing {
+                Some(txn) => txn.snapshot.get_or_insert_with(Vec::new).extend(items),
+                None => return Err("log contains snapshot outside of a transaction".to_string()),
+            },
+            Message::Commit => match ongoing.take() {
+                Some(txn) => {
+                    observer.on_start()?;
+                    if let Some(snapshot) = txn.snapshot {
+                        observer.on_snapshot(Box::new(snapshot.into_iter()))?;
+                    }
+                    observer.on_updates(Box::new(txn.batches.into_iter().flatten()))?;
+                    observer.on_commit()?;
+                    replayed += 1;
+                }
+                None => return Err("log contains commit outside of a transaction".to_string()),
+            },
+            Message::Abort => {
+                ongoing = None;
+                flushed = false;
+            }
+            Message::Flush => match ongoing {
+                Some(_) => flushed = true,
+                None => return Err("log contains flush outside of a transaction".to_string()),
+            },
+            Message::Complete => (),
+            Message::Ack(_) => return Err("log contains an acknowledgement".to_string()),
+            Message::Resume(_) => return Err("log contains a resume header".to_string()),
+            Message::Version(_) => return Err("log contains a version announcement".to_string()),
+            Message::Identify(_) => return Err("log contains an identification".to_string()),
+            Message::Framed { .. } => return Err("log contains a framed message".to_string()),
+        }
+    }
+    Ok(replayed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::OpenOptions;
+
+    use tempfile::NamedTempFile;
+
+    use crate::accumulate::UpdatesMockObserver;
+    use crate::sinks::JsonLinesObserver;
+
+    /// Log a couple of transactions and replay them, discarding aborted
+    /// and incomplete ones.
+    #[test]
+    fn log_and_replay() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let file = OpenOptions::new()
+            .append(true)
+            .open(tempfile.path())
+            .unwrap();
+        let mut wal = WalObserver::new(UpdatesMockObserver::<u64>::new(), file);
+        let observer = &mut wal as &mut dyn Observer<u64, String>;
+
+        observer.on_start().unwrap();
+        observer
+            .on_updates(Box::new(vec![1, 2].into_iter()))
+            .unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![4].into_iter())).unwrap();
+        observer.on_abort().unwrap();
+
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![5].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![6].into_iter())).unwrap();
+        wal.sync().unwrap();
+
+        assert_eq!(wal.observer.received_updates, vec![1, 2, 3, 4, 5, 6]);
+
+        let mut mock = UpdatesMockObserver::<u64>::new();
+        let replayed = replay(tempfile.reopen().unwrap(), &mut mock).unwrap();
+        assert_eq!(replayed, 2);
+        assert_eq!(mock.received_updates, vec![1, 2, 3, 5]);
+        assert_eq!(mock.called_on_commit, 2);
+    }
+
+    /// Check that a flushed transaction is replayed along with the one
+    /// continuing it.
+    #[test]
+    fn replay_flushed() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let file = OpenOptions::new()
+            .append(true)
+            .open(tempfile.path())
+            .unwrap();
+        let mut wal = WalObserver::new(UpdatesMockObserver::<u64>::new(), file);
+        let observer = &mut wal as &mut dyn Observer<u64, String>;
+
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_flush().unwrap();
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![2].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+
+        observer.on_start().unwrap();
+        observer.on_updates(Box::new(vec![3].into_iter())).unwrap();
+        observer.on_flush().unwrap();
+        wal.sync().unwrap();
+
+        assert_eq!(wal.observer.called_on_commit, 1);
+
+        let mut mock = UpdatesMockObserver::<u64>::new();
+        let replayed = replay(tempfile.reopen().unwrap(), &mut mock).unwrap();
+        assert_eq!(replayed, 1);
+        assert_eq!(mock.received_updates, vec![1, 2]);
+        assert_eq!(mock.called_on_commit, 1);
+    }
+
+    /// Check that a snapshot is logged and replayed as such.
+    #[test]
+    fn replay_snapshot() {
+        let tempfile = NamedTempFile::new().unwrap();
+        let file = OpenOptions::new()
+            .append(true)
+            .open(tempfile.path())
+            .unwrap();
+        let mut wal = WalObserver::new(UpdatesMockObserver::<u64>::new(), file);
+        let observer = &mut wal as &mut dyn Observer<u64, String>;
+
+        observer.on_start().unwrap();
+        observer.on_snapshot(Box::new(vec![1].into_iter())).unwrap();
+        observer.on_updates(Box::new(vec![2].into_iter())).unwrap();
+        observer.on_commit().unwrap();
+
+        let mut json = JsonLinesObserver::with_writer(Vec::new());
+        let replayed = replay::<_, _, u64>(tempfile.reopen().unwrap(), &mut json).unwrap();
+        assert_eq!(replayed, 1);
+
+        let output = String::from_utf8(json.into_inner()).unwrap();
+        assert_eq!(
+            output.lines().collect::<Vec<_>>(),
+            vec![
+                r#""start""#,
+                r#"{"snapshot":1}"#,
+                r#"{"update":2}"#,
+                r#""commit""#
+            ]
+        );
+    }
+}